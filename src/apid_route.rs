@@ -0,0 +1,198 @@
+use std::collections::BTreeMap;
+
+use crate::*;
+use crate::ccsds::*;
+use crate::rule_route::*;
+use crate::sequence::*;
+use crate::time_code::*;
+
+
+/// A set of APIDs, given as individual APIDs and inclusive ranges of APIDs
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApidSet {
+    #[serde(default)]
+    pub apids: Vec<u16>,
+
+    /// Inclusive ranges of APIDs, given as (first, last)
+    #[serde(default)]
+    pub ranges: Vec<(u16, u16)>,
+}
+
+impl ApidSet {
+    pub fn contains(&self, apid: u16) -> bool {
+        return self.apids.contains(&apid) ||
+               self.ranges.iter().any(|(first, last)| *first <= apid && apid <= *last);
+    }
+
+    /// An APID set with no APIDs or ranges matches every APID
+    pub fn is_empty(&self) -> bool {
+        return self.apids.is_empty() && self.ranges.is_empty();
+    }
+}
+
+/// The action to take with a packet that matches a rule
+pub type ApidAction = RouteAction;
+
+/// A range of packet times, where either end may be left open
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeRange {
+    /// The earliest time in the range, inclusive
+    #[serde(default)]
+    pub start: Option<CcsdsTime>,
+
+    /// The latest time in the range, exclusive
+    #[serde(default)]
+    pub end: Option<CcsdsTime>,
+}
+
+impl TimeRange {
+    pub fn contains(&self, time: &CcsdsTime) -> bool {
+        return self.start.is_none_or(|start| start <= *time) &&
+               self.end.is_none_or(|end| *time < end);
+    }
+}
+
+/// An APID rule matches packets by APID, packet type, and packet time
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApidRule {
+    /// The APIDs matched by this rule. If no APIDs are given, all APIDs match.
+    #[serde(default)]
+    pub apids: ApidSet,
+
+    /// The packet type matched by this rule. If no type is given, both types match.
+    #[serde(default)]
+    pub packet_type: Option<PacketType>,
+
+    /// The packet times matched by this rule. Packets without a time do not match a rule with a time range.
+    #[serde(default)]
+    pub time_range: Option<TimeRange>,
+
+    pub action: ApidAction,
+}
+
+impl RouteRule for ApidRule {
+    type Subject = CcsdsPacketInfo;
+
+    fn matches(&self, packet: &CcsdsPacketInfo) -> bool {
+        let header = &packet.header;
+
+        let apid_matches = self.apids.is_empty() || self.apids.contains(header.apid);
+        let type_matches = self.packet_type.is_none_or(|packet_type| packet_type == header.packet_type);
+        let time_matches = match (&self.time_range, &packet.time) {
+            (None, _) => true,
+            (Some(time_range), Some(time)) => time_range.contains(time),
+            (Some(_), None) => false,
+        };
+
+        return apid_matches && type_matches && time_matches;
+    }
+
+    fn action(&self) -> &RouteAction {
+        return &self.action;
+    }
+}
+
+/// The APID route settings describe a set of named outputs, and the rules that decide
+/// which outputs receive each packet.
+///
+/// Rules are checked in order, and the first matching rule decides what happens to a packet.
+/// Packets that match no rule use the default action.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApidRouteSettings {
+    pub outputs: BTreeMap<String, StreamConfig>,
+
+    #[serde(default)]
+    pub rules: Vec<ApidRule>,
+
+    #[serde(default)]
+    pub default: ApidAction,
+
+    /// Check the sequence count of each APID, logging a warning for each anomaly
+    #[serde(default)]
+    pub monitor_sequence: bool,
+
+    /// The time code in each packet's secondary header, used to match rules with a time range.
+    /// Without a time code, packets use the time decoded by their input's framer, if any.
+    #[serde(default)]
+    pub time_code: Option<TimeCodeSettings>,
+}
+
+impl ApidRouteSettings {
+    /// Check that every rule refers to an output that exists, and that the time code is valid
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(time_code) = self.time_code.as_ref() {
+            time_code.validate()?;
+        }
+
+        let actions = self.rules.iter().map(|rule| &rule.action).chain(std::iter::once(&self.default));
+        return check_output_names(actions, |name| self.outputs.contains_key(name));
+    }
+
+    /// Open each output and create a router
+    pub fn open(&self) -> Result<ApidRouter, String> {
+        self.validate()?;
+
+        let router = RuleRouter::open(self.rules.clone(), self.default.clone(), &self.outputs)?;
+        let mut apid_router = ApidRouter::new(router);
+
+        apid_router.time_code = self.time_code.clone();
+
+        if self.monitor_sequence {
+            apid_router.sequence_monitor = Some(SequenceMonitor::with_logging());
+        }
+
+        return Ok(apid_router);
+    }
+}
+
+/// An APID router writes each CCSDS packet to the outputs selected by its rules.
+#[derive(Debug)]
+pub struct ApidRouter {
+    pub router: RuleRouter<ApidRule>,
+
+    /// An optional monitor that checks the sequence counts of all packets passing through the router
+    pub sequence_monitor: Option<SequenceMonitor>,
+
+    /// The time code used to read the time of packets whose framer did not decode a time
+    pub time_code: Option<TimeCodeSettings>,
+}
+
+impl ApidRouter {
+    pub fn new(router: RuleRouter<ApidRule>) -> ApidRouter {
+        return ApidRouter { router, sequence_monitor: None, time_code: None };
+    }
+
+    /// Route a single packet, returning the number of outputs it was written to.
+    pub fn route_packet(&mut self, packet: &[u8]) -> Result<usize, String> {
+        return self.route_timed_packet(packet, None);
+    }
+
+    /// Route a single packet with the time decoded by its framer, if any.
+    /// A packet without a framer time has its time read with the router's time code.
+    pub fn route_timed_packet(&mut self, packet: &[u8], time: Option<CcsdsTime>) -> Result<usize, String> {
+        let info = match time {
+            Some(time) => CcsdsPacketInfo { header: CcsdsPrimaryHeader::decode(packet)?, time: Some(time) },
+            None => CcsdsPacketInfo::decode(packet, self.time_code.as_ref())?,
+        };
+
+        if let Some(monitor) = self.sequence_monitor.as_mut() {
+            monitor.check(&info.header);
+        }
+
+        return Ok(self.router.route(&info, packet));
+    }
+}
+
+impl MessageRouter for ApidRouter {
+    fn route_message(&mut self, message: &[u8]) -> Result<usize, String> {
+        return self.route_packet(message);
+    }
+
+    fn route_timed_message(&mut self, message: &[u8], time: Option<CcsdsTime>) -> Result<usize, String> {
+        return self.route_timed_packet(message, time);
+    }
+
+    fn record_drop(&mut self) {
+        self.router.dropped += 1;
+    }
+}
@@ -0,0 +1,359 @@
+use std::fmt;
+
+use bytes::BytesMut;
+
+use crate::framing::*;
+use crate::time_code::*;
+
+
+/// The size of a CCSDS Space Packet primary header in bytes
+pub const CCSDS_PRI_HEADER_SIZE: usize = 6;
+
+/// The largest possible CCSDS Space Packet, including its primary header
+pub const CCSDS_MAX_PACKET_LENGTH: usize = CCSDS_PRI_HEADER_SIZE + 65536;
+
+/// The only packet version number defined by the CCSDS Space Packet Protocol
+pub const CCSDS_VERSION: u8 = 0;
+
+/// The largest APID. This APID is reserved for idle packets.
+pub const CCSDS_IDLE_APID: u16 = 0x7FF;
+
+/// The sequence count is a 14 bit field, so it wraps at this value
+pub const CCSDS_SEQUENCE_COUNT_MODULUS: u16 = 0x4000;
+
+/// The packet type distinguishes telemetry from telecommand packets
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash, Serialize, Deserialize)]
+pub enum PacketType {
+    Telemetry = 0,
+    Command = 1,
+}
+
+/// The sequence flags indicate whether a packet is part of a group of segmented packets
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub enum SequenceFlags {
+    Continuation = 0,
+    First = 1,
+    Last = 2,
+    Unsegmented = 3,
+}
+
+/// A decoded CCSDS Space Packet primary header
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub struct CcsdsPrimaryHeader {
+    pub version: u8,
+    pub packet_type: PacketType,
+    pub secondary_header_flag: bool,
+    pub apid: u16,
+    pub sequence_flags: SequenceFlags,
+    pub sequence_count: u16,
+    /// The packet data length field, which is one less than the number of bytes after the primary header
+    pub length: u16,
+}
+
+impl Default for CcsdsPrimaryHeader {
+    fn default() -> CcsdsPrimaryHeader {
+        CcsdsPrimaryHeader { version: CCSDS_VERSION,
+                             packet_type: PacketType::Telemetry,
+                             secondary_header_flag: false,
+                             apid: 0,
+                             sequence_flags: SequenceFlags::Unsegmented,
+                             sequence_count: 0,
+                             length: 0,
+        }
+    }
+}
+
+impl fmt::Display for CcsdsPrimaryHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ccsds apid={} type={:?} shdr={} seq_flags={:?} seq={} len={}",
+               self.apid,
+               self.packet_type,
+               self.secondary_header_flag,
+               self.sequence_flags,
+               self.sequence_count,
+               self.packet_length())
+    }
+}
+
+impl CcsdsPrimaryHeader {
+    /// Decode a primary header from the start of the given bytes
+    pub fn decode(bytes: &[u8]) -> Result<CcsdsPrimaryHeader, String> {
+        if bytes.len() < CCSDS_PRI_HEADER_SIZE {
+            return Err(format!("CCSDS primary header requires {} bytes, but only {} were provided",
+                               CCSDS_PRI_HEADER_SIZE,
+                               bytes.len()));
+        }
+
+        let control = u16::from_be_bytes([bytes[0], bytes[1]]);
+        let sequence = u16::from_be_bytes([bytes[2], bytes[3]]);
+        let length = u16::from_be_bytes([bytes[4], bytes[5]]);
+
+        let packet_type = if control & 0x1000 != 0 {
+            PacketType::Command
+        } else {
+            PacketType::Telemetry
+        };
+
+        let sequence_flags = match sequence >> 14 {
+            0 => SequenceFlags::Continuation,
+            1 => SequenceFlags::First,
+            2 => SequenceFlags::Last,
+            _ => SequenceFlags::Unsegmented,
+        };
+
+        return Ok(CcsdsPrimaryHeader { version: (control >> 13) as u8,
+                                       packet_type,
+                                       secondary_header_flag: control & 0x0800 != 0,
+                                       apid: control & 0x07FF,
+                                       sequence_flags,
+                                       sequence_count: sequence & 0x3FFF,
+                                       length,
+        });
+    }
+
+    /// Encode the primary header into its 6 byte form
+    pub fn encode(&self) -> [u8; CCSDS_PRI_HEADER_SIZE] {
+        let control: u16 = ((self.version as u16 & 0x7) << 13) |
+                           ((self.packet_type as u16) << 12) |
+                           ((self.secondary_header_flag as u16) << 11) |
+                           (self.apid & 0x07FF);
+        let sequence: u16 = ((self.sequence_flags as u16) << 14) | (self.sequence_count & 0x3FFF);
+
+        let control_bytes = control.to_be_bytes();
+        let sequence_bytes = sequence.to_be_bytes();
+        let length_bytes = self.length.to_be_bytes();
+
+        return [control_bytes[0], control_bytes[1],
+                sequence_bytes[0], sequence_bytes[1],
+                length_bytes[0], length_bytes[1]];
+    }
+
+    /// The full length of the packet in bytes, including the primary header
+    pub fn packet_length(&self) -> usize {
+        return CCSDS_PRI_HEADER_SIZE + self.length as usize + 1;
+    }
+
+    /// Set the packet data length field from the full length of the packet in bytes
+    pub fn set_packet_length(&mut self, packet_length: usize) -> Result<(), String> {
+        if packet_length <= CCSDS_PRI_HEADER_SIZE || packet_length > CCSDS_MAX_PACKET_LENGTH {
+            return Err(format!("Packet length {} can not be encoded in a CCSDS primary header", packet_length));
+        }
+
+        self.length = (packet_length - CCSDS_PRI_HEADER_SIZE - 1) as u16;
+        return Ok(());
+    }
+
+    /// Whether this is an idle packet
+    pub fn is_idle(&self) -> bool {
+        return self.apid == CCSDS_IDLE_APID;
+    }
+}
+
+/// The decoded primary header and secondary header time of a CCSDS Space Packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CcsdsPacketInfo {
+    pub header: CcsdsPrimaryHeader,
+
+    /// The time from the secondary header, if the packet has a secondary header holding a valid time code
+    pub time: Option<CcsdsTime>,
+}
+
+impl CcsdsPacketInfo {
+    /// Decode a packet's header, reading its time with the given time code
+    pub fn decode(packet: &[u8], time_code: Option<&TimeCodeSettings>) -> Result<CcsdsPacketInfo, String> {
+        let header = CcsdsPrimaryHeader::decode(packet)?;
+        let time = time_code.and_then(|time_code| packet_time(&header, packet, time_code));
+
+        return Ok(CcsdsPacketInfo { header, time });
+    }
+}
+
+/// A CCSDS Space Packet, with its decoded primary header and secondary header time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CcsdsPacket {
+    pub header: CcsdsPrimaryHeader,
+
+    /// The time from the secondary header, if the packet has a secondary header holding a valid time code
+    pub time: Option<CcsdsTime>,
+
+    /// The whole packet, including its primary header
+    pub bytes: BytesMut,
+}
+
+impl CcsdsPacket {
+    /// Decode a packet, reading its time with the given time code
+    pub fn decode(bytes: BytesMut, time_code: Option<&TimeCodeSettings>) -> Result<CcsdsPacket, String> {
+        let info = CcsdsPacketInfo::decode(&bytes, time_code)?;
+
+        return Ok(CcsdsPacket { header: info.header, time: info.time, bytes });
+    }
+}
+
+/// Read the time at the start of a packet's secondary header
+pub fn packet_time(header: &CcsdsPrimaryHeader, packet: &[u8], time_code: &TimeCodeSettings) -> Option<CcsdsTime> {
+    if !header.secondary_header_flag || packet.len() < CCSDS_PRI_HEADER_SIZE {
+        return None;
+    }
+
+    return time_code.decode(&packet[CCSDS_PRI_HEADER_SIZE..]).ok();
+}
+
+/// The CCSDS framing settings limit which headers are accepted as the start of a packet.
+///
+/// A header outside of these limits is treated as a loss of synchronization.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CcsdsFramingSettings {
+    /// The smallest acceptable packet length, including the primary header
+    #[serde(default = "default_min_packet_length")]
+    pub min_length: usize,
+
+    /// The largest acceptable packet length, including the primary header
+    #[serde(default = "default_max_packet_length")]
+    pub max_length: usize,
+
+    /// The time code at the start of each packet's secondary header, if packets carry a time
+    #[serde(default)]
+    pub time_code: Option<TimeCodeSettings>,
+}
+
+fn default_min_packet_length() -> usize {
+    CCSDS_PRI_HEADER_SIZE + 1
+}
+
+fn default_max_packet_length() -> usize {
+    CCSDS_MAX_PACKET_LENGTH
+}
+
+impl Default for CcsdsFramingSettings {
+    fn default() -> CcsdsFramingSettings {
+        CcsdsFramingSettings { min_length: default_min_packet_length(),
+                               max_length: default_max_packet_length(),
+                               time_code: None,
+        }
+    }
+}
+
+/// The CCSDS framer extracts whole CCSDS Space Packets from a stream using
+/// the packet data length in each primary header.
+///
+/// When a malformed header is found, the framer drops one byte at a time until
+/// it finds a header that passes validation, reporting an error for each
+/// loss of synchronization.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CcsdsFramer {
+    pub settings: CcsdsFramingSettings,
+
+    /// The number of times synchronization was lost
+    pub resync_count: u64,
+
+    /// The number of bytes dropped while searching for a valid header
+    pub bytes_skipped: u64,
+
+    /// The time of the most recent packet, if it carried one
+    pub last_time: Option<CcsdsTime>,
+
+    in_sync: bool,
+}
+
+impl CcsdsFramer {
+    pub fn new(settings: CcsdsFramingSettings) -> CcsdsFramer {
+        return CcsdsFramer { settings, resync_count: 0, bytes_skipped: 0, last_time: None, in_sync: true };
+    }
+
+    fn validate(&self, header: &CcsdsPrimaryHeader) -> Result<(), String> {
+        if header.version != CCSDS_VERSION {
+            return Err(format!("CCSDS version {} is not valid", header.version));
+        }
+
+        let packet_length = header.packet_length();
+        if packet_length < self.settings.min_length || packet_length > self.settings.max_length {
+            return Err(format!("CCSDS packet length {} is outside of the range {} to {}",
+                               packet_length,
+                               self.settings.min_length,
+                               self.settings.max_length));
+        }
+
+        return Ok(());
+    }
+
+    /// Extract the next packet, decoding its time if the settings give a time code
+    pub fn next_packet(&mut self, buffer: &mut BytesMut) -> Result<Option<CcsdsPacket>, String> {
+        match self.split_packet(buffer)? {
+            Some(bytes) => {
+                let packet = CcsdsPacket::decode(bytes, self.settings.time_code.as_ref())?;
+                self.last_time = packet.time;
+                return Ok(Some(packet));
+            },
+
+            None => {
+                return Ok(None);
+            },
+        }
+    }
+
+    /// Split the next packet from the buffer, resynchronizing on malformed headers
+    fn split_packet(&mut self, buffer: &mut BytesMut) -> Result<Option<BytesMut>, String> {
+        let mut error: Option<String> = None;
+
+        while buffer.len() >= CCSDS_PRI_HEADER_SIZE {
+            let header = CcsdsPrimaryHeader::decode(&buffer[..])?;
+
+            match self.validate(&header) {
+                Ok(()) => {
+                    self.in_sync = true;
+
+                    if let Some(string) = error {
+                        // report the loss of synchronization now that a valid header has been found,
+                        // leaving the packet in the buffer for the next call
+                        return Err(string);
+                    }
+
+                    if buffer.len() < header.packet_length() {
+                        return Ok(None);
+                    }
+
+                    return Ok(Some(buffer.split_to(header.packet_length())));
+                },
+
+                Err(string) => {
+                    if self.in_sync {
+                        self.in_sync = false;
+                        self.resync_count += 1;
+                        error = Some(format!("CCSDS synchronization lost ({}), resynchronizing", string));
+                    }
+
+                    buffer.advance(1);
+                    self.bytes_skipped += 1;
+                },
+            }
+        }
+
+        match error {
+            Some(string) => Err(string),
+            None => Ok(None),
+        }
+    }
+}
+
+impl Framer for CcsdsFramer {
+    fn next_message(&mut self, buffer: &mut BytesMut) -> Result<Option<BytesMut>, String> {
+        return self.next_packet(buffer).map(|packet| packet.map(|packet| packet.bytes));
+    }
+
+    fn message_time(&self) -> Option<CcsdsTime> {
+        return self.last_time;
+    }
+
+    fn encode_message(&mut self, message: &[u8], buffer: &mut BytesMut) -> Result<(), String> {
+        let header = CcsdsPrimaryHeader::decode(message)?;
+
+        if header.packet_length() != message.len() {
+            return Err(format!("CCSDS packet length field gives {} bytes, but the packet is {} bytes",
+                               header.packet_length(),
+                               message.len()));
+        }
+
+        buffer.extend_from_slice(message);
+        return Ok(());
+    }
+}
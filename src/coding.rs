@@ -0,0 +1,81 @@
+/// The length of the CCSDS pseudo-random sequence before it repeats
+pub const PN_SEQUENCE_LENGTH: usize = 255;
+
+/// The size of a frame error control field in bytes
+pub const FECF_SIZE: usize = 2;
+
+/// The initial value of the CRC-16-CCITT used for the frame error control field
+pub const CRC16_INIT: u16 = 0xFFFF;
+
+/// The CRC-16-CCITT generator polynomial, x^16 + x^12 + x^5 + 1
+pub const CRC16_POLY: u16 = 0x1021;
+
+/// Generate the CCSDS pseudo-random sequence.
+///
+/// The sequence comes from the polynomial h(x) = x^8 + x^7 + x^5 + x^3 + 1,
+/// starting from a state of all ones, and repeats every 255 bytes.
+pub fn pn_sequence() -> [u8; PN_SEQUENCE_LENGTH] {
+    let mut sequence = [0u8; PN_SEQUENCE_LENGTH];
+    let mut state: u8 = 0xFF;
+
+    for byte in sequence.iter_mut() {
+        for _ in 0..8 {
+            let bit = state >> 7;
+            let feedback = (state >> 7) ^ (state >> 4) ^ (state >> 2) ^ state;
+
+            *byte = (*byte << 1) | bit;
+            state = (state << 1) | (feedback & 1);
+        }
+    }
+
+    return sequence;
+}
+
+/// Apply the CCSDS pseudo-random sequence to a frame.
+///
+/// Randomizing and de-randomizing are the same operation, as the sequence
+/// is exclusive-ored with the frame.
+pub fn randomize(frame: &mut [u8], sequence: &[u8; PN_SEQUENCE_LENGTH]) {
+    for (byte, pn_byte) in frame.iter_mut().zip(sequence.iter().cycle()) {
+        *byte ^= pn_byte;
+    }
+}
+
+/// Compute the CRC-16-CCITT of some bytes, as used in the CCSDS frame error control field
+pub fn crc16_ccitt(bytes: &[u8]) -> u16 {
+    return crc16_ccitt_update(CRC16_INIT, bytes);
+}
+
+/// Continue a CRC-16-CCITT computation with more bytes
+pub fn crc16_ccitt_update(mut crc: u16, bytes: &[u8]) -> u16 {
+    for byte in bytes.iter() {
+        crc ^= (*byte as u16) << 8;
+
+        for _ in 0..8 {
+            if crc & 0x8000 != 0 {
+                crc = (crc << 1) ^ CRC16_POLY;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+
+    return crc;
+}
+
+/// Check the frame error control field at the end of a frame
+pub fn check_fecf(frame: &[u8]) -> Result<(), String> {
+    if frame.len() < FECF_SIZE {
+        return Err(format!("Frame of {} bytes is too short to hold a frame error control field", frame.len()));
+    }
+
+    let data_len = frame.len() - FECF_SIZE;
+    let expected = crc16_ccitt(&frame[..data_len]);
+    let received = u16::from_be_bytes([frame[data_len], frame[data_len + 1]]);
+
+    if expected != received {
+        return Err(format!("Frame error control field mismatch (computed 0x{:04X}, received 0x{:04X})", expected, received));
+    }
+
+    return Ok(());
+}
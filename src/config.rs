@@ -0,0 +1,359 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use serde_json::{Map, Value};
+
+
+/// The key which lists other configuration files to include, given as a path or a list of paths.
+/// Paths are relative to the including file.
+pub const CONFIG_INCLUDE_KEY: &str = "include";
+
+
+/// The file formats used for configuration, such as stream settings and router configs
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl fmt::Display for ConfigFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigFormat::Json => write!(f, "json"),
+            ConfigFormat::Toml => write!(f, "toml"),
+            ConfigFormat::Yaml => write!(f, "yaml"),
+        }
+    }
+}
+
+impl FromStr for ConfigFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<ConfigFormat, String> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(ConfigFormat::Json),
+            "toml" => Ok(ConfigFormat::Toml),
+            "yaml" | "yml" => Ok(ConfigFormat::Yaml),
+            _ => Err(format!("Unknown config format ({})", s)),
+        }
+    }
+}
+
+impl ConfigFormat {
+    /// Select a format from a file's extension
+    pub fn from_path(path: &Path) -> Result<ConfigFormat, String> {
+        let extension = path.extension()
+                            .and_then(|extension| extension.to_str())
+                            .ok_or_else(|| format!("Config file {} has no extension to select its format", path.display()))?;
+
+        return ConfigFormat::from_str(extension);
+    }
+
+    /// Parse a value from text in this format
+    pub fn parse<T: DeserializeOwned>(&self, text: &str) -> Result<T, String> {
+        let result = match self {
+            ConfigFormat::Json => serde_json::from_str(text).map_err(|err| err.to_string()),
+            ConfigFormat::Toml => toml::from_str(text).map_err(|err| err.to_string()),
+            ConfigFormat::Yaml => serde_yaml::from_str(text).map_err(|err| err.to_string()),
+        };
+
+        return result;
+    }
+
+    /// Write a value as text in this format
+    pub fn format<T: Serialize>(&self, value: &T) -> Result<String, String> {
+        let result = match self {
+            ConfigFormat::Json => serde_json::to_string_pretty(value).map_err(|err| err.to_string()),
+            ConfigFormat::Toml => toml::to_string_pretty(value).map_err(|err| err.to_string()),
+            ConfigFormat::Yaml => serde_yaml::to_string(value).map_err(|err| err.to_string()),
+        };
+
+        return result;
+    }
+}
+
+/// Load a configuration file, with its format selected by its extension.
+///
+/// Files may include other files with an "include" key at the top level. Included files are merged
+/// in order, and then the including file is merged over them, so its values take precedence.
+///
+/// String values may refer to environment variables as ${VAR}, or ${VAR:-default} to use a default
+/// when the variable is unset or empty. A literal '$' is written as "$$". A value given entirely
+/// as one reference, such as "${PORT}", is read as a number or boolean when it expands to one,
+/// so that variables can set numeric fields such as ports, and is kept as a string for fields
+/// which hold a string.
+pub fn load_config<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let mut coerced = Vec::new();
+    let mut value = load_config_value(path, &mut Vec::new(), &mut coerced)?;
+
+    loop {
+        let err = match serde_path_to_error::deserialize(&value) {
+            Ok(config) => return Ok(config),
+            Err(err) => err,
+        };
+
+        // a reference read as a number or boolean for a field which needs a string is put back as the string
+        let key = err.path().to_string();
+        let restored = match coerced.iter().position(|(coerced_key, _)| *coerced_key == key) {
+            Some(index) => {
+                let (_, expanded) = coerced.swap_remove(index);
+                match value_at_key(&mut value, "", &key) {
+                    Some(field) if !field.is_string() => {
+                        *field = Value::String(expanded);
+                        true
+                    },
+                    _ => false,
+                }
+            },
+
+            None => false,
+        };
+
+        if !restored {
+            return Err(format!("Could not parse config {}: at key '{}': {}", path.display(), key, err.inner()));
+        }
+    }
+}
+
+/// Load a configuration file as written, without expanding its variables or includes
+pub fn load_config_unexpanded(path: &Path) -> Result<Value, String> {
+    let format = ConfigFormat::from_path(path)?;
+
+    let text = fs::read_to_string(path).map_err(|err| format!("Could not read config {}: {}", path.display(), err))?;
+
+    return format.parse(&text).map_err(|err| format!("Could not parse config {}: {}", path.display(), err));
+}
+
+/// Convert a configuration file to the format selected by another file's extension.
+///
+/// The configuration is converted as written, so its variable references and includes are kept
+/// rather than replaced by the current environment and the included files.
+pub fn convert_config(from: &Path, to: &Path) -> Result<(), String> {
+    let value = load_config_unexpanded(from)?;
+    return save_config(to, &value);
+}
+
+/// Load a configuration file as a tree of values, expanding its variables and includes.
+/// The keys and expanded strings of references read as numbers or booleans are added to 'coerced'.
+fn load_config_value(path: &Path, including: &mut Vec<PathBuf>, coerced: &mut Vec<(String, String)>) -> Result<Value, String> {
+    // canonicalize so that the same file reached by two paths is recognized as an include cycle
+    let full_path = path.canonicalize().map_err(|err| format!("Could not read config {}: {}", path.display(), err))?;
+    if including.contains(&full_path) {
+        return Err(format!("Config {} is included in a cycle", path.display()));
+    }
+
+    let mut value = load_config_unexpanded(path)?;
+
+    expand_value(&mut value, "", coerced).map_err(|err| format!("Config {}: {}", path.display(), err))?;
+
+    let include_paths = match value.as_object_mut().and_then(|map| map.remove(CONFIG_INCLUDE_KEY)) {
+        Some(Value::String(include_path)) => vec![include_path],
+
+        Some(Value::Array(include_paths)) => {
+            let mut paths = Vec::new();
+            for include_path in include_paths {
+                match include_path {
+                    Value::String(include_path) => paths.push(include_path),
+                    _ => return Err(format!("Config {}: at key '{}': includes must be paths", path.display(), CONFIG_INCLUDE_KEY)),
+                }
+            }
+            paths
+        },
+
+        Some(_) => {
+            return Err(format!("Config {}: at key '{}': includes must be a path or a list of paths", path.display(), CONFIG_INCLUDE_KEY));
+        },
+
+        None => Vec::new(),
+    };
+
+    if include_paths.is_empty() {
+        return Ok(value);
+    }
+
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    including.push(full_path);
+    let mut merged = Value::Object(Map::new());
+    for include_path in include_paths {
+        let included = load_config_value(&directory.join(include_path), including, coerced)?;
+        merge_values(&mut merged, included);
+    }
+    including.pop();
+
+    merge_values(&mut merged, value);
+
+    return Ok(merged);
+}
+
+/// Merge one value over another. Objects are merged key by key, and any other value replaces the original.
+fn merge_values(base: &mut Value, over: Value) {
+    match (base, over) {
+        (Value::Object(base_map), Value::Object(over_map)) => {
+            for (key, over_value) in over_map {
+                match base_map.get_mut(&key) {
+                    Some(base_value) => merge_values(base_value, over_value),
+                    None => {
+                        base_map.insert(key, over_value);
+                    },
+                }
+            }
+        },
+
+        (base, over) => {
+            *base = over;
+        },
+    }
+}
+
+/// Expand the environment variables in every string within a value, naming the key of any failure
+fn expand_value(value: &mut Value, key: &str, coerced: &mut Vec<(String, String)>) -> Result<(), String> {
+    match value {
+        Value::String(string) => {
+            let expanded = expand_variables(string).map_err(|err| format!("at key '{}': {}", key, err))?;
+
+            match coerce_scalar(&expanded).filter(|_| is_single_reference(string)) {
+                Some(scalar) => {
+                    coerced.push((key.to_string(), expanded));
+                    *value = scalar;
+                },
+
+                None => *value = Value::String(expanded),
+            }
+        },
+
+        Value::Array(values) => {
+            for (index, value) in values.iter_mut().enumerate() {
+                expand_value(value, &index_key(key, index), coerced)?;
+            }
+        },
+
+        Value::Object(map) => {
+            for (name, value) in map.iter_mut() {
+                expand_value(value, &child_key(key, name), coerced)?;
+            }
+        },
+
+        _ => {},
+    }
+
+    return Ok(());
+}
+
+/// The key of a value within an object, in the form used by error messages, such as "inputs.radio"
+fn child_key(key: &str, name: &str) -> String {
+    if key.is_empty() {
+        return name.to_string();
+    }
+
+    return format!("{}.{}", key, name);
+}
+
+/// The key of a value within an array, in the form used by error messages, such as "routes.telemetry.inputs[0]"
+fn index_key(key: &str, index: usize) -> String {
+    return format!("{}[{}]", key, index);
+}
+
+/// Find the value with the given key within a value with the key 'key'
+fn value_at_key<'a>(value: &'a mut Value, key: &str, target: &str) -> Option<&'a mut Value> {
+    if key == target {
+        return Some(value);
+    }
+
+    match value {
+        Value::Array(values) => {
+            return values.iter_mut().enumerate().find_map(|(index, value)| value_at_key(value, &index_key(key, index), target));
+        },
+
+        Value::Object(map) => {
+            return map.iter_mut().find_map(|(name, value)| value_at_key(value, &child_key(key, name), target));
+        },
+
+        _ => return None,
+    }
+}
+
+/// Whether a string is exactly one variable reference, with nothing around it
+fn is_single_reference(text: &str) -> bool {
+    match text.strip_prefix("${").and_then(|rest| rest.strip_suffix('}')) {
+        Some(reference) => !reference.contains('}'),
+        None => false,
+    }
+}
+
+/// Read an expanded variable as a number or boolean, if it is one
+fn coerce_scalar(text: &str) -> Option<Value> {
+    match text {
+        "true" => return Some(Value::Bool(true)),
+        "false" => return Some(Value::Bool(false)),
+        _ => {},
+    }
+
+    if let Ok(integer) = text.parse::<i64>() {
+        return Some(Value::from(integer));
+    }
+
+    if let Ok(integer) = text.parse::<u64>() {
+        return Some(Value::from(integer));
+    }
+
+    return text.parse::<f64>().ok().filter(|float| float.is_finite()).map(Value::from);
+}
+
+/// Expand references to environment variables in a string, given as ${VAR} or ${VAR:-default}.
+pub fn expand_variables(text: &str) -> Result<String, String> {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(index) = rest.find('$') {
+        expanded.push_str(&rest[..index]);
+        rest = &rest[index..];
+
+        if let Some(after) = rest.strip_prefix("$$") {
+            expanded.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after.find('}').ok_or_else(|| format!("Unterminated variable reference in \"{}\"", text))?;
+            let reference = &after[..end];
+
+            let (name, default) = match reference.find(":-") {
+                Some(split) => (&reference[..split], Some(&reference[split + 2..])),
+                None => (reference, None),
+            };
+
+            if name.is_empty() {
+                return Err(format!("Empty variable reference in \"{}\"", text));
+            }
+
+            match (env::var(name).ok().filter(|value| !value.is_empty()), default) {
+                (Some(value), _) => expanded.push_str(&value),
+                (None, Some(default)) => expanded.push_str(default),
+                (None, None) => return Err(format!("Environment variable '{}' is not set", name)),
+            }
+
+            rest = &after[end + 1..];
+        } else {
+            expanded.push('$');
+            rest = &rest[1..];
+        }
+    }
+
+    expanded.push_str(rest);
+
+    return Ok(expanded);
+}
+
+/// Save a configuration file, with its format selected by its extension
+pub fn save_config<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let format = ConfigFormat::from_path(path)?;
+
+    let text = format.format(value).map_err(|err| format!("Could not write config as {}: {}", format, err))?;
+
+    return fs::write(path, text).map_err(|err| format!("Could not write config {}: {}", path.display(), err));
+}
@@ -0,0 +1,289 @@
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde_json::{json, Value};
+
+use crate::*;
+use crate::router::*;
+use crate::pacing::*;
+
+
+/// The default address of the control socket
+pub const CONTROL_DEFAULT_ADDRESS: &str = "127.0.0.1:7878";
+
+/// Whether a stream opened through the control socket is an input or an output
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub enum StreamDirection {
+    Input,
+    Output,
+}
+
+impl fmt::Display for StreamDirection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StreamDirection::Input => write!(f, "input"),
+            StreamDirection::Output => write!(f, "output"),
+        }
+    }
+}
+
+/// A command sent to a running router over its control socket.
+///
+/// Commands are sent as a single line of words, such as "open output debug udp:127.0.0.1:9000",
+/// and each command is answered with a single line of JSON, either {"ok": result} or {"error": message}.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlCommand {
+    /// List the inputs, outputs and routes: "list"
+    List,
+    /// Open a stream from a descriptor: "open input|output NAME DESCRIPTOR"
+    Open { direction: StreamDirection, name: String, descriptor: String },
+    /// Close a stream, removing it from any routes: "close NAME"
+    Close { name: String },
+    /// Add an input and output to a route, creating it if needed: "connect ROUTE INPUT OUTPUT"
+    Connect { route: String, input: String, output: String },
+    /// Remove a route: "disconnect ROUTE"
+    Disconnect { route: String },
+    /// Stop delivering messages on a route: "pause ROUTE"
+    Pause { route: String },
+    /// Start delivering messages on a paused route: "resume ROUTE"
+    Resume { route: String },
+    /// Limit the rate of messages on a route, or remove its limit: "pace ROUTE PACING|none"
+    Pace { route: String, pacing: Option<PacingSettings> },
+    /// Report the counters of each input, output and route: "stats"
+    Stats,
+}
+
+impl fmt::Display for ControlCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ControlCommand::List => write!(f, "list"),
+
+            ControlCommand::Open { direction, name, descriptor } => write!(f, "open {} {} {}", direction, name, descriptor),
+
+            ControlCommand::Close { name } => write!(f, "close {}", name),
+
+            ControlCommand::Connect { route, input, output } => write!(f, "connect {} {} {}", route, input, output),
+
+            ControlCommand::Disconnect { route } => write!(f, "disconnect {}", route),
+
+            ControlCommand::Pause { route } => write!(f, "pause {}", route),
+
+            ControlCommand::Resume { route } => write!(f, "resume {}", route),
+
+            ControlCommand::Pace { route, pacing: Some(pacing) } => write!(f, "pace {} {}", route, pacing),
+
+            ControlCommand::Pace { route, pacing: None } => write!(f, "pace {} none", route),
+
+            ControlCommand::Stats => write!(f, "stats"),
+        }
+    }
+}
+
+impl FromStr for ControlCommand {
+    type Err = String;
+    fn from_str(s: &str) -> Result<ControlCommand, String> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+
+        let result = match words.as_slice() {
+            ["list"] => ControlCommand::List,
+
+            ["open", "input", name, descriptor] => {
+                ControlCommand::Open { direction: StreamDirection::Input, name: name.to_string(), descriptor: descriptor.to_string() }
+            },
+
+            ["open", "output", name, descriptor] => {
+                ControlCommand::Open { direction: StreamDirection::Output, name: name.to_string(), descriptor: descriptor.to_string() }
+            },
+
+            ["close", name] => ControlCommand::Close { name: name.to_string() },
+
+            ["connect", route, input, output] => {
+                ControlCommand::Connect { route: route.to_string(), input: input.to_string(), output: output.to_string() }
+            },
+
+            ["disconnect", route] => ControlCommand::Disconnect { route: route.to_string() },
+
+            ["pause", route] => ControlCommand::Pause { route: route.to_string() },
+
+            ["resume", route] => ControlCommand::Resume { route: route.to_string() },
+
+            ["pace", route, "none"] => ControlCommand::Pace { route: route.to_string(), pacing: None },
+
+            ["pace", route, pacing] => ControlCommand::Pace { route: route.to_string(), pacing: Some(PacingSettings::from_str(pacing)?) },
+
+            ["stats"] => ControlCommand::Stats,
+
+            _ => return Err(format!("Unknown command ({})", s.trim())),
+        };
+
+        return Ok(result);
+    }
+}
+
+impl ControlCommand {
+    /// Run a command against a router, returning its result as JSON.
+    ///
+    /// Commands which change the routing graph edit a copy of the router's config and reload it,
+    /// so streams which the command does not touch are left open.
+    pub fn apply(&self, router: &mut Router) -> Result<Value, String> {
+        let mut config = router.config.clone();
+
+        match self {
+            ControlCommand::List => {
+                return serde_json::to_value(&router.config).map_err(|err| err.to_string());
+            },
+
+            ControlCommand::Stats => {
+                return Ok(json!({ "inputs": router.input_counters(),
+                                  "outputs": router.output_counters(),
+                                  "routes": router.route_counters(),
+                }));
+            },
+
+            ControlCommand::Open { direction, name, descriptor } => {
+                if config.inputs.contains_key(name) || config.outputs.contains_key(name) {
+                    return Err(format!("A stream named '{}' already exists", name));
+                }
+
+                let stream_config = StreamConfig::Descriptor(descriptor.clone());
+                match direction {
+                    StreamDirection::Input => config.inputs.insert(name.clone(), stream_config),
+                    StreamDirection::Output => config.outputs.insert(name.clone(), stream_config),
+                };
+            },
+
+            ControlCommand::Close { name } => {
+                if config.inputs.remove(name).is_none() && config.outputs.remove(name).is_none() {
+                    return Err(format!("No stream named '{}'", name));
+                }
+
+                for route in config.routes.values_mut() {
+                    route.inputs.retain(|input| input != name);
+                    route.outputs.retain(|output| output != name);
+                }
+                config.routes.retain(|_, route| !route.inputs.is_empty() && !route.outputs.is_empty());
+            },
+
+            ControlCommand::Connect { route, input, output } => {
+                let route = config.routes.entry(route.clone()).or_default();
+
+                if !route.inputs.contains(input) {
+                    route.inputs.push(input.clone());
+                }
+                if !route.outputs.contains(output) {
+                    route.outputs.push(output.clone());
+                }
+            },
+
+            ControlCommand::Disconnect { route } => {
+                if config.routes.remove(route).is_none() {
+                    return Err(format!("No route named '{}'", route));
+                }
+            },
+
+            ControlCommand::Pause { route } | ControlCommand::Resume { route } => {
+                let paused = matches!(self, ControlCommand::Pause { .. });
+
+                match config.routes.get_mut(route) {
+                    Some(route) => route.paused = paused,
+                    None => return Err(format!("No route named '{}'", route)),
+                }
+            },
+
+            ControlCommand::Pace { route, pacing } => {
+                match config.routes.get_mut(route) {
+                    Some(route) => route.pacing = pacing.clone(),
+                    None => return Err(format!("No route named '{}'", route)),
+                }
+            },
+        }
+
+        let summary = router.reload(config)?;
+        if !summary.errors.is_empty() {
+            return Err(summary.errors.join(", "));
+        }
+
+        return Ok(json!({ "opened": summary.opened, "closed": summary.closed, "rerouted": summary.rerouted }));
+    }
+}
+
+/// Serve the control socket, handling each client on its own thread
+pub fn serve_control(listener: TcpListener, router: Arc<Mutex<Router>>) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let router = Arc::clone(&router);
+                thread::spawn(move || handle_control_client(stream, router));
+            },
+
+            Err(err) => {
+                warn!(error = %err, "control socket accept error");
+            },
+        }
+    }
+}
+
+fn handle_control_client(stream: TcpStream, router: Arc<Mutex<Router>>) {
+    let peer = stream.peer_addr().map(|peer| peer.to_string()).unwrap_or_default();
+    let span = info_span!("control", peer = %peer);
+    let _entered = span.enter();
+    debug!("control client connected");
+
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let result = ControlCommand::from_str(&line).and_then(|command| {
+            info!(command = %command, "control command");
+            let mut router = router.lock().map_err(|_| "router lock poisoned".to_string())?;
+            command.apply(&mut router)
+        });
+
+        if let Err(string) = &result {
+            warn!(command = %line.trim(), error = %string, "control command failed");
+        }
+
+        let reply = match result {
+            Ok(value) => json!({ "ok": value }),
+            Err(string) => json!({ "error": string }),
+        };
+
+        if writeln!(writer, "{}", reply).is_err() {
+            return;
+        }
+    }
+}
+
+/// Send a single command to a router's control socket, returning its JSON reply
+pub fn send_control_command(address: &str, command: &ControlCommand) -> Result<Value, String> {
+    let stream = TcpStream::connect(address).map_err(|err| format!("Could not connect to {}: {}", address, err))?;
+
+    let mut writer = stream.try_clone().map_err(|err| err.to_string())?;
+    writeln!(writer, "{}", command).map_err(|err| format!("Could not send command: {}", err))?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).map_err(|err| format!("Could not read reply: {}", err))?;
+
+    let reply: Value = serde_json::from_str(&line).map_err(|err| format!("Could not parse reply ({}): {}", line.trim(), err))?;
+
+    if let Some(error) = reply.get("error") {
+        return Err(error.as_str().unwrap_or("unknown error").to_string());
+    }
+
+    return Ok(reply.get("ok").cloned().unwrap_or(Value::Null));
+}
@@ -0,0 +1,319 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, Stdout, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+
+use ratatui::Terminal;
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Paragraph, Sparkline};
+use ratatui::Frame;
+
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::*;
+use crate::router::*;
+use crate::stats::*;
+
+
+/// How often the dashboard samples counters and redraws
+pub const DASHBOARD_REFRESH_MS: u64 = 250;
+
+/// The number of rate samples kept for each stream's sparkline
+const DASHBOARD_HISTORY_LEN: usize = 240;
+
+/// The number of log lines kept for the recent errors pane
+const LOG_BUFFER_LINES: usize = 100;
+
+/// The height of each stream's pane, including its border
+const STREAM_PANE_HEIGHT: u16 = 4;
+
+/// The height of the recent errors pane, including its border
+const ERROR_PANE_HEIGHT: u16 = 8;
+
+
+/// A log writer which keeps the most recent lines in memory, so the dashboard can show
+/// them instead of writing over the terminal.
+#[derive(Debug, Clone, Default)]
+pub struct LogBuffer {
+    lines: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl LogBuffer {
+    pub fn new() -> LogBuffer {
+        return LogBuffer::default();
+    }
+
+    /// The kept lines, oldest first
+    pub fn lines(&self) -> Vec<String> {
+        return self.lines.lock().unwrap_or_else(|err| err.into_inner()).iter().cloned().collect();
+    }
+
+    fn push(&self, text: &str) {
+        let mut lines = self.lines.lock().unwrap_or_else(|err| err.into_inner());
+
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            if lines.len() == LOG_BUFFER_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(line.to_string());
+        }
+    }
+}
+
+/// Collects one log event's text, adding it to the buffer when dropped
+pub struct LogBufferWriter {
+    buffer: LogBuffer,
+    text: Vec<u8>,
+}
+
+impl Write for LogBufferWriter {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.text.extend_from_slice(bytes);
+        return Ok(bytes.len());
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return Ok(());
+    }
+}
+
+impl Drop for LogBufferWriter {
+    fn drop(&mut self) {
+        self.buffer.push(&String::from_utf8_lossy(&self.text));
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogBuffer {
+    type Writer = LogBufferWriter;
+
+    fn make_writer(&'a self) -> LogBufferWriter {
+        return LogBufferWriter { buffer: self.clone(), text: Vec::new() };
+    }
+}
+
+/// The recent byte rates of a stream
+#[derive(Debug, Default)]
+struct StreamHistory {
+    previous: StreamCounters,
+    rates: VecDeque<u64>,
+}
+
+/// A stream as shown on the dashboard
+struct StreamView {
+    title: String,
+    option: Option<StreamOption>,
+    counters: StreamCounters,
+    rates: StreamRates,
+}
+
+/// A terminal dashboard showing the throughput of each stream, the routes, and recent errors.
+///
+/// The terminal is put in raw mode on an alternate screen while the dashboard exists, and is
+/// restored when it is dropped.
+pub struct Dashboard {
+    terminal: Terminal<CrosstermBackend<Stdout>>,
+    history: BTreeMap<String, StreamHistory>,
+    sampled: Instant,
+    log: LogBuffer,
+}
+
+impl Dashboard {
+    /// Take over the terminal, showing the given log's lines as recent errors
+    pub fn new(log: LogBuffer) -> Result<Dashboard, String> {
+        enable_raw_mode().map_err(|err| format!("Could not set up terminal: {}", err))?;
+
+        let mut stdout = io::stdout();
+        if let Err(err) = execute!(stdout, EnterAlternateScreen) {
+            let _ = disable_raw_mode();
+            return Err(format!("Could not set up terminal: {}", err));
+        }
+
+        let terminal = match Terminal::new(CrosstermBackend::new(stdout)) {
+            Ok(terminal) => terminal,
+
+            Err(err) => {
+                let _ = execute!(io::stdout(), LeaveAlternateScreen);
+                let _ = disable_raw_mode();
+                return Err(format!("Could not set up terminal: {}", err));
+            },
+        };
+
+        return Ok(Dashboard { terminal, history: BTreeMap::new(), sampled: Instant::now(), log });
+    }
+
+    /// Wait up to the given time for a key press, returning whether the user asked to quit
+    pub fn wait_for_quit(&mut self, timeout: Duration) -> Result<bool, String> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if !event::poll(remaining).map_err(|err| err.to_string())? {
+                return Ok(false);
+            }
+
+            if let Event::Key(key) = event::read().map_err(|err| err.to_string())? {
+                let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                let quit = matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) || ctrl_c;
+
+                if key.kind == KeyEventKind::Press && quit {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+
+    /// Sample the router's counters and redraw
+    pub fn update(&mut self, router: &Router) -> Result<(), String> {
+        let elapsed = self.sampled.elapsed();
+        self.sampled = Instant::now();
+
+        let mut streams = Vec::new();
+        let inputs = router.input_counters().into_iter().map(|(name, counters)| ("input", name, counters));
+        let outputs = router.output_counters().into_iter().map(|(name, counters)| ("output", name, counters));
+
+        for (direction, name, counters) in inputs.chain(outputs) {
+            let config = match direction {
+                "input" => router.config.inputs.get(&name),
+                _ => router.config.outputs.get(&name),
+            };
+
+            let title = format!("{} {} ({})", direction, name, config.map(|config| config.to_string()).unwrap_or_default());
+            let option = config.and_then(|config| config.settings().ok()).map(|(option, _)| option);
+
+            let history = self.history.entry(title.clone()).or_default();
+            let rates = counters.rates_since(&history.previous, elapsed);
+
+            if history.rates.len() == DASHBOARD_HISTORY_LEN {
+                history.rates.pop_front();
+            }
+            history.rates.push_back(rates.bytes_per_second as u64);
+            history.previous = counters.clone();
+
+            streams.push(StreamView { title, option, counters, rates });
+        }
+
+        // forget streams which have been closed
+        self.history.retain(|title, _| streams.iter().any(|stream| &stream.title == title));
+
+        let route_counters = router.route_counters();
+        let routes: Vec<Line> = router.config.routes.iter().map(|(name, route)| {
+            let counters = route_counters.get(name).copied().unwrap_or_default();
+
+            Line::from(format!("{}: {} -> {}  {} msgs  {}{}",
+                               name,
+                               route.inputs.join(", "),
+                               route.outputs.join(", "),
+                               counters.messages,
+                               format_bytes(counters.bytes as f64),
+                               if route.paused { "  (paused)" } else { "" }))
+        }).collect();
+
+        let errors = self.log.lines();
+        let history = &self.history;
+
+        self.terminal.draw(|frame| draw(frame, &streams, history, &routes, &errors))
+                     .map_err(|err| format!("Could not draw dashboard: {}", err))?;
+
+        return Ok(());
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+        let _ = execute!(self.terminal.backend_mut(), LeaveAlternateScreen);
+        let _ = self.terminal.show_cursor();
+    }
+}
+
+fn draw(frame: &mut Frame,
+        streams: &[StreamView],
+        history: &BTreeMap<String, StreamHistory>,
+        routes: &[Line],
+        errors: &[String]) {
+    let [header_area, streams_area, routes_area, errors_area] =
+        Layout::vertical([Constraint::Length(1),
+                          Constraint::Min(STREAM_PANE_HEIGHT),
+                          Constraint::Length(routes.len() as u16 + 2),
+                          Constraint::Length(ERROR_PANE_HEIGHT)]).areas(frame.area());
+
+    frame.render_widget(Paragraph::new("backplane - press q to quit"), header_area);
+
+    let stream_areas = Layout::vertical(streams.iter().map(|_| Constraint::Length(STREAM_PANE_HEIGHT))).split(streams_area);
+    for (stream, area) in streams.iter().zip(stream_areas.iter()) {
+        draw_stream(frame, stream, history.get(&stream.title), *area);
+    }
+
+    frame.render_widget(Paragraph::new(routes.to_vec()).block(Block::default().borders(Borders::ALL).title("routes")), routes_area);
+
+    // show the most recent errors which fit
+    let shown = errors_area.height.saturating_sub(2) as usize;
+    let recent: Vec<Line> = errors[errors.len().saturating_sub(shown)..].iter().map(|line| Line::from(line.as_str())).collect();
+    frame.render_widget(Paragraph::new(recent).style(Style::default().fg(Color::Red))
+                                              .block(Block::default().borders(Borders::ALL).title("recent errors")),
+                        errors_area);
+}
+
+fn draw_stream(frame: &mut Frame, stream: &StreamView, history: Option<&StreamHistory>, area: Rect) {
+    let block = Block::default().borders(Borders::ALL).title(stream.title.as_str());
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let [text_area, sparkline_area] = Layout::horizontal([Constraint::Length(48), Constraint::Min(1)]).areas(inner);
+
+    let counters = &stream.counters;
+
+    let connection = match (stream.option, &counters.peer) {
+        (Some(StreamOption::TcpServer), Some(peer)) => format!("  client {}", peer),
+        (Some(StreamOption::TcpServer), None) => "  no client".to_string(),
+        (Some(StreamOption::TcpClient), Some(peer)) => format!("  server {}", peer),
+        (Some(StreamOption::TcpClient), None) => "  disconnected".to_string(),
+        _ => String::new(),
+    };
+
+    let lines = vec![Line::from(format!("{}/s  {:.1} msg/s  {}  {} msgs",
+                                        format_bytes(stream.rates.bytes_per_second),
+                                        stream.rates.messages_per_second,
+                                        format_bytes(counters.bytes as f64),
+                                        counters.messages)),
+                     Line::from(format!("{} errors  {} reconnects  {} dropped{}",
+                                        counters.errors,
+                                        counters.reconnects,
+                                        counters.dropped,
+                                        connection))];
+    frame.render_widget(Paragraph::new(lines), text_area);
+
+    if let Some(history) = history {
+        // the most recent samples are drawn at the right
+        let width = sparkline_area.width as usize;
+        let skip = history.rates.len().saturating_sub(width);
+        let data: Vec<u64> = history.rates.iter().skip(skip).copied().collect();
+
+        frame.render_widget(Sparkline::default().data(&data).style(Style::default().fg(Color::Green)), sparkline_area);
+    }
+}
+
+/// A number of bytes with a unit, such as "1.5 kB"
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "kB", "MB", "GB", "TB"];
+
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        return format!("{:.0} {}", value, UNITS[unit]);
+    }
+
+    return format!("{:.1} {}", value, UNITS[unit]);
+}
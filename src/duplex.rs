@@ -0,0 +1,177 @@
+use std::fmt;
+use std::net::{Shutdown, TcpStream};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::Duration;
+
+use bytes::BytesMut;
+
+use serialport::SerialPort;
+
+use crate::*;
+use crate::stream_read::*;
+
+
+/// The number of bytes requested from a stream on each read when bridging
+pub const BRIDGE_READ_SIZE: usize = 4096;
+
+/// How long to wait before reading again when a read returns no bytes
+pub const BRIDGE_IDLE_MS: u64 = 10;
+
+/// A duplex stream is a single bidirectional connection, such as a TCP socket
+/// or a serial port.
+///
+/// Opening a read stream and a write stream from the same settings opens two
+/// connections. A duplex stream opens one connection and can then be split into
+/// a read half and a write half which share that connection.
+#[derive(Debug)]
+pub enum DuplexStream {
+    Tcp(TcpStream),
+    Serial(Box<dyn SerialPort>),
+}
+
+impl FromStr for DuplexStream {
+    type Err = String;
+    fn from_str(duplex_stream_desc: &str) -> Result<DuplexStream, String> {
+        let result;
+
+        if let Ok(tcp_server_settings) = TcpServerSettings::from_str(duplex_stream_desc) {
+            result = tcp_server_settings.open_duplex_stream();
+        } else if let Ok(tcp_client_settings) = TcpClientSettings::from_str(duplex_stream_desc) {
+            result = tcp_client_settings.open_duplex_stream();
+        } else if let Ok(serial_settings) = SerialSettings::from_str(duplex_stream_desc) {
+            result = serial_settings.open_duplex_stream();
+        } else {
+            result = Err("No matching duplex stream settings!".to_string());
+        }
+
+        return result;
+    }
+}
+
+impl fmt::Display for DuplexStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DuplexStream::Tcp(tcp_stream) => {
+                match tcp_stream.peer_addr() {
+                    Ok(addr) => write!(f, "tcp:{}", addr),
+                    Err(_) => write!(f, "tcp:unconnected"),
+                }
+            },
+
+            DuplexStream::Serial(serial_port) => {
+                write!(f, "serial:{}", serial_port.name().unwrap_or_default())
+            },
+        }
+    }
+}
+
+impl DuplexStream {
+    /// Another handle to the stream's connection, for stream types which have one
+    pub fn connection(&self) -> Option<TcpStream> {
+        match self {
+            DuplexStream::Tcp(tcp_stream) => tcp_stream.try_clone().ok(),
+            DuplexStream::Serial(_) => None,
+        }
+    }
+
+    /// Split the duplex stream into a read half and a write half.
+    ///
+    /// Both halves refer to the same underlying connection.
+    pub fn split(self) -> Result<(ReadStream, WriteStream), String> {
+        let result;
+
+        match self {
+            DuplexStream::Tcp(tcp_stream) => {
+                result = tcp_stream.try_clone()
+                                   .map(|write_half| (ReadStream::Tcp(tcp_stream), WriteStream::Tcp(write_half)))
+                                   .map_err(|err| format!("Could not split TCP stream: {}", err));
+            },
+
+            DuplexStream::Serial(serial_port) => {
+                result = serial_port.try_clone()
+                                    .map(|write_half| (ReadStream::Serial(serial_port), WriteStream::Serial(write_half)))
+                                    .map_err(|err| format!("Could not split serial port: {}", err));
+            },
+        }
+
+        return result;
+    }
+}
+
+/// Move bytes from a read stream to a write stream until the read stream
+/// finishes, either stream reports an error, or the stop flag is set.
+pub fn pump(read_stream: &mut ReadStream, write_stream: &mut WriteStream, stop: &AtomicBool) -> Result<(), String> {
+    let mut bytes = BytesMut::with_capacity(BRIDGE_READ_SIZE);
+
+    while !stop.load(Ordering::Relaxed) {
+        bytes.clear();
+
+        match read_stream.stream_read(&mut bytes, BRIDGE_READ_SIZE) {
+            StreamReadResult::BytesRead(0) => {
+                // nothing available yet- this happens when a read times out, so wait
+                // a little rather than spinning on a stream which returns immediately
+                thread::sleep(Duration::from_millis(BRIDGE_IDLE_MS));
+            },
+
+            StreamReadResult::BytesRead(_) => {
+                write_stream.stream_write(&bytes)?;
+            },
+
+            StreamReadResult::Finished => {
+                return Ok(());
+            },
+
+            StreamReadResult::Error(string) => {
+                return Err(string);
+            },
+        }
+    }
+
+    return Ok(());
+}
+
+/// Bridge two duplex streams, moving bytes in both directions.
+///
+/// Each direction is pumped on its own thread. This function returns when either
+/// direction finishes or fails, with the result of that direction, after stopping
+/// the other direction.
+pub fn bridge(left: DuplexStream, right: DuplexStream) -> Result<(), String> {
+    // TCP connections are shut down to wake a direction blocked in a read. Serial
+    // reads time out, so that direction sees the stop flag on its own.
+    let connections: Vec<TcpStream> = left.connection().into_iter().chain(right.connection()).collect();
+
+    let (mut left_read, mut left_write) = left.split()?;
+    let (mut right_read, mut right_write) = right.split()?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = channel();
+
+    let left_to_right_sender = sender.clone();
+    let left_to_right_stop = Arc::clone(&stop);
+    let left_to_right = thread::spawn(move || {
+        let result = pump(&mut left_read, &mut right_write, &left_to_right_stop);
+        let _ = left_to_right_sender.send(result);
+    });
+
+    let right_to_left_stop = Arc::clone(&stop);
+    let right_to_left = thread::spawn(move || {
+        let result = pump(&mut right_read, &mut left_write, &right_to_left_stop);
+        let _ = sender.send(result);
+    });
+
+    let result = receiver.recv().map_err(|err| format!("Bridge thread failed: {}", err));
+
+    stop.store(true, Ordering::Relaxed);
+    for connection in connections.iter() {
+        let _ = connection.shutdown(Shutdown::Both);
+    }
+
+    let _ = left_to_right.join();
+    let _ = right_to_left.join();
+
+    return result?;
+}
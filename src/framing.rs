@@ -0,0 +1,1073 @@
+use std::fmt;
+use std::net::TcpStream;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use bytes::{BytesMut, BufMut};
+
+use crate::*;
+use crate::stream_read::*;
+use crate::ccsds::*;
+use crate::tm::*;
+use crate::transform::*;
+use crate::stats::*;
+use crate::time_code::*;
+
+
+// TODO framers only see the bytes that have been read so far, so a framer that
+// is waiting for the rest of a message will hold it until more bytes arrive or
+// the stream finishes.
+
+/// The number of bytes requested from a stream on each read when framing
+pub const FRAMING_READ_SIZE: usize = 4096;
+
+/// A framer finds message boundaries in a stream of bytes, and encodes
+/// messages so that their boundaries can be found again.
+pub trait Framer: Send {
+    /// Remove the next complete message from the front of the buffer, if there is one.
+    ///
+    /// Bytes which can not be part of a message may be removed from the buffer
+    /// as well. If an error is returned, the framer has already skipped past the
+    /// problem, and can be called again to continue framing.
+    fn next_message(&mut self, buffer: &mut BytesMut) -> Result<Option<BytesMut>, String>;
+
+    /// The time decoded from the most recent message, for framers which read a time from each message
+    fn message_time(&self) -> Option<CcsdsTime> {
+        return None;
+    }
+
+    /// Encode a message onto the end of the buffer.
+    fn encode_message(&mut self, message: &[u8], buffer: &mut BytesMut) -> Result<(), String>;
+
+    /// Record the counters of any transforms the framer applies in the stats of its stream
+    fn set_stats(&mut self, _stats: &Arc<StreamStats>) {
+    }
+}
+
+/// The result of reading a message from a framed stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameReadResult {
+    /// A complete message
+    Message(BytesMut),
+    /// No message is available yet. This occurs when a read times out.
+    NoMessage,
+    /// The stream has finished and no further messages will be read.
+    Finished,
+    /// The framer could not find a valid message, and skipped some bytes. Reading may continue.
+    FramingError(String),
+    /// An error occurred while reading from the stream.
+    Error(String),
+}
+
+/// The width of a length field
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub enum LengthWidth {
+    U8,
+    U16,
+    U32,
+}
+
+impl LengthWidth {
+    pub fn num_bytes(&self) -> usize {
+        match self {
+            LengthWidth::U8 => 1,
+            LengthWidth::U16 => 2,
+            LengthWidth::U32 => 4,
+        }
+    }
+}
+
+/// The byte order of a length field
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub enum Endianness {
+    Big,
+    Little,
+}
+
+/// The framing settings select a framer for a stream.
+///
+/// These settings can be written as a descriptor string, such as "line",
+/// "fixed:1024", "delimiter:0d0a", "length:u16be:4:1", "length:u32be:0:0:65536", "cobs", "slip", "ccsds", "ccsds:7:1024:cuc:4:2", "asm:1115" or "tm_packets:1115".
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FramingSettings {
+    /// No framing- each read from the stream is a message
+    Raw,
+    /// Each message is a fixed number of bytes
+    Fixed { size: usize },
+    /// Each message ends with the given delimiter
+    Delimiter { delimiter: Vec<u8> },
+    /// Each message contains a length field.
+    ///
+    /// The length field starts 'offset' bytes into the message, and the full message
+    /// length is the offset, plus the width of the field, plus the value of the field,
+    /// plus the adjustment. A field at offset 0 is removed from messages, and a field
+    /// further in is kept as part of the message's header. A full message length above
+    /// 'max_length', if given, is treated as a corrupt length field.
+    LengthPrefix {
+        width: LengthWidth,
+        endianness: Endianness,
+        #[serde(default)]
+        offset: usize,
+        #[serde(default)]
+        adjustment: i64,
+        #[serde(default)]
+        max_length: Option<usize>,
+    },
+    /// Each message is COBS encoded and ends with a 0 byte
+    Cobs,
+    /// Each message is SLIP encoded
+    Slip,
+    /// Each message is a CCSDS Space Packet
+    Ccsds(CcsdsFramingSettings),
+    /// Each message is a fixed length frame following an attached sync marker
+    FrameSync(FrameSyncSettings),
+    /// Each message is a CCSDS Space Packet extracted from TM frames. A stream's transforms
+    /// are applied to each frame, before its packets are extracted.
+    TmPackets(TmPacketFramingSettings),
+}
+
+impl Default for FramingSettings {
+    fn default() -> FramingSettings {
+        return FramingSettings::Raw;
+    }
+}
+
+impl FramingSettings {
+    /// Create a framer from these settings
+    pub fn framer(&self) -> Box<dyn Framer> {
+        match self {
+            FramingSettings::Raw => Box::new(RawFramer),
+
+            FramingSettings::Fixed { size } => Box::new(FixedFramer::new(*size)),
+
+            FramingSettings::Delimiter { delimiter } => Box::new(DelimiterFramer::new(delimiter.clone())),
+
+            FramingSettings::LengthPrefix { width, endianness, offset, adjustment, max_length } => {
+                let mut framer = LengthPrefixFramer::new(*width, *endianness, *offset, *adjustment);
+                if let Some(max_length) = max_length {
+                    framer = framer.with_max_length(*max_length);
+                }
+                Box::new(framer)
+            },
+
+            FramingSettings::Cobs => Box::new(CobsFramer),
+
+            FramingSettings::Slip => Box::new(SlipFramer),
+
+            FramingSettings::Ccsds(ccsds_settings) => Box::new(CcsdsFramer::new(ccsds_settings.clone())),
+
+            FramingSettings::FrameSync(sync_settings) => Box::new(FrameSyncFramer::new(sync_settings.clone())),
+
+            FramingSettings::TmPackets(tm_settings) => Box::new(TmPacketFramer::new(tm_settings.clone())),
+        }
+    }
+}
+
+impl fmt::Display for FramingSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FramingSettings::Raw => write!(f, "raw"),
+
+            FramingSettings::Fixed { size } => write!(f, "fixed:{}", size),
+
+            FramingSettings::Delimiter { delimiter } => {
+                if delimiter[..] == b"\n"[..] {
+                    write!(f, "line")
+                } else {
+                    write!(f, "delimiter:")?;
+                    for byte in delimiter.iter() {
+                        write!(f, "{:02x}", byte)?;
+                    }
+                    Ok(())
+                }
+            },
+
+            FramingSettings::LengthPrefix { width, endianness, offset, adjustment, max_length } => {
+                let kind_str = match (width, endianness) {
+                    (LengthWidth::U8, _) => "u8",
+                    (LengthWidth::U16, Endianness::Big) => "u16be",
+                    (LengthWidth::U16, Endianness::Little) => "u16le",
+                    (LengthWidth::U32, Endianness::Big) => "u32be",
+                    (LengthWidth::U32, Endianness::Little) => "u32le",
+                };
+                write!(f, "length:{}:{}:{}", kind_str, offset, adjustment)?;
+                if let Some(max_length) = max_length {
+                    write!(f, ":{}", max_length)?;
+                }
+                Ok(())
+            },
+
+            FramingSettings::Cobs => write!(f, "cobs"),
+
+            FramingSettings::Slip => write!(f, "slip"),
+
+            FramingSettings::Ccsds(ccsds_settings) => {
+                write!(f, "ccsds:{}:{}", ccsds_settings.min_length, ccsds_settings.max_length)?;
+                if let Some(time_code) = ccsds_settings.time_code.as_ref() {
+                    write!(f, ":{}", time_code)?;
+                }
+                Ok(())
+            },
+
+            FramingSettings::FrameSync(sync_settings) => {
+                write!(f, "asm:{}:", sync_settings.frame_length)?;
+                for byte in sync_settings.asm.iter() {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            },
+
+            FramingSettings::TmPackets(tm_settings) => {
+                write!(f, "tm_packets:{}:", tm_settings.sync.frame_length)?;
+                for byte in tm_settings.sync.asm.iter() {
+                    write!(f, "{:02x}", byte)?;
+                }
+                if tm_settings.extractor.fecf_present {
+                    write!(f, ":fecf")?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl FromStr for FramingSettings {
+    type Err = StreamSettingsParseError;
+    fn from_str(s: &str) -> Result<FramingSettings, StreamSettingsParseError> {
+        let mut parts = s.split(':');
+        let name = parts.next().ok_or(StreamSettingsParseError(()))?;
+
+        let result;
+        match name {
+            "raw" => {
+                result = FramingSettings::Raw;
+            },
+
+            "line" => {
+                result = FramingSettings::Delimiter { delimiter: b"\n".to_vec() };
+            },
+
+            "fixed" => {
+                let size_str = parts.next().ok_or(StreamSettingsParseError(()))?;
+                let size = size_str.parse::<usize>().map_err(|_| StreamSettingsParseError(()))?;
+                if size == 0 {
+                    return Err(StreamSettingsParseError(()));
+                }
+                result = FramingSettings::Fixed { size };
+            },
+
+            "delimiter" => {
+                let hex_str = parts.next().ok_or(StreamSettingsParseError(()))?;
+                let delimiter = parse_hex(hex_str).ok_or(StreamSettingsParseError(()))?;
+                if delimiter.is_empty() {
+                    return Err(StreamSettingsParseError(()));
+                }
+                result = FramingSettings::Delimiter { delimiter };
+            },
+
+            "length" => {
+                let kind_str = parts.next().ok_or(StreamSettingsParseError(()))?;
+                let (width, endianness) = match kind_str {
+                    "u8" => (LengthWidth::U8, Endianness::Big),
+                    "u16be" => (LengthWidth::U16, Endianness::Big),
+                    "u16le" => (LengthWidth::U16, Endianness::Little),
+                    "u32be" => (LengthWidth::U32, Endianness::Big),
+                    "u32le" => (LengthWidth::U32, Endianness::Little),
+                    _ => return Err(StreamSettingsParseError(())),
+                };
+
+                let offset = match parts.next() {
+                    Some(offset_str) => offset_str.parse::<usize>().map_err(|_| StreamSettingsParseError(()))?,
+                    None => 0,
+                };
+
+                let adjustment = match parts.next() {
+                    Some(adjustment_str) => adjustment_str.parse::<i64>().map_err(|_| StreamSettingsParseError(()))?,
+                    None => 0,
+                };
+
+                let max_length = match parts.next() {
+                    Some(max_str) => Some(max_str.parse::<usize>().map_err(|_| StreamSettingsParseError(()))?),
+                    None => None,
+                };
+
+                result = FramingSettings::LengthPrefix { width, endianness, offset, adjustment, max_length };
+            },
+
+            "cobs" => {
+                result = FramingSettings::Cobs;
+            },
+
+            "slip" => {
+                result = FramingSettings::Slip;
+            },
+
+            "ccsds" => {
+                let mut ccsds_settings = CcsdsFramingSettings::default();
+
+                if let Some(min_str) = parts.next() {
+                    ccsds_settings.min_length = min_str.parse::<usize>().map_err(|_| StreamSettingsParseError(()))?;
+                }
+
+                if let Some(max_str) = parts.next() {
+                    ccsds_settings.max_length = max_str.parse::<usize>().map_err(|_| StreamSettingsParseError(()))?;
+                }
+
+                let time_code_str = parts.by_ref().collect::<Vec<&str>>().join(":");
+                if !time_code_str.is_empty() {
+                    let time_code = TimeCodeSettings::parse(&time_code_str).ok_or(StreamSettingsParseError(()))?;
+                    ccsds_settings.time_code = Some(time_code);
+                }
+
+                result = FramingSettings::Ccsds(ccsds_settings);
+            },
+
+            "asm" => {
+                let mut sync_settings = FrameSyncSettings::default();
+
+                let length_str = parts.next().ok_or(StreamSettingsParseError(()))?;
+                sync_settings.frame_length = length_str.parse::<usize>().map_err(|_| StreamSettingsParseError(()))?;
+
+                if let Some(asm_str) = parts.next() {
+                    sync_settings.asm = parse_hex(asm_str).ok_or(StreamSettingsParseError(()))?;
+                }
+
+                if sync_settings.frame_length == 0 || sync_settings.asm.is_empty() {
+                    return Err(StreamSettingsParseError(()));
+                }
+
+                result = FramingSettings::FrameSync(sync_settings);
+            },
+
+            "tm_packets" => {
+                let mut tm_settings = TmPacketFramingSettings::default();
+
+                let length_str = parts.next().ok_or(StreamSettingsParseError(()))?;
+                tm_settings.sync.frame_length = length_str.parse::<usize>().map_err(|_| StreamSettingsParseError(()))?;
+
+                if let Some(asm_str) = parts.next() {
+                    tm_settings.sync.asm = parse_hex(asm_str).ok_or(StreamSettingsParseError(()))?;
+                }
+
+                match parts.next() {
+                    Some("fecf") => tm_settings.extractor.fecf_present = true,
+                    Some(_) => return Err(StreamSettingsParseError(())),
+                    None => {},
+                }
+
+                if tm_settings.sync.frame_length == 0 || tm_settings.sync.asm.is_empty() {
+                    return Err(StreamSettingsParseError(()));
+                }
+
+                result = FramingSettings::TmPackets(tm_settings);
+            },
+
+            _ => {
+                return Err(StreamSettingsParseError(()));
+            },
+        }
+
+        if parts.next().is_some() {
+            return Err(StreamSettingsParseError(()));
+        }
+
+        return Ok(result);
+    }
+}
+
+fn parse_hex(hex_str: &str) -> Option<Vec<u8>> {
+    if !hex_str.len().is_multiple_of(2) {
+        return None;
+    }
+
+    return (0..hex_str.len()).step_by(2)
+                             .map(|index| hex_str.get(index..index + 2).and_then(|byte_str| u8::from_str_radix(byte_str, 16).ok()))
+                             .collect();
+}
+
+/// Split a stream descriptor with optional framing and transform suffixes, such as
+/// "tcp_client:127.0.0.1:8000|slip" or "udp:127.0.0.1:8001|asm:1115|derandomize|fecf_check"
+pub fn split_descriptor(desc: &str) -> Result<(&str, FramingSettings, Vec<TransformSettings>), String> {
+    let mut parts = desc.split('|');
+
+    // split always returns at least one part
+    let stream_desc = parts.next().unwrap_or(desc);
+
+    let framing = match parts.next() {
+        Some(framing_desc) => {
+            FramingSettings::from_str(framing_desc)
+                            .map_err(|_| format!("Could not parse framing ({})", framing_desc))?
+        },
+
+        None => FramingSettings::Raw,
+    };
+
+    let mut transforms = Vec::new();
+    for transform_desc in parts {
+        let transform = TransformSettings::from_str(transform_desc)
+                                         .map_err(|_| format!("Could not parse transform ({})", transform_desc))?;
+        transforms.push(transform);
+    }
+
+    return Ok((stream_desc, framing, transforms));
+}
+
+
+/* Framers */
+/// The raw framer treats all bytes read as a single message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawFramer;
+
+impl Framer for RawFramer {
+    fn next_message(&mut self, buffer: &mut BytesMut) -> Result<Option<BytesMut>, String> {
+        if buffer.is_empty() {
+            return Ok(None);
+        }
+
+        let len = buffer.len();
+        return Ok(Some(buffer.split_to(len)));
+    }
+
+    fn encode_message(&mut self, message: &[u8], buffer: &mut BytesMut) -> Result<(), String> {
+        buffer.extend_from_slice(message);
+        return Ok(());
+    }
+}
+
+/// The fixed framer splits a stream into messages of a fixed size
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixedFramer {
+    pub size: usize,
+}
+
+impl FixedFramer {
+    pub fn new(size: usize) -> FixedFramer {
+        return FixedFramer { size };
+    }
+}
+
+impl Framer for FixedFramer {
+    fn next_message(&mut self, buffer: &mut BytesMut) -> Result<Option<BytesMut>, String> {
+        if self.size == 0 || buffer.len() < self.size {
+            return Ok(None);
+        }
+
+        return Ok(Some(buffer.split_to(self.size)));
+    }
+
+    fn encode_message(&mut self, message: &[u8], buffer: &mut BytesMut) -> Result<(), String> {
+        if message.len() != self.size {
+            return Err(format!("Message length {} does not match fixed frame size {}", message.len(), self.size));
+        }
+
+        buffer.extend_from_slice(message);
+        return Ok(());
+    }
+}
+
+/// The delimiter framer splits a stream after each occurrence of a delimiter.
+///
+/// The delimiter is not included in the message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DelimiterFramer {
+    pub delimiter: Vec<u8>,
+}
+
+impl DelimiterFramer {
+    pub fn new(delimiter: Vec<u8>) -> DelimiterFramer {
+        return DelimiterFramer { delimiter };
+    }
+}
+
+impl Framer for DelimiterFramer {
+    fn next_message(&mut self, buffer: &mut BytesMut) -> Result<Option<BytesMut>, String> {
+        if self.delimiter.is_empty() || buffer.len() < self.delimiter.len() {
+            return Ok(None);
+        }
+
+        let position = buffer.windows(self.delimiter.len())
+                             .position(|window| window == &self.delimiter[..]);
+
+        match position {
+            Some(index) => {
+                let message = buffer.split_to(index);
+                buffer.advance(self.delimiter.len());
+                return Ok(Some(message));
+            },
+
+            None => {
+                return Ok(None);
+            },
+        }
+    }
+
+    fn encode_message(&mut self, message: &[u8], buffer: &mut BytesMut) -> Result<(), String> {
+        buffer.extend_from_slice(message);
+        buffer.extend_from_slice(&self.delimiter);
+        return Ok(());
+    }
+}
+
+/// The length prefix framer uses a length field within each message to find its end.
+///
+/// A length field at the start of the message (an offset of 0) is a prefix, which is removed
+/// from messages when reading and inserted when writing. A length field further into the
+/// message is part of the message's own header, so it is kept when reading, and when writing
+/// it is filled in over the bytes already in its place.
+///
+/// With a maximum length, a length field giving a longer message is treated like any other
+/// invalid length- one byte is dropped and an error is reported, rather than waiting for
+/// a message which may never arrive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LengthPrefixFramer {
+    pub width: LengthWidth,
+    pub endianness: Endianness,
+    pub offset: usize,
+    pub adjustment: i64,
+    /// The largest full message length accepted, including the length field
+    pub max_length: Option<usize>,
+}
+
+impl LengthPrefixFramer {
+    pub fn new(width: LengthWidth, endianness: Endianness, offset: usize, adjustment: i64) -> LengthPrefixFramer {
+        return LengthPrefixFramer { width, endianness, offset, adjustment, max_length: None };
+    }
+
+    pub fn with_max_length(mut self, max_length: usize) -> LengthPrefixFramer {
+        self.max_length = Some(max_length);
+        return self;
+    }
+
+    fn read_field(&self, bytes: &[u8]) -> u64 {
+        let mut value: u64 = 0;
+
+        match self.endianness {
+            Endianness::Big => {
+                for byte in bytes.iter() {
+                    value = (value << 8) | *byte as u64;
+                }
+            },
+
+            Endianness::Little => {
+                for byte in bytes.iter().rev() {
+                    value = (value << 8) | *byte as u64;
+                }
+            },
+        }
+
+        return value;
+    }
+
+    fn write_field(&self, value: u64, buffer: &mut BytesMut) {
+        let num_bytes = self.width.num_bytes();
+
+        for index in 0..num_bytes {
+            let shift = match self.endianness {
+                Endianness::Big => 8 * (num_bytes - index - 1),
+                Endianness::Little => 8 * index,
+            };
+            buffer.put_u8((value >> shift) as u8);
+        }
+    }
+}
+
+impl Framer for LengthPrefixFramer {
+    fn next_message(&mut self, buffer: &mut BytesMut) -> Result<Option<BytesMut>, String> {
+        let header_len = self.offset + self.width.num_bytes();
+        if buffer.len() < header_len {
+            return Ok(None);
+        }
+
+        let field = self.read_field(&buffer[self.offset..header_len]);
+        let total_len = header_len as i64 + field as i64 + self.adjustment;
+
+        if total_len < header_len as i64 {
+            // the length can not be right, so drop a byte to look for the next message
+            buffer.advance(1);
+            return Err(format!("Length field {} gives an invalid message length {}", field, total_len));
+        }
+
+        let total_len = total_len as usize;
+        if let Some(max_length) = self.max_length {
+            if total_len > max_length {
+                buffer.advance(1);
+                return Err(format!("Length field {} gives a message length {} above the maximum {}", field, total_len, max_length));
+            }
+        }
+
+        if buffer.len() < total_len {
+            return Ok(None);
+        }
+
+        let mut message = buffer.split_to(total_len);
+        if self.offset == 0 {
+            message.advance(header_len);
+        }
+
+        return Ok(Some(message));
+    }
+
+    fn encode_message(&mut self, message: &[u8], buffer: &mut BytesMut) -> Result<(), String> {
+        // a prefix is added to the message, while a field within the header is already counted in its length
+        let header_len;
+        if self.offset == 0 {
+            header_len = 0;
+        } else {
+            header_len = self.offset + self.width.num_bytes();
+        }
+
+        if message.len() < header_len {
+            return Err(format!("Message length {} is shorter than the length field's end {}", message.len(), header_len));
+        }
+
+        let field = message.len() as i64 - header_len as i64 - self.adjustment;
+        let max_field = match self.width {
+            LengthWidth::U8 => u8::MAX as i64,
+            LengthWidth::U16 => u16::MAX as i64,
+            LengthWidth::U32 => u32::MAX as i64,
+        };
+
+        if field < 0 || field > max_field {
+            return Err(format!("Message length {} can not be encoded in a {:?} length field", message.len(), self.width));
+        }
+
+        // the full message length read back includes a prefix, but not a field already in the header
+        let encoded_len = if self.offset == 0 {
+            message.len() + self.width.num_bytes()
+        } else {
+            message.len()
+        };
+        if let Some(max_length) = self.max_length {
+            if encoded_len > max_length {
+                return Err(format!("Message length {} is above the maximum {}", encoded_len, max_length));
+            }
+        }
+
+        buffer.reserve(message.len() + self.width.num_bytes());
+        buffer.extend_from_slice(&message[..self.offset]);
+        self.write_field(field as u64, buffer);
+        buffer.extend_from_slice(&message[header_len..]);
+
+        return Ok(());
+    }
+}
+
+/// The COBS framer uses Consistent Overhead Byte Stuffing, with each message ending in a 0 byte
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CobsFramer;
+
+impl Framer for CobsFramer {
+    fn next_message(&mut self, buffer: &mut BytesMut) -> Result<Option<BytesMut>, String> {
+        let end = match buffer.iter().position(|byte| *byte == 0) {
+            Some(end) => end,
+            None => return Ok(None),
+        };
+
+        let encoded = buffer.split_to(end);
+        buffer.advance(1);
+
+        let mut message = BytesMut::with_capacity(encoded.len());
+        let mut index = 0;
+        while index < encoded.len() {
+            let code = encoded[index] as usize;
+            if index + code > encoded.len() {
+                return Err("COBS message ended in the middle of a block".to_string());
+            }
+
+            message.extend_from_slice(&encoded[index + 1..index + code]);
+            index += code;
+
+            if code < 0xFF && index < encoded.len() {
+                message.put_u8(0);
+            }
+        }
+
+        return Ok(Some(message));
+    }
+
+    fn encode_message(&mut self, message: &[u8], buffer: &mut BytesMut) -> Result<(), String> {
+        buffer.reserve(message.len() + message.len() / 254 + 2);
+
+        let mut block: Vec<u8> = Vec::with_capacity(254);
+        for byte in message.iter() {
+            if *byte == 0 {
+                buffer.put_u8(block.len() as u8 + 1);
+                buffer.extend_from_slice(&block);
+                block.clear();
+            } else {
+                block.push(*byte);
+
+                if block.len() == 254 {
+                    buffer.put_u8(0xFF);
+                    buffer.extend_from_slice(&block);
+                    block.clear();
+                }
+            }
+        }
+
+        buffer.put_u8(block.len() as u8 + 1);
+        buffer.extend_from_slice(&block);
+        buffer.put_u8(0);
+
+        return Ok(());
+    }
+}
+
+pub const SLIP_END: u8 = 0xC0;
+pub const SLIP_ESC: u8 = 0xDB;
+pub const SLIP_ESC_END: u8 = 0xDC;
+pub const SLIP_ESC_ESC: u8 = 0xDD;
+
+/// The SLIP framer uses the Serial Line Internet Protocol (RFC 1055) encoding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlipFramer;
+
+impl Framer for SlipFramer {
+    fn next_message(&mut self, buffer: &mut BytesMut) -> Result<Option<BytesMut>, String> {
+        loop {
+            let end = match buffer.iter().position(|byte| *byte == SLIP_END) {
+                Some(end) => end,
+                None => return Ok(None),
+            };
+
+            let encoded = buffer.split_to(end);
+            buffer.advance(1);
+
+            // back to back END bytes are allowed, and do not make a message
+            if encoded.is_empty() {
+                continue;
+            }
+
+            let mut message = BytesMut::with_capacity(encoded.len());
+            let mut bytes = encoded.iter();
+            while let Some(byte) = bytes.next() {
+                if *byte == SLIP_ESC {
+                    match bytes.next() {
+                        Some(&SLIP_ESC_END) => message.put_u8(SLIP_END),
+                        Some(&SLIP_ESC_ESC) => message.put_u8(SLIP_ESC),
+                        _ => return Err("Invalid SLIP escape sequence".to_string()),
+                    }
+                } else {
+                    message.put_u8(*byte);
+                }
+            }
+
+            return Ok(Some(message));
+        }
+    }
+
+    fn encode_message(&mut self, message: &[u8], buffer: &mut BytesMut) -> Result<(), String> {
+        // in the worst case every byte is escaped
+        buffer.reserve(2 * message.len() + 2);
+
+        buffer.put_u8(SLIP_END);
+        for byte in message.iter() {
+            match *byte {
+                SLIP_END => {
+                    buffer.put_u8(SLIP_ESC);
+                    buffer.put_u8(SLIP_ESC_END);
+                },
+
+                SLIP_ESC => {
+                    buffer.put_u8(SLIP_ESC);
+                    buffer.put_u8(SLIP_ESC_ESC);
+                },
+
+                _ => {
+                    buffer.put_u8(*byte);
+                },
+            }
+        }
+        buffer.put_u8(SLIP_END);
+
+        return Ok(());
+    }
+}
+
+
+/* Framed Streams */
+/// A framed read stream reads whole messages from a read stream, and applies
+/// a series of transforms to each message.
+pub struct FramedReadStream {
+    pub stream: ReadStream,
+    framer: Box<dyn Framer>,
+    transforms: Vec<Box<dyn Transform>>,
+    buffer: BytesMut,
+    finished: bool,
+    stats: Arc<StreamStats>,
+}
+
+impl fmt::Debug for FramedReadStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FramedReadStream")
+         .field("stream", &self.stream)
+         .field("buffered", &self.buffer.len())
+         .field("finished", &self.finished)
+         .finish()
+    }
+}
+
+impl FromStr for FramedReadStream {
+    type Err = String;
+    fn from_str(read_stream_desc: &str) -> Result<FramedReadStream, String> {
+        let (stream_desc, framing, transforms) = split_descriptor(read_stream_desc)?;
+        let stream = ReadStream::from_str(stream_desc)?;
+        let transforms = transforms.iter().map(|transform| transform.transform()).collect::<Result<_, String>>()?;
+        return Ok(FramedReadStream::from_settings(stream, &framing, transforms));
+    }
+}
+
+impl FramedReadStream {
+    pub fn new(stream: ReadStream, mut framer: Box<dyn Framer>) -> FramedReadStream {
+        let stats = Arc::new(StreamStats::new());
+        stats.set_peer(stream.peer());
+        framer.set_stats(&stats);
+
+        return FramedReadStream { stream,
+                                  framer,
+                                  transforms: Vec::new(),
+                                  buffer: BytesMut::with_capacity(FRAMING_READ_SIZE),
+                                  finished: false,
+                                  stats,
+        };
+    }
+
+    /// Read messages with the framer given by the framing settings, applying the transforms to each message.
+    ///
+    /// Framing which extracts packets from frames applies the transforms to each frame instead,
+    /// before its packets are extracted.
+    pub fn from_settings(stream: ReadStream, framing: &FramingSettings, transforms: Vec<Box<dyn Transform>>) -> FramedReadStream {
+        match framing {
+            FramingSettings::TmPackets(tm_settings) => {
+                let framer = TmPacketFramer::new(tm_settings.clone()).with_frame_transforms(transforms);
+                return FramedReadStream::new(stream, Box::new(framer));
+            },
+
+            _ => {
+                return FramedReadStream::new(stream, framing.framer()).with_transforms(transforms);
+            },
+        }
+    }
+
+    /// Apply the given transforms, in order, to each message read
+    pub fn with_transforms(mut self, mut transforms: Vec<Box<dyn Transform>>) -> FramedReadStream {
+        for transform in transforms.iter_mut() {
+            transform.set_stats(&self.stats);
+        }
+        self.transforms = transforms;
+        return self;
+    }
+
+    /// Record this stream's counters in the given stats, such as stats kept across reconnects
+    pub fn with_stats(mut self, stats: Arc<StreamStats>) -> FramedReadStream {
+        stats.set_peer(self.stream.peer());
+        self.framer.set_stats(&stats);
+        for transform in self.transforms.iter_mut() {
+            transform.set_stats(&stats);
+        }
+        self.stats = stats;
+        return self;
+    }
+
+    /// A snapshot of this stream's counters
+    pub fn stats(&self) -> StreamCounters {
+        return self.stats.counters();
+    }
+
+    /// The shared counters of this stream, which may be read from other threads
+    pub fn stats_handle(&self) -> Arc<StreamStats> {
+        return Arc::clone(&self.stats);
+    }
+
+    /// The time decoded from the most recent message read, if the framer reads a time from each message
+    pub fn message_time(&self) -> Option<CcsdsTime> {
+        return self.framer.message_time();
+    }
+
+    /// Read the next message from the stream.
+    pub fn read_message(&mut self) -> FrameReadResult {
+        let result = self.next_message();
+
+        match result {
+            FrameReadResult::Message(_) => self.stats.record_message(),
+            FrameReadResult::FramingError(_) => self.stats.record_error(),
+
+            FrameReadResult::Error(_) => {
+                self.stats.record_error();
+                self.stats.set_peer(None);
+            },
+
+            FrameReadResult::Finished => self.stats.set_peer(None),
+
+            FrameReadResult::NoMessage => {},
+        }
+
+        return result;
+    }
+
+    fn next_message(&mut self) -> FrameReadResult {
+        loop {
+            match self.framer.next_message(&mut self.buffer) {
+                Ok(Some(message)) => {
+                    match apply_transforms(&mut self.transforms, message) {
+                        Ok(Some(message)) => return FrameReadResult::Message(message),
+
+                        // the message was dropped, so look for the next one
+                        Ok(None) => continue,
+
+                        Err(string) => return FrameReadResult::FramingError(string),
+                    }
+                },
+
+                Ok(None) => {},
+
+                Err(string) => {
+                    return FrameReadResult::FramingError(string);
+                },
+            }
+
+            if self.finished {
+                if !self.buffer.is_empty() {
+                    let num_bytes = self.buffer.len();
+                    self.buffer.clear();
+                    return FrameReadResult::FramingError(format!("Stream ended with a partial message of {} bytes", num_bytes));
+                }
+
+                return FrameReadResult::Finished;
+            }
+
+            match self.stream.stream_read(&mut self.buffer, FRAMING_READ_SIZE) {
+                StreamReadResult::BytesRead(0) => {
+                    return FrameReadResult::NoMessage;
+                },
+
+                StreamReadResult::BytesRead(num_bytes) => {
+                    self.stats.record_operation(num_bytes);
+                },
+
+                StreamReadResult::Finished => {
+                    self.finished = true;
+                },
+
+                StreamReadResult::Error(string) => {
+                    return FrameReadResult::Error(string);
+                },
+            }
+        }
+    }
+}
+
+/// A framed write stream applies a series of transforms to each message, and writes
+/// the results as whole messages to a write stream
+pub struct FramedWriteStream {
+    pub stream: WriteStream,
+    framer: Box<dyn Framer>,
+    transforms: Vec<Box<dyn Transform>>,
+    buffer: BytesMut,
+    stats: Arc<StreamStats>,
+}
+
+impl fmt::Debug for FramedWriteStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FramedWriteStream")
+         .field("stream", &self.stream)
+         .finish()
+    }
+}
+
+impl FromStr for FramedWriteStream {
+    type Err = String;
+    fn from_str(write_stream_desc: &str) -> Result<FramedWriteStream, String> {
+        let (stream_desc, framing, transforms) = split_descriptor(write_stream_desc)?;
+        let stream = WriteStream::from_str(stream_desc)?;
+        let transforms = transforms.iter().map(|transform| transform.transform()).collect::<Result<_, String>>()?;
+        return Ok(FramedWriteStream::new(stream, framing.framer()).with_transforms(transforms));
+    }
+}
+
+impl FramedWriteStream {
+    pub fn new(stream: WriteStream, framer: Box<dyn Framer>) -> FramedWriteStream {
+        let stats = Arc::new(StreamStats::new());
+        stats.set_peer(stream.peer());
+
+        return FramedWriteStream { stream,
+                                   framer,
+                                   transforms: Vec::new(),
+                                   buffer: BytesMut::with_capacity(FRAMING_READ_SIZE),
+                                   stats,
+        };
+    }
+
+    /// Apply the given transforms, in order, to each message before it is written
+    pub fn with_transforms(mut self, transforms: Vec<Box<dyn Transform>>) -> FramedWriteStream {
+        self.transforms = transforms;
+        return self;
+    }
+
+    /// Record this stream's counters in the given stats, such as stats kept across reopening the stream
+    pub fn with_stats(mut self, stats: Arc<StreamStats>) -> FramedWriteStream {
+        stats.set_peer(self.stream.peer());
+        self.stats = stats;
+        return self;
+    }
+
+    /// A snapshot of this stream's counters
+    pub fn stats(&self) -> StreamCounters {
+        return self.stats.counters();
+    }
+
+    /// The shared counters of this stream, which may be read from other threads
+    pub fn stats_handle(&self) -> Arc<StreamStats> {
+        return Arc::clone(&self.stats);
+    }
+
+    /// Encode and write a message to the stream, returning the number of bytes written.
+    pub fn write_message(&mut self, message: &[u8]) -> Result<usize, String> {
+        let result = self.encode_and_write(message);
+
+        match result {
+            // a message dropped by a transform is not written
+            Ok(0) => {},
+
+            Ok(num_bytes) => {
+                self.stats.record_operation(num_bytes);
+                self.stats.record_message();
+            },
+
+            // a failed write may mean the other end has gone
+            Err(_) => {
+                self.stats.record_error();
+                self.stats.set_peer(self.stream.peer());
+            },
+        }
+
+        return result;
+    }
+
+    /// A second handle to the stream's connection, which can be used to close it from another thread
+    pub fn connection(&self) -> Option<TcpStream> {
+        return self.stream.connection();
+    }
+
+    /// Write a message as with write_message, giving the name of the stream it came from and the time it was received
+    pub fn write_message_from(&mut self, source: &str, message: &[u8], received: SystemTime) -> Result<usize, String> {
+        self.stream.set_origin(source, received)?;
+        return self.write_message(message);
+    }
+
+    fn encode_and_write(&mut self, message: &[u8]) -> Result<usize, String> {
+        self.buffer.clear();
+
+        if self.transforms.is_empty() {
+            self.framer.encode_message(message, &mut self.buffer)?;
+        } else {
+            match apply_transforms(&mut self.transforms, BytesMut::from(message))? {
+                Some(transformed) => self.framer.encode_message(&transformed, &mut self.buffer)?,
+                None => return Ok(0),
+            }
+        }
+
+        return self.stream.stream_write(&self.buffer);
+    }
+}
@@ -0,0 +1,240 @@
+use std::fmt;
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::*;
+use crate::ccsds::*;
+use crate::stream_write::*;
+use crate::time_code::*;
+
+
+/// The default number of bytes shown on each line of a hexdump
+pub const HEXDUMP_DEFAULT_WIDTH: usize = 16;
+
+
+/// The hexdump settings describe how to render written bytes as text, for watching a link while debugging.
+///
+/// The descriptor is "hexdump" or "print", followed by any options and then an optional file name,
+/// all separated by ':'. Without a file name the dump is written to stdout. The options are
+/// "width=N" for the bytes per line, "max=N" to show at most N bytes of each message,
+/// "noascii" to leave out the ASCII column, and "ccsds" to decode a CCSDS primary header line.
+/// For example "hexdump:width=32:ccsds" or "print:max=64:dump.txt".
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HexdumpSettings {
+    #[serde(default = "default_width")]
+    pub width: usize,
+
+    #[serde(default = "default_ascii")]
+    pub ascii: bool,
+
+    /// The most bytes shown from each message, or 0 to show every byte
+    #[serde(default)]
+    pub max_bytes: usize,
+
+    /// Show a line decoding each message's CCSDS primary header
+    #[serde(default)]
+    pub ccsds: bool,
+
+    /// The file to write to, or None for stdout
+    #[serde(default)]
+    pub file_name: Option<String>,
+
+    /// The descriptor was given as "print", so it is displayed that way again
+    #[serde(default)]
+    pub print: bool,
+}
+
+fn default_width() -> usize {
+    return HEXDUMP_DEFAULT_WIDTH;
+}
+
+fn default_ascii() -> bool {
+    return true;
+}
+
+impl Default for HexdumpSettings {
+    fn default() -> HexdumpSettings {
+        HexdumpSettings { width: HEXDUMP_DEFAULT_WIDTH,
+                          ascii: true,
+                          max_bytes: 0,
+                          ccsds: false,
+                          file_name: None,
+                          print: false,
+        }
+    }
+}
+
+impl fmt::Display for HexdumpSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", if self.print { "print" } else { "hexdump" })?;
+
+        if self.width != HEXDUMP_DEFAULT_WIDTH {
+            write!(f, ":width={}", self.width)?;
+        }
+        if self.max_bytes != 0 {
+            write!(f, ":max={}", self.max_bytes)?;
+        }
+        if !self.ascii {
+            write!(f, ":noascii")?;
+        }
+        if self.ccsds {
+            write!(f, ":ccsds")?;
+        }
+        if let Some(file_name) = &self.file_name {
+            write!(f, ":{}", file_name)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for HexdumpSettings {
+    type Err = StreamSettingsParseError;
+    fn from_str(s: &str) -> Result<HexdumpSettings, StreamSettingsParseError> {
+        let name = s.split(':').next();
+        let rest = match name {
+            Some("hexdump") | Some("print") => s.split_once(':').map(|(_, rest)| rest).unwrap_or(""),
+            _ => return Err(StreamSettingsParseError(())),
+        };
+
+        let mut settings = HexdumpSettings { print: name == Some("print"), ..Default::default() };
+
+        let mut parts = rest.split(':').peekable();
+        while let Some(part) = parts.peek() {
+            if let Some(width_str) = part.strip_prefix("width=") {
+                settings.width = width_str.parse::<usize>().map_err(|_| StreamSettingsParseError(()))?;
+            } else if let Some(max_str) = part.strip_prefix("max=") {
+                settings.max_bytes = max_str.parse::<usize>().map_err(|_| StreamSettingsParseError(()))?;
+            } else if *part == "noascii" {
+                settings.ascii = false;
+            } else if *part == "ascii" {
+                settings.ascii = true;
+            } else if *part == "ccsds" {
+                settings.ccsds = true;
+            } else {
+                break;
+            }
+            parts.next();
+        }
+
+        // the rest is the file name, which may itself contain ':'
+        let file_name = parts.collect::<Vec<&str>>().join(":");
+        if !file_name.is_empty() && file_name != "-" {
+            settings.file_name = Some(file_name);
+        }
+
+        settings.validate().map_err(|_| StreamSettingsParseError(()))?;
+
+        return Ok(settings);
+    }
+}
+
+impl HexdumpSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.width == 0 {
+            return Err("Hexdump width must be greater than 0".to_string());
+        }
+
+        return Ok(());
+    }
+
+    pub fn open_write_stream(&self) -> Result<WriteStream, String> {
+        self.validate()?;
+
+        let output: Box<dyn Write + Send> = match &self.file_name {
+            Some(file_name) => {
+                let file = File::create(file_name).map_err(|err| format!("File open error for writing: {}", err))?;
+                Box::new(file)
+            },
+
+            None => Box::new(io::stdout()),
+        };
+
+        return Ok(WriteStream::Hexdump(HexdumpWriter { settings: self.clone(), output }));
+    }
+
+    /// Render a chunk of bytes as a header line with the given time, an optional CCSDS header line,
+    /// and the hexdump lines
+    pub fn render(&self, time: &CcsdsTime, bytes: &[u8]) -> String {
+        let mut text = String::new();
+
+        let _ = writeln!(text, "{} {} bytes", time, bytes.len());
+
+        if self.ccsds {
+            match CcsdsPrimaryHeader::decode(bytes) {
+                Ok(header) => {
+                    let _ = writeln!(text, "  {}", header);
+                },
+
+                Err(string) => {
+                    let _ = writeln!(text, "  ccsds error: {}", string);
+                },
+            }
+        }
+
+        let shown = if self.max_bytes == 0 { bytes.len() } else { bytes.len().min(self.max_bytes) };
+
+        for (line_index, line) in bytes[..shown].chunks(self.width).enumerate() {
+            let _ = write!(text, "{:04x} ", line_index * self.width);
+
+            for index in 0..self.width {
+                // an extra space between each group of 8 bytes
+                if index % 8 == 0 {
+                    text.push(' ');
+                }
+
+                match line.get(index) {
+                    Some(byte) => {
+                        let _ = write!(text, "{:02x} ", byte);
+                    },
+                    None => text.push_str("   "),
+                }
+            }
+
+            if self.ascii {
+                text.push('|');
+                for byte in line.iter() {
+                    text.push(if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' });
+                }
+                text.push('|');
+            }
+
+            let trimmed_len = text.trim_end().len();
+            text.truncate(trimmed_len);
+            text.push('\n');
+        }
+
+        if shown < bytes.len() {
+            let _ = writeln!(text, "... {} more bytes", bytes.len() - shown);
+        }
+
+        return text;
+    }
+}
+
+/// Writes each chunk of bytes as a timestamped hexdump
+pub struct HexdumpWriter {
+    settings: HexdumpSettings,
+    output: Box<dyn Write + Send>,
+}
+
+impl fmt::Debug for HexdumpWriter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HexdumpWriter")
+         .field("settings", &self.settings)
+         .finish()
+    }
+}
+
+impl StreamWrite for HexdumpWriter {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, String> {
+        let text = self.settings.render(&CcsdsTime::now(), bytes);
+
+        self.output.write_all(text.as_bytes())
+            .and_then(|_| self.output.flush())
+            .map_err(|err| format!("IO error {}", err))
+            .map(|_| bytes.len())
+    }
+}
@@ -1,23 +1,68 @@
+#![allow(non_local_definitions)]
+
 extern crate serde;
 #[macro_use] extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
+extern crate serde_yaml;
 
 extern crate num;
 #[macro_use] extern crate num_derive;
 
+#[macro_use] extern crate tracing;
+
 pub mod stream_read;
 pub mod stream_write;
+pub mod duplex;
+pub mod framing;
+pub mod ccsds;
+pub mod rule_route;
+pub mod apid_route;
+pub mod sequence;
+pub mod tm;
+pub mod coding;
+pub mod transform;
+pub mod reed_solomon;
+pub mod tc;
+pub mod time_code;
+pub mod router;
+pub mod config;
+pub mod control;
+pub mod stats;
+pub mod hexdump;
+pub mod pcap;
+pub mod recording;
+pub mod pacing;
+pub mod queue;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "tui")]
+pub mod dashboard;
 
 use std::fmt;
 use std::fs::File;
 use std::io::BufReader;
 use std::net::{TcpListener, TcpStream, UdpSocket, SocketAddrV4};
 use std::error::Error;
+use std::path::Path;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime};
 
 use bytes::BytesMut;
 
+use serialport::SerialPort;
+
 use crate::stream_write::*;
 use crate::stream_read::*;
+use crate::duplex::*;
+use crate::framing::*;
+use crate::transform::*;
+use crate::config::*;
+use crate::hexdump::*;
+use crate::pcap::*;
+use crate::recording::*;
 
 
 /// The stream settings are all the settings for all stream types
@@ -34,9 +79,50 @@ pub struct StreamSettings {
 
     #[serde(default)]
     pub udp: UdpSettings,
+
+    #[serde(default)]
+    pub serial: SerialSettings,
+
+    #[serde(default)]
+    pub hexdump: HexdumpSettings,
+
+    #[serde(default)]
+    pub pcap: PcapSettings,
+
+    #[serde(default)]
+    pub recording: RecordingSettings,
+
+    #[serde(default)]
+    pub framing: FramingSettings,
+
+    #[serde(default)]
+    pub transforms: Vec<TransformSettings>,
 }
 
 impl StreamSettings {
+    /// Load stream settings from a JSON, TOML, or YAML file, selected by the file's extension
+    pub fn load(path: &Path) -> Result<StreamSettings, String> {
+        return load_config(path);
+    }
+
+    /// Save stream settings as a JSON, TOML, or YAML file, selected by the file's extension
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        return save_config(path, self);
+    }
+
+    /// Check the settings used by the given stream option, and the settings of each transform
+    pub fn validate(&self, option: &StreamOption) -> Result<(), String> {
+        for transform in self.transforms.iter() {
+            transform.validate()?;
+        }
+
+        match option {
+            StreamOption::Hexdump => return self.hexdump.validate(),
+            StreamOption::Recording => return self.recording.validate(),
+            _ => return Ok(()),
+        }
+    }
+
     pub fn open_input(&self, input_option: &StreamOption) -> Result<ReadStream, String> {
         let result;
 
@@ -56,6 +142,22 @@ impl StreamSettings {
             StreamOption::Udp => {
                 result = self.udp.open_read_stream();
             },
+
+            StreamOption::Serial => {
+                result = self.serial.open_read_stream();
+            },
+
+            StreamOption::Hexdump => {
+                result = Err("A hexdump stream can only be written".to_string());
+            },
+
+            StreamOption::Pcap => {
+                result = self.pcap.open_read_stream();
+            },
+
+            StreamOption::Recording => {
+                result = self.recording.open_read_stream();
+            },
         }
 
         result
@@ -80,6 +182,131 @@ impl StreamSettings {
             StreamOption::Udp => {
                 result = self.udp.open_write_stream();
             },
+
+            StreamOption::Serial => {
+                result = self.serial.open_write_stream();
+            },
+
+            StreamOption::Hexdump => {
+                result = self.hexdump.open_write_stream();
+            },
+
+            StreamOption::Pcap => {
+                result = self.pcap.open_write_stream();
+            },
+
+            StreamOption::Recording => {
+                result = self.recording.open_write_stream();
+            },
+        }
+
+        result
+    }
+
+    /// Open an input stream which reads whole messages using the framing settings.
+    pub fn open_framed_input(&self, input_option: &StreamOption) -> Result<FramedReadStream, String> {
+        let stream = self.open_input(input_option)?;
+        return Ok(FramedReadStream::from_settings(stream, &self.framing, self.open_transforms()?));
+    }
+
+    /// Open an output stream which writes whole messages using the framing settings.
+    pub fn open_framed_output(&self, output_option: &StreamOption) -> Result<FramedWriteStream, String> {
+        let stream = self.open_output(output_option)?;
+        return Ok(FramedWriteStream::new(stream, self.framing.framer()).with_transforms(self.open_transforms()?));
+    }
+
+    fn open_transforms(&self) -> Result<Vec<Box<dyn Transform>>, String> {
+        return self.transforms.iter().map(|transform| transform.transform()).collect();
+    }
+
+    /// The descriptor string for the given stream type, including any framing
+    pub fn descriptor(&self, option: &StreamOption) -> String {
+        let stream_desc = match option {
+            StreamOption::File => self.file.to_string(),
+            StreamOption::TcpClient => self.tcp_client.to_string(),
+            StreamOption::TcpServer => self.tcp_server.to_string(),
+            StreamOption::Udp => self.udp.to_string(),
+            StreamOption::Serial => self.serial.to_string(),
+            StreamOption::Hexdump => self.hexdump.to_string(),
+            StreamOption::Pcap => self.pcap.to_string(),
+            StreamOption::Recording => self.recording.to_string(),
+        };
+
+        let mut desc = stream_desc;
+
+        if self.framing != FramingSettings::Raw || !self.transforms.is_empty() {
+            desc = format!("{}|{}", desc, self.framing);
+        }
+
+        for transform in self.transforms.iter() {
+            desc = format!("{}|{}", desc, transform);
+        }
+
+        return desc;
+    }
+
+    /// Parse a descriptor string, such as "udp:127.0.0.1:8001|ccsds", into a stream type and
+    /// the settings for that type. Settings for other stream types are left as their defaults.
+    pub fn from_descriptor(desc: &str) -> Result<(StreamOption, StreamSettings), String> {
+        let (stream_desc, framing, transforms) = split_descriptor(desc)?;
+
+        let mut settings = StreamSettings { framing, transforms, ..Default::default() };
+        let option;
+
+        if let Ok(file_settings) = FileSettings::from_str(stream_desc) {
+            settings.file = file_settings;
+            option = StreamOption::File;
+        } else if let Ok(udp_settings) = UdpSettings::from_str(stream_desc) {
+            settings.udp = udp_settings;
+            option = StreamOption::Udp;
+        } else if let Ok(tcp_server_settings) = TcpServerSettings::from_str(stream_desc) {
+            settings.tcp_server = tcp_server_settings;
+            option = StreamOption::TcpServer;
+        } else if let Ok(tcp_client_settings) = TcpClientSettings::from_str(stream_desc) {
+            settings.tcp_client = tcp_client_settings;
+            option = StreamOption::TcpClient;
+        } else if let Ok(serial_settings) = SerialSettings::from_str(stream_desc) {
+            settings.serial = serial_settings;
+            option = StreamOption::Serial;
+        } else if let Ok(hexdump_settings) = HexdumpSettings::from_str(stream_desc) {
+            settings.hexdump = hexdump_settings;
+            option = StreamOption::Hexdump;
+        } else if let Ok(pcap_settings) = PcapSettings::from_str(stream_desc) {
+            settings.pcap = pcap_settings;
+            option = StreamOption::Pcap;
+        } else if let Ok(recording_settings) = RecordingSettings::from_str(stream_desc) {
+            settings.recording = recording_settings;
+            option = StreamOption::Recording;
+        } else {
+            return Err(format!("Could not parse stream ({})", stream_desc));
+        }
+
+        return Ok((option, settings));
+    }
+
+    /// Open a single bidirectional connection for the given stream type.
+    ///
+    /// Only connection oriented streams (TCP clients and servers, and serial ports)
+    /// can be opened as duplex streams.
+    pub fn open_duplex(&self, duplex_option: &StreamOption) -> Result<DuplexStream, String> {
+        let result: Result<DuplexStream, String>;
+
+        match duplex_option {
+            StreamOption::TcpClient => {
+                result = self.tcp_client.open_duplex_stream();
+            },
+
+            StreamOption::TcpServer => {
+                result = self.tcp_server.open_duplex_stream();
+            },
+
+            StreamOption::Serial => {
+                result = self.serial.open_duplex_stream();
+            },
+
+            StreamOption::File | StreamOption::Udp | StreamOption::Hexdump | StreamOption::Pcap | StreamOption::Recording => {
+                result = Err(format!("Stream type {:?} can not be opened as a duplex stream", duplex_option));
+            },
         }
 
         result
@@ -98,6 +325,14 @@ pub enum StreamOption {
     TcpServer = 3,
     /// The stream is a UDP socket with a given port
     Udp = 4,
+    /// The stream is a serial port with a given baud rate
+    Serial = 5,
+    /// The stream renders written bytes as a hexdump, to stdout or a file
+    Hexdump = 6,
+    /// The stream is a pcap capture file, written as synthetic UDP packets or read for its UDP payloads
+    Pcap = 7,
+    /// The stream is a recording of timestamped messages, replayed with their original timing
+    Recording = 8,
 }
 
 impl Default for StreamOption {
@@ -106,6 +341,117 @@ impl Default for StreamOption {
     }
 }
 
+/// A stream config selects and configures a single stream.
+///
+/// A stream can be given either as a descriptor string, such as "udp:127.0.0.1:8001|ccsds",
+/// or as a stream option along with its settings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
+pub enum StreamConfig {
+    Descriptor(String),
+    Settings {
+        stream: StreamOption,
+        #[serde(default)]
+        settings: StreamSettings,
+    },
+}
+
+/// The settings form of a stream config
+#[derive(Deserialize)]
+struct StreamConfigSettings {
+    stream: StreamOption,
+    #[serde(default)]
+    settings: StreamSettings,
+}
+
+// a stream config is selected by the kind of value it is given as, rather than by trying each form
+// in turn as an untagged enum does, so that an error names the key within the settings that caused it
+impl<'de> serde::Deserialize<'de> for StreamConfig {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<StreamConfig, D::Error> {
+        return deserializer.deserialize_any(StreamConfigVisitor);
+    }
+}
+
+struct StreamConfigVisitor;
+
+impl<'de> serde::de::Visitor<'de> for StreamConfigVisitor {
+    type Value = StreamConfig;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a stream descriptor, or a stream option with its settings")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, desc: &str) -> Result<StreamConfig, E> {
+        return Ok(StreamConfig::Descriptor(desc.to_string()));
+    }
+
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> Result<StreamConfig, A::Error> {
+        let config: StreamConfigSettings = serde::Deserialize::deserialize(serde::de::value::MapAccessDeserializer::new(map))?;
+        config.settings.validate(&config.stream).map_err(serde::de::Error::custom)?;
+        return Ok(StreamConfig::Settings { stream: config.stream, settings: config.settings });
+    }
+}
+
+impl fmt::Display for StreamConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StreamConfig::Descriptor(desc) => {
+                write!(f, "{}", desc)
+            },
+
+            StreamConfig::Settings { stream, settings } => {
+                write!(f, "{}", settings.descriptor(stream))
+            },
+        }
+    }
+}
+
+impl StreamConfig {
+    pub fn open_input(&self) -> Result<FramedReadStream, String> {
+        match self {
+            StreamConfig::Descriptor(desc) => FramedReadStream::from_str(desc),
+
+            StreamConfig::Settings { stream, settings } => settings.open_framed_input(stream),
+        }
+    }
+
+    pub fn open_output(&self) -> Result<FramedWriteStream, String> {
+        match self {
+            StreamConfig::Descriptor(desc) => FramedWriteStream::from_str(desc),
+
+            StreamConfig::Settings { stream, settings } => settings.open_framed_output(stream),
+        }
+    }
+
+    /// Open an output without waiting for a client to connect. A TCP server output is bound,
+    /// and accepts its client in the background, dropping messages until a client connects.
+    pub fn open_listening_output(&self) -> Result<FramedWriteStream, String> {
+        match self.settings()? {
+            (StreamOption::TcpServer, settings) => {
+                let stream = settings.tcp_server.open_listening_write_stream()?;
+                return Ok(FramedWriteStream::new(stream, settings.framing.framer()).with_transforms(settings.open_transforms()?));
+            },
+
+            _ => {
+                return self.open_output();
+            },
+        }
+    }
+
+    /// The stream type and settings selected by this config
+    pub fn settings(&self) -> Result<(StreamOption, StreamSettings), String> {
+        match self {
+            StreamConfig::Descriptor(desc) => StreamSettings::from_descriptor(desc),
+
+            StreamConfig::Settings { stream, settings } => {
+                settings.validate(stream)?;
+                Ok((*stream, settings.clone()))
+            },
+        }
+    }
+}
+
 /* Input Streams */
 /// The file settings are everything needed to open and read from a file as an input or output
 /// stream
@@ -129,9 +475,8 @@ impl fmt::Display for FileSettings {
 impl FromStr for FileSettings {
     type Err = StreamSettingsParseError;
     fn from_str(s: &str) -> Result<FileSettings, StreamSettingsParseError> {
-        let prefix = "file:";
-        if s.starts_with(prefix) {
-            Ok(FileSettings { file_name: s[prefix.len()..].to_string() })
+        if let Some(file_name) = s.strip_prefix("file:") {
+            Ok(FileSettings { file_name: file_name.to_string() })
         } else {
             Err(StreamSettingsParseError(()))
         }
@@ -149,7 +494,7 @@ impl FileSettings {
 
     pub fn open_write_stream(&self) -> Result<WriteStream, String> {
         let result = File::create(self.file_name.clone())
-                        .map(|outfile| WriteStream::File(outfile))
+                        .map(WriteStream::File)
                         .map_err(|err| format!("File open error for writing: {}", err));
 
         return result;
@@ -181,13 +526,12 @@ impl fmt::Display for TcpClientSettings {
 impl FromStr for TcpClientSettings {
     type Err = StreamSettingsParseError;
     fn from_str(s: &str) -> Result<TcpClientSettings, StreamSettingsParseError> {
-        let prefix = "tcp_client:";
-        if s.starts_with(prefix) {
-            let mut parts = s[prefix.len()..].split(':');
+        if let Some(rest) = s.strip_prefix("tcp_client:") {
+            let mut parts = rest.split(':');
             let addr = parts.next().ok_or(StreamSettingsParseError(()))?;
             let port_str = parts.next().ok_or(StreamSettingsParseError(()))?;
             let port = port_str.parse::<u16>().map_err(|_| StreamSettingsParseError(()))?;
-            Ok(TcpClientSettings { ip: addr.to_string(), port: port })
+            Ok(TcpClientSettings { ip: addr.to_string(), port })
         } else {
             Err(StreamSettingsParseError(()))
         }
@@ -195,25 +539,30 @@ impl FromStr for TcpClientSettings {
 }
 
 impl TcpClientSettings {
-    pub fn open_read_stream(&self) -> Result<ReadStream, String> {
-        let addr = SocketAddrV4::new(self.ip.parse().unwrap(),
-                                     self.port);
-        let result = TcpStream::connect(&addr)
-                       .map(|sock| ReadStream::Tcp(sock))
+    fn connect(&self) -> Result<TcpStream, String> {
+        let ip = self.ip.parse().map_err(|err| format!("Could not parse ip ({}): {}", self.ip, err))?;
+        let addr = SocketAddrV4::new(ip, self.port);
+
+        let result = TcpStream::connect(addr)
                        .map_err(|err| format!("TCP Client Open Error: {}", err));
 
+        if result.is_ok() {
+            debug!(address = %addr, "connected to server");
+        }
+
         return result;
     }
 
-    pub fn open_write_stream(&self) -> Result<WriteStream, String> {
-        let addr = SocketAddrV4::new(self.ip.parse().unwrap(),
-                                     self.port);
+    pub fn open_read_stream(&self) -> Result<ReadStream, String> {
+        return self.connect().and_then(set_read_timeout).map(ReadStream::Tcp);
+    }
 
-        let result = TcpStream::connect(&addr)
-                       .map(|sock| WriteStream::Tcp(sock))
-                       .map_err(|err| format!("TCP Client Open Error: {}", err));
+    pub fn open_write_stream(&self) -> Result<WriteStream, String> {
+        return self.connect().map(WriteStream::Tcp);
+    }
 
-        return result;
+    pub fn open_duplex_stream(&self) -> Result<DuplexStream, String> {
+        return self.connect().map(DuplexStream::Tcp);
     }
 }
 
@@ -242,13 +591,12 @@ impl fmt::Display for TcpServerSettings {
 impl FromStr for TcpServerSettings {
     type Err = StreamSettingsParseError;
     fn from_str(s: &str) -> Result<TcpServerSettings, StreamSettingsParseError> {
-        let prefix = "tcp_client:";
-        if s.starts_with(prefix) {
-            let mut parts = s[prefix.len()..].split(':');
+        if let Some(rest) = s.strip_prefix("tcp_server:") {
+            let mut parts = rest.split(':');
             let addr = parts.next().ok_or(StreamSettingsParseError(()))?;
             let port_str = parts.next().ok_or(StreamSettingsParseError(()))?;
             let port = port_str.parse::<u16>().map_err(|_| StreamSettingsParseError(()))?;
-            Ok(TcpServerSettings { ip: addr.to_string(), port: port })
+            Ok(TcpServerSettings { ip: addr.to_string(), port })
         } else {
             Err(StreamSettingsParseError(()))
         }
@@ -256,23 +604,80 @@ impl FromStr for TcpServerSettings {
 }
 
 impl TcpServerSettings {
-    pub fn open_read_stream(&self) -> Result<ReadStream, String> {
-        let addr = SocketAddrV4::new(self.ip.parse().unwrap(), self.port);
-        let listener = TcpListener::bind(&addr).unwrap();
-        let (sock, _) = listener.accept().map_err(|err| format!("TCP Server Open Error: {}", err))?;
-        return Ok(ReadStream::Tcp(sock));
+    /// Bind the server's address, without waiting for a client
+    pub fn bind(&self) -> Result<TcpListener, String> {
+        let ip = self.ip.parse().map_err(|err| format!("Could not parse ip ({}): {}", self.ip, err))?;
+        let addr = SocketAddrV4::new(ip, self.port);
+
+        return TcpListener::bind(addr).map_err(|err| format!("TCP Server Bind Error: {}", err));
     }
 
-    pub fn open_write_stream(&self) -> Result<WriteStream, String> {
-        let addr = SocketAddrV4::new(self.ip.parse().unwrap(), self.port);
-        let listener = TcpListener::bind(&addr).unwrap();
+    fn accept(&self) -> Result<TcpStream, String> {
+        let listener = self.bind()?;
+        let addr = format!("{}:{}", self.ip, self.port);
+        debug!(address = %addr, "waiting for a client");
 
         let result = listener.accept()
-                             .map(|(sock, _)| WriteStream::Tcp(sock))
+                             .map(|(sock, peer)| {
+                                 info!(address = %addr, peer = %peer, "accepted client");
+                                 sock
+                             })
                              .map_err(|err| format!("TCP Server Open Error: {}", err));
 
         return result;
     }
+
+    pub fn open_read_stream(&self) -> Result<ReadStream, String> {
+        return self.accept().and_then(set_read_timeout).map(ReadStream::Tcp);
+    }
+
+    pub fn open_write_stream(&self) -> Result<WriteStream, String> {
+        return self.accept().map(WriteStream::Tcp);
+    }
+
+    pub fn open_duplex_stream(&self) -> Result<DuplexStream, String> {
+        return self.accept().map(DuplexStream::Tcp);
+    }
+
+    /// Open a write stream which accepts clients in the background, rather than waiting for
+    /// a client before returning. Messages written while no client is connected are dropped.
+    pub fn open_listening_write_stream(&self) -> Result<WriteStream, String> {
+        return self.bind().and_then(TcpServerWriter::new).map(WriteStream::TcpServer);
+    }
+}
+
+/// Wait for a client to connect to a listener, returning None if asked to stop first
+pub fn accept_unless_stopped(listener: &TcpListener, stop: &AtomicBool) -> Result<Option<TcpStream>, String> {
+    listener.set_nonblocking(true).map_err(|err| format!("Could not set TCP listener non-blocking: {}", err))?;
+
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((sock, peer)) => {
+                sock.set_nonblocking(false).map_err(|err| format!("Could not set TCP stream blocking: {}", err))?;
+                info!(peer = %peer, "accepted client");
+                return Ok(Some(sock));
+            },
+
+            Err(ref err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(SOCKET_READ_TIMEOUT_MS));
+            },
+
+            Err(err) => {
+                return Err(format!("TCP Server Open Error: {}", err));
+            },
+        }
+    }
+
+    return Ok(None);
+}
+
+/// Bound each read from a TCP socket, so a reader can check whether it should stop
+/// even when no data arrives
+fn set_read_timeout(stream: TcpStream) -> Result<TcpStream, String> {
+    stream.set_read_timeout(Some(Duration::from_millis(SOCKET_READ_TIMEOUT_MS)))
+          .map_err(|err| format!("Could not set TCP read timeout: {}", err))?;
+
+    return Ok(stream);
 }
 
 /// The udp settings are everything needed to open a UDP socket and use it as an input or output
@@ -300,13 +705,12 @@ impl fmt::Display for UdpSettings {
 impl FromStr for UdpSettings {
     type Err = StreamSettingsParseError;
     fn from_str(s: &str) -> Result<UdpSettings, StreamSettingsParseError> {
-        let prefix = "tcp_client:";
-        if s.starts_with(prefix) {
-            let mut parts = s[prefix.len()..].split(':');
+        if let Some(rest) = s.strip_prefix("udp:") {
+            let mut parts = rest.split(':');
             let addr = parts.next().ok_or(StreamSettingsParseError(()))?;
             let port_str = parts.next().ok_or(StreamSettingsParseError(()))?;
             let port = port_str.parse::<u16>().map_err(|_| StreamSettingsParseError(()))?;
-            Ok(UdpSettings { ip: addr.to_string(), port: port })
+            Ok(UdpSettings { ip: addr.to_string(), port })
         } else {
             Err(StreamSettingsParseError(()))
         }
@@ -316,7 +720,13 @@ impl FromStr for UdpSettings {
 
 impl UdpSettings {
     pub fn open_read_stream(&self) -> Result<ReadStream, String> {
-        let sock = UdpSocket::bind("0.0.0.0:0").map_err(|_err| "Couldn't bind to udp address/port")?;
+        let ip = self.ip.parse().map_err(|err| format!("Could not parse ip ({}): {}", self.ip, err))?;
+        let addr = SocketAddrV4::new(ip, self.port);
+
+        let sock = UdpSocket::bind(addr).map_err(|err| format!("Could not bind UDP socket to {}: {}", addr, err))?;
+        sock.set_read_timeout(Some(Duration::from_millis(SOCKET_READ_TIMEOUT_MS)))
+            .map_err(|err| format!("Could not set UDP read timeout: {}", err))?;
+
         return Ok(ReadStream::Udp(sock));
     }
 
@@ -341,6 +751,75 @@ impl UdpSettings {
     }
 }
 
+/// The serial settings are everything needed to open a serial port and use it as an input or
+/// output stream
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerialSettings {
+    pub port_name: String,
+    pub baud_rate: u32,
+}
+
+impl Default for SerialSettings {
+    fn default() -> Self {
+        SerialSettings { port_name: "/dev/ttyUSB0".to_string(),
+                         baud_rate: 9600,
+        }
+    }
+}
+
+impl fmt::Display for SerialSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "serial:{}:{}", self.port_name, self.baud_rate)
+    }
+}
+
+impl FromStr for SerialSettings {
+    type Err = StreamSettingsParseError;
+    fn from_str(s: &str) -> Result<SerialSettings, StreamSettingsParseError> {
+        if let Some(rest) = s.strip_prefix("serial:") {
+            // the baud rate is split from the end, as port names may contain ':'
+            let mut parts = rest.rsplitn(2, ':');
+            let baud_str = parts.next().ok_or(StreamSettingsParseError(()))?;
+            let port_name = parts.next().ok_or(StreamSettingsParseError(()))?;
+            let baud_rate = baud_str.parse::<u32>().map_err(|_| StreamSettingsParseError(()))?;
+            Ok(SerialSettings { port_name: port_name.to_string(), baud_rate })
+        } else {
+            Err(StreamSettingsParseError(()))
+        }
+    }
+}
+
+impl SerialSettings {
+    fn open(&self) -> Result<Box<dyn SerialPort>, String> {
+        // NOTE the timeout only bounds each read, so a quiet serial line results in reads of 0
+        // bytes rather than the end of the stream.
+        let result = serialport::new(self.port_name.clone(), self.baud_rate)
+                       .timeout(Duration::from_millis(SERIAL_READ_TIMEOUT_MS))
+                       .open()
+                       .map_err(|err| format!("Serial Port Open Error: {}", err));
+
+        return result;
+    }
+
+    pub fn open_read_stream(&self) -> Result<ReadStream, String> {
+        return self.open().map(ReadStream::Serial);
+    }
+
+    pub fn open_write_stream(&self) -> Result<WriteStream, String> {
+        return self.open().map(WriteStream::Serial);
+    }
+
+    pub fn open_duplex_stream(&self) -> Result<DuplexStream, String> {
+        return self.open().map(DuplexStream::Serial);
+    }
+}
+
+/// The read timeout used for serial ports
+pub const SERIAL_READ_TIMEOUT_MS: u64 = 100;
+
+/// The read timeout used for TCP and UDP input streams. A read which times out reads 0 bytes.
+pub const SOCKET_READ_TIMEOUT_MS: u64 = 100;
+
 
 /* Input/Output Streams */
 /// A read stream is a source of bytes.
@@ -352,6 +831,9 @@ pub enum ReadStream {
     File(BufReader<File>),
     Udp(UdpSocket),
     Tcp(TcpStream),
+    Serial(Box<dyn SerialPort>),
+    Pcap(PcapReader),
+    Recording(RecordingReader),
     Null,
 }
 
@@ -374,6 +856,12 @@ impl FromStr for ReadStream {
             result = tcp_server_settings.open_read_stream();
         } else if let Ok(tcp_client_settings) = TcpClientSettings::from_str(read_stream_desc) {
             result = tcp_client_settings.open_read_stream();
+        } else if let Ok(serial_settings) = SerialSettings::from_str(read_stream_desc) {
+            result = serial_settings.open_read_stream();
+        } else if let Ok(pcap_settings) = PcapSettings::from_str(read_stream_desc) {
+            result = pcap_settings.open_read_stream();
+        } else if let Ok(recording_settings) = RecordingSettings::from_str(read_stream_desc) {
+            result = recording_settings.open_read_stream();
         } else {
             result = Err("No matching stream settings!".to_string());
         }
@@ -383,6 +871,25 @@ impl FromStr for ReadStream {
 }
 
 impl ReadStream {
+    /// The address at the other end of a connected stream
+    pub fn peer(&self) -> Option<String> {
+        match self {
+            ReadStream::Tcp(tcp_stream) => tcp_stream.peer_addr().ok().map(|addr| addr.to_string()),
+            _ => None,
+        }
+    }
+
+    /// The local address of a network stream, which gives the port chosen when binding port 0
+    pub fn local_addr(&self) -> Option<String> {
+        let addr = match self {
+            ReadStream::Udp(socket) => socket.local_addr(),
+            ReadStream::Tcp(tcp_stream) => tcp_stream.local_addr(),
+            _ => return None,
+        };
+
+        return addr.ok().map(|addr| addr.to_string());
+    }
+
     pub fn stream_read(&mut self,
                        bytes: &mut BytesMut,
                        num_bytes: usize) -> StreamReadResult {
@@ -403,6 +910,18 @@ impl ReadStream {
                 result = tcp_stream.read_bytes(bytes, num_bytes);
             },
 
+            ReadStream::Serial(serial_port) => {
+                result = serial_port.read_bytes(bytes, num_bytes);
+            },
+
+            ReadStream::Pcap(pcap_reader) => {
+                result = pcap_reader.read_bytes(bytes, num_bytes);
+            },
+
+            ReadStream::Recording(recording_reader) => {
+                result = recording_reader.read_bytes(bytes, num_bytes);
+            },
+
             ReadStream::Null => {
                 // TODO is this an error, or should it just always return no bytes?
                 result = StreamReadResult::Error("Reading a Null Stream! This should not happen!".to_string());
@@ -425,6 +944,11 @@ pub enum WriteStream {
     File(File),
     Udp((UdpSocket, SocketAddrV4)),
     Tcp(TcpStream),
+    TcpServer(TcpServerWriter),
+    Serial(Box<dyn SerialPort>),
+    Hexdump(HexdumpWriter),
+    Pcap(PcapWriter),
+    Recording(RecordingWriter),
     Null,
 }
 
@@ -441,6 +965,14 @@ impl FromStr for WriteStream {
             result = tcp_server_settings.open_write_stream();
         } else if let Ok(tcp_client_settings) = TcpClientSettings::from_str(write_stream_desc) {
             result = tcp_client_settings.open_write_stream();
+        } else if let Ok(serial_settings) = SerialSettings::from_str(write_stream_desc) {
+            result = serial_settings.open_write_stream();
+        } else if let Ok(hexdump_settings) = HexdumpSettings::from_str(write_stream_desc) {
+            result = hexdump_settings.open_write_stream();
+        } else if let Ok(pcap_settings) = PcapSettings::from_str(write_stream_desc) {
+            result = pcap_settings.open_write_stream();
+        } else if let Ok(recording_settings) = RecordingSettings::from_str(write_stream_desc) {
+            result = recording_settings.open_write_stream();
         } else {
             result = Err("No matching stream settings!".to_string());
         }
@@ -450,6 +982,38 @@ impl FromStr for WriteStream {
 }
 
 impl WriteStream {
+    /// The address at the other end of a connected stream
+    pub fn peer(&self) -> Option<String> {
+        match self {
+            WriteStream::Tcp(tcp_stream) => tcp_stream.peer_addr().ok().map(|addr| addr.to_string()),
+            WriteStream::TcpServer(tcp_server_writer) => tcp_server_writer.peer(),
+            _ => None,
+        }
+    }
+
+    /// A second handle to the stream's connection, which can be used to close it from another thread
+    pub fn connection(&self) -> Option<TcpStream> {
+        match self {
+            WriteStream::Tcp(tcp_stream) => tcp_stream.try_clone().ok(),
+            WriteStream::TcpServer(tcp_server_writer) => tcp_server_writer.connection(),
+            _ => None,
+        }
+    }
+
+    /// Set the name of the stream which the next write comes from, and when it was received,
+    /// for streams which record them
+    pub fn set_origin(&mut self, source: &str, received: SystemTime) -> Result<(), String> {
+        match self {
+            WriteStream::Recording(recording_writer) => {
+                recording_writer.set_source(source)?;
+                recording_writer.set_received(received);
+                return Ok(());
+            },
+
+            _ => return Ok(()),
+        }
+    }
+
     pub fn stream_write(&mut self, bytes: &BytesMut) -> Result<usize, String> {
                        
         let result;
@@ -467,6 +1031,26 @@ impl WriteStream {
                 result = tcp_stream.write_bytes(bytes);
             },
 
+            WriteStream::TcpServer(tcp_server_writer) => {
+                result = tcp_server_writer.write_bytes(bytes);
+            },
+
+            WriteStream::Serial(serial_port) => {
+                result = serial_port.write_bytes(bytes);
+            },
+
+            WriteStream::Hexdump(hexdump_writer) => {
+                result = hexdump_writer.write_bytes(bytes);
+            },
+
+            WriteStream::Pcap(pcap_writer) => {
+                result = pcap_writer.write_bytes(bytes);
+            },
+
+            WriteStream::Recording(recording_writer) => {
+                result = recording_writer.write_bytes(bytes);
+            },
+
             WriteStream::Null => {
                 // TODO should this be a sink like /dev/null, and 'write' all bytes, or
                 // should it write 0 bytes?
@@ -489,7 +1073,7 @@ pub struct StreamSettingsParseError(());
 
 impl fmt::Display for StreamSettingsParseError {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        fmt.write_str(self.description())
+        fmt.write_str("error parsing stream settings")
     }
 }
 
@@ -1,39 +1,554 @@
 extern crate clap;
+extern crate backplane;
 
-use clap::{App, Arg, ArgMatches};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+
+use tracing::{error, info, warn};
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+#[cfg(feature = "tui")]
+use tracing::Level;
+#[cfg(feature = "tui")]
+use tracing_subscriber::fmt::writer::MakeWriterExt;
+
+use backplane::StreamConfig;
+use backplane::duplex::*;
+use backplane::router::*;
+use backplane::config::*;
+use backplane::control::*;
+use backplane::stats::*;
+use backplane::pacing::*;
+#[cfg(feature = "metrics")]
+use backplane::metrics::*;
+#[cfg(feature = "tui")]
+use backplane::dashboard::*;
 
 
-// TODO
-// the main function should take as input two strings, each specifying
-// an endpoint. it should then forward bytes from the input to the output.
-// potentially it could merge input streams into a single output stream.
 fn main() {
-    let matches = App::new("backplane")
+    let app = App::new("backplane")
         .version("0.1")
         .author("Noah Ryan")
         .about("Route bytes between various interfaces")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(Arg::with_name("INPUT")
                   .help("Input interface")
                   .short("i")
                   .long("input")
-                  .required(true)
+                  .required_unless_one(&["BRIDGE", "CONFIG"])
                   .multiple(false)
                   .empty_values(false))
         .arg(Arg::with_name("OUTPUT")
                   .help("Output interface")
                   .short("o")
                   .long("output")
-                  .required(true)
+                  .required_unless_one(&["BRIDGE", "CONFIG"])
                   .multiple(true)
                   .empty_values(false))
-        .get_matches();
+        .arg(Arg::with_name("BRIDGE")
+                  .help("Bridge two bidirectional interfaces, such as a serial port and a TCP socket")
+                  .short("b")
+                  .long("bridge")
+                  .number_of_values(2)
+                  .value_names(&["LEFT", "RIGHT"])
+                  .conflicts_with_all(&["INPUT", "OUTPUT"]))
+        .arg(Arg::with_name("CONFIG")
+                  .help("Route between the inputs and outputs given in a router config file")
+                  .short("c")
+                  .long("config")
+                  .takes_value(true)
+                  .conflicts_with_all(&["INPUT", "OUTPUT", "BRIDGE"]))
+        .arg(Arg::with_name("CONTROL")
+                  .help("Serve a control socket for changing routes while running")
+                  .long("control")
+                  .value_name("ADDRESS")
+                  .takes_value(true)
+                  .min_values(0)
+                  .conflicts_with("BRIDGE"))
+        .arg(Arg::with_name("LOG_LEVEL")
+                  .help("The level of log events to show, such as \"debug\", or filter directives such as \"warn,backplane::router=debug\"")
+                  .long("log-level")
+                  .value_name("LEVEL")
+                  .takes_value(true)
+                  .default_value("info"))
+        .arg(Arg::with_name("LOG_FORMAT")
+                  .help("The format of log events, written to stderr")
+                  .long("log-format")
+                  .value_name("FORMAT")
+                  .takes_value(true)
+                  .possible_values(&["text", "json"])
+                  .default_value("text"))
+        .arg(Arg::with_name("PACE")
+                  .help("Limit the rate of routed messages, as \"bytes:N\" or \"messages:N\" per second, or \"delay:MS\" between messages")
+                  .long("pace")
+                  .value_name("PACING")
+                  .takes_value(true)
+                  .conflicts_with_all(&["BRIDGE", "CONFIG"]))
+        .arg(Arg::with_name("STATS_INTERVAL")
+                  .help("Print the counters and rates of each stream every given number of seconds")
+                  .long("stats-interval")
+                  .value_name("SECONDS")
+                  .takes_value(true)
+                  .conflicts_with("BRIDGE"))
+        .subcommand(SubCommand::with_name("ctl")
+                    .about("Send a command to a running router's control socket")
+                    .after_help("COMMANDS:\n    list\n    open input|output NAME DESCRIPTOR\n    close NAME\n    \
+                                 connect ROUTE INPUT OUTPUT\n    disconnect ROUTE\n    pause ROUTE\n    resume ROUTE\n    \
+                                 pace ROUTE PACING|none\n    stats")
+                    .arg(Arg::with_name("ADDRESS")
+                              .help("The control socket address")
+                              .short("a")
+                              .long("address")
+                              .takes_value(true)
+                              .default_value(CONTROL_DEFAULT_ADDRESS))
+                    .arg(Arg::with_name("COMMAND")
+                              .help("The command, such as \"open output debug udp:127.0.0.1:9000\"")
+                              .required(true)
+                              .multiple(true)))
+        .subcommand(SubCommand::with_name("config")
+                    .about("Work with configuration files")
+                    .setting(AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(SubCommand::with_name("convert")
+                                .about("Convert a configuration file between JSON, TOML and YAML, selected by file extension, \
+                                        keeping its variable references and includes")
+                                .arg(Arg::with_name("FROM")
+                                          .help("The configuration file to read")
+                                          .required(true))
+                                .arg(Arg::with_name("TO")
+                                          .help("The configuration file to write")
+                                          .required(true))));
+
+    #[cfg(feature = "metrics")]
+    let app = app.arg(Arg::with_name("METRICS")
+                           .help("Serve stream and route counters for Prometheus on http://ADDRESS/metrics")
+                           .long("metrics")
+                           .value_name("ADDRESS")
+                           .takes_value(true)
+                           .min_values(0)
+                           .conflicts_with("BRIDGE"));
+
+    #[cfg(feature = "tui")]
+    let app = app.arg(Arg::with_name("TUI")
+                           .help("Show a dashboard of each stream's traffic and recent errors in the terminal")
+                           .long("tui")
+                           .conflicts_with_all(&["BRIDGE", "STATS_INTERVAL"]));
+
+    let matches = app.get_matches();
+
+    // the dashboard shows warnings and errors itself, as writing them would draw over it
+    #[cfg(feature = "tui")]
+    let log_buffer = if matches.is_present("TUI") { Some(LogBuffer::new()) } else { None };
+    #[cfg(feature = "tui")]
+    let writer = match &log_buffer {
+        Some(log_buffer) => BoxMakeWriter::new(log_buffer.clone().with_max_level(Level::WARN)),
+        None => BoxMakeWriter::new(std::io::stderr),
+    };
+    #[cfg(not(feature = "tui"))]
+    let writer = BoxMakeWriter::new(std::io::stderr);
+
+    if let Err(string) = init_logging(matches.value_of("LOG_LEVEL").unwrap(), matches.value_of("LOG_FORMAT").unwrap(), writer) {
+        eprintln!("Log error: {}", string);
+        std::process::exit(1);
+    }
+
+    #[cfg(feature = "tui")]
+    let result = run(matches, log_buffer.clone());
+    #[cfg(not(feature = "tui"))]
+    let result = run(matches);
+
+    if let Err(string) = &result {
+        error!(error = %string, "failed");
+    }
+
+    // anything logged while the dashboard was not showing, such as an error opening the router,
+    // is written out once the terminal is restored
+    #[cfg(feature = "tui")]
+    {
+        if let Some(log_buffer) = log_buffer {
+            for line in log_buffer.lines() {
+                eprintln!("{}", line);
+            }
+        }
+    }
+
+    if result.is_err() {
+        std::process::exit(1);
+    }
+}
+
+/// Write log events, as text or as one JSON object per line
+fn init_logging(level: &str, format: &str, writer: BoxMakeWriter) -> Result<(), String> {
+    let filter = EnvFilter::try_new(level).map_err(|err| format!("Invalid log level ({}): {}", level, err))?;
+
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_writer(writer).with_ansi(false);
+
+    let result = match format {
+        "json" => builder.json().with_current_span(true).with_span_list(true).try_init(),
+        _ => builder.try_init(),
+    };
+
+    result.map_err(|err| err.to_string())
+}
+
+/// Run the command given by the arguments, returning an error if it failed
+fn run(matches: ArgMatches, #[cfg(feature = "tui")] log_buffer: Option<LogBuffer>) -> Result<(), String> {
+    if let Some(config_matches) = matches.subcommand_matches("config") {
+        return run_config(config_matches);
+    }
+
+    if let Some(ctl_matches) = matches.subcommand_matches("ctl") {
+        return run_ctl(ctl_matches);
+    }
+
+    if let Some(bridge_names) = matches.values_of("BRIDGE") {
+        let bridge_names: Vec<&str> = bridge_names.collect();
+        return run_bridge(bridge_names[0], bridge_names[1]);
+    }
+
+    let config;
+    let config_path = matches.value_of("CONFIG").map(Path::new);
+    if let Some(config_path) = config_path {
+        match RouterConfig::load(config_path) {
+            Ok(loaded) => config = loaded,
+
+            Err(string) => {
+                return Err(format!("Could not load config {}: {}", config_path.display(), string));
+            },
+        }
+    } else {
+        let input_name = matches.value_of("INPUT").unwrap();
+        let output_names: Vec<&str> = matches.values_of("OUTPUT").unwrap().collect();
+
+        let pacing = match matches.value_of("PACE").map(PacingSettings::from_str) {
+            Some(Ok(pacing)) => Some(pacing),
+
+            Some(Err(string)) => {
+                return Err(format!("Could not parse pacing: {}", string));
+            },
+
+            None => None,
+        };
+
+        info!(input = %input_name, outputs = ?output_names, "routing");
+
+        config = single_route_config(input_name, &output_names, pacing);
+    }
+
+    // the control option may be given without an address to use the default
+    let control_address = if matches.is_present("CONTROL") {
+        Some(matches.value_of("CONTROL").unwrap_or(CONTROL_DEFAULT_ADDRESS))
+    } else {
+        None
+    };
+
+    let stats_interval = match matches.value_of("STATS_INTERVAL").map(f64::from_str) {
+        Some(Ok(seconds)) if seconds > 0.0 => Some(Duration::from_secs_f64(seconds)),
+
+        Some(_) => {
+            return Err("The stats interval must be a positive number of seconds".to_string());
+        },
+
+        None => None,
+    };
+
+    let options = RunOptions { config_path,
+                               control_address,
+                               stats_interval,
+                               #[cfg(feature = "metrics")]
+                               metrics_address: if matches.is_present("METRICS") {
+                                   Some(matches.value_of("METRICS").unwrap_or(METRICS_DEFAULT_ADDRESS))
+                               } else {
+                                   None
+                               },
+                               #[cfg(feature = "tui")]
+                               log_buffer,
+    };
+
+    return run_router(config, &options);
+}
+
+/// The options for running a router
+struct RunOptions<'a> {
+    /// The config file to reload on change
+    config_path: Option<&'a Path>,
+
+    /// The address to serve the control socket on
+    control_address: Option<&'a str>,
+
+    /// How often to print stream statistics
+    stats_interval: Option<Duration>,
+
+    /// The address to serve Prometheus metrics on
+    #[cfg(feature = "metrics")]
+    metrics_address: Option<&'a str>,
+
+    /// The log shown by the dashboard, when the dashboard is used
+    #[cfg(feature = "tui")]
+    log_buffer: Option<LogBuffer>,
+}
+
+/// Run a router until all of its inputs finish, or forever when it has a control socket.
+/// With the dashboard, the router runs until the user quits. TCP inputs reconnect rather than
+/// finishing, so a router with a TCP input also runs until it is interrupted.
+///
+/// When the router came from a config file, the file is reloaded when it changes or when the
+/// process receives SIGHUP. A config which fails to load or validate is reported, and the
+/// router keeps running with its current config.
+///
+/// The router fails if it can not be opened, or if an input or output fails while it runs.
+///
+/// NOTE reloading the config file replaces any changes made through the control socket.
+fn run_router(config: RouterConfig, options: &RunOptions) -> Result<(), String> {
+    let router = match Router::open(config) {
+        Ok(router) => Arc::new(Mutex::new(router)),
+
+        Err(string) => {
+            return Err(format!("Could not open router: {}", string));
+        },
+    };
+
+    if let Some(address) = options.control_address {
+        match TcpListener::bind(address) {
+            Ok(listener) => {
+                let control_router = Arc::clone(&router);
+                thread::spawn(move || serve_control(listener, control_router));
+                info!(address = %address, "control socket listening");
+            },
+
+            Err(err) => {
+                return Err(format!("Could not open control socket on {}: {}", address, err));
+            },
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    {
+        if let Some(address) = options.metrics_address {
+            match TcpListener::bind(address) {
+                Ok(listener) => {
+                    let metrics_router = Arc::clone(&router);
+                    thread::spawn(move || serve_metrics(listener, metrics_router));
+                    info!(address = %address, path = METRICS_PATH, "serving metrics");
+                },
+
+                Err(err) => {
+                    return Err(format!("Could not open metrics endpoint on {}: {}", address, err));
+                },
+            }
+        }
+    }
+
+    let reload_requested = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    {
+        if let Err(err) = signal_hook::flag::register(signal_hook::consts::SIGHUP, Arc::clone(&reload_requested)) {
+            warn!(error = %err, "could not register for SIGHUP, reloading only when the config changes");
+        }
+    }
 
-    run(matches);
+    let mut modified = options.config_path.and_then(modified_time);
+
+    let mut stats_printer = StatsPrinter::new();
+
+    #[cfg(feature = "tui")]
+    let mut dashboard = match &options.log_buffer {
+        Some(log_buffer) => match Dashboard::new(log_buffer.clone()) {
+            Ok(dashboard) => Some(dashboard),
+
+            Err(string) => {
+                router.lock().unwrap_or_else(|err| err.into_inner()).stop();
+                return Err(format!("Could not start dashboard: {}", string));
+            },
+        },
+
+        None => None,
+    };
+    #[cfg(feature = "tui")]
+    let keep_running = options.control_address.is_some() || dashboard.is_some();
+    #[cfg(not(feature = "tui"))]
+    let keep_running = options.control_address.is_some();
+
+    loop {
+        let poll = options.stats_interval.map_or(RELOAD_POLL_MS, |interval| RELOAD_POLL_MS.min(interval.as_millis() as u64));
+
+        #[cfg(feature = "tui")]
+        {
+            let quit = match dashboard.as_mut() {
+                Some(dashboard) => dashboard.wait_for_quit(Duration::from_millis(DASHBOARD_REFRESH_MS)),
+
+                None => {
+                    thread::sleep(Duration::from_millis(poll));
+                    Ok(false)
+                },
+            };
+
+            if quit != Ok(false) {
+                // restore the terminal before stopping, which can take a moment
+                drop(dashboard.take());
+                if let Err(string) = quit {
+                    error!(error = %string, "dashboard error");
+                }
+
+                router.lock().unwrap_or_else(|err| err.into_inner()).stop();
+                break;
+            }
+        }
+        #[cfg(not(feature = "tui"))]
+        thread::sleep(Duration::from_millis(poll));
+
+        let mut router = router.lock().unwrap_or_else(|err| err.into_inner());
+
+        #[cfg(feature = "tui")]
+        {
+            if let Some(dashboard) = dashboard.as_mut() {
+                if let Err(string) = dashboard.update(&router) {
+                    error!(error = %string, "dashboard error");
+                }
+            }
+        }
+
+        if let Some(interval) = options.stats_interval {
+            if stats_printer.printed.elapsed() >= interval {
+                stats_printer.print(&router);
+            }
+        }
+
+        if !keep_running && router.is_finished() {
+            break;
+        }
+
+        if let Some(path) = options.config_path {
+            let new_modified = modified_time(path);
+            let signalled = reload_requested.swap(false, Ordering::Relaxed);
+
+            if signalled || new_modified != modified {
+                modified = new_modified;
+
+                match RouterConfig::load(path).and_then(|config| router.reload(config)) {
+                    Ok(summary) => info!(config = %path.display(), summary = %summary, "reloaded config"),
+                    Err(string) => error!(config = %path.display(), error = %string, "reload failed, keeping the current config"),
+                }
+            }
+        }
+    }
+
+    let router = std::mem::take(&mut *router.lock().unwrap_or_else(|err| err.into_inner()));
+    return router.wait().map_err(|string| format!("Router error: {}", string));
+}
+
+/// How often to check a router's config file for changes
+const RELOAD_POLL_MS: u64 = 500;
+
+/// Prints the counters of a router's streams, along with their rates since the last print
+struct StatsPrinter {
+    printed: Instant,
+    previous: BTreeMap<String, StreamCounters>,
+}
+
+impl StatsPrinter {
+    fn new() -> StatsPrinter {
+        StatsPrinter { printed: Instant::now(), previous: BTreeMap::new() }
+    }
+
+    fn print(&mut self, router: &Router) {
+        let elapsed = self.printed.elapsed();
+        self.printed = Instant::now();
+
+        let inputs = router.input_counters().into_iter().map(|(name, counters)| ("input", name, counters));
+        let outputs = router.output_counters().into_iter().map(|(name, counters)| ("output", name, counters));
+
+        let mut current = BTreeMap::new();
+        for (direction, name, counters) in inputs.chain(outputs) {
+            let key = format!("{} {}", direction, name);
+
+            let descriptor = match direction {
+                "input" => router.config.inputs.get(&name),
+                _ => router.config.outputs.get(&name),
+            }.map(|config| config.to_string()).unwrap_or_default();
+
+            let previous = self.previous.get(&key).cloned().unwrap_or_default();
+            let rates = counters.rates_since(&previous, elapsed);
+
+            println!("{} '{}' ({}): {} {}", direction, name, descriptor, counters, rates);
+
+            current.insert(key, counters);
+        }
+
+        for (name, counters) in router.route_counters() {
+            println!("route '{}': messages={} bytes={}", name, counters.messages, counters.bytes);
+        }
+
+        for (name, counters) in router.sequence_counters() {
+            println!("input '{}' sequence: packets={} gaps={} missing={} duplicates={} out_of_order={}",
+                     name, counters.packets, counters.gaps, counters.missing_packets, counters.duplicates, counters.out_of_order);
+        }
+
+        self.previous = current;
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// A router config with a single route from one input to each output
+fn single_route_config(input_name: &str, output_names: &[&str], pacing: Option<PacingSettings>) -> RouterConfig {
+    let mut config = RouterConfig::default();
+    let mut route = RouteSettings { pacing, ..Default::default() };
+
+    config.inputs.insert("input".to_string(), StreamConfig::Descriptor(input_name.to_string()));
+    route.inputs.push("input".to_string());
+
+    for (index, output_name) in output_names.iter().enumerate() {
+        let name = format!("output{}", index);
+        config.outputs.insert(name.clone(), StreamConfig::Descriptor(output_name.to_string()));
+        route.outputs.push(name);
+    }
+
+    config.routes.insert("route".to_string(), route);
+
+    config
+}
+
+fn run_bridge(left_name: &str, right_name: &str) -> Result<(), String> {
+    info!(left = %left_name, right = %right_name, "bridging");
+
+    return DuplexStream::from_str(left_name)
+           .and_then(|left| DuplexStream::from_str(right_name).map(|right| (left, right)))
+           .and_then(|(left, right)| bridge(left, right))
+           .map_err(|string| format!("Bridge error: {}", string));
+}
+
+fn run_config(matches: &ArgMatches) -> Result<(), String> {
+    if let Some(convert_matches) = matches.subcommand_matches("convert") {
+        let from = Path::new(convert_matches.value_of("FROM").unwrap());
+        let to = Path::new(convert_matches.value_of("TO").unwrap());
+
+        return convert_config(from, to).map_err(|string| format!("Could not convert config {}: {}", from.display(), string));
+    }
+
+    return Ok(());
 }
 
-fn run(matches: ArgMatches) {
-    let input_name = matches.value_of("INPUT").unwrap().to_string();
-    let output_names: Vec<&str> = matches.values_of("OUTPUT").unwrap().collect();
+/// Send a command to a router's control socket and print its reply. A command which can not be parsed or sent,
+/// or which the router answers with an error, is an error.
+fn run_ctl(matches: &ArgMatches) -> Result<(), String> {
+    let address = matches.value_of("ADDRESS").unwrap();
+    let words: Vec<&str> = matches.values_of("COMMAND").unwrap().collect();
+
+    let reply = ControlCommand::from_str(&words.join(" "))
+                .and_then(|command| send_control_command(address, &command))
+                .map_err(|string| format!("Control error: {}", string))?;
 
-    println!("{:?} -> {:?}", input_name, output_names);
+    println!("{}", serde_json::to_string_pretty(&reply).unwrap_or_default());
+    return Ok(());
 }
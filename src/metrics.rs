@@ -0,0 +1,159 @@
+use std::fmt::Write as FmtWrite;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::router::*;
+use crate::stats::*;
+
+
+/// The default address of the metrics endpoint
+pub const METRICS_DEFAULT_ADDRESS: &str = "127.0.0.1:9898";
+
+/// The path which serves the metrics
+pub const METRICS_PATH: &str = "/metrics";
+
+/// How long to wait for a scraper to send its request
+const METRICS_REQUEST_TIMEOUT_MS: u64 = 1000;
+
+/// The content type of the Prometheus text format
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+
+/// A Prometheus metric about streams, and how to read it from a stream's counters
+struct StreamMetric {
+    name: &'static str,
+    help: &'static str,
+    value: fn(&StreamCounters) -> u64,
+}
+
+const STREAM_METRICS: [StreamMetric; 8] = [
+    StreamMetric { name: "backplane_stream_bytes_total", help: "Bytes read or written by a stream", value: |counters| counters.bytes },
+    StreamMetric { name: "backplane_stream_messages_total", help: "Messages read or written by a stream", value: |counters| counters.messages },
+    StreamMetric { name: "backplane_stream_operations_total", help: "Reads or writes made by a stream", value: |counters| counters.operations },
+    StreamMetric { name: "backplane_stream_errors_total", help: "Errors reading or writing a stream", value: |counters| counters.errors },
+    StreamMetric { name: "backplane_stream_reconnects_total", help: "Times a stream was reopened after disconnecting", value: |counters| counters.reconnects },
+    StreamMetric { name: "backplane_stream_dropped_total", help: "Messages dropped by an output's queue", value: |counters| counters.dropped },
+    StreamMetric { name: "backplane_stream_corrected_total", help: "Codewords corrected by a stream's decoding transforms", value: |counters| counters.corrected },
+    StreamMetric { name: "backplane_stream_uncorrectable_total", help: "Codewords a stream's decoding transforms could not correct", value: |counters| counters.uncorrectable },
+];
+
+/// Render a router's stream and route counters in the Prometheus text format.
+///
+/// Streams are labelled with their direction, name, and descriptor, such as
+/// `{direction="input",name="radio",descriptor="udp:127.0.0.1:8001"}`, and routes with their name.
+pub fn render_metrics(router: &Router) -> String {
+    let mut streams = Vec::new();
+    for (name, counters) in router.input_counters() {
+        let descriptor = router.config.inputs.get(&name).map(|config| config.to_string()).unwrap_or_default();
+        streams.push((stream_labels("input", &name, &descriptor), counters));
+    }
+    for (name, counters) in router.output_counters() {
+        let descriptor = router.config.outputs.get(&name).map(|config| config.to_string()).unwrap_or_default();
+        streams.push((stream_labels("output", &name, &descriptor), counters));
+    }
+
+    let mut text = String::new();
+
+    for metric in STREAM_METRICS.iter() {
+        let _ = writeln!(text, "# HELP {} {}", metric.name, metric.help);
+        let _ = writeln!(text, "# TYPE {} counter", metric.name);
+        for (labels, counters) in streams.iter() {
+            let _ = writeln!(text, "{}{{{}}} {}", metric.name, labels, (metric.value)(counters));
+        }
+    }
+
+    let _ = writeln!(text, "# HELP backplane_stream_last_activity_seconds Unix time of a stream's last read or write");
+    let _ = writeln!(text, "# TYPE backplane_stream_last_activity_seconds gauge");
+    for (labels, counters) in streams.iter() {
+        if let Some(last_activity) = counters.last_activity {
+            let seconds = last_activity.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+            let _ = writeln!(text, "backplane_stream_last_activity_seconds{{{}}} {:.3}", labels, seconds);
+        }
+    }
+
+    let routes = router.route_counters();
+
+    let _ = writeln!(text, "# HELP backplane_route_messages_total Messages passed through a route");
+    let _ = writeln!(text, "# TYPE backplane_route_messages_total counter");
+    for (name, counters) in routes.iter() {
+        let _ = writeln!(text, "backplane_route_messages_total{{route=\"{}\"}} {}", escape_label(name), counters.messages);
+    }
+
+    let _ = writeln!(text, "# HELP backplane_route_bytes_total Bytes passed through a route");
+    let _ = writeln!(text, "# TYPE backplane_route_bytes_total counter");
+    for (name, counters) in routes.iter() {
+        let _ = writeln!(text, "backplane_route_bytes_total{{route=\"{}\"}} {}", escape_label(name), counters.bytes);
+    }
+
+    return text;
+}
+
+fn stream_labels(direction: &str, name: &str, descriptor: &str) -> String {
+    return format!("direction=\"{}\",name=\"{}\",descriptor=\"{}\"", direction, escape_label(name), escape_label(descriptor));
+}
+
+/// Escape a label value as required by the Prometheus text format
+fn escape_label(value: &str) -> String {
+    return value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+}
+
+/// Serve a router's metrics over HTTP, answering GET requests for /metrics.
+///
+/// Scrapes are handled one at a time, as each only takes a snapshot of the counters.
+pub fn serve_metrics(listener: TcpListener, router: Arc<Mutex<Router>>) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(err) = handle_metrics_request(stream, &router) {
+                    warn!(error = %err, "metrics request error");
+                }
+            },
+
+            Err(err) => {
+                warn!(error = %err, "metrics socket accept error");
+            },
+        }
+    }
+}
+
+fn handle_metrics_request(stream: TcpStream, router: &Arc<Mutex<Router>>) -> Result<(), String> {
+    stream.set_read_timeout(Some(Duration::from_millis(METRICS_REQUEST_TIMEOUT_MS))).map_err(|err| err.to_string())?;
+
+    let mut writer = stream.try_clone().map_err(|err| err.to_string())?;
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).map_err(|err| err.to_string())?;
+
+    // the headers are not needed, but are read so the client sees a clean close
+    loop {
+        let mut header = String::new();
+        let num_bytes = reader.read_line(&mut header).map_err(|err| err.to_string())?;
+        if num_bytes == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let words: Vec<&str> = request_line.split_whitespace().collect();
+
+    let (status, body) = match words.as_slice() {
+        ["GET", path, ..] if path.split('?').next() == Some(METRICS_PATH) => {
+            let router = router.lock().unwrap_or_else(|err| err.into_inner());
+            ("200 OK", render_metrics(&router))
+        },
+
+        ["GET", ..] => ("404 Not Found", "Not Found\n".to_string()),
+
+        _ => ("405 Method Not Allowed", "Method Not Allowed\n".to_string()),
+    };
+
+    let response = format!("HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                           status,
+                           METRICS_CONTENT_TYPE,
+                           body.len(),
+                           body);
+
+    return writer.write_all(response.as_bytes()).map_err(|err| err.to_string());
+}
@@ -0,0 +1,142 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+
+/// The pacing settings limit how fast messages are passed along a route.
+///
+/// These settings can be written as a descriptor string: "bytes:N" for N bytes per second,
+/// "messages:N" for N messages per second, or "delay:MS" for a fixed delay of MS milliseconds
+/// between messages. A byte or message rate may be followed by a burst size, such as
+/// "bytes:10000:2000", which lets up to that many bytes or messages through at once after
+/// a quiet period.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PacingSettings {
+    BytesPerSecond {
+        rate: u64,
+        #[serde(default)]
+        burst: u64,
+    },
+    MessagesPerSecond {
+        rate: u64,
+        #[serde(default)]
+        burst: u64,
+    },
+    Delay {
+        millis: u64,
+    },
+}
+
+impl PacingSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            PacingSettings::BytesPerSecond { rate: 0, .. } | PacingSettings::MessagesPerSecond { rate: 0, .. } => {
+                Err(format!("Pacing rate must be greater than 0 ({})", self))
+            },
+
+            PacingSettings::Delay { millis: 0 } => Err(format!("Pacing delay must be greater than 0 ({})", self)),
+
+            _ => Ok(()),
+        }
+    }
+
+    /// Create a pacer from these settings
+    pub fn pacer(&self) -> Pacer {
+        match self {
+            PacingSettings::BytesPerSecond { rate, burst } => Pacer::new(PacingUnit::Bytes, *rate as f64, *burst as f64),
+
+            PacingSettings::MessagesPerSecond { rate, burst } => Pacer::new(PacingUnit::Messages, *rate as f64, *burst as f64),
+
+            PacingSettings::Delay { millis } => Pacer::new(PacingUnit::Messages, 1000.0 / *millis as f64, 0.0),
+        }
+    }
+}
+
+impl fmt::Display for PacingSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PacingSettings::BytesPerSecond { rate, burst: 0 } => write!(f, "bytes:{}", rate),
+            PacingSettings::BytesPerSecond { rate, burst } => write!(f, "bytes:{}:{}", rate, burst),
+            PacingSettings::MessagesPerSecond { rate, burst: 0 } => write!(f, "messages:{}", rate),
+            PacingSettings::MessagesPerSecond { rate, burst } => write!(f, "messages:{}:{}", rate, burst),
+            PacingSettings::Delay { millis } => write!(f, "delay:{}", millis),
+        }
+    }
+}
+
+impl FromStr for PacingSettings {
+    type Err = String;
+    fn from_str(s: &str) -> Result<PacingSettings, String> {
+        let parts: Vec<&str> = s.split(':').collect();
+
+        let parse_number = |part: &str| -> Result<u64, String> {
+            match part.parse::<u64>() {
+                Ok(number) => Ok(number),
+                Err(_) => Err(format!("Invalid number in pacing ({})", s)),
+            }
+        };
+
+        let result = match parts.as_slice() {
+            ["bytes", rate] => PacingSettings::BytesPerSecond { rate: parse_number(rate)?, burst: 0 },
+            ["bytes", rate, burst] => PacingSettings::BytesPerSecond { rate: parse_number(rate)?, burst: parse_number(burst)? },
+            ["messages", rate] => PacingSettings::MessagesPerSecond { rate: parse_number(rate)?, burst: 0 },
+            ["messages", rate, burst] => PacingSettings::MessagesPerSecond { rate: parse_number(rate)?, burst: parse_number(burst)? },
+            ["delay", millis] => PacingSettings::Delay { millis: parse_number(millis)? },
+            _ => return Err(format!("Could not parse pacing ({})", s)),
+        };
+
+        result.validate()?;
+
+        return Ok(result);
+    }
+}
+
+/// What a pacer's tokens count
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum PacingUnit {
+    Bytes,
+    Messages,
+}
+
+/// A token bucket which limits the rate of messages.
+///
+/// Tokens refill at the pacing rate up to the burst size. A message may be sent once the bucket
+/// is not in debt, and then takes its cost from the bucket, so a message larger than the burst
+/// size is still sent, and the following messages wait for the debt to be repaid.
+#[derive(Debug, Clone)]
+pub struct Pacer {
+    unit: PacingUnit,
+    /// Tokens added per second
+    rate: f64,
+    /// The most tokens kept
+    burst: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Pacer {
+    pub fn new(unit: PacingUnit, rate: f64, burst: f64) -> Pacer {
+        return Pacer { unit, rate, burst, tokens: 0.0, updated: Instant::now() };
+    }
+
+    /// Take the cost of a message of the given size from the bucket, returning how long to wait
+    /// before sending it
+    pub fn reserve(&mut self, num_bytes: usize) -> Duration {
+        let now = Instant::now();
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.updated = now;
+
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+
+        // a message waits until any debt from earlier messages is repaid
+        let wait = if self.tokens < 0.0 { Duration::from_secs_f64(-self.tokens / self.rate) } else { Duration::from_secs(0) };
+
+        let cost = match self.unit {
+            PacingUnit::Bytes => num_bytes as f64,
+            PacingUnit::Messages => 1.0,
+        };
+        self.tokens -= cost;
+
+        return wait;
+    }
+}
@@ -0,0 +1,708 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read, Write};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::BytesMut;
+
+use crate::*;
+use crate::stream_read::*;
+use crate::stream_write::*;
+
+
+/// The UDP port used for the synthetic UDP/IP records written when no port is given
+pub const PCAP_DEFAULT_PORT: u16 = 8001;
+
+/// The snapshot length written in pcap file headers
+pub const PCAP_SNAPLEN: u32 = 65535;
+
+/// The longest record read from a capture, which is libpcap's largest snapshot length
+pub const PCAP_MAX_RECORD_SIZE: usize = 262_144;
+
+/// The longest pcapng block read from a capture, as for libpcap
+pub const PCAPNG_MAX_BLOCK_SIZE: usize = 16 * 1024 * 1024;
+
+/* Link types, from the tcpdump.org list of LINKTYPE values */
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LOOP: u32 = 108;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_USER0: u32 = 147;
+const LINKTYPE_USER15: u32 = 162;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+/* File format magic numbers */
+const PCAP_MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+
+/* pcapng block types */
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_PACKET: u32 = 2;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88a8;
+
+const IP_PROTOCOL_UDP: u8 = 17;
+const IPV4_HEADER_SIZE: usize = 20;
+const IPV6_HEADER_SIZE: usize = 40;
+const UDP_HEADER_SIZE: usize = 8;
+
+
+/// How messages are wrapped in the records of a written capture
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub enum PcapLink {
+    /// Each message is the payload of a synthetic IPv4/UDP packet, which Wireshark can decode by port
+    Udp,
+    /// Each message is written as-is with the first user link type, DLT_USER0
+    User,
+}
+
+impl Default for PcapLink {
+    fn default() -> PcapLink {
+        return PcapLink::Udp;
+    }
+}
+
+impl fmt::Display for PcapLink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PcapLink::Udp => write!(f, "udp"),
+            PcapLink::User => write!(f, "user"),
+        }
+    }
+}
+
+impl FromStr for PcapLink {
+    type Err = String;
+    fn from_str(s: &str) -> Result<PcapLink, String> {
+        match s {
+            "udp" => Ok(PcapLink::Udp),
+            "user" => Ok(PcapLink::User),
+            _ => Err(format!("Unknown pcap link type ({})", s)),
+        }
+    }
+}
+
+/// The pcap settings describe a packet capture file, written for inspection in tools such as Wireshark,
+/// or read to replay captured traffic.
+///
+/// The descriptor is "pcap", followed by any options and then the file name, all separated by ':'.
+/// The options are "port=N", which is the UDP port of written records, and which filters the UDP
+/// payloads read from a capture, and "link=udp" or "link=user", which selects how written messages
+/// are wrapped. For example "pcap:port=8001:capture.pcap".
+///
+/// Captures are written in the classic pcap format. Both pcap and pcapng captures can be read.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PcapSettings {
+    pub file_name: String,
+
+    #[serde(default)]
+    pub port: Option<u16>,
+
+    #[serde(default)]
+    pub link: PcapLink,
+}
+
+impl Default for PcapSettings {
+    fn default() -> PcapSettings {
+        PcapSettings { file_name: "capture.pcap".to_string(),
+                       port: None,
+                       link: PcapLink::Udp,
+        }
+    }
+}
+
+impl fmt::Display for PcapSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "pcap")?;
+
+        if let Some(port) = self.port {
+            write!(f, ":port={}", port)?;
+        }
+        if self.link != PcapLink::Udp {
+            write!(f, ":link={}", self.link)?;
+        }
+
+        write!(f, ":{}", self.file_name)
+    }
+}
+
+impl FromStr for PcapSettings {
+    type Err = StreamSettingsParseError;
+    fn from_str(s: &str) -> Result<PcapSettings, StreamSettingsParseError> {
+        let rest = s.strip_prefix("pcap:").ok_or(StreamSettingsParseError(()))?;
+
+        let mut settings = PcapSettings::default();
+
+        let mut parts = rest.split(':').peekable();
+        while let Some(part) = parts.peek() {
+            if let Some(port_str) = part.strip_prefix("port=") {
+                settings.port = Some(port_str.parse::<u16>().map_err(|_| StreamSettingsParseError(()))?);
+            } else if let Some(link_str) = part.strip_prefix("link=") {
+                settings.link = PcapLink::from_str(link_str).map_err(|_| StreamSettingsParseError(()))?;
+            } else {
+                break;
+            }
+            parts.next();
+        }
+
+        // the rest is the file name, which may itself contain ':'
+        settings.file_name = parts.collect::<Vec<&str>>().join(":");
+        if settings.file_name.is_empty() {
+            return Err(StreamSettingsParseError(()));
+        }
+
+        return Ok(settings);
+    }
+}
+
+impl PcapSettings {
+    pub fn open_read_stream(&self) -> Result<ReadStream, String> {
+        let file = File::open(&self.file_name).map_err(|err| format!("File open error for reading: {}", err))?;
+        let reader = PcapReader::new(BufReader::new(file), self.port)?;
+        return Ok(ReadStream::Pcap(reader));
+    }
+
+    pub fn open_write_stream(&self) -> Result<WriteStream, String> {
+        let file = File::create(&self.file_name).map_err(|err| format!("File open error for writing: {}", err))?;
+        let writer = PcapWriter::new(file, self.link, self.port.unwrap_or(PCAP_DEFAULT_PORT))?;
+        return Ok(WriteStream::Pcap(writer));
+    }
+}
+
+
+/* Writing */
+/// Writes each message as a record of a classic pcap capture
+#[derive(Debug)]
+pub struct PcapWriter {
+    file: File,
+    link: PcapLink,
+    port: u16,
+    /// The IPv4 identification of the next synthetic packet
+    ip_id: u16,
+}
+
+impl PcapWriter {
+    /// Start a capture, writing its file header
+    pub fn new(mut file: File, link: PcapLink, port: u16) -> Result<PcapWriter, String> {
+        let linktype = match link {
+            PcapLink::Udp => LINKTYPE_RAW,
+            PcapLink::User => LINKTYPE_USER0,
+        };
+
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&PCAP_MAGIC_MICROS.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&4u16.to_le_bytes());
+        header.extend_from_slice(&0i32.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&PCAP_SNAPLEN.to_le_bytes());
+        header.extend_from_slice(&linktype.to_le_bytes());
+
+        file.write_all(&header).map_err(|err| format!("IO error {}", err))?;
+
+        return Ok(PcapWriter { file, link, port, ip_id: 0 });
+    }
+
+    /// Wrap a message in a synthetic IPv4/UDP packet from and to localhost on the writer's port
+    fn udp_packet(&mut self, bytes: &[u8]) -> Result<Vec<u8>, String> {
+        if bytes.len() > UDP_MAX_DATAGRAM_SIZE {
+            return Err(format!("Message of {} bytes is too long for a UDP record", bytes.len()));
+        }
+
+        let udp_length = (UDP_HEADER_SIZE + bytes.len()) as u16;
+        let total_length = (IPV4_HEADER_SIZE + UDP_HEADER_SIZE + bytes.len()) as u16;
+        let localhost = [127, 0, 0, 1];
+
+        let mut packet = Vec::with_capacity(total_length as usize);
+        packet.push(0x45);
+        packet.push(0);
+        packet.extend_from_slice(&total_length.to_be_bytes());
+        packet.extend_from_slice(&self.ip_id.to_be_bytes());
+        // don't fragment
+        packet.extend_from_slice(&0x4000u16.to_be_bytes());
+        packet.push(64);
+        packet.push(IP_PROTOCOL_UDP);
+        packet.extend_from_slice(&[0, 0]);
+        packet.extend_from_slice(&localhost);
+        packet.extend_from_slice(&localhost);
+
+        let checksum = ipv4_checksum(&packet[..IPV4_HEADER_SIZE]);
+        packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+        // a UDP checksum of 0 means no checksum for IPv4
+        packet.extend_from_slice(&self.port.to_be_bytes());
+        packet.extend_from_slice(&self.port.to_be_bytes());
+        packet.extend_from_slice(&udp_length.to_be_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        packet.extend_from_slice(bytes);
+
+        self.ip_id = self.ip_id.wrapping_add(1);
+
+        return Ok(packet);
+    }
+}
+
+impl StreamWrite for PcapWriter {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, String> {
+        let packet = match self.link {
+            PcapLink::Udp => self.udp_packet(bytes)?,
+            PcapLink::User => bytes.to_vec(),
+        };
+
+        // longer records would not be read back, as they are past the snapshot length in the header
+        if packet.len() > PCAP_SNAPLEN as usize {
+            return Err(format!("Message of {} bytes is too long for a pcap record", bytes.len()));
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        let mut record = Vec::with_capacity(16 + packet.len());
+        record.extend_from_slice(&(now.as_secs() as u32).to_le_bytes());
+        record.extend_from_slice(&now.subsec_micros().to_le_bytes());
+        record.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        record.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        record.extend_from_slice(&packet);
+
+        self.file.write_all(&record)
+            .map_err(|err| format!("IO error {}", err))
+            .map(|_| bytes.len())
+    }
+}
+
+fn ipv4_checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header.chunks(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]]) as u32).sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    return !(sum as u16);
+}
+
+
+/* Reading */
+/// A packet read from a capture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PcapPacket {
+    /// The time the packet was captured
+    pub time: SystemTime,
+    /// The UDP payload, or the whole record for user link types
+    pub payload: Vec<u8>,
+}
+
+/// The layout of the capture being read
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PcapFormat {
+    /// The longest record allowed by the capture's snapshot length
+    Classic { big_endian: bool, nanos: bool, linktype: u32, max_length: usize },
+    /// The link types of the interfaces of the current section, and their timestamp resolution in units per second
+    Ng { big_endian: bool, interfaces: Vec<(u32, u64)> },
+}
+
+/// Reads the UDP payloads from a pcap or pcapng capture, optionally only those to or from a given port.
+///
+/// Ethernet, raw IP, Linux cooked, and loopback captures are understood. Records with a user
+/// link type (DLT_USER0 to DLT_USER15) are read as-is. IP fragments are skipped.
+pub struct PcapReader {
+    reader: BufReader<File>,
+    format: PcapFormat,
+    port: Option<u16>,
+}
+
+impl fmt::Debug for PcapReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PcapReader")
+         .field("format", &self.format)
+         .field("port", &self.port)
+         .finish()
+    }
+}
+
+impl PcapReader {
+    /// Read a capture's header, selecting the pcap or pcapng format by its magic number
+    pub fn new(mut reader: BufReader<File>, port: Option<u16>) -> Result<PcapReader, String> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic).map_err(|err| format!("Could not read pcap header: {}", err))?;
+
+        let format;
+        if u32::from_le_bytes(magic) == PCAPNG_SECTION_HEADER {
+            let big_endian = read_section_header(&mut reader)?;
+            format = PcapFormat::Ng { big_endian, interfaces: Vec::new() };
+        } else {
+            let (big_endian, nanos) = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+                (PCAP_MAGIC_MICROS, _) => (false, false),
+                (PCAP_MAGIC_NANOS, _) => (false, true),
+                (_, PCAP_MAGIC_MICROS) => (true, false),
+                (_, PCAP_MAGIC_NANOS) => (true, true),
+                _ => return Err("Not a pcap or pcapng file".to_string()),
+            };
+
+            let mut header = [0; 20];
+            reader.read_exact(&mut header).map_err(|err| format!("Could not read pcap header: {}", err))?;
+
+            // a snapshot length of 0, or one past the largest libpcap allows, is not trusted
+            let snaplen = read_u32(&header[12..16], big_endian) as usize;
+            let max_length = if snaplen == 0 { PCAP_MAX_RECORD_SIZE } else { snaplen.min(PCAP_MAX_RECORD_SIZE) };
+
+            // the upper bits of the link type field may hold FCS information
+            let linktype = read_u32(&header[16..20], big_endian) & 0x0fff_ffff;
+
+            format = PcapFormat::Classic { big_endian, nanos, linktype, max_length };
+        }
+
+        return Ok(PcapReader { reader, format, port });
+    }
+
+    /// Read the next packet which passes the port filter, or None at the end of the capture
+    pub fn next_packet(&mut self) -> Result<Option<PcapPacket>, String> {
+        loop {
+            let record = match self.format {
+                PcapFormat::Classic { .. } => self.next_classic_record()?,
+                PcapFormat::Ng { .. } => self.next_ng_record()?,
+            };
+
+            let (time, linktype, data) = match record {
+                Some(record) => record,
+                None => return Ok(None),
+            };
+
+            if let Some(payload) = link_payload(linktype, &data, self.port) {
+                return Ok(Some(PcapPacket { time, payload: payload.to_vec() }));
+            }
+        }
+    }
+
+    fn next_classic_record(&mut self) -> Result<Option<(SystemTime, u32, Vec<u8>)>, String> {
+        let (big_endian, nanos, linktype, max_length) = match self.format {
+            PcapFormat::Classic { big_endian, nanos, linktype, max_length } => (big_endian, nanos, linktype, max_length),
+            _ => return Ok(None),
+        };
+
+        let mut header = [0; 16];
+        if !read_or_end(&mut self.reader, &mut header)? {
+            return Ok(None);
+        }
+
+        let seconds = read_u32(&header[0..4], big_endian) as u64;
+        let fraction = read_u32(&header[4..8], big_endian) as u64;
+        let captured_length = read_u32(&header[8..12], big_endian) as usize;
+
+        let subsec = if nanos { Duration::from_nanos(fraction) } else { Duration::from_micros(fraction) };
+        let time = UNIX_EPOCH + Duration::from_secs(seconds) + subsec;
+
+        let data = read_exact_vec(&mut self.reader, captured_length, max_length)?;
+
+        return Ok(Some((time, linktype, data)));
+    }
+
+    fn next_ng_record(&mut self) -> Result<Option<(SystemTime, u32, Vec<u8>)>, String> {
+        loop {
+            let big_endian = match &self.format {
+                PcapFormat::Ng { big_endian, .. } => *big_endian,
+                _ => return Ok(None),
+            };
+
+            let mut block_type_bytes = [0; 4];
+            if !read_or_end(&mut self.reader, &mut block_type_bytes)? {
+                return Ok(None);
+            }
+
+            // a new section may change the byte order and starts a new set of interfaces
+            if u32::from_le_bytes(block_type_bytes) == PCAPNG_SECTION_HEADER {
+                let big_endian = read_section_header(&mut self.reader)?;
+                self.format = PcapFormat::Ng { big_endian, interfaces: Vec::new() };
+                continue;
+            }
+
+            let block_type = read_u32(&block_type_bytes, big_endian);
+
+            let mut length_bytes = [0; 4];
+            self.reader.read_exact(&mut length_bytes).map_err(|err| format!("Truncated pcapng block: {}", err))?;
+            let block_length = read_u32(&length_bytes, big_endian) as usize;
+            if block_length < 12 || !block_length.is_multiple_of(4) {
+                return Err(format!("Invalid pcapng block length {}", block_length));
+            }
+
+            // the body is followed by a copy of the block length
+            let block = read_exact_vec(&mut self.reader, block_length - 8, PCAPNG_MAX_BLOCK_SIZE)?;
+            let body = &block[..block.len() - 4];
+
+            let interfaces = match &mut self.format {
+                PcapFormat::Ng { interfaces, .. } => interfaces,
+                _ => return Ok(None),
+            };
+
+            match block_type {
+                PCAPNG_INTERFACE_DESCRIPTION => {
+                    if body.len() < 8 {
+                        return Err("Truncated pcapng interface description".to_string());
+                    }
+                    let linktype = read_u16(&body[0..2], big_endian) as u32;
+                    interfaces.push((linktype, interface_resolution(&body[8..], big_endian)));
+                },
+
+                PCAPNG_ENHANCED_PACKET | PCAPNG_PACKET => {
+                    if body.len() < 20 {
+                        return Err("Truncated pcapng packet".to_string());
+                    }
+
+                    let interface = if block_type == PCAPNG_PACKET {
+                        read_u16(&body[0..2], big_endian) as usize
+                    } else {
+                        read_u32(&body[0..4], big_endian) as usize
+                    };
+                    let timestamp = ((read_u32(&body[4..8], big_endian) as u64) << 32) | read_u32(&body[8..12], big_endian) as u64;
+                    let captured_length = (read_u32(&body[12..16], big_endian) as usize).min(body.len() - 20);
+
+                    let (linktype, resolution) = *interfaces.get(interface)
+                                                            .ok_or_else(|| format!("pcapng packet for unknown interface {}", interface))?;
+
+                    let time = ng_time(timestamp, resolution)?;
+
+                    return Ok(Some((time, linktype, body[20..20 + captured_length].to_vec())));
+                },
+
+                PCAPNG_SIMPLE_PACKET => {
+                    if body.len() < 4 {
+                        return Err("Truncated pcapng simple packet".to_string());
+                    }
+
+                    let (linktype, _) = *interfaces.first().ok_or("pcapng simple packet without an interface")?;
+                    let original_length = (read_u32(&body[0..4], big_endian) as usize).min(body.len() - 4);
+
+                    // simple packets have no timestamp
+                    return Ok(Some((UNIX_EPOCH, linktype, body[4..4 + original_length].to_vec())));
+                },
+
+                // statistics, name resolution, and other blocks are not needed
+                _ => {},
+            }
+        }
+    }
+}
+
+impl StreamRead for PcapReader {
+    fn read_bytes(&mut self, bytes: &mut BytesMut, _num_bytes: usize) -> StreamReadResult {
+        // like a UDP socket, each read gives one packet
+        match self.next_packet() {
+            Ok(Some(packet)) => {
+                bytes.extend_from_slice(&packet.payload);
+                return StreamReadResult::BytesRead(packet.payload.len());
+            },
+
+            Ok(None) => return StreamReadResult::Finished,
+
+            Err(string) => return StreamReadResult::Error(string),
+        }
+    }
+}
+
+/// Read the rest of a pcapng section header, after its block type, returning whether the section is big endian
+fn read_section_header(reader: &mut BufReader<File>) -> Result<bool, String> {
+    let mut header = [0; 8];
+    reader.read_exact(&mut header).map_err(|err| format!("Could not read pcapng section header: {}", err))?;
+
+    let big_endian = match (u32::from_le_bytes([header[4], header[5], header[6], header[7]]),
+                            u32::from_be_bytes([header[4], header[5], header[6], header[7]])) {
+        (PCAPNG_BYTE_ORDER_MAGIC, _) => false,
+        (_, PCAPNG_BYTE_ORDER_MAGIC) => true,
+        _ => return Err("Invalid pcapng byte order magic".to_string()),
+    };
+
+    let block_length = read_u32(&header[0..4], big_endian) as usize;
+    if block_length < 28 {
+        return Err(format!("Invalid pcapng section header length {}", block_length));
+    }
+
+    // skip the version, section length, options, and trailing block length
+    read_exact_vec(reader, block_length - 12, PCAPNG_MAX_BLOCK_SIZE)?;
+
+    return Ok(big_endian);
+}
+
+/// The timestamp units per second of a pcapng interface, from its if_tsresol option
+fn interface_resolution(mut options: &[u8], big_endian: bool) -> u64 {
+    const OPTION_END: u16 = 0;
+    const OPTION_TSRESOL: u16 = 9;
+
+    while options.len() >= 4 {
+        let code = read_u16(&options[0..2], big_endian);
+        let length = read_u16(&options[2..4], big_endian) as usize;
+
+        if code == OPTION_END {
+            break;
+        }
+
+        if code == OPTION_TSRESOL && length >= 1 && options.len() > 4 {
+            let resolution = options[4];
+            let exponent = (resolution & 0x7f) as u32;
+
+            // the high bit selects a power of two rather than a power of ten
+            let units = if resolution & 0x80 != 0 { 2u64.checked_pow(exponent) } else { 10u64.checked_pow(exponent) };
+            return units.unwrap_or(1_000_000).max(1);
+        }
+
+        // options are padded to 32 bits
+        let padded = 4 + length.div_ceil(4) * 4;
+        options = &options[padded.min(options.len())..];
+    }
+
+    return 1_000_000;
+}
+
+/// The time of a pcapng timestamp with the given units per second, or an error if it is past what a SystemTime can hold
+fn ng_time(timestamp: u64, resolution: u64) -> Result<SystemTime, String> {
+    // the fraction is scaled in 128 bits, as fine resolutions overflow 64
+    let nanos = (timestamp % resolution) as u128 * 1_000_000_000 / resolution as u128;
+    let since_epoch = Duration::new(timestamp / resolution, nanos as u32);
+
+    return UNIX_EPOCH.checked_add(since_epoch)
+                     .ok_or_else(|| format!("pcapng timestamp {} is out of range", timestamp));
+}
+
+/// Find the payload of a record: the UDP payload for network link types, or the whole record for user link types
+fn link_payload(linktype: u32, data: &[u8], port: Option<u16>) -> Option<&[u8]> {
+    match linktype {
+        LINKTYPE_USER0..=LINKTYPE_USER15 => return Some(data),
+
+        LINKTYPE_ETHERNET => {
+            let mut offset = 12;
+            let mut ethertype = read_u16(data.get(offset..offset + 2)?, true);
+
+            while ethertype == ETHERTYPE_VLAN || ethertype == ETHERTYPE_QINQ {
+                offset += 4;
+                ethertype = read_u16(data.get(offset..offset + 2)?, true);
+            }
+
+            return ip_udp_payload(ethertype_version(ethertype)?, data.get(offset + 2..)?, port);
+        },
+
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => {
+            let version = data.first()? >> 4;
+            return ip_udp_payload(version, data, port);
+        },
+
+        LINKTYPE_LINUX_SLL => {
+            let ethertype = read_u16(data.get(14..16)?, true);
+            return ip_udp_payload(ethertype_version(ethertype)?, data.get(16..)?, port);
+        },
+
+        LINKTYPE_LINUX_SLL2 => {
+            let ethertype = read_u16(data.get(0..2)?, true);
+            return ip_udp_payload(ethertype_version(ethertype)?, data.get(20..)?, port);
+        },
+
+        // the address family is in the capturing host's byte order, so the IP version is checked instead
+        LINKTYPE_NULL | LINKTYPE_LOOP => {
+            let packet = data.get(4..)?;
+            return ip_udp_payload(packet.first()? >> 4, packet, port);
+        },
+
+        _ => return None,
+    }
+}
+
+fn ethertype_version(ethertype: u16) -> Option<u8> {
+    match ethertype {
+        ETHERTYPE_IPV4 => Some(4),
+        ETHERTYPE_IPV6 => Some(6),
+        _ => None,
+    }
+}
+
+/// Find the payload of a UDP packet within an IP packet, if it passes the port filter
+fn ip_udp_payload(version: u8, packet: &[u8], port: Option<u16>) -> Option<&[u8]> {
+    let udp = match version {
+        4 => {
+            let header_length = ((packet.first()? & 0x0f) as usize) * 4;
+            let fragment = read_u16(packet.get(6..8)?, true);
+
+            // fragments are skipped, as is anything with more fragments to follow
+            if fragment & 0x3fff != 0 || *packet.get(9)? != IP_PROTOCOL_UDP || header_length < IPV4_HEADER_SIZE {
+                return None;
+            }
+
+            packet.get(header_length..)?
+        },
+
+        6 => {
+            const HOP_BY_HOP: u8 = 0;
+            const ROUTING: u8 = 43;
+            const DESTINATION_OPTIONS: u8 = 60;
+
+            let mut next_header = *packet.get(6)?;
+            let mut offset = IPV6_HEADER_SIZE;
+
+            while matches!(next_header, HOP_BY_HOP | ROUTING | DESTINATION_OPTIONS) {
+                next_header = *packet.get(offset)?;
+                offset += (*packet.get(offset + 1)? as usize + 1) * 8;
+            }
+
+            if next_header != IP_PROTOCOL_UDP {
+                return None;
+            }
+
+            packet.get(offset..)?
+        },
+
+        _ => return None,
+    };
+
+    let source_port = read_u16(udp.get(0..2)?, true);
+    let destination_port = read_u16(udp.get(2..4)?, true);
+    let udp_length = read_u16(udp.get(4..6)?, true) as usize;
+
+    if port.is_some_and(|port| port != source_port && port != destination_port) {
+        return None;
+    }
+
+    // a truncated capture keeps only part of the payload
+    let end = udp_length.clamp(UDP_HEADER_SIZE, udp.len().max(UDP_HEADER_SIZE));
+    return udp.get(UDP_HEADER_SIZE..end);
+}
+
+fn read_u16(bytes: &[u8], big_endian: bool) -> u16 {
+    let pair = [bytes[0], bytes[1]];
+    if big_endian { u16::from_be_bytes(pair) } else { u16::from_le_bytes(pair) }
+}
+
+fn read_u32(bytes: &[u8], big_endian: bool) -> u32 {
+    let quad = [bytes[0], bytes[1], bytes[2], bytes[3]];
+    if big_endian { u32::from_be_bytes(quad) } else { u32::from_le_bytes(quad) }
+}
+
+/// Fill a buffer, returning false if the reader was already at its end
+fn read_or_end(reader: &mut BufReader<File>, buffer: &mut [u8]) -> Result<bool, String> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err("Truncated pcap record".to_string()),
+            Ok(num_bytes) => filled += num_bytes,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {},
+            Err(err) => return Err(format!("IO error {}", err)),
+        }
+    }
+
+    return Ok(true);
+}
+
+/// Read the given number of bytes, which were themselves read from the file, and so are checked against a limit first
+fn read_exact_vec(reader: &mut BufReader<File>, length: usize, max_length: usize) -> Result<Vec<u8>, String> {
+    if length > max_length {
+        return Err(format!("pcap record of {} bytes is longer than the limit of {} bytes", length, max_length));
+    }
+
+    let mut data = vec![0; length];
+    reader.read_exact(&mut data).map_err(|err| format!("Truncated pcap record: {}", err))?;
+    return Ok(data);
+}
@@ -0,0 +1,390 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::*;
+use crate::framing::*;
+use crate::pacing::*;
+use crate::stats::*;
+
+
+/// The number of messages an output queue holds when no capacity is given
+pub const QUEUE_DEFAULT_CAPACITY: usize = 1000;
+
+/// How often a waiting queue writer or blocked sender checks whether it should stop
+const QUEUE_POLL_MS: u64 = 100;
+
+/// How often a flush checks whether the queue has been written
+const QUEUE_FLUSH_POLL_MS: u64 = 10;
+
+/// How long to wait before reopening an output which was disconnected
+pub const OUTPUT_RECONNECT_DELAY_MS: u64 = 1000;
+
+/// How long a dropped queue waits for its writer thread to close the output
+const QUEUE_CLOSE_WAIT_MS: u64 = 1000;
+
+/// How long a router waits for its queued outputs to be written when it finishes
+pub const QUEUE_FLUSH_TIMEOUT_MS: u64 = 5000;
+
+
+/// What an output queue does with a message when it is full
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub enum FullQueuePolicy {
+    /// Wait for room, holding up the input, as an output without a queue does
+    Block,
+    /// Drop the new message
+    DropNewest,
+    /// Drop the oldest queued message to make room
+    DropOldest,
+    /// Drop every queued message and close the output, reopening it for a new consumer.
+    /// Only TCP outputs can be disconnected, as reopening a file would replace it.
+    Disconnect,
+}
+
+impl Default for FullQueuePolicy {
+    fn default() -> FullQueuePolicy {
+        return FullQueuePolicy::Block;
+    }
+}
+
+impl fmt::Display for FullQueuePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FullQueuePolicy::Block => write!(f, "block"),
+            FullQueuePolicy::DropNewest => write!(f, "drop_newest"),
+            FullQueuePolicy::DropOldest => write!(f, "drop_oldest"),
+            FullQueuePolicy::Disconnect => write!(f, "disconnect"),
+        }
+    }
+}
+
+/// The queue settings give an output its own writer thread, fed by a bounded queue, so a slow
+/// output does not hold up the inputs routed to it or the other outputs of those inputs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueueSettings {
+    /// The most messages held in the queue
+    #[serde(default = "default_capacity")]
+    pub capacity: usize,
+
+    #[serde(default)]
+    pub policy: FullQueuePolicy,
+
+    /// The most bytes per second written to the output
+    #[serde(default)]
+    pub max_bandwidth: Option<u64>,
+}
+
+fn default_capacity() -> usize {
+    return QUEUE_DEFAULT_CAPACITY;
+}
+
+impl Default for QueueSettings {
+    fn default() -> QueueSettings {
+        QueueSettings { capacity: QUEUE_DEFAULT_CAPACITY,
+                        policy: FullQueuePolicy::Block,
+                        max_bandwidth: None,
+        }
+    }
+}
+
+impl fmt::Display for QueueSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "capacity={} policy={}", self.capacity, self.policy)?;
+
+        if let Some(max_bandwidth) = self.max_bandwidth {
+            write!(f, " max_bandwidth={}", max_bandwidth)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl QueueSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.capacity == 0 {
+            return Err("Queue capacity must be greater than 0".to_string());
+        }
+
+        if self.max_bandwidth == Some(0) {
+            return Err("Queue max bandwidth must be greater than 0".to_string());
+        }
+
+        return Ok(());
+    }
+}
+
+/// A message waiting in an output queue, with the name of the input it came from and when it was received
+#[derive(Debug)]
+struct QueuedMessage {
+    source: String,
+    message: Vec<u8>,
+    received: SystemTime,
+}
+
+/// The state shared between an output queue's senders and its writer thread
+#[derive(Debug)]
+struct QueueState {
+    settings: QueueSettings,
+    messages: Mutex<VecDeque<QueuedMessage>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    stats: Arc<StreamStats>,
+
+    /// Set when the queue is dropped, stopping the writer thread
+    closed: AtomicBool,
+
+    /// Cleared when the output is disconnected, until the writer thread reopens it
+    connected: AtomicBool,
+
+    /// Set while the writer thread holds a message taken from the queue
+    writing: AtomicBool,
+
+    /// A handle to the output's connection, used to disconnect it while a write is blocked
+    connection: Mutex<Option<TcpStream>>,
+}
+
+impl QueueState {
+    fn lock_messages(&self) -> MutexGuard<'_, VecDeque<QueuedMessage>> {
+        return self.messages.lock().unwrap_or_else(|err| err.into_inner());
+    }
+
+    /// Close the output's connection, so a blocked write fails and the writer thread reopens it
+    fn disconnect(&self) {
+        self.connected.store(false, Ordering::Relaxed);
+
+        if let Some(connection) = self.connection.lock().unwrap_or_else(|err| err.into_inner()).take() {
+            let _ = connection.shutdown(Shutdown::Both);
+        }
+
+        self.not_empty.notify_all();
+    }
+}
+
+/// A bounded queue in front of an output, written to the output by its own thread.
+///
+/// The writer thread stops when the queue is dropped, and the drop waits a short time for it to
+/// close the output, so the output can be opened again. A writer blocked on a slow output is
+/// left to finish its write on its own.
+#[derive(Debug)]
+pub struct OutputQueue {
+    state: Arc<QueueState>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl OutputQueue {
+    /// Start writing to an opened output through a queue. The stream config is used to reopen
+    /// the output after it is disconnected.
+    pub fn start(name: &str, config: &StreamConfig, output: FramedWriteStream, settings: &QueueSettings) -> OutputQueue {
+        let state = Arc::new(QueueState { settings: settings.clone(),
+                                          messages: Mutex::new(VecDeque::with_capacity(settings.capacity)),
+                                          not_empty: Condvar::new(),
+                                          not_full: Condvar::new(),
+                                          stats: output.stats_handle(),
+                                          closed: AtomicBool::new(false),
+                                          connected: AtomicBool::new(true),
+                                          writing: AtomicBool::new(false),
+                                          connection: Mutex::new(output.connection()),
+        });
+
+        let thread_state = Arc::clone(&state);
+        let output_name = name.to_string();
+        let output_config = config.clone();
+        let handle = thread::spawn(move || {
+            let span = info_span!("output", output = %output_name, stream = %output_config);
+            let _entered = span.enter();
+
+            write_queue(&thread_state, &output_config, output);
+        });
+
+        return OutputQueue { state, handle: Some(handle) };
+    }
+
+    /// Queue a message from the named input, received at the given time, applying the queue's policy if it is full.
+    ///
+    /// A blocked send gives up, dropping the message, when the input is stopped.
+    pub fn send(&self, source: &str, message: &[u8], received: SystemTime, stop: &AtomicBool) {
+        let state = &self.state;
+
+        // while a disconnected output is reopened, its messages are dropped
+        if !state.connected.load(Ordering::Relaxed) {
+            state.stats.record_drop(1);
+            return;
+        }
+
+        let mut messages = state.lock_messages();
+
+        if messages.len() >= state.settings.capacity {
+            match state.settings.policy {
+                FullQueuePolicy::Block => {
+                    while messages.len() >= state.settings.capacity {
+                        if stop.load(Ordering::Relaxed) || state.closed.load(Ordering::Relaxed) {
+                            state.stats.record_drop(1);
+                            return;
+                        }
+
+                        messages = state.not_full.wait_timeout(messages, Duration::from_millis(QUEUE_POLL_MS))
+                                                 .unwrap_or_else(|err| err.into_inner()).0;
+                    }
+                },
+
+                FullQueuePolicy::DropNewest => {
+                    state.stats.record_drop(1);
+                    return;
+                },
+
+                FullQueuePolicy::DropOldest => {
+                    messages.pop_front();
+                    state.stats.record_drop(1);
+                },
+
+                FullQueuePolicy::Disconnect => {
+                    state.stats.record_drop(messages.len() as u64 + 1);
+                    messages.clear();
+                    drop(messages);
+
+                    warn!(capacity = state.settings.capacity, "output queue full, disconnecting");
+                    state.disconnect();
+                    return;
+                },
+            }
+        }
+
+        messages.push_back(QueuedMessage { source: source.to_string(), message: message.to_vec(), received });
+        state.not_empty.notify_one();
+    }
+}
+
+impl OutputQueue {
+    /// Wait until every queued message has been written, the output is disconnected, or the deadline
+    /// passes, returning the number of messages left unwritten
+    pub fn flush(&self, deadline: Instant) -> usize {
+        let state = &self.state;
+
+        loop {
+            let remaining = state.lock_messages().len() + state.writing.load(Ordering::Relaxed) as usize;
+
+            if remaining == 0 || !state.connected.load(Ordering::Relaxed) || Instant::now() >= deadline {
+                return remaining;
+            }
+
+            thread::sleep(Duration::from_millis(QUEUE_FLUSH_POLL_MS));
+        }
+    }
+}
+
+impl Drop for OutputQueue {
+    fn drop(&mut self) {
+        self.state.closed.store(true, Ordering::Relaxed);
+        self.state.not_empty.notify_all();
+        self.state.not_full.notify_all();
+
+        if let Some(handle) = self.handle.take() {
+            let deadline = Instant::now() + Duration::from_millis(QUEUE_CLOSE_WAIT_MS);
+            while !handle.is_finished() && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(QUEUE_FLUSH_POLL_MS));
+            }
+
+            if handle.is_finished() {
+                let _ = handle.join();
+            }
+        }
+    }
+}
+
+/// Write queued messages to an output until the queue is closed, reopening the output when it is disconnected
+fn write_queue(state: &QueueState, config: &StreamConfig, output: FramedWriteStream) {
+    let mut pacer = state.settings.max_bandwidth.map(|rate| Pacer::new(PacingUnit::Bytes, rate as f64, 0.0));
+    let mut output = Some(output);
+
+    while !state.closed.load(Ordering::Relaxed) {
+        if !state.connected.load(Ordering::Relaxed) {
+            // close the old connection before opening a new one
+            drop(output.take());
+            output = reopen_output(state, config);
+        }
+
+        let output = match output.as_mut() {
+            Some(output) => output,
+            None => return,
+        };
+
+        let queued = {
+            let mut messages = state.lock_messages();
+            if messages.is_empty() {
+                messages = state.not_empty.wait_timeout(messages, Duration::from_millis(QUEUE_POLL_MS))
+                                          .unwrap_or_else(|err| err.into_inner()).0;
+            }
+
+            let queued = messages.pop_front();
+            state.writing.store(queued.is_some(), Ordering::Relaxed);
+            queued
+        };
+
+        let queued = match queued {
+            Some(queued) => queued,
+            None => continue,
+        };
+        state.not_full.notify_one();
+
+        if let Some(pacer) = pacer.as_mut() {
+            thread::sleep(pacer.reserve(queued.message.len()));
+        }
+
+        let result = output.write_message_from(&queued.source, &queued.message, queued.received);
+        if let Err(string) = &result {
+            if state.connected.load(Ordering::Relaxed) {
+                warn!(error = %string, "could not write to output");
+            }
+        }
+
+        // a TCP server output accepts its client while writing, and may lose it when a write fails
+        if state.connected.load(Ordering::Relaxed) {
+            let mut connection = state.connection.lock().unwrap_or_else(|err| err.into_inner());
+            if connection.is_none() || result.is_err() {
+                *connection = output.connection();
+            }
+        }
+        state.writing.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Reopen a disconnected output, retrying until it opens or the queue is closed.
+///
+/// A TCP server output is bound again without waiting for a client, which it accepts while writing.
+fn reopen_output(state: &QueueState, config: &StreamConfig) -> Option<FramedWriteStream> {
+    loop {
+        let deadline = Instant::now() + Duration::from_millis(OUTPUT_RECONNECT_DELAY_MS);
+        while Instant::now() < deadline {
+            if state.closed.load(Ordering::Relaxed) {
+                return None;
+            }
+            thread::sleep(deadline.saturating_duration_since(Instant::now()).min(Duration::from_millis(QUEUE_POLL_MS)));
+        }
+
+        if state.closed.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        match config.open_listening_output() {
+            Ok(reopened) => {
+                let reopened = reopened.with_stats(Arc::clone(&state.stats));
+
+                *state.connection.lock().unwrap_or_else(|err| err.into_inner()) = reopened.connection();
+                state.stats.record_reconnect();
+                state.connected.store(true, Ordering::Relaxed);
+
+                info!(reconnects = state.stats.counters().reconnects, "reopened output");
+                return Some(reopened);
+            },
+
+            Err(err) => {
+                state.stats.record_error();
+                warn!(error = %err, "could not reopen output");
+            },
+        }
+    }
+}
@@ -0,0 +1,560 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use bytes::BytesMut;
+
+use crate::*;
+use crate::stream_read::*;
+use crate::stream_write::*;
+
+
+/// The magic bytes at the start of a recording, which also give the format version
+pub const RECORDING_MAGIC: &[u8; 8] = b"BPREC001";
+
+/// The extension added to a recording's file name to name its index
+pub const RECORDING_INDEX_EXTENSION: &str = "idx";
+
+/// The most time between the index's seek points, so a seek reads at most this much of the recording
+pub const RECORDING_INDEX_INTERVAL_MS: u64 = 1000;
+
+/// The size of each record's header: a timestamp, a source id, and a length
+pub const RECORD_HEADER_SIZE: usize = 14;
+
+/// The longest message recorded, which also bounds the lengths read back from a recording
+pub const RECORDING_MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// The source id of messages written without a source stream
+pub const RECORDING_UNKNOWN_SOURCE: u16 = 0;
+
+
+/// How fast a recording is replayed
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub enum ReplaySpeed {
+    /// Replay at a multiple of the recorded timing, where 1 is the original speed
+    Multiple(f64),
+    /// Replay every message as fast as possible
+    Max,
+}
+
+// a valid speed is never NaN, so equality is reflexive for every speed that can be replayed
+impl Eq for ReplaySpeed {}
+
+impl Default for ReplaySpeed {
+    fn default() -> ReplaySpeed {
+        return ReplaySpeed::Multiple(1.0);
+    }
+}
+
+impl ReplaySpeed {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ReplaySpeed::Multiple(multiple) if !(multiple.is_finite() && *multiple > 0.0) => {
+                return Err(format!("Replay speed must be a positive number or 'max' ({})", multiple));
+            },
+
+            _ => return Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for ReplaySpeed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplaySpeed::Multiple(multiple) => write!(f, "{}", multiple),
+            ReplaySpeed::Max => write!(f, "max"),
+        }
+    }
+}
+
+impl FromStr for ReplaySpeed {
+    type Err = String;
+    fn from_str(s: &str) -> Result<ReplaySpeed, String> {
+        if s == "max" {
+            return Ok(ReplaySpeed::Max);
+        }
+
+        let speed = match s.parse::<f64>() {
+            Ok(multiple) => ReplaySpeed::Multiple(multiple),
+            Err(_) => return Err(format!("Replay speed must be a positive number or 'max' ({})", s)),
+        };
+
+        speed.validate()?;
+
+        return Ok(speed);
+    }
+}
+
+/// The recording settings describe a file of timestamped messages. Each written message is stored
+/// with the time it was received, the stream it came from, and its length, and a sidecar index
+/// with the source stream names and seek points is written alongside, named by adding ".idx".
+///
+/// The descriptor is "record", followed by any options and then the file name, all separated by ':'.
+/// The options only apply when reading: "speed=N" replays at N times the recorded timing, with
+/// "speed=max" replaying as fast as possible, "start=SECONDS" starts replay the given number of
+/// seconds into the recording, and "source=NAME" replays only the messages from one stream.
+/// For example "record:speed=2:start=30:pass.rec".
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordingSettings {
+    pub file_name: String,
+
+    #[serde(default)]
+    pub speed: ReplaySpeed,
+
+    /// The time into the recording to start replay
+    #[serde(default)]
+    pub start: Option<Duration>,
+
+    /// The name of the only source stream to replay
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+impl Default for RecordingSettings {
+    fn default() -> RecordingSettings {
+        RecordingSettings { file_name: "recording.rec".to_string(),
+                            speed: ReplaySpeed::default(),
+                            start: None,
+                            source: None,
+        }
+    }
+}
+
+impl fmt::Display for RecordingSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "record")?;
+
+        if self.speed != ReplaySpeed::default() {
+            write!(f, ":speed={}", self.speed)?;
+        }
+        if let Some(start) = self.start {
+            write!(f, ":start={}", start.as_secs_f64())?;
+        }
+        if let Some(source) = &self.source {
+            write!(f, ":source={}", source)?;
+        }
+
+        write!(f, ":{}", self.file_name)
+    }
+}
+
+impl FromStr for RecordingSettings {
+    type Err = StreamSettingsParseError;
+    fn from_str(s: &str) -> Result<RecordingSettings, StreamSettingsParseError> {
+        let rest = s.strip_prefix("record:").ok_or(StreamSettingsParseError(()))?;
+
+        let mut settings = RecordingSettings::default();
+
+        let mut parts = rest.split(':').peekable();
+        while let Some(part) = parts.peek() {
+            if let Some(speed_str) = part.strip_prefix("speed=") {
+                settings.speed = ReplaySpeed::from_str(speed_str).map_err(|_| StreamSettingsParseError(()))?;
+            } else if let Some(start_str) = part.strip_prefix("start=") {
+                let seconds = start_str.parse::<f64>().map_err(|_| StreamSettingsParseError(()))?;
+                settings.start = Some(Duration::try_from_secs_f64(seconds).map_err(|_| StreamSettingsParseError(()))?);
+            } else if let Some(source_str) = part.strip_prefix("source=") {
+                settings.source = Some(source_str.to_string());
+            } else {
+                break;
+            }
+            parts.next();
+        }
+
+        // the rest is the file name, which may itself contain ':'
+        settings.file_name = parts.collect::<Vec<&str>>().join(":");
+        if settings.file_name.is_empty() {
+            return Err(StreamSettingsParseError(()));
+        }
+
+        return Ok(settings);
+    }
+}
+
+impl RecordingSettings {
+    /// The file name of the recording's index
+    pub fn index_file_name(&self) -> String {
+        return format!("{}.{}", self.file_name, RECORDING_INDEX_EXTENSION);
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        return self.speed.validate();
+    }
+
+    pub fn open_read_stream(&self) -> Result<ReadStream, String> {
+        self.validate()?;
+
+        let mut reader = RecordingReader::open(&self.file_name, &self.index_file_name())?;
+
+        reader.set_speed(self.speed)?;
+
+        if let Some(source) = &self.source {
+            reader.filter_source(source)?;
+        }
+
+        if let Some(start) = self.start {
+            if let Some(first_time) = reader.first_time()? {
+                reader.seek(first_time + start)?;
+            }
+        }
+
+        return Ok(ReadStream::Recording(reader));
+    }
+
+    pub fn open_write_stream(&self) -> Result<WriteStream, String> {
+        let writer = RecordingWriter::create(&self.file_name, &self.index_file_name())?;
+        return Ok(WriteStream::Recording(writer));
+    }
+}
+
+
+/* Index */
+/// A line of a recording's index, which is written as JSON lines
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexEntry {
+    /// The name of the stream given a source id
+    Source { id: u16, name: String },
+
+    /// The byte offset in the recording of the first message written at or after a time,
+    /// in nanoseconds since the Unix epoch
+    Seek { time: u64, offset: u64 },
+}
+
+/// A recorded message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// The time the message was received
+    pub time: SystemTime,
+    /// The id of the stream the message came from, named in the recording's index
+    pub source: u16,
+    pub payload: Vec<u8>,
+}
+
+fn nanos_since_epoch(time: SystemTime) -> u64 {
+    return time.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+}
+
+
+/* Writing */
+/// Writes each message as a timestamped record, keeping the recording's index up to date
+#[derive(Debug)]
+pub struct RecordingWriter {
+    file: File,
+    index: File,
+    /// The number of bytes written to the recording so far
+    offset: u64,
+    sources: BTreeMap<String, u16>,
+    source: u16,
+    /// The time of the last seek point written to the index
+    last_seek: Option<SystemTime>,
+    /// When the next message was received, if it was given, rather than when it is written
+    received: Option<SystemTime>,
+}
+
+impl RecordingWriter {
+    /// Create a recording and its index, replacing any existing files
+    pub fn create(file_name: &str, index_file_name: &str) -> Result<RecordingWriter, String> {
+        let mut file = File::create(file_name).map_err(|err| format!("File open error for writing: {}", err))?;
+        let index = File::create(index_file_name).map_err(|err| format!("File open error for writing: {}", err))?;
+
+        file.write_all(RECORDING_MAGIC).map_err(|err| format!("IO error {}", err))?;
+
+        return Ok(RecordingWriter { file,
+                                    index,
+                                    offset: RECORDING_MAGIC.len() as u64,
+                                    sources: BTreeMap::new(),
+                                    source: RECORDING_UNKNOWN_SOURCE,
+                                    last_seek: None,
+                                    received: None,
+        });
+    }
+
+    /// Set when the next message was received, so a message held in a queue or by pacing is
+    /// recorded with the time it arrived
+    pub fn set_received(&mut self, time: SystemTime) {
+        self.received = Some(time);
+    }
+
+    /// Set the stream which the following messages come from, adding it to the index if it is new
+    pub fn set_source(&mut self, name: &str) -> Result<(), String> {
+        if let Some(id) = self.sources.get(name) {
+            self.source = *id;
+            return Ok(());
+        }
+
+        let id = self.sources.len() as u16 + 1;
+        self.write_index(&IndexEntry::Source { id, name: name.to_string() })?;
+        self.sources.insert(name.to_string(), id);
+        self.source = id;
+
+        return Ok(());
+    }
+
+    fn write_index(&mut self, entry: &IndexEntry) -> Result<(), String> {
+        let mut line = serde_json::to_string(entry).map_err(|err| err.to_string())?;
+        line.push('\n');
+
+        return self.index.write_all(line.as_bytes()).map_err(|err| format!("IO error {}", err));
+    }
+}
+
+impl StreamWrite for RecordingWriter {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, String> {
+        if bytes.len() > RECORDING_MAX_MESSAGE_SIZE {
+            return Err(format!("Message of {} bytes is too long to record", bytes.len()));
+        }
+
+        let time = self.received.take().unwrap_or_else(SystemTime::now);
+
+        let seek_due = match self.last_seek {
+            Some(last_seek) => time.duration_since(last_seek).unwrap_or_default() >= Duration::from_millis(RECORDING_INDEX_INTERVAL_MS),
+            None => true,
+        };
+
+        if seek_due {
+            self.write_index(&IndexEntry::Seek { time: nanos_since_epoch(time), offset: self.offset })?;
+            self.last_seek = Some(time);
+        }
+
+        let mut record = Vec::with_capacity(RECORD_HEADER_SIZE + bytes.len());
+        record.extend_from_slice(&nanos_since_epoch(time).to_be_bytes());
+        record.extend_from_slice(&self.source.to_be_bytes());
+        record.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+        record.extend_from_slice(bytes);
+
+        self.file.write_all(&record).map_err(|err| format!("IO error {}", err))?;
+        self.offset += record.len() as u64;
+
+        return Ok(bytes.len());
+    }
+}
+
+
+/* Reading */
+/// Replays the messages of a recording, one message per read, paced by their recorded times.
+///
+/// Without an index the recording can still be replayed, but source names are unknown and seeking
+/// reads from the start of the recording.
+#[derive(Debug)]
+pub struct RecordingReader {
+    reader: BufReader<File>,
+    sources: BTreeMap<u16, String>,
+    seeks: Vec<(SystemTime, u64)>,
+    speed: ReplaySpeed,
+    source_filter: Option<u16>,
+    /// The next message, read ahead when it was not yet due
+    pending: Option<Record>,
+    /// The recorded time of the first message replayed and when it was replayed, which pace the rest
+    replay_start: Option<(SystemTime, Instant)>,
+}
+
+impl RecordingReader {
+    /// Open a recording and, if it exists, its index
+    pub fn open(file_name: &str, index_file_name: &str) -> Result<RecordingReader, String> {
+        let file = File::open(file_name).map_err(|err| format!("File open error for reading: {}", err))?;
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic).map_err(|err| format!("Could not read recording header: {}", err))?;
+        if &magic != RECORDING_MAGIC {
+            return Err(format!("{} is not a recording", file_name));
+        }
+
+        let mut sources = BTreeMap::new();
+        let mut seeks = Vec::new();
+
+        match File::open(index_file_name) {
+            Ok(index) => {
+                for (line_index, line) in BufReader::new(index).lines().enumerate() {
+                    let line = line.map_err(|err| format!("IO error {}", err))?;
+
+                    // a recording may have been cut off part way through a line
+                    match serde_json::from_str::<IndexEntry>(&line) {
+                        Ok(IndexEntry::Source { id, name }) => {
+                            sources.insert(id, name);
+                        },
+
+                        Ok(IndexEntry::Seek { time, offset }) => {
+                            seeks.push((UNIX_EPOCH + Duration::from_nanos(time), offset));
+                        },
+
+                        Err(err) => {
+                            warn!(index = %index_file_name, line = line_index + 1, error = %err, "skipping bad index line");
+                        },
+                    }
+                }
+            },
+
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                warn!(index = %index_file_name, "recording has no index");
+            },
+
+            Err(err) => return Err(format!("File open error for reading: {}", err)),
+        }
+
+        return Ok(RecordingReader { reader,
+                                    sources,
+                                    seeks,
+                                    speed: ReplaySpeed::default(),
+                                    source_filter: None,
+                                    pending: None,
+                                    replay_start: None,
+        });
+    }
+
+    pub fn set_speed(&mut self, speed: ReplaySpeed) -> Result<(), String> {
+        speed.validate()?;
+
+        self.speed = speed;
+        self.replay_start = None;
+
+        return Ok(());
+    }
+
+    /// The names of the recorded streams, by source id
+    pub fn sources(&self) -> &BTreeMap<u16, String> {
+        return &self.sources;
+    }
+
+    /// Replay only the messages from the named stream
+    pub fn filter_source(&mut self, name: &str) -> Result<(), String> {
+        let id = self.sources.iter()
+                             .find(|(_, source)| source.as_str() == name)
+                             .map(|(id, _)| *id)
+                             .ok_or_else(|| format!("Recording has no source stream '{}'", name))?;
+
+        self.source_filter = Some(id);
+        return Ok(());
+    }
+
+    /// The time of the first message, from the index or else the recording itself
+    pub fn first_time(&mut self) -> Result<Option<SystemTime>, String> {
+        if let Some((time, _)) = self.seeks.first() {
+            return Ok(Some(*time));
+        }
+
+        let position = self.reader.stream_position().map_err(|err| format!("IO error {}", err))?;
+        self.reader.seek(SeekFrom::Start(RECORDING_MAGIC.len() as u64)).map_err(|err| format!("IO error {}", err))?;
+
+        let time = self.next_record()?.map(|record| record.time);
+
+        self.reader.seek(SeekFrom::Start(position)).map_err(|err| format!("IO error {}", err))?;
+
+        return Ok(time);
+    }
+
+    /// Move to the first message recorded at or after the given time
+    pub fn seek(&mut self, time: SystemTime) -> Result<(), String> {
+        // start from the last seek point before the time, or the start of the recording
+        let offset = self.seeks.iter()
+                               .take_while(|(seek_time, _)| *seek_time <= time)
+                               .last()
+                               .map(|(_, offset)| *offset)
+                               .unwrap_or(RECORDING_MAGIC.len() as u64);
+
+        self.reader.seek(SeekFrom::Start(offset)).map_err(|err| format!("IO error {}", err))?;
+        self.pending = None;
+        self.replay_start = None;
+
+        while let Some(record) = self.next_record()? {
+            if record.time >= time {
+                self.pending = Some(record);
+                break;
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Read the next record which passes the source filter, or None at the end of the recording
+    pub fn next_record(&mut self) -> Result<Option<Record>, String> {
+        loop {
+            let mut header = [0; RECORD_HEADER_SIZE];
+            if !self.read_header(&mut header)? {
+                return Ok(None);
+            }
+
+            let time = UNIX_EPOCH + Duration::from_nanos(u64::from_be_bytes([header[0], header[1], header[2], header[3],
+                                                                              header[4], header[5], header[6], header[7]]));
+            let source = u16::from_be_bytes([header[8], header[9]]);
+            let length = u32::from_be_bytes([header[10], header[11], header[12], header[13]]) as usize;
+            if length > RECORDING_MAX_MESSAGE_SIZE {
+                return Err(format!("Record of {} bytes is longer than the limit of {} bytes", length, RECORDING_MAX_MESSAGE_SIZE));
+            }
+
+            let mut payload = vec![0; length];
+            match self.reader.read_exact(&mut payload) {
+                Ok(()) => {},
+
+                // the last record of a recording which was cut off is ignored
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+
+                Err(err) => return Err(format!("IO error {}", err)),
+            }
+
+            if self.source_filter.is_none_or(|id| id == source) {
+                return Ok(Some(Record { time, source, payload }));
+            }
+        }
+    }
+
+    /// Fill a record header, returning false at the end of the recording
+    fn read_header(&mut self, header: &mut [u8]) -> Result<bool, String> {
+        let mut filled = 0;
+        while filled < header.len() {
+            match self.reader.read(&mut header[filled..]) {
+                Ok(0) => return Ok(false),
+                Ok(num_bytes) => filled += num_bytes,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {},
+                Err(err) => return Err(format!("IO error {}", err)),
+            }
+        }
+
+        return Ok(true);
+    }
+
+    /// How long until a message recorded at the given time is due to be replayed
+    fn time_until_due(&mut self, time: SystemTime) -> Duration {
+        let multiple = match self.speed {
+            ReplaySpeed::Multiple(multiple) => multiple,
+            ReplaySpeed::Max => return Duration::from_secs(0),
+        };
+
+        let (first_time, started) = *self.replay_start.get_or_insert((time, Instant::now()));
+
+        let recorded = time.duration_since(first_time).unwrap_or_default();
+        let due = started + recorded.div_f64(multiple);
+
+        return due.saturating_duration_since(Instant::now());
+    }
+}
+
+impl StreamRead for RecordingReader {
+    fn read_bytes(&mut self, bytes: &mut BytesMut, _num_bytes: usize) -> StreamReadResult {
+        let record = match self.pending.take() {
+            Some(record) => record,
+
+            None => match self.next_record() {
+                Ok(Some(record)) => record,
+                Ok(None) => return StreamReadResult::Finished,
+                Err(string) => return StreamReadResult::Error(string),
+            },
+        };
+
+        // wait at most as long as a socket read would, so a long gap does not hold up stopping
+        let wait = self.time_until_due(record.time);
+        if wait > Duration::from_millis(SOCKET_READ_TIMEOUT_MS) {
+            thread::sleep(Duration::from_millis(SOCKET_READ_TIMEOUT_MS));
+            self.pending = Some(record);
+            return StreamReadResult::BytesRead(0);
+        }
+        thread::sleep(wait);
+
+        bytes.extend_from_slice(&record.payload);
+        return StreamReadResult::BytesRead(record.payload.len());
+    }
+}
@@ -0,0 +1,501 @@
+use std::fmt;
+use std::sync::Arc;
+
+use bytes::BytesMut;
+
+use crate::stats::*;
+use crate::transform::*;
+
+
+/// The number of symbols in a Reed-Solomon codeword
+pub const RS_N: usize = 255;
+
+/// The number of data symbols in a Reed-Solomon codeword
+pub const RS_K: usize = 223;
+
+/// The number of parity symbols in a Reed-Solomon codeword
+pub const RS_PARITY: usize = RS_N - RS_K;
+
+/// The number of symbol errors that can be corrected in each codeword
+pub const RS_MAX_CORRECTABLE: usize = RS_PARITY / 2;
+
+/// The largest interleave depth defined for CCSDS Reed-Solomon codeblocks
+pub const RS_MAX_INTERLEAVE: usize = 8;
+
+/// The interleave depths defined for CCSDS Reed-Solomon codeblocks
+pub const RS_INTERLEAVE_DEPTHS: [usize; 6] = [1, 2, 3, 4, 5, 8];
+
+/// The field generator polynomial, x^8 + x^7 + x^2 + x + 1
+const GF_POLY: u16 = 0x187;
+
+/// The first consecutive root of the code generator polynomial
+const FCR: usize = 112;
+
+/// The primitive element used to generate the roots of the code generator polynomial
+const PRIM: usize = 11;
+
+/// The rows of the matrix converting from the conventional basis to the Berlekamp dual basis
+const TAL: [u8; 8] = [0x8d, 0xef, 0xec, 0x86, 0xfa, 0x99, 0xaf, 0x7b];
+
+
+/// The Reed-Solomon settings describe the CCSDS Reed-Solomon (255,223) code used on a link
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReedSolomonSettings {
+    /// The number of interleaved codewords in each codeblock, from 1 to 5, or 8
+    pub interleave: usize,
+
+    /// Whether symbols use the Berlekamp dual basis representation, as required by CCSDS
+    #[serde(default = "default_dual_basis")]
+    pub dual_basis: bool,
+}
+
+fn default_dual_basis() -> bool {
+    true
+}
+
+impl Default for ReedSolomonSettings {
+    fn default() -> ReedSolomonSettings {
+        ReedSolomonSettings { interleave: 1, dual_basis: default_dual_basis() }
+    }
+}
+
+impl fmt::Display for ReedSolomonSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.dual_basis {
+            write!(f, "{}", self.interleave)
+        } else {
+            write!(f, "{}:conventional", self.interleave)
+        }
+    }
+}
+
+impl ReedSolomonSettings {
+    /// Parse settings of the form "5" or "5:conventional"
+    pub fn parse(s: &str) -> Option<ReedSolomonSettings> {
+        let mut parts = s.split(':');
+
+        let interleave = parts.next()?.parse::<usize>().ok()?;
+
+        let dual_basis = match parts.next() {
+            None | Some("dual") => true,
+            Some("conventional") => false,
+            Some(_) => return None,
+        };
+
+        if parts.next().is_some() {
+            return None;
+        }
+
+        let settings = ReedSolomonSettings { interleave, dual_basis };
+        settings.validate().ok()?;
+
+        return Some(settings);
+    }
+
+    /// Check that the interleave depth is one defined by CCSDS
+    pub fn validate(&self) -> Result<(), String> {
+        if !RS_INTERLEAVE_DEPTHS.contains(&self.interleave) {
+            return Err(format!("Reed-Solomon interleave depth {} is not one of {:?}", self.interleave, RS_INTERLEAVE_DEPTHS));
+        }
+
+        return Ok(());
+    }
+
+    /// The length of a codeblock in bytes
+    pub fn codeblock_length(&self) -> usize {
+        return RS_N * self.interleave;
+    }
+
+    /// The length of the data in a codeblock in bytes
+    pub fn data_length(&self) -> usize {
+        return RS_K * self.interleave;
+    }
+}
+
+/// The tables used for arithmetic in GF(2^8), and for the code itself
+#[derive(Clone)]
+pub struct ReedSolomonCodec {
+    alpha_to: [u8; 256],
+    index_of: [u8; 256],
+    generator: [u8; RS_PARITY + 1],
+    to_dual: [u8; 256],
+    to_conventional: [u8; 256],
+}
+
+impl fmt::Debug for ReedSolomonCodec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ReedSolomonCodec(255, 223)")
+    }
+}
+
+impl Default for ReedSolomonCodec {
+    fn default() -> ReedSolomonCodec {
+        return ReedSolomonCodec::new();
+    }
+}
+
+impl ReedSolomonCodec {
+    pub fn new() -> ReedSolomonCodec {
+        let mut codec = ReedSolomonCodec { alpha_to: [0; 256],
+                                           index_of: [0; 256],
+                                           generator: [0; RS_PARITY + 1],
+                                           to_dual: [0; 256],
+                                           to_conventional: [0; 256],
+        };
+
+        // log and antilog tables
+        let mut element: u16 = 1;
+        for power in 0..RS_N {
+            codec.alpha_to[power] = element as u8;
+            codec.index_of[element as usize] = power as u8;
+
+            element <<= 1;
+            if element & 0x100 != 0 {
+                element ^= GF_POLY;
+            }
+        }
+
+        // the generator polynomial is the product of (x - alpha^(prim * (fcr + root))),
+        // with generator[power] holding the coefficient of x^power
+        codec.generator[0] = 1;
+        for root in 0..RS_PARITY {
+            let root_value = codec.pow((FCR + root) * PRIM);
+
+            for power in (1..=root + 1).rev() {
+                codec.generator[power] = codec.generator[power - 1] ^ codec.mul(codec.generator[power], root_value);
+            }
+            codec.generator[0] = codec.mul(codec.generator[0], root_value);
+        }
+
+        // basis conversion tables
+        for value in 0..256 {
+            let mut dual: u8 = 0;
+            for bit in 0..8 {
+                if value & (1 << bit) != 0 {
+                    dual ^= TAL[7 - bit];
+                }
+            }
+            codec.to_dual[value] = dual;
+            codec.to_conventional[dual as usize] = value as u8;
+        }
+
+        return codec;
+    }
+
+    fn mul(&self, a: u8, b: u8) -> u8 {
+        if a == 0 || b == 0 {
+            return 0;
+        }
+
+        let power = self.index_of[a as usize] as usize + self.index_of[b as usize] as usize;
+        return self.alpha_to[power % RS_N];
+    }
+
+    fn div(&self, a: u8, b: u8) -> u8 {
+        if a == 0 {
+            return 0;
+        }
+
+        let power = RS_N + self.index_of[a as usize] as usize - self.index_of[b as usize] as usize;
+        return self.alpha_to[power % RS_N];
+    }
+
+    fn pow(&self, power: usize) -> u8 {
+        return self.alpha_to[power % RS_N];
+    }
+
+    fn eval(&self, poly: &[u8], x: u8) -> u8 {
+        // poly[power] is the coefficient of x^power
+        return poly.iter().rev().fold(0, |acc, coeff| self.mul(acc, x) ^ coeff);
+    }
+
+    /// Compute the parity symbols for one codeword's data, in the conventional basis.
+    pub fn encode(&self, data: &[u8; RS_K]) -> [u8; RS_PARITY] {
+        // parity[0] is the highest order coefficient of the remainder
+        let mut parity = [0u8; RS_PARITY];
+
+        for symbol in data.iter() {
+            let feedback = symbol ^ parity[0];
+
+            for index in 0..RS_PARITY - 1 {
+                parity[index] = parity[index + 1] ^ self.mul(feedback, self.generator[RS_PARITY - 1 - index]);
+            }
+            parity[RS_PARITY - 1] = self.mul(feedback, self.generator[0]);
+        }
+
+        return parity;
+    }
+
+    /// Correct the errors in one codeword, in the conventional basis, returning the number of
+    /// symbols corrected, or None if the codeword can not be corrected.
+    ///
+    /// The first symbol of the codeword is the highest order coefficient.
+    pub fn decode(&self, codeword: &mut [u8; RS_N]) -> Option<usize> {
+        // syndromes, S_j = r(alpha^(prim * (fcr + j)))
+        let mut syndromes = [0u8; RS_PARITY];
+        let mut has_errors = false;
+        for (index, syndrome) in syndromes.iter_mut().enumerate() {
+            let root = self.pow((FCR + index) * PRIM);
+            *syndrome = codeword.iter().fold(0, |acc, symbol| self.mul(acc, root) ^ symbol);
+            has_errors |= *syndrome != 0;
+        }
+
+        if !has_errors {
+            return Some(0);
+        }
+
+        // Berlekamp-Massey, finding the error locator polynomial lambda
+        let mut lambda = [0u8; RS_PARITY + 1];
+        let mut prev_lambda = [0u8; RS_PARITY + 1];
+        lambda[0] = 1;
+        prev_lambda[0] = 1;
+
+        let mut num_errors = 0;
+        let mut shift = 1;
+        let mut prev_discrepancy: u8 = 1;
+
+        for step in 0..RS_PARITY {
+            let mut discrepancy = syndromes[step];
+            for index in 1..=num_errors {
+                discrepancy ^= self.mul(lambda[index], syndromes[step - index]);
+            }
+
+            if discrepancy == 0 {
+                shift += 1;
+            } else {
+                let scale = self.div(discrepancy, prev_discrepancy);
+                let old_lambda = lambda;
+
+                for index in shift..=RS_PARITY {
+                    lambda[index] ^= self.mul(scale, prev_lambda[index - shift]);
+                }
+
+                if 2 * num_errors <= step {
+                    num_errors = step + 1 - num_errors;
+                    prev_lambda = old_lambda;
+                    prev_discrepancy = discrepancy;
+                    shift = 1;
+                } else {
+                    shift += 1;
+                }
+            }
+        }
+
+        let degree = lambda.iter().rposition(|coeff| *coeff != 0).unwrap_or(0);
+        if degree != num_errors || num_errors > RS_MAX_CORRECTABLE {
+            return None;
+        }
+
+        // the error evaluator polynomial, omega = syndromes * lambda mod x^(2t)
+        let mut omega = [0u8; RS_PARITY];
+        for (index, omega_coeff) in omega.iter_mut().enumerate() {
+            for lambda_index in 0..=index.min(degree) {
+                *omega_coeff ^= self.mul(lambda[lambda_index], syndromes[index - lambda_index]);
+            }
+        }
+
+        // the formal derivative of lambda keeps only the odd powers
+        let mut lambda_prime = [0u8; RS_PARITY];
+        for power in (1..=degree).step_by(2) {
+            lambda_prime[power - 1] = lambda[power];
+        }
+
+        // Chien search, checking each symbol position for a root of lambda
+        let mut corrections: Vec<(usize, u8)> = Vec::with_capacity(num_errors);
+        for position in 0..RS_N {
+            // position i holds the coefficient of x^(n - 1 - i), with locator X = alpha^(prim * (n - 1 - i))
+            let locator_power = PRIM * (RS_N - 1 - position);
+            let locator_inverse = self.pow(RS_N - locator_power % RS_N);
+
+            if self.eval(&lambda[..=degree], locator_inverse) == 0 {
+                // Forney's algorithm, e = X^(1 - fcr) * omega(X^-1) / lambda'(X^-1)
+                let numerator = self.eval(&omega, locator_inverse);
+                let denominator = self.eval(&lambda_prime, locator_inverse);
+                if denominator == 0 {
+                    return None;
+                }
+
+                let scale = self.pow(locator_power * (RS_N + 1 - FCR % RS_N));
+                let magnitude = self.mul(scale, self.div(numerator, denominator));
+                corrections.push((position, magnitude));
+            }
+        }
+
+        if corrections.len() != num_errors {
+            return None;
+        }
+
+        for (position, magnitude) in corrections.iter() {
+            codeword[*position] ^= magnitude;
+        }
+
+        return Some(num_errors);
+    }
+
+    /// Convert a symbol from the conventional basis to the dual basis
+    pub fn to_dual(&self, symbol: u8) -> u8 {
+        return self.to_dual[symbol as usize];
+    }
+
+    /// Convert a symbol from the dual basis to the conventional basis
+    pub fn to_conventional(&self, symbol: u8) -> u8 {
+        return self.to_conventional[symbol as usize];
+    }
+}
+
+/// The Reed-Solomon encoder adds the parity symbols of each interleaved codeword to a frame
+#[derive(Debug, Clone)]
+pub struct ReedSolomonEncoder {
+    pub settings: ReedSolomonSettings,
+    codec: ReedSolomonCodec,
+}
+
+impl ReedSolomonEncoder {
+    pub fn new(settings: ReedSolomonSettings) -> ReedSolomonEncoder {
+        return ReedSolomonEncoder { settings, codec: ReedSolomonCodec::new() };
+    }
+
+    /// Encode a frame of 223 * interleave bytes into a codeblock of 255 * interleave bytes
+    pub fn encode(&self, frame: &[u8]) -> Result<BytesMut, String> {
+        self.settings.validate()?;
+        let interleave = self.settings.interleave;
+
+        if frame.len() != self.settings.data_length() {
+            return Err(format!("Reed-Solomon encoding with interleave {} requires {} bytes, but the frame is {} bytes",
+                               interleave,
+                               self.settings.data_length(),
+                               frame.len()));
+        }
+
+        let mut codeblock = BytesMut::with_capacity(self.settings.codeblock_length());
+        codeblock.extend_from_slice(frame);
+        codeblock.resize(self.settings.codeblock_length(), 0);
+
+        for codeword_index in 0..interleave {
+            let mut data = [0u8; RS_K];
+            for (symbol_index, symbol) in data.iter_mut().enumerate() {
+                let byte = frame[symbol_index * interleave + codeword_index];
+                *symbol = if self.settings.dual_basis { self.codec.to_conventional(byte) } else { byte };
+            }
+
+            let parity = self.codec.encode(&data);
+            for (parity_index, symbol) in parity.iter().enumerate() {
+                let byte = if self.settings.dual_basis { self.codec.to_dual(*symbol) } else { *symbol };
+                codeblock[(RS_K + parity_index) * interleave + codeword_index] = byte;
+            }
+        }
+
+        return Ok(codeblock);
+    }
+}
+
+impl Transform for ReedSolomonEncoder {
+    fn transform(&mut self, message: BytesMut) -> Result<Option<BytesMut>, String> {
+        return self.encode(&message).map(Some);
+    }
+}
+
+/// The Reed-Solomon decoder corrects each interleaved codeword in a codeblock, and
+/// returns the data without its parity symbols.
+#[derive(Debug, Clone)]
+pub struct ReedSolomonDecoder {
+    pub settings: ReedSolomonSettings,
+
+    /// The number of codewords with at least one corrected symbol
+    pub codewords_corrected: u64,
+
+    /// The total number of symbols corrected
+    pub symbols_corrected: u64,
+
+    /// The number of codewords with too many errors to correct
+    pub codewords_uncorrectable: u64,
+
+    /// The number of codeblocks dropped because they contained an uncorrectable codeword
+    pub codeblocks_dropped: u64,
+
+    codec: ReedSolomonCodec,
+
+    /// The stats of the stream being decoded, which also count corrections
+    stats: Option<Arc<StreamStats>>,
+}
+
+impl ReedSolomonDecoder {
+    pub fn new(settings: ReedSolomonSettings) -> ReedSolomonDecoder {
+        return ReedSolomonDecoder { settings,
+                                    codewords_corrected: 0,
+                                    symbols_corrected: 0,
+                                    codewords_uncorrectable: 0,
+                                    codeblocks_dropped: 0,
+                                    codec: ReedSolomonCodec::new(),
+                                    stats: None,
+        };
+    }
+
+    /// Correct a codeblock of 255 * interleave bytes, returning its 223 * interleave bytes of data.
+    pub fn decode(&mut self, codeblock: &[u8]) -> Result<BytesMut, String> {
+        self.settings.validate()?;
+        let interleave = self.settings.interleave;
+
+        if codeblock.len() != self.settings.codeblock_length() {
+            return Err(format!("Reed-Solomon decoding with interleave {} requires {} bytes, but the codeblock is {} bytes",
+                               interleave,
+                               self.settings.codeblock_length(),
+                               codeblock.len()));
+        }
+
+        let mut frame = BytesMut::with_capacity(self.settings.data_length());
+        frame.extend_from_slice(&codeblock[..self.settings.data_length()]);
+
+        let mut corrected = 0;
+        let mut uncorrectable = 0;
+        for codeword_index in 0..interleave {
+            let mut codeword = [0u8; RS_N];
+            for (symbol_index, symbol) in codeword.iter_mut().enumerate() {
+                let byte = codeblock[symbol_index * interleave + codeword_index];
+                *symbol = if self.settings.dual_basis { self.codec.to_conventional(byte) } else { byte };
+            }
+
+            match self.codec.decode(&mut codeword) {
+                Some(0) => {},
+
+                Some(num_corrected) => {
+                    debug!(codeword = codeword_index, symbols = num_corrected, "corrected Reed-Solomon codeword");
+                    self.codewords_corrected += 1;
+                    self.symbols_corrected += num_corrected as u64;
+                    corrected += 1;
+
+                    for (symbol_index, symbol) in codeword[..RS_K].iter().enumerate() {
+                        let byte = if self.settings.dual_basis { self.codec.to_dual(*symbol) } else { *symbol };
+                        frame[symbol_index * interleave + codeword_index] = byte;
+                    }
+                },
+
+                None => {
+                    self.codewords_uncorrectable += 1;
+                    uncorrectable += 1;
+                },
+            }
+        }
+
+        if let Some(stats) = &self.stats {
+            stats.record_corrections(corrected, uncorrectable);
+        }
+
+        if uncorrectable > 0 {
+            self.codeblocks_dropped += 1;
+            return Err(format!("Reed-Solomon codeblock has {} uncorrectable codewords", uncorrectable));
+        }
+
+        return Ok(frame);
+    }
+}
+
+impl Transform for ReedSolomonDecoder {
+    fn transform(&mut self, message: BytesMut) -> Result<Option<BytesMut>, String> {
+        return self.decode(&message).map(Some);
+    }
+
+    fn set_stats(&mut self, stats: &Arc<StreamStats>) {
+        self.stats = Some(Arc::clone(stats));
+    }
+}
@@ -0,0 +1,1217 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, RwLock, Weak};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::*;
+use crate::framing::*;
+use crate::config::*;
+use crate::stats::*;
+use crate::pacing::*;
+use crate::queue::*;
+use crate::sequence::*;
+
+
+/// A route connects inputs to outputs. Every message read from any of the inputs
+/// is written to all of the outputs.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteSettings {
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+
+    /// A paused route delivers nothing until it is resumed
+    #[serde(default)]
+    pub paused: bool,
+
+    /// Limit the rate of messages through the route. A paced route writes to its outputs from its
+    /// own thread, so its inputs and their other routes are only held up once it has fallen
+    /// PACED_ROUTE_CAPACITY messages behind.
+    #[serde(default)]
+    pub pacing: Option<PacingSettings>,
+}
+
+/// The router config describes a routing graph of named inputs, named outputs, and named
+/// routes between them.
+///
+/// Router configs can be written in JSON, TOML or YAML. An example in JSON is
+/// ```json
+/// {
+///     "inputs":  { "radio": "udp:0.0.0.0:8001|ccsds" },
+///     "outputs": { "archive": "file:archive.bin", "display": "tcp_client:127.0.0.1:9000|ccsds" },
+///     "routes":  { "telemetry": { "inputs": ["radio"], "outputs": ["archive", "display"] } }
+/// }
+/// ```
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouterConfig {
+    #[serde(default)]
+    pub inputs: BTreeMap<String, StreamConfig>,
+
+    #[serde(default)]
+    pub outputs: BTreeMap<String, StreamConfig>,
+
+    #[serde(default)]
+    pub routes: BTreeMap<String, RouteSettings>,
+
+    /// The outputs written through a queue by their own thread, by output name.
+    /// Other outputs are written directly by the threads of their inputs.
+    #[serde(default)]
+    pub queues: BTreeMap<String, QueueSettings>,
+
+    /// The inputs whose CCSDS packets have their sequence counts checked, logging a warning
+    /// for each gap, duplicate or out of order packet
+    #[serde(default)]
+    pub monitor_sequence: BTreeSet<String>,
+}
+
+impl RouterConfig {
+    /// Load a router config from a JSON, TOML, or YAML file, selected by the file's extension
+    pub fn load(path: &Path) -> Result<RouterConfig, String> {
+        return load_config(path);
+    }
+
+    /// Save a router config as a JSON, TOML, or YAML file, selected by the file's extension
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        return save_config(path, self);
+    }
+
+    /// The routes from an input, including paused routes
+    pub fn routes_of<'a>(&'a self, input_name: &'a str) -> impl Iterator<Item=(&'a String, &'a RouteSettings)> + 'a {
+        return self.routes.iter().filter(move |(_, route)| route.inputs.iter().any(|name| name == input_name));
+    }
+
+    /// The outputs which an input can feed through any of its routes, in order and without duplicates
+    pub fn outputs_of(&self, input_name: &str) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+
+        for (_, route) in self.routes_of(input_name) {
+            for name in route.outputs.iter() {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+
+        return names;
+    }
+
+    /// Check the routing graph before opening any streams.
+    ///
+    /// Every route must name inputs and outputs that exist, no two streams may bind the
+    /// same local address, port, or file, and no output may feed back into an input that
+    /// routes to it.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(name) = self.inputs.keys().find(|name| self.outputs.contains_key(*name)) {
+            return Err(format!("Name '{}' is used for both an input and an output", name));
+        }
+
+        for (route_name, route) in self.routes.iter() {
+            if route.inputs.is_empty() || route.outputs.is_empty() {
+                return Err(format!("Route '{}' must have at least one input and one output", route_name));
+            }
+
+            if let Some(name) = route.inputs.iter().find(|name| !self.inputs.contains_key(*name)) {
+                return Err(format!("Route '{}' refers to an unknown input '{}'", route_name, name));
+            }
+
+            if let Some(name) = route.outputs.iter().find(|name| !self.outputs.contains_key(*name)) {
+                return Err(format!("Route '{}' refers to an unknown output '{}'", route_name, name));
+            }
+
+            if let Some(pacing) = &route.pacing {
+                pacing.validate().map_err(|err| format!("Route '{}': {}", route_name, err))?;
+            }
+        }
+
+        for (output_name, queue) in self.queues.iter() {
+            if !self.outputs.contains_key(output_name) {
+                return Err(format!("Queue given for an unknown output '{}'", output_name));
+            }
+
+            queue.validate().map_err(|err| format!("Output '{}': {}", output_name, err))?;
+        }
+
+        let inputs = resolve_streams(&self.inputs)?;
+        let outputs = resolve_streams(&self.outputs)?;
+
+        for input_name in self.monitor_sequence.iter() {
+            match inputs.get(input_name) {
+                None => return Err(format!("Sequence monitor given for an unknown input '{}'", input_name)),
+
+                Some((_, settings)) => {
+                    if !matches!(settings.framing, FramingSettings::Ccsds(_) | FramingSettings::TmPackets(_)) {
+                        return Err(format!("Input '{}': only inputs framed as CCSDS packets can monitor sequence counts", input_name));
+                    }
+                },
+            }
+        }
+
+        // reopening any other output after a disconnect would truncate it, or reopen a device
+        for (output_name, _) in self.queues.iter().filter(|(_, queue)| queue.policy == FullQueuePolicy::Disconnect) {
+            if let Some((option, _)) = outputs.get(output_name) {
+                if !matches!(option, StreamOption::TcpClient | StreamOption::TcpServer) {
+                    return Err(format!("Output '{}': only TCP outputs can use the disconnect queue policy", output_name));
+                }
+            }
+        }
+
+        check_binds(&inputs, &outputs)?;
+
+        return self.check_cycles(&inputs, &outputs);
+    }
+
+    fn check_cycles(&self,
+                    inputs: &BTreeMap<String, (StreamOption, StreamSettings)>,
+                    outputs: &BTreeMap<String, (StreamOption, StreamSettings)>) -> Result<(), String> {
+        // each input leads to the inputs fed by the outputs it is routed to
+        let mut edges: BTreeMap<&str, Vec<(String, &str)>> = BTreeMap::new();
+        for input_name in inputs.keys() {
+            let mut next = Vec::new();
+            for output_name in self.outputs_of(input_name) {
+                for (fed_name, fed_stream) in inputs.iter() {
+                    if feeds(&outputs[&output_name], fed_stream) {
+                        next.push((output_name.clone(), fed_name.as_str()));
+                    }
+                }
+            }
+            edges.insert(input_name, next);
+        }
+
+        let mut finished: BTreeSet<&str> = BTreeSet::new();
+        for input_name in inputs.keys() {
+            let mut path = Vec::new();
+            if let Some(cycle) = find_cycle(input_name, &edges, &mut path, &mut finished) {
+                return Err(format!("Routing cycle: {}", cycle));
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+fn resolve_streams(streams: &BTreeMap<String, StreamConfig>) -> Result<BTreeMap<String, (StreamOption, StreamSettings)>, String> {
+    let mut resolved = BTreeMap::new();
+
+    for (name, stream_config) in streams.iter() {
+        let settings = stream_config.settings().map_err(|err| format!("Stream '{}': {}", name, err))?;
+        resolved.insert(name.clone(), settings);
+    }
+
+    return Ok(resolved);
+}
+
+/// Depth first search for a cycle starting at the given input, returning a description of the cycle
+fn find_cycle<'a>(input_name: &'a str,
+                  edges: &BTreeMap<&'a str, Vec<(String, &'a str)>>,
+                  path: &mut Vec<(&'a str, String)>,
+                  finished: &mut BTreeSet<&'a str>) -> Option<String> {
+    if finished.contains(input_name) {
+        return None;
+    }
+
+    if let Some(start) = path.iter().position(|(name, _)| *name == input_name) {
+        let mut cycle = String::new();
+        for (name, output_name) in path[start..].iter() {
+            cycle.push_str(&format!("input '{}' -> output '{}' -> ", name, output_name));
+        }
+        cycle.push_str(&format!("input '{}'", input_name));
+        return Some(cycle);
+    }
+
+    for (output_name, fed_name) in edges[input_name].iter() {
+        path.push((input_name, output_name.clone()));
+        let cycle = find_cycle(fed_name, edges, path, finished);
+        path.pop();
+
+        if cycle.is_some() {
+            return cycle;
+        }
+    }
+
+    finished.insert(input_name);
+    return None;
+}
+
+/// A local resource held by a stream, which only one stream can hold at a time
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Bind {
+    Tcp(String, u16),
+    Udp(String, u16),
+    Serial(String),
+    File(String),
+}
+
+impl Bind {
+    fn conflicts(&self, other: &Bind) -> bool {
+        match (self, other) {
+            (Bind::Tcp(ip, port), Bind::Tcp(other_ip, other_port)) |
+            (Bind::Udp(ip, port), Bind::Udp(other_ip, other_port)) => {
+                addresses_match(ip, *port, other_ip, *other_port)
+            },
+
+            (Bind::Serial(name), Bind::Serial(other_name)) |
+            (Bind::File(name), Bind::File(other_name)) => {
+                name == other_name
+            },
+
+            _ => false,
+        }
+    }
+}
+
+/// The resource bound by a stream. Files are only bound when written, as any number of
+/// streams can read the same file.
+fn bind((option, settings): &(StreamOption, StreamSettings), is_output: bool) -> Option<Bind> {
+    match option {
+        StreamOption::TcpServer => Some(Bind::Tcp(settings.tcp_server.ip.clone(), settings.tcp_server.port)),
+
+        StreamOption::Udp if !is_output => Some(Bind::Udp(settings.udp.ip.clone(), settings.udp.port)),
+
+        StreamOption::Serial => Some(Bind::Serial(settings.serial.port_name.clone())),
+
+        StreamOption::File if is_output => Some(Bind::File(settings.file.file_name.clone())),
+
+        StreamOption::Hexdump if is_output => settings.hexdump.file_name.clone().map(Bind::File),
+
+        StreamOption::Pcap if is_output => Some(Bind::File(settings.pcap.file_name.clone())),
+
+        StreamOption::Recording if is_output => Some(Bind::File(settings.recording.file_name.clone())),
+
+        _ => None,
+    }
+}
+
+fn check_binds(inputs: &BTreeMap<String, (StreamOption, StreamSettings)>,
+               outputs: &BTreeMap<String, (StreamOption, StreamSettings)>) -> Result<(), String> {
+    let binds: Vec<(&String, Bind)> =
+        inputs.iter().filter_map(|(name, stream)| bind(stream, false).map(|bind| (name, bind)))
+              .chain(outputs.iter().filter_map(|(name, stream)| bind(stream, true).map(|bind| (name, bind))))
+              .collect();
+
+    for (index, (name, bind)) in binds.iter().enumerate() {
+        if let Some((other_name, _)) = binds[index + 1..].iter().find(|(_, other)| bind.conflicts(other)) {
+            return Err(format!("Streams '{}' and '{}' both bind {:?}", name, other_name, bind));
+        }
+    }
+
+    return Ok(());
+}
+
+/// Whether an address and port refer to the same socket as another, treating 0.0.0.0 as any address
+fn addresses_match(ip: &str, port: u16, other_ip: &str, other_port: u16) -> bool {
+    return port == other_port && (ip == other_ip || ip == "0.0.0.0" || other_ip == "0.0.0.0");
+}
+
+/// Whether data written to an output would be read back by an input
+fn feeds((output_option, output): &(StreamOption, StreamSettings),
+         (input_option, input): &(StreamOption, StreamSettings)) -> bool {
+    match (output_option, input_option) {
+        (StreamOption::TcpClient, StreamOption::TcpServer) => {
+            addresses_match(&output.tcp_client.ip, output.tcp_client.port, &input.tcp_server.ip, input.tcp_server.port)
+        },
+
+        (StreamOption::TcpServer, StreamOption::TcpClient) => {
+            addresses_match(&output.tcp_server.ip, output.tcp_server.port, &input.tcp_client.ip, input.tcp_client.port)
+        },
+
+        (StreamOption::Udp, StreamOption::Udp) => {
+            addresses_match(&output.udp.ip, output.udp.port, &input.udp.ip, input.udp.port)
+        },
+
+        (StreamOption::File, StreamOption::File) => {
+            output.file.file_name == input.file.file_name
+        },
+
+        (StreamOption::Hexdump, StreamOption::File) => {
+            output.hexdump.file_name.as_ref() == Some(&input.file.file_name)
+        },
+
+        (StreamOption::Pcap, StreamOption::Pcap) => {
+            output.pcap.file_name == input.pcap.file_name
+        },
+
+        (StreamOption::Recording, StreamOption::Recording) => {
+            output.recording.file_name == input.recording.file_name
+        },
+
+        _ => false,
+    }
+}
+
+/// An output as written by the threads of the inputs routed to it
+#[derive(Debug)]
+pub enum OutputWriter {
+    /// Each message is written by the input's thread, which waits for the write
+    Direct(Mutex<FramedWriteStream>),
+    /// Each message is queued for the output's own thread to write
+    Queued(OutputQueue),
+}
+
+impl OutputWriter {
+    /// Write or queue a message from the named input, received at the given time
+    pub fn send(&self, source: &str, message: &[u8], received: SystemTime, stop: &AtomicBool) -> Result<(), String> {
+        match self {
+            OutputWriter::Direct(output) => {
+                let mut output = output.lock().map_err(|_| "output lock poisoned".to_string())?;
+                output.write_message_from(source, message, received)?;
+            },
+
+            OutputWriter::Queued(queue) => queue.send(source, message, received, stop),
+        }
+
+        return Ok(());
+    }
+
+    /// Wait for any queued messages to be written, until the deadline, returning the number left unwritten
+    pub fn flush(&self, deadline: Instant) -> usize {
+        match self {
+            OutputWriter::Direct(_) => return 0,
+            OutputWriter::Queued(queue) => return queue.flush(deadline),
+        }
+    }
+}
+
+/// An output shared between the threads of each input routed to it
+pub type SharedOutput = Arc<OutputWriter>;
+
+/// The number of messages a paced route holds before holding up its inputs
+pub const PACED_ROUTE_CAPACITY: usize = QUEUE_DEFAULT_CAPACITY;
+
+/// How often a paced route's waiting thread or blocked sender checks whether it should stop
+const PACED_ROUTE_POLL_MS: u64 = 100;
+
+/// A message waiting in a paced route, with the outputs it is written to
+#[derive(Debug)]
+struct PacedMessage {
+    source: String,
+    message: Vec<u8>,
+    received: SystemTime,
+    /// Waiting messages do not keep their outputs open, so an output closed by a reload is skipped
+    outputs: Vec<(String, Weak<OutputWriter>)>,
+}
+
+/// The state shared between a paced route's senders and its thread
+#[derive(Debug)]
+struct PacedRouteState {
+    messages: Mutex<VecDeque<PacedMessage>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+
+    /// Set when the route is dropped, stopping its thread
+    closed: AtomicBool,
+
+    /// Set while the route's thread holds a message taken from the queue
+    writing: AtomicBool,
+}
+
+impl PacedRouteState {
+    fn lock_messages(&self) -> MutexGuard<'_, VecDeque<PacedMessage>> {
+        return self.messages.lock().unwrap_or_else(|err| err.into_inner());
+    }
+}
+
+/// A paced route, which writes the messages of its inputs to their outputs from its own thread,
+/// waiting for the route's pacer before each message. The inputs of the route share its pacer,
+/// so their messages are paced together.
+///
+/// The thread stops when the route is dropped.
+#[derive(Debug)]
+pub struct PacedRoute {
+    state: Arc<PacedRouteState>,
+}
+
+impl PacedRoute {
+    pub fn start(name: &str, pacing: &PacingSettings) -> PacedRoute {
+        let state = Arc::new(PacedRouteState { messages: Mutex::new(VecDeque::new()),
+                                               not_empty: Condvar::new(),
+                                               not_full: Condvar::new(),
+                                               closed: AtomicBool::new(false),
+                                               writing: AtomicBool::new(false),
+        });
+
+        let thread_state = Arc::clone(&state);
+        let route_name = name.to_string();
+        let pacer = pacing.pacer();
+        let route_pacing = pacing.clone();
+        thread::spawn(move || {
+            let span = info_span!("route", route = %route_name, pacing = %route_pacing);
+            let _entered = span.enter();
+
+            write_paced_route(&thread_state, pacer);
+        });
+
+        return PacedRoute { state };
+    }
+
+    /// Queue a message from the named input, received at the given time, to be written to the given outputs.
+    ///
+    /// A full route holds up the input until it has room, and the message is dropped if the input is stopped first.
+    pub fn send(&self, source: &str, message: &[u8], received: SystemTime, outputs: &[(String, SharedOutput)], stop: &AtomicBool) {
+        let state = &self.state;
+
+        let mut messages = state.lock_messages();
+        while messages.len() >= PACED_ROUTE_CAPACITY {
+            if stop.load(Ordering::Relaxed) || state.closed.load(Ordering::Relaxed) {
+                return;
+            }
+
+            messages = state.not_full.wait_timeout(messages, Duration::from_millis(PACED_ROUTE_POLL_MS))
+                                     .unwrap_or_else(|err| err.into_inner()).0;
+        }
+
+        let outputs = outputs.iter().map(|(name, output)| (name.clone(), Arc::downgrade(output))).collect();
+        messages.push_back(PacedMessage { source: source.to_string(), message: message.to_vec(), received, outputs });
+        state.not_empty.notify_one();
+    }
+
+    /// Wait until every queued message has been written
+    pub fn flush(&self) {
+        let state = &self.state;
+
+        while state.writing.load(Ordering::Relaxed) || !state.lock_messages().is_empty() {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+impl Drop for PacedRoute {
+    fn drop(&mut self) {
+        self.state.closed.store(true, Ordering::Relaxed);
+        self.state.not_empty.notify_all();
+        self.state.not_full.notify_all();
+    }
+}
+
+/// Write a paced route's messages at its pace until the route is closed
+fn write_paced_route(state: &PacedRouteState, mut pacer: Pacer) {
+    while !state.closed.load(Ordering::Relaxed) {
+        let paced = {
+            let mut messages = state.lock_messages();
+            if messages.is_empty() {
+                messages = state.not_empty.wait_timeout(messages, Duration::from_millis(PACED_ROUTE_POLL_MS))
+                                          .unwrap_or_else(|err| err.into_inner()).0;
+            }
+
+            let paced = messages.pop_front();
+            state.writing.store(paced.is_some(), Ordering::Relaxed);
+            paced
+        };
+
+        let paced = match paced {
+            Some(paced) => paced,
+            None => continue,
+        };
+        state.not_full.notify_one();
+
+        if !wait_unless_stopped(pacer.reserve(paced.message.len()), &state.closed) {
+            return;
+        }
+
+        for (output_name, output) in paced.outputs.iter() {
+            if let Some(output) = output.upgrade() {
+                if let Err(string) = output.send(&paced.source, &paced.message, paced.received, &state.closed) {
+                    warn!(output = %output_name, error = %string, "could not write to output");
+                }
+            }
+        }
+        state.writing.store(false, Ordering::Relaxed);
+    }
+}
+
+/// A paced route, shared between the threads of its inputs
+pub type SharedPacedRoute = Arc<PacedRoute>;
+
+/// The counters of a route, shared with the threads of its inputs
+#[derive(Debug, Default)]
+pub struct RouteStats {
+    messages: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl RouteStats {
+    fn record(&self, num_bytes: usize) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(num_bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn counters(&self) -> RouteCounters {
+        return RouteCounters { messages: self.messages.load(Ordering::Relaxed),
+                               bytes: self.bytes.load(Ordering::Relaxed),
+        };
+    }
+}
+
+/// The counts of messages and bytes passed through a route
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteCounters {
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+/// The routes and outputs fed by an input
+#[derive(Debug, Default)]
+pub struct InputRoutes {
+    /// The counters of each active route from the input
+    pub routes: Vec<Arc<RouteStats>>,
+
+    /// The outputs of the active routes which are not paced, without duplicates
+    pub outputs: Vec<(String, SharedOutput)>,
+
+    /// The active paced routes, each with those of its outputs which no earlier route writes to
+    pub paced: Vec<(SharedPacedRoute, Vec<(String, SharedOutput)>)>,
+}
+
+impl InputRoutes {
+    fn same_as(&self, other: &InputRoutes) -> bool {
+        return self.routes.len() == other.routes.len() &&
+               self.paced.len() == other.paced.len() &&
+               self.routes.iter().zip(other.routes.iter()).all(|(route, other)| Arc::ptr_eq(route, other)) &&
+               same_outputs(&self.outputs, &other.outputs) &&
+               self.paced.iter().zip(other.paced.iter())
+                   .all(|((route, outputs), (other, other_outputs))| Arc::ptr_eq(route, other) && same_outputs(outputs, other_outputs));
+    }
+
+    /// The names of every output written to, directly or through a paced route
+    fn output_names(&self) -> Vec<&String> {
+        return self.outputs.iter()
+                           .chain(self.paced.iter().flat_map(|(_, outputs)| outputs.iter()))
+                           .map(|(name, _)| name)
+                           .collect();
+    }
+}
+
+fn same_outputs(outputs: &[(String, SharedOutput)], other: &[(String, SharedOutput)]) -> bool {
+    return outputs.len() == other.len() &&
+           outputs.iter().zip(other.iter()).all(|((name, output), (other_name, other))| name == other_name && Arc::ptr_eq(output, other));
+}
+
+/// The routes of an input. These can be replaced while the input is running.
+pub type RoutedOutputs = Arc<RwLock<InputRoutes>>;
+
+/// How long to wait for a stopped input's thread to finish before leaving it to finish on its own
+const INPUT_STOP_WAIT_MS: u64 = 1000;
+
+/// How long to wait before reopening a TCP input which has disconnected
+pub const INPUT_RECONNECT_DELAY_MS: u64 = 1000;
+
+/// An open output, along with the config it was opened from
+#[derive(Debug)]
+struct OutputEntry {
+    config: StreamConfig,
+    queue: Option<QueueSettings>,
+    stream: SharedOutput,
+    stats: Arc<StreamStats>,
+}
+
+/// The sequence monitor of an input, shared with the input's thread
+pub type SharedSequenceMonitor = Arc<Mutex<SequenceMonitor>>;
+
+/// A running input, along with the config it was opened from
+#[derive(Debug)]
+struct InputEntry {
+    config: StreamConfig,
+    stop: Arc<AtomicBool>,
+    outputs: RoutedOutputs,
+    stats: Arc<StreamStats>,
+    monitor: Option<SharedSequenceMonitor>,
+
+    /// The local address the input was bound to, for network inputs
+    local_addr: Option<String>,
+
+    /// Set when the input's thread ends with an error
+    failed: Arc<AtomicBool>,
+
+    handle: JoinHandle<Result<(), String>>,
+}
+
+impl InputEntry {
+    /// Whether the input's thread has ended with an error
+    fn failed(&self) -> bool {
+        return self.handle.is_finished() && self.failed.load(Ordering::Relaxed);
+    }
+
+    /// Ask the input to stop, waiting a short time for its thread to finish.
+    ///
+    /// NOTE an input blocked writing to an output without a queue only sees the request once
+    /// its write finishes, and is left to finish on its own if this takes too long.
+    fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        let deadline = Instant::now() + Duration::from_millis(INPUT_STOP_WAIT_MS);
+        while !self.handle.is_finished() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        if self.handle.is_finished() {
+            let _ = self.handle.join();
+        }
+    }
+}
+
+/// The changes made by reloading a router config
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct ReloadSummary {
+    /// The streams opened, given as "input 'name'" or "output 'name'"
+    pub opened: Vec<String>,
+
+    /// The streams closed
+    pub closed: Vec<String>,
+
+    /// The inputs which kept running but now write to a different set of outputs
+    pub rerouted: Vec<String>,
+
+    /// The streams which could not be opened. These are retried on the next reload.
+    pub errors: Vec<String>,
+}
+
+impl fmt::Display for ReloadSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "opened {:?}, closed {:?}, rerouted {:?}", self.opened, self.closed, self.rerouted)?;
+        if !self.errors.is_empty() {
+            write!(f, ", errors {:?}", self.errors)?;
+        }
+        Ok(())
+    }
+}
+
+/// A router runs a routing graph, with a thread for each input writing to its outputs.
+///
+/// A router can be given a new config while running. Only the streams whose configs changed
+/// are closed and reopened, and inputs whose routes changed keep running with their new outputs.
+///
+/// TCP inputs reconnect when their connection ends, with each reconnect counted in the input's
+/// stats, rather than finishing as file and other inputs do.
+#[derive(Debug, Default)]
+pub struct Router {
+    pub config: RouterConfig,
+    outputs: BTreeMap<String, OutputEntry>,
+    inputs: BTreeMap<String, InputEntry>,
+    routes: BTreeMap<String, Arc<RouteStats>>,
+    paced: BTreeMap<String, (PacingSettings, SharedPacedRoute)>,
+}
+
+impl Router {
+    /// Validate a config, open its outputs, and start a thread for each of its inputs.
+    ///
+    /// TCP servers are bound when they are opened, and accept their clients in the background.
+    pub fn open(config: RouterConfig) -> Result<Router, String> {
+        let mut router = Router::default();
+
+        let summary = router.reload(config)?;
+        if !summary.errors.is_empty() {
+            router.stop();
+            return Err(summary.errors.join(", "));
+        }
+
+        return Ok(router);
+    }
+
+    /// Move to a new config, leaving streams whose config has not changed open.
+    ///
+    /// An invalid config is rejected without changing anything. Streams which fail to open are
+    /// reported in the summary, and the rest of the config is still applied. Inputs which failed
+    /// to open, or which have since failed, are opened again on the next reload.
+    ///
+    /// Opening a stream does not wait for a TCP client to connect, so a reload does not hold up
+    /// the other users of a router while a TCP server waits for a client.
+    pub fn reload(&mut self, config: RouterConfig) -> Result<ReloadSummary, String> {
+        config.validate()?;
+
+        let mut summary = ReloadSummary::default();
+
+        // close removed, changed and failed streams first, so their replacements can bind the same resources
+        let stopped: Vec<String> = self.inputs.iter()
+                                              .filter(|(name, entry)| config.inputs.get(*name) != Some(&entry.config) ||
+                                                                      config.monitor_sequence.contains(*name) != entry.monitor.is_some() ||
+                                                                      entry.failed())
+                                              .map(|(name, _)| name.clone())
+                                              .collect();
+        for name in stopped {
+            if let Some(entry) = self.inputs.remove(&name) {
+                info!(input = %name, stream = %entry.config, "stopping input");
+                entry.stop();
+                summary.closed.push(format!("input '{}'", name));
+            }
+        }
+
+        let closed: Vec<String> = self.outputs.iter()
+                                              .filter(|(name, entry)| config.outputs.get(*name) != Some(&entry.config) ||
+                                                                      config.queues.get(*name) != entry.queue.as_ref())
+                                              .map(|(name, _)| name.clone())
+                                              .collect();
+        // running inputs hold their outputs, so detach closed outputs from them before the outputs are reopened
+        for entry in self.inputs.values() {
+            let mut outputs = entry.outputs.write().unwrap_or_else(|err| err.into_inner());
+            outputs.outputs.retain(|(name, _)| !closed.contains(name));
+            for (_, paced_outputs) in outputs.paced.iter_mut() {
+                paced_outputs.retain(|(name, _)| !closed.contains(name));
+            }
+        }
+        for name in closed {
+            if let Some(entry) = self.outputs.remove(&name) {
+                info!(output = %name, stream = %entry.config, "closed output");
+            }
+            summary.closed.push(format!("output '{}'", name));
+        }
+
+        for (name, stream_config) in config.outputs.iter() {
+            if self.outputs.contains_key(name) {
+                continue;
+            }
+
+            match stream_config.open_listening_output() {
+                Ok(stream) => {
+                    let queue = config.queues.get(name).cloned();
+                    let stats = stream.stats_handle();
+
+                    let writer = match &queue {
+                        Some(queue) => OutputWriter::Queued(OutputQueue::start(name, stream_config, stream, queue)),
+                        None => OutputWriter::Direct(Mutex::new(stream)),
+                    };
+
+                    let entry = OutputEntry { config: stream_config.clone(), queue, stats, stream: Arc::new(writer) };
+                    self.outputs.insert(name.clone(), entry);
+                    summary.opened.push(format!("output '{}'", name));
+                    info!(output = %name, stream = %stream_config, "opened output");
+                },
+
+                Err(err) => {
+                    error!(output = %name, stream = %stream_config, error = %err, "could not open output");
+                    summary.errors.push(format!("Could not open output '{}': {}", name, err));
+                },
+            }
+        }
+
+        for (name, route) in config.routes.iter() {
+            match self.config.routes.get(name) {
+                None => info!(route = %name, inputs = ?route.inputs, outputs = ?route.outputs, paused = route.paused, "added route"),
+                Some(old_route) if old_route != route => {
+                    info!(route = %name, inputs = ?route.inputs, outputs = ?route.outputs, paused = route.paused, "changed route")
+                },
+                Some(_) => {},
+            }
+        }
+        for name in self.config.routes.keys().filter(|name| !config.routes.contains_key(*name)) {
+            info!(route = %name, "removed route");
+        }
+
+        // route counters are kept for as long as their route exists
+        self.routes.retain(|name, _| config.routes.contains_key(name));
+        for name in config.routes.keys() {
+            self.routes.entry(name.clone()).or_default();
+        }
+
+        // paced routes are replaced when their pacing changes, and otherwise kept so a reload does not reset their pacers
+        self.paced.retain(|name, (pacing, _)| config.routes.get(name).and_then(|route| route.pacing.as_ref()) == Some(pacing));
+        for (name, route) in config.routes.iter() {
+            if let Some(pacing) = &route.pacing {
+                self.paced.entry(name.clone()).or_insert_with(|| (pacing.clone(), Arc::new(PacedRoute::start(name, pacing))));
+            }
+        }
+
+        // update the routes of running inputs, which includes attaching reopened outputs
+        for (name, entry) in self.inputs.iter() {
+            let routed = self.input_routes(&config, name);
+
+            let mut outputs = entry.outputs.write().unwrap_or_else(|err| err.into_inner());
+            if !outputs.same_as(&routed) {
+                info!(input = %name, outputs = ?routed.output_names(), "rerouted input");
+                *outputs = routed;
+                summary.rerouted.push(format!("input '{}'", name));
+            }
+        }
+
+        for (name, stream_config) in config.inputs.iter() {
+            if self.inputs.contains_key(name) {
+                continue;
+            }
+
+            let source = match InputSource::open(stream_config) {
+                Ok(source) => source,
+
+                Err(err) => {
+                    error!(input = %name, stream = %stream_config, error = %err, "could not open input");
+                    summary.errors.push(format!("Could not open input '{}': {}", name, err));
+                    continue;
+                },
+            };
+            info!(input = %name, stream = %stream_config, "opened input");
+
+            let local_addr = source.local_addr();
+            let outputs = Arc::new(RwLock::new(self.input_routes(&config, name)));
+            let stop = Arc::new(AtomicBool::new(false));
+            let stats = Arc::new(StreamStats::new());
+            let failed = Arc::new(AtomicBool::new(false));
+            let monitor = if config.monitor_sequence.contains(name) {
+                Some(Arc::new(Mutex::new(SequenceMonitor::with_logging())))
+            } else {
+                None
+            };
+
+            let input_name = name.clone();
+            let input_config = stream_config.clone();
+            let thread_outputs = Arc::clone(&outputs);
+            let thread_stop = Arc::clone(&stop);
+            let thread_stats = Arc::clone(&stats);
+            let thread_failed = Arc::clone(&failed);
+            let thread_monitor = monitor.clone();
+            let handle = thread::spawn(move || {
+                let span = info_span!("input", input = %input_name, stream = %input_config);
+                let _entered = span.enter();
+
+                let result = run_input(&input_name, source, &thread_outputs, &thread_stop, &thread_stats, thread_monitor.as_deref());
+                match &result {
+                    Ok(()) => info!("input closed"),
+
+                    Err(string) => {
+                        error!(error = %string, "input failed");
+                        thread_failed.store(true, Ordering::Relaxed);
+                    },
+                }
+                result
+            });
+
+            let entry = InputEntry { config: stream_config.clone(), stop, outputs, stats, monitor, local_addr, failed, handle };
+            self.inputs.insert(name.clone(), entry);
+            summary.opened.push(format!("input '{}'", name));
+        }
+
+        self.config = config;
+
+        return Ok(summary);
+    }
+
+    /// The active routes from an input in the given config, and their open outputs
+    ///
+    /// An output written by both an unpaced and a paced route gets each message once, without
+    /// waiting for the pacing.
+    fn input_routes(&self, config: &RouterConfig, input_name: &str) -> InputRoutes {
+        let mut routed = InputRoutes::default();
+
+        let active: Vec<(&String, &RouteSettings)> = config.routes_of(input_name).filter(|(_, route)| !route.paused).collect();
+
+        for (route_name, _) in active.iter() {
+            if let Some(stats) = self.routes.get(*route_name) {
+                routed.routes.push(Arc::clone(stats));
+            }
+        }
+
+        let mut written: Vec<&String> = Vec::new();
+
+        for (_, route) in active.iter().filter(|(_, route)| route.pacing.is_none()) {
+            for output_name in route.outputs.iter() {
+                if written.contains(&output_name) {
+                    continue;
+                }
+                written.push(output_name);
+
+                if let Some(entry) = self.outputs.get(output_name) {
+                    routed.outputs.push((output_name.clone(), Arc::clone(&entry.stream)));
+                }
+            }
+        }
+
+        for (route_name, route) in active.iter() {
+            let paced_route = match self.paced.get(*route_name) {
+                Some((_, paced_route)) => paced_route,
+                None => continue,
+            };
+
+            let mut outputs = Vec::new();
+            for output_name in route.outputs.iter() {
+                if written.contains(&output_name) {
+                    continue;
+                }
+                written.push(output_name);
+
+                if let Some(entry) = self.outputs.get(output_name) {
+                    outputs.push((output_name.clone(), Arc::clone(&entry.stream)));
+                }
+            }
+
+            routed.paced.push((Arc::clone(paced_route), outputs));
+        }
+
+        return routed;
+    }
+
+    /// The counters of each route
+    pub fn route_counters(&self) -> BTreeMap<String, RouteCounters> {
+        return self.routes.iter().map(|(name, stats)| (name.clone(), stats.counters())).collect();
+    }
+
+    /// The counters of each input, which are kept across reconnects
+    pub fn input_counters(&self) -> BTreeMap<String, StreamCounters> {
+        return self.inputs.iter().map(|(name, entry)| (name.clone(), entry.stats.counters())).collect();
+    }
+
+    /// The sequence counters of each input with a sequence monitor
+    pub fn sequence_counters(&self) -> BTreeMap<String, SequenceCounters> {
+        return self.inputs.iter()
+                          .filter_map(|(name, entry)| entry.monitor.as_ref().map(|monitor| (name, monitor)))
+                          .map(|(name, monitor)| (name.clone(), monitor.lock().unwrap_or_else(|err| err.into_inner()).counters()))
+                          .collect();
+    }
+
+    /// The local address of each network input, which gives the port chosen for an input bound to port 0
+    pub fn input_addresses(&self) -> BTreeMap<String, String> {
+        return self.inputs.iter()
+                          .filter_map(|(name, entry)| entry.local_addr.as_ref().map(|addr| (name.clone(), addr.clone())))
+                          .collect();
+    }
+
+    /// The counters of each output
+    pub fn output_counters(&self) -> BTreeMap<String, StreamCounters> {
+        return self.outputs.iter().map(|(name, entry)| (name.clone(), entry.stats.counters())).collect();
+    }
+
+    /// Whether every input has finished.
+    ///
+    /// NOTE TCP inputs reconnect instead of finishing, so this is never true for a router with
+    /// a TCP input until the router is stopped.
+    pub fn is_finished(&self) -> bool {
+        return self.inputs.values().all(|entry| entry.handle.is_finished());
+    }
+
+    /// Stop every input and paced route, and close every output
+    pub fn stop(&mut self) {
+        for (_, entry) in std::mem::take(&mut self.inputs) {
+            entry.stop();
+        }
+        self.paced.clear();
+        self.outputs.clear();
+    }
+
+    /// Wait for every input to finish and for paced routes and queued outputs to be written, returning the first error
+    pub fn wait(mut self) -> Result<(), String> {
+        let mut result = Ok(());
+
+        for (name, entry) in std::mem::take(&mut self.inputs) {
+            let input_result = match entry.handle.join() {
+                Ok(input_result) => input_result,
+                Err(_) => Err("thread panicked".to_string()),
+            };
+
+            if let Err(string) = input_result {
+                if result.is_ok() {
+                    result = Err(format!("Input '{}': {}", name, string));
+                }
+            }
+        }
+
+        for (_, paced_route) in self.paced.values() {
+            paced_route.flush();
+        }
+
+        let deadline = Instant::now() + Duration::from_millis(QUEUE_FLUSH_TIMEOUT_MS);
+        for (name, entry) in self.outputs.iter() {
+            let unwritten = entry.stream.flush(deadline);
+            if unwritten > 0 {
+                warn!(output = %name, unwritten, "gave up waiting for queued messages to be written");
+            }
+        }
+
+        return result;
+    }
+}
+
+/// An input opened by a reload, before its thread starts reading from it
+enum InputSource {
+    /// An open stream. Its config is used to open the stream again after a TCP client disconnects.
+    Stream(Box<FramedReadStream>, StreamConfig),
+
+    /// A bound TCP server, which accepts its clients in the input's thread
+    Listener(TcpListener, StreamSettings),
+}
+
+impl InputSource {
+    fn open(stream_config: &StreamConfig) -> Result<InputSource, String> {
+        match stream_config.settings()? {
+            (StreamOption::TcpServer, settings) => {
+                let listener = settings.tcp_server.bind()?;
+                return Ok(InputSource::Listener(listener, settings));
+            },
+
+            _ => {
+                let input = stream_config.open_input()?;
+                return Ok(InputSource::Stream(Box::new(input), stream_config.clone()));
+            },
+        }
+    }
+
+    /// The local address of a network input
+    fn local_addr(&self) -> Option<String> {
+        match self {
+            InputSource::Stream(stream, _) => return stream.stream.local_addr(),
+            InputSource::Listener(listener, _) => return listener.local_addr().ok().map(|addr| addr.to_string()),
+        }
+    }
+
+    /// Wait for a TCP server's next client, returning None if the input is stopped first
+    fn accept(listener: &TcpListener, settings: &StreamSettings, stop: &AtomicBool) -> Result<Option<FramedReadStream>, String> {
+        let client = match accept_unless_stopped(listener, stop)? {
+            Some(client) => set_read_timeout(client)?,
+            None => return Ok(None),
+        };
+
+        let stream = ReadStream::Tcp(client);
+        return Ok(Some(FramedReadStream::from_settings(stream, &settings.framing, settings.open_transforms()?)));
+    }
+}
+
+/// Read messages from an input until it finishes or is stopped, writing each to the routed outputs.
+///
+/// TCP inputs are reopened after they disconnect or fail, so a router keeps accepting clients
+/// or reconnecting to servers until it is stopped. Other inputs end their thread when they finish.
+fn run_input(name: &str,
+             source: InputSource,
+             outputs: &RoutedOutputs,
+             stop: &AtomicBool,
+             stats: &Arc<StreamStats>,
+             monitor: Option<&Mutex<SequenceMonitor>>) -> Result<(), String> {
+    match source {
+        InputSource::Listener(listener, settings) => {
+            let mut accepted = 0;
+
+            // the listener stays bound between clients, and is closed when the input stops
+            loop {
+                let mut input = match InputSource::accept(&listener, &settings, stop)? {
+                    Some(input) => input.with_stats(Arc::clone(stats)),
+                    None => return Ok(()),
+                };
+
+                if accepted > 0 {
+                    stats.record_reconnect();
+                }
+                accepted += 1;
+
+                match route_input(name, &mut input, outputs, stop, monitor) {
+                    _ if stop.load(Ordering::Relaxed) => return Ok(()),
+                    Ok(()) => info!("client disconnected, waiting for a client"),
+                    Err(string) => warn!(error = %string, "client failed, waiting for a client"),
+                }
+            }
+        },
+
+        InputSource::Stream(input, stream_config) => {
+            let reconnects = matches!(stream_config.settings(), Ok((StreamOption::TcpClient, _)));
+
+            let mut input = (*input).with_stats(Arc::clone(stats));
+
+            loop {
+                let result = route_input(name, &mut input, outputs, stop, monitor);
+
+                if !reconnects || stop.load(Ordering::Relaxed) {
+                    return result;
+                }
+
+                match result {
+                    Ok(()) => info!("input disconnected, reconnecting"),
+                    Err(string) => warn!(error = %string, "input failed, reconnecting"),
+                }
+
+                // keep trying until the input opens again or the router stops it
+                loop {
+                    if !wait_unless_stopped(Duration::from_millis(INPUT_RECONNECT_DELAY_MS), stop) {
+                        return Ok(());
+                    }
+
+                    match stream_config.open_input() {
+                        Ok(reopened) => {
+                            input = reopened.with_stats(Arc::clone(stats));
+                            stats.record_reconnect();
+                            info!(reconnects = stats.counters().reconnects, "reconnected input");
+                            break;
+                        },
+
+                        Err(err) => {
+                            stats.record_error();
+                            warn!(error = %err, "could not reopen input");
+                        },
+                    }
+                }
+            }
+        },
+    }
+}
+
+/// Wait for the given time, returning early with false if the input is stopped
+fn wait_unless_stopped(wait: Duration, stop: &AtomicBool) -> bool {
+    let deadline = Instant::now() + wait;
+
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return true;
+        }
+
+        thread::sleep(remaining.min(Duration::from_millis(SOCKET_READ_TIMEOUT_MS)));
+    }
+}
+
+/// Route messages from an open input until it finishes, fails, or is stopped.
+///
+/// A failed write is reported and routing continues, so one broken output does not stop the others.
+/// Messages for paced routes are handed to the routes' own threads, so they do not wait for the pacing.
+///
+/// Each message is sent with the time decoded from it when the input's framing reads a time code,
+/// such as CCSDS packets with a time in their secondary header, and otherwise with the time it was read.
+fn route_input(name: &str,
+               input: &mut FramedReadStream,
+               outputs: &RoutedOutputs,
+               stop: &AtomicBool,
+               monitor: Option<&Mutex<SequenceMonitor>>) -> Result<(), String> {
+    while !stop.load(Ordering::Relaxed) {
+        match input.read_message() {
+            FrameReadResult::Message(message) => {
+                let received = input.message_time().map(SystemTime::from).unwrap_or_else(SystemTime::now);
+
+                if let Some(monitor) = monitor {
+                    let mut monitor = monitor.lock().unwrap_or_else(|err| err.into_inner());
+                    if let Err(string) = monitor.check_packet(&message) {
+                        warn!(error = %string, "could not check sequence count");
+                    }
+                }
+
+                let routed = outputs.read().unwrap_or_else(|err| err.into_inner());
+
+                for route in routed.routes.iter() {
+                    route.record(message.len());
+                }
+
+                for (output_name, output) in routed.outputs.iter() {
+                    if let Err(string) = output.send(name, &message, received, stop) {
+                        warn!(output = %output_name, error = %string, "could not write to output");
+                    }
+                }
+
+                for (paced_route, route_outputs) in routed.paced.iter() {
+                    paced_route.send(name, &message, received, route_outputs, stop);
+                }
+            },
+
+            FrameReadResult::NoMessage => {},
+
+            FrameReadResult::FramingError(string) => {
+                warn!(error = %string, "framing error");
+            },
+
+            FrameReadResult::Finished => {
+                return Ok(());
+            },
+
+            FrameReadResult::Error(string) => {
+                return Err(string);
+            },
+        }
+    }
+
+    return Ok(());
+}
@@ -0,0 +1,181 @@
+use std::collections::BTreeMap;
+
+use crate::*;
+use crate::framing::*;
+use crate::time_code::*;
+
+
+/// The action to take with a packet or frame that matches a rule
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RouteAction {
+    /// Write to each of the named outputs
+    Route(Vec<String>),
+    /// Discard
+    Drop,
+}
+
+impl Default for RouteAction {
+    fn default() -> RouteAction {
+        return RouteAction::Drop;
+    }
+}
+
+/// A route rule matches some kind of message, such as a packet or a frame header,
+/// and gives the action for the messages it matches
+pub trait RouteRule {
+    /// What the rule looks at to decide whether a message matches
+    type Subject;
+
+    fn matches(&self, subject: &Self::Subject) -> bool;
+
+    fn action(&self) -> &RouteAction;
+}
+
+/// Check that each output named by a set of actions exists
+pub(crate) fn check_output_names<'a, I, F>(actions: I, has_output: F) -> Result<(), String>
+    where I: Iterator<Item=&'a RouteAction>,
+          F: Fn(&str) -> bool {
+    for action in actions {
+        if let RouteAction::Route(names) = action {
+            if let Some(name) = names.iter().find(|name| !has_output(name)) {
+                return Err(format!("Route refers to an unknown output '{}'", name));
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+/// A rule router writes each message to the outputs selected by its rules.
+///
+/// Rules are checked in order, and the first matching rule decides what happens to a message.
+/// Messages that match no rule use the default action.
+#[derive(Debug)]
+pub struct RuleRouter<R> {
+    pub rules: Vec<R>,
+    pub default: RouteAction,
+    pub outputs: BTreeMap<String, FramedWriteStream>,
+
+    /// The number of messages written to at least one output
+    pub routed: u64,
+
+    /// The number of messages dropped, either by a drop action, because they could not be read,
+    /// or because every write to their outputs failed
+    pub dropped: u64,
+}
+
+impl<R: RouteRule> RuleRouter<R> {
+    pub fn new(rules: Vec<R>,
+               default: RouteAction,
+               outputs: BTreeMap<String, FramedWriteStream>) -> Result<RuleRouter<R>, String> {
+        let actions = rules.iter().map(|rule| rule.action()).chain(std::iter::once(&default));
+        check_output_names(actions, |name| outputs.contains_key(name))?;
+
+        return Ok(RuleRouter { rules, default, outputs, routed: 0, dropped: 0 });
+    }
+
+    /// Open each output and create a router
+    pub fn open(rules: Vec<R>,
+                default: RouteAction,
+                output_configs: &BTreeMap<String, StreamConfig>) -> Result<RuleRouter<R>, String> {
+        let actions = rules.iter().map(|rule| rule.action()).chain(std::iter::once(&default));
+        check_output_names(actions, |name| output_configs.contains_key(name))?;
+
+        let mut outputs = BTreeMap::new();
+        for (name, stream_config) in output_configs.iter() {
+            let stream = stream_config.open_output()
+                                      .map_err(|err| format!("Could not open output '{}': {}", name, err))?;
+            outputs.insert(name.clone(), stream);
+        }
+
+        return RuleRouter::new(rules, default, outputs);
+    }
+
+    /// The action for a message
+    pub fn action(&self, subject: &R::Subject) -> &RouteAction {
+        return self.rules.iter()
+                         .find(|rule| rule.matches(subject))
+                         .map_or(&self.default, |rule| rule.action());
+    }
+
+    /// Write a message to the outputs selected for it, returning the number of outputs it was written to.
+    ///
+    /// An output which can not be written to is logged and skipped, so one failed output
+    /// does not stop the message reaching the others.
+    pub fn route(&mut self, subject: &R::Subject, message: &[u8]) -> usize {
+        let names = match self.action(subject) {
+            RouteAction::Route(names) => names.clone(),
+            RouteAction::Drop => Vec::new(),
+        };
+
+        if names.is_empty() {
+            self.dropped += 1;
+            return 0;
+        }
+
+        let mut num_written = 0;
+        for name in names.iter() {
+            if let Some(output) = self.outputs.get_mut(name) {
+                match output.write_message(message) {
+                    Ok(_) => num_written += 1,
+                    Err(string) => warn!(output = %name, error = %string, "could not write to output"),
+                }
+            }
+        }
+
+        if num_written > 0 {
+            self.routed += 1;
+        } else {
+            self.dropped += 1;
+        }
+
+        return num_written;
+    }
+}
+
+/// A message router routes each message read from an input
+pub trait MessageRouter {
+    /// Route a single message, returning the number of outputs it was written to
+    fn route_message(&mut self, message: &[u8]) -> Result<usize, String>;
+
+    /// Route a single message along with the time its framer decoded, if any.
+    /// Routers which do not match on times route the message alone.
+    fn route_timed_message(&mut self, message: &[u8], _time: Option<CcsdsTime>) -> Result<usize, String> {
+        return self.route_message(message);
+    }
+
+    /// Count a message which was dropped before it could be routed
+    fn record_drop(&mut self);
+
+    /// Route messages from an input until it finishes.
+    ///
+    /// Framing errors and messages which can not be routed are dropped and routing
+    /// continues, while stream errors end routing.
+    fn route_stream(&mut self, input: &mut FramedReadStream) -> Result<(), String> {
+        loop {
+            match input.read_message() {
+                FrameReadResult::Message(message) => {
+                    let time = input.message_time();
+                    if let Err(string) = self.route_timed_message(&message, time) {
+                        warn!(error = %string, "could not route message");
+                        self.record_drop();
+                    }
+                },
+
+                FrameReadResult::NoMessage => {},
+
+                FrameReadResult::FramingError(_) => {
+                    self.record_drop();
+                },
+
+                FrameReadResult::Finished => {
+                    return Ok(());
+                },
+
+                FrameReadResult::Error(string) => {
+                    return Err(string);
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::ccsds::*;
+
+
+/// A sequence anomaly is a break in the sequence counts of an APID
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceAnomaly {
+    /// One or more packets are missing before the received packet
+    Gap { expected: u16, received: u16, missing: u16 },
+    /// The received packet has the same count as the previous packet
+    Duplicate { count: u16 },
+    /// The received packet is older than the previous packet
+    OutOfOrder { expected: u16, received: u16 },
+}
+
+/// A sequence event reports an anomaly for a particular APID
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SequenceEvent {
+    pub apid: u16,
+    pub anomaly: SequenceAnomaly,
+}
+
+impl fmt::Display for SequenceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.anomaly {
+            SequenceAnomaly::Gap { expected, received, missing } => {
+                write!(f, "apid {}: sequence gap of {} packets (expected {}, received {})",
+                       self.apid, missing, expected, received)
+            },
+
+            SequenceAnomaly::Duplicate { count } => {
+                write!(f, "apid {}: duplicate sequence count {}", self.apid, count)
+            },
+
+            SequenceAnomaly::OutOfOrder { expected, received } => {
+                write!(f, "apid {}: out of order sequence count (expected {}, received {})",
+                       self.apid, expected, received)
+            },
+        }
+    }
+}
+
+/// Sequence counters, kept for all APIDs together and for each APID
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SequenceCounters {
+    pub packets: u64,
+    pub gaps: u64,
+    pub missing_packets: u64,
+    pub duplicates: u64,
+    pub out_of_order: u64,
+}
+
+impl SequenceCounters {
+    fn record(&mut self, anomaly: &Option<SequenceAnomaly>) {
+        self.packets += 1;
+
+        match anomaly {
+            Some(SequenceAnomaly::Gap { missing, .. }) => {
+                self.gaps += 1;
+                self.missing_packets += *missing as u64;
+            },
+
+            Some(SequenceAnomaly::Duplicate { .. }) => {
+                self.duplicates += 1;
+            },
+
+            Some(SequenceAnomaly::OutOfOrder { .. }) => {
+                self.out_of_order += 1;
+            },
+
+            None => {},
+        }
+    }
+}
+
+/// The callback type for sequence events
+pub type SequenceCallback = Box<dyn FnMut(&SequenceEvent) + Send>;
+
+/// The sequence monitor tracks the sequence count of each APID and reports gaps,
+/// duplicates, and out of order packets.
+///
+/// Sequence counts wrap at 2^14. A count that is ahead of the expected count by
+/// less than half of this range is treated as a gap, and a count that is behind it
+/// is treated as out of order. Idle packets are not checked.
+#[derive(Default)]
+pub struct SequenceMonitor {
+    last_counts: BTreeMap<u16, u16>,
+    counters: SequenceCounters,
+    apid_counters: BTreeMap<u16, SequenceCounters>,
+    callback: Option<SequenceCallback>,
+}
+
+impl fmt::Debug for SequenceMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SequenceMonitor")
+         .field("last_counts", &self.last_counts)
+         .field("counters", &self.counters)
+         .field("apid_counters", &self.apid_counters)
+         .finish()
+    }
+}
+
+impl SequenceMonitor {
+    pub fn new() -> SequenceMonitor {
+        return SequenceMonitor::default();
+    }
+
+    /// Create a sequence monitor which calls the given function for each anomaly
+    pub fn with_callback(callback: SequenceCallback) -> SequenceMonitor {
+        let mut monitor = SequenceMonitor::new();
+        monitor.callback = Some(callback);
+        return monitor;
+    }
+
+    /// Create a sequence monitor which logs a warning for each anomaly
+    pub fn with_logging() -> SequenceMonitor {
+        return SequenceMonitor::with_callback(Box::new(|event| warn!(apid = event.apid, "{}", event)));
+    }
+
+    /// Check the sequence count of a packet's header, returning the anomaly found, if any.
+    pub fn check(&mut self, header: &CcsdsPrimaryHeader) -> Option<SequenceEvent> {
+        if header.is_idle() {
+            return None;
+        }
+
+        let apid = header.apid;
+        let received = header.sequence_count;
+
+        let mut anomaly = None;
+        let mut newest = received;
+
+        if let Some(last) = self.last_counts.get(&apid) {
+            let expected = (last + 1) % CCSDS_SEQUENCE_COUNT_MODULUS;
+            let distance = received.wrapping_sub(*last) % CCSDS_SEQUENCE_COUNT_MODULUS;
+
+            if distance == 0 {
+                anomaly = Some(SequenceAnomaly::Duplicate { count: received });
+            } else if distance >= CCSDS_SEQUENCE_COUNT_MODULUS / 2 {
+                // the packet is older than the last packet, so the last count is kept
+                newest = *last;
+                anomaly = Some(SequenceAnomaly::OutOfOrder { expected, received });
+            } else if distance > 1 {
+                anomaly = Some(SequenceAnomaly::Gap { expected, received, missing: distance - 1 });
+            }
+        }
+
+        self.last_counts.insert(apid, newest);
+        self.counters.record(&anomaly);
+        self.apid_counters.entry(apid).or_default().record(&anomaly);
+
+        let event = anomaly.map(|anomaly| SequenceEvent { apid, anomaly });
+
+        if let (Some(event), Some(callback)) = (event.as_ref(), self.callback.as_mut()) {
+            callback(event);
+        }
+
+        return event;
+    }
+
+    /// Check the sequence count of a packet
+    pub fn check_packet(&mut self, packet: &[u8]) -> Result<Option<SequenceEvent>, String> {
+        let header = CcsdsPrimaryHeader::decode(packet)?;
+        return Ok(self.check(&header));
+    }
+
+    /// The counters for all APIDs
+    pub fn counters(&self) -> SequenceCounters {
+        return self.counters;
+    }
+
+    /// The counters for a single APID
+    pub fn apid_counters(&self, apid: u16) -> Option<SequenceCounters> {
+        return self.apid_counters.get(&apid).copied();
+    }
+
+    /// Forget the last sequence count for an APID, so its next packet starts a new sequence
+    pub fn reset_apid(&mut self, apid: u16) {
+        self.last_counts.remove(&apid);
+    }
+}
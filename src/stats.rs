@@ -0,0 +1,180 @@
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::time_code::*;
+
+
+/// The counters of a stream, updated by the stream as it is used and readable from other threads.
+///
+/// For a read stream, operations are reads, while for a write stream they are writes.
+#[derive(Debug, Default)]
+pub struct StreamStats {
+    bytes: AtomicU64,
+    messages: AtomicU64,
+    operations: AtomicU64,
+    errors: AtomicU64,
+    reconnects: AtomicU64,
+    dropped: AtomicU64,
+    corrected: AtomicU64,
+    uncorrectable: AtomicU64,
+
+    /// The time of the last read or write, in nanoseconds since the Unix epoch, or 0 if there has been none
+    last_activity: AtomicU64,
+
+    /// The address at the other end of a connected stream
+    peer: Mutex<Option<String>>,
+}
+
+impl StreamStats {
+    pub fn new() -> StreamStats {
+        return StreamStats::default();
+    }
+
+    /// Record a read or write of some number of bytes
+    pub fn record_operation(&self, num_bytes: usize) {
+        self.operations.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(num_bytes as u64, Ordering::Relaxed);
+
+        if num_bytes > 0 {
+            self.touch();
+        }
+    }
+
+    /// Record a whole message read or written
+    pub fn record_message(&self) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+        self.touch();
+    }
+
+    pub fn record_error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_reconnect(&self) {
+        self.reconnects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record messages dropped by a full or disconnected output queue
+    pub fn record_drop(&self, num_messages: u64) {
+        self.dropped.fetch_add(num_messages, Ordering::Relaxed);
+    }
+
+    /// Record codewords corrected by a decoder, and codewords with too many errors to correct
+    pub fn record_corrections(&self, corrected: u64, uncorrectable: u64) {
+        self.corrected.fetch_add(corrected, Ordering::Relaxed);
+        self.uncorrectable.fetch_add(uncorrectable, Ordering::Relaxed);
+    }
+
+    /// Record the address at the other end of the stream, or None when it is not connected
+    pub fn set_peer(&self, peer: Option<String>) {
+        *self.peer.lock().unwrap_or_else(|err| err.into_inner()) = peer;
+    }
+
+    fn touch(&self) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        self.last_activity.store(now.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// A snapshot of the counters
+    pub fn counters(&self) -> StreamCounters {
+        let last_activity = match self.last_activity.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(UNIX_EPOCH + Duration::from_nanos(nanos)),
+        };
+
+        return StreamCounters { bytes: self.bytes.load(Ordering::Relaxed),
+                                messages: self.messages.load(Ordering::Relaxed),
+                                operations: self.operations.load(Ordering::Relaxed),
+                                errors: self.errors.load(Ordering::Relaxed),
+                                reconnects: self.reconnects.load(Ordering::Relaxed),
+                                dropped: self.dropped.load(Ordering::Relaxed),
+                                corrected: self.corrected.load(Ordering::Relaxed),
+                                uncorrectable: self.uncorrectable.load(Ordering::Relaxed),
+                                last_activity,
+                                peer: self.peer.lock().unwrap_or_else(|err| err.into_inner()).clone(),
+        };
+    }
+}
+
+/// A snapshot of a stream's counters
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamCounters {
+    pub bytes: u64,
+    pub messages: u64,
+    /// The number of reads or writes
+    pub operations: u64,
+    pub errors: u64,
+    pub reconnects: u64,
+
+    /// The number of messages dropped by an output's queue
+    #[serde(default)]
+    pub dropped: u64,
+
+    /// The number of codewords corrected by a decoding transform, such as Reed-Solomon decoding
+    #[serde(default)]
+    pub corrected: u64,
+
+    /// The number of codewords a decoding transform could not correct
+    #[serde(default)]
+    pub uncorrectable: u64,
+
+    pub last_activity: Option<SystemTime>,
+
+    /// The address at the other end of a connected stream, such as a TCP server's client
+    #[serde(default)]
+    pub peer: Option<String>,
+}
+
+impl StreamCounters {
+    /// The rates of bytes and messages between an earlier snapshot and this one
+    pub fn rates_since(&self, earlier: &StreamCounters, elapsed: Duration) -> StreamRates {
+        let seconds = elapsed.as_secs_f64();
+        if seconds <= 0.0 {
+            return StreamRates::default();
+        }
+
+        return StreamRates { bytes_per_second: self.bytes.saturating_sub(earlier.bytes) as f64 / seconds,
+                             messages_per_second: self.messages.saturating_sub(earlier.messages) as f64 / seconds,
+        };
+    }
+}
+
+impl fmt::Display for StreamCounters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bytes={} messages={} operations={} errors={} reconnects={} dropped={} corrected={} uncorrectable={}",
+               self.bytes,
+               self.messages,
+               self.operations,
+               self.errors,
+               self.reconnects,
+               self.dropped,
+               self.corrected,
+               self.uncorrectable)?;
+
+        match self.last_activity {
+            Some(time) => write!(f, " last_activity={}", CcsdsTime::from(time))?,
+            None => write!(f, " last_activity=never")?,
+        }
+
+        if let Some(peer) = &self.peer {
+            write!(f, " peer={}", peer)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The throughput of a stream over some period
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct StreamRates {
+    pub bytes_per_second: f64,
+    pub messages_per_second: f64,
+}
+
+impl fmt::Display for StreamRates {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.1} B/s {:.1} msg/s", self.bytes_per_second, self.messages_per_second)
+    }
+}
@@ -1,19 +1,23 @@
 use std::fs::File;
-use std::io::{Read, BufReader};
+use std::io::{Read, BufReader, ErrorKind};
 use std::net::{TcpStream, UdpSocket};
 use std::borrow::BorrowMut;
 
 use bytes::BytesMut;
 
+use serialport::SerialPort;
+
 
 // TODO this API does not make blocking vs non-block calls apparent
 // ideally there would be a timeout provided, which could be 0 (non-blocking)
 // a timeout, or infinite (block until data is available). This would cover the
 // case of files which are being written as well as read.
 // TODO this error return of String should be replaced with a error handling strategy
-// TODO this should include serial reading
 // TODO this might include stdin reading
 
+/// The largest payload of a UDP datagram
+pub const UDP_MAX_DATAGRAM_SIZE: usize = 65507;
+
 pub enum StreamReadResult {
     BytesRead(usize),
     Finished,
@@ -26,7 +30,44 @@ pub trait StreamRead {
 
 impl StreamRead for TcpStream {
     fn read_bytes(&mut self, bytes: &mut BytesMut, num_bytes: usize) -> StreamReadResult {
-        read_bytes_from_reader(self, bytes, num_bytes)
+        match read_into(self, bytes, num_bytes) {
+            // a read of 0 bytes from a TCP socket means the other end has closed the connection
+            Ok(0) => {
+                return StreamReadResult::Finished;
+            },
+
+            Ok(bytes_read) => {
+                return StreamReadResult::BytesRead(bytes_read);
+            },
+
+            // a timeout just means no data arrived
+            Err(ref err) if is_timeout(err) => {
+                return StreamReadResult::BytesRead(0);
+            },
+
+            Err(err) => {
+                return StreamReadResult::Error(format!("Stream Read Error: {}", err));
+            },
+        }
+    }
+}
+
+impl StreamRead for Box<dyn SerialPort> {
+    fn read_bytes(&mut self, bytes: &mut BytesMut, num_bytes: usize) -> StreamReadResult {
+        match read_into(self, bytes, num_bytes) {
+            Ok(bytes_read) => {
+                return StreamReadResult::BytesRead(bytes_read);
+            },
+
+            // a timeout on a serial port just means no data arrived, not that the port is closed
+            Err(ref err) if err.kind() == ErrorKind::TimedOut => {
+                return StreamReadResult::BytesRead(0);
+            },
+
+            Err(err) => {
+                return StreamReadResult::Error(format!("Serial Port Read Error: {}", err));
+            },
+        }
     }
 }
 
@@ -45,23 +86,53 @@ impl StreamRead for BufReader<File> {
 }
 
 impl StreamRead for UdpSocket {
-    fn read_bytes(&mut self, bytes: &mut BytesMut, _num_bytes: usize) -> StreamReadResult {
-        // for UDP we just read a message
-        bytes.clear();
-        match self.recv(bytes).map_err(|err| format!("Udp Socket Read Error: {}", err)) {
+    fn read_bytes(&mut self, bytes: &mut BytesMut, num_bytes: usize) -> StreamReadResult {
+        // for UDP we just read a message. The read must have room for a whole datagram,
+        // as any part of a datagram that does not fit is discarded.
+        let num_bytes = num_bytes.max(UDP_MAX_DATAGRAM_SIZE);
+        let old_len = bytes.len();
+
+        bytes.reserve(num_bytes);
+        bytes.resize(old_len + num_bytes, 0);
+
+        match self.recv(&mut bytes[old_len..]) {
             Ok(bytes_read) => {
+                bytes.truncate(old_len + bytes_read);
                 return StreamReadResult::BytesRead(bytes_read);
             },
 
-            Err(string) => {
-                return StreamReadResult::Error(string);
+            Err(ref err) if is_timeout(err) => {
+                bytes.truncate(old_len);
+                return StreamReadResult::BytesRead(0);
+            },
+
+            Err(err) => {
+                bytes.truncate(old_len);
+                return StreamReadResult::Error(format!("Udp Socket Read Error: {}", err));
             }
         }
     }
 }
 
+/// Whether a read error is a timeout, which sockets report as either WouldBlock or TimedOut
+fn is_timeout(err: &std::io::Error) -> bool {
+    return err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut;
+}
+
 
 fn read_bytes_from_reader<R: Read>(reader: &mut R, bytes: &mut BytesMut, num_bytes: usize) -> StreamReadResult {
+    match read_into(reader, bytes, num_bytes).map_err(|err| format!("Stream Read Error: {}", err)) {
+        Ok(bytes_read) => {
+            return StreamReadResult::BytesRead(bytes_read);
+        },
+
+        Err(string) => {
+            return StreamReadResult::Error(string);
+        }
+    }
+}
+
+fn read_into<R: Read>(reader: &mut R, bytes: &mut BytesMut, num_bytes: usize) -> std::io::Result<usize> {
     let old_len = bytes.len();
     let new_len = old_len + num_bytes;
 
@@ -76,19 +147,19 @@ fn read_bytes_from_reader<R: Read>(reader: &mut R, bytes: &mut BytesMut, num_byt
     let mut_bytes: &mut [u8] = bytes.borrow_mut();
 
     // read up to num_bytes bytes from the reader
-    let result = reader.read(&mut mut_bytes[old_len..(old_len + num_bytes)])
-                       .map_err(|err| format!("Stream Read Error: {}", err));
+    let result = reader.read(&mut mut_bytes[old_len..(old_len + num_bytes)]);
 
     match result {
         Ok(bytes_read) => {
             // if byte were read, set the BytesMut length to reflect the new data available
             bytes.truncate(old_len + bytes_read);
-            return StreamReadResult::BytesRead(bytes_read);
         },
 
-        Err(string) => {
-            return StreamReadResult::Error(string);
+        Err(_) => {
+            // nothing was read, so remove the space reserved for the read
+            bytes.truncate(old_len);
         }
     }
-}
 
+    return result;
+}
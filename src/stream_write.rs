@@ -1,9 +1,10 @@
 use std::fs::File;
 use std::io::prelude::*;
-use std::net::{TcpStream, UdpSocket, SocketAddrV4};
+use std::net::{TcpListener, TcpStream, UdpSocket, SocketAddrV4};
+
+use serialport::SerialPort;
 
 
-// TODO this should include serial writing
 // TODO this might include stdin writing
 pub trait StreamWrite {
     fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, String>;
@@ -11,7 +12,7 @@ pub trait StreamWrite {
 
 impl StreamWrite for File {
     fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, String> {
-        self.write_all(&bytes)
+        self.write_all(bytes)
             .map_err(|err| format!("IO error {}", err))
             .map(|_| bytes.len())
     }
@@ -20,16 +21,81 @@ impl StreamWrite for File {
 // TODO make this a Udp stream type instead of a tuple
 impl StreamWrite for (UdpSocket, SocketAddrV4) {
     fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, String> {
-        self.0.send_to(&bytes, &self.1)
+        self.0.send_to(bytes, self.1)
                 .map_err(|err| format!("IO error {}", err))
     }
 }
 
 impl StreamWrite for TcpStream {
     fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, String> {
-        self.write_all(&bytes)
+        self.write_all(bytes)
             .map_err(|err| format!("IO error {}", err))
             .map(|_| bytes.len())
     }
 }
 
+/// A TCP server write stream which accepts clients without waiting for them.
+///
+/// Writes are dropped while no client is connected, and a client whose write fails is
+/// closed so that the next client can connect.
+#[derive(Debug)]
+pub struct TcpServerWriter {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+}
+
+impl TcpServerWriter {
+    pub fn new(listener: TcpListener) -> Result<TcpServerWriter, String> {
+        listener.set_nonblocking(true)
+                .map_err(|err| format!("Could not set TCP listener non-blocking: {}", err))?;
+
+        return Ok(TcpServerWriter { listener, client: None });
+    }
+
+    /// The connected client, accepting a waiting client if none is connected
+    fn client(&mut self) -> Option<&mut TcpStream> {
+        if self.client.is_none() {
+            if let Ok((client, peer)) = self.listener.accept() {
+                if client.set_nonblocking(false).is_ok() {
+                    info!(peer = %peer, "accepted client");
+                    self.client = Some(client);
+                }
+            }
+        }
+
+        return self.client.as_mut();
+    }
+
+    /// The address of the connected client
+    pub fn peer(&self) -> Option<String> {
+        return self.client.as_ref().and_then(|client| client.peer_addr().ok()).map(|addr| addr.to_string());
+    }
+
+    /// A second handle to the connected client
+    pub fn connection(&self) -> Option<TcpStream> {
+        return self.client.as_ref().and_then(|client| client.try_clone().ok());
+    }
+}
+
+impl StreamWrite for TcpServerWriter {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, String> {
+        let result = match self.client() {
+            Some(client) => client.write_bytes(bytes),
+            None => return Ok(0),
+        };
+
+        if result.is_err() {
+            self.client = None;
+        }
+
+        return result;
+    }
+}
+
+impl StreamWrite for Box<dyn SerialPort> {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, String> {
+        self.write_all(bytes)
+            .map_err(|err| format!("IO error {}", err))
+            .map(|_| bytes.len())
+    }
+}
@@ -0,0 +1,278 @@
+use std::fmt;
+
+use bytes::{BytesMut, BufMut};
+
+use crate::coding::*;
+use crate::transform::*;
+
+
+/// The size of a TC transfer frame primary header in bytes
+pub const TC_PRI_HEADER_SIZE: usize = 5;
+
+/// The largest TC transfer frame, including its header and frame error control field
+pub const TC_MAX_FRAME_LENGTH: usize = 1024;
+
+/// The start sequence which begins each CLTU
+pub const CLTU_START_SEQUENCE: [u8; 2] = [0xEB, 0x90];
+
+/// The tail sequence which ends each CLTU
+pub const CLTU_TAIL_SEQUENCE: [u8; 8] = [0xC5, 0xC5, 0xC5, 0xC5, 0xC5, 0xC5, 0xC5, 0x79];
+
+/// The number of information bytes in each CLTU codeblock
+pub const CLTU_INFO_BYTES: usize = 7;
+
+/// The byte used to fill the last codeblock of a CLTU
+pub const CLTU_FILL_BYTE: u8 = 0x55;
+
+/// The largest spacecraft id, which is a 10 bit field
+pub const TC_MAX_SCID: u16 = 0x3FF;
+
+/// The largest virtual channel id, which is a 6 bit field
+pub const TC_MAX_VCID: u8 = 0x3F;
+
+/// The BCH (63,56) generator polynomial, x^7 + x^6 + x^2 + 1, without its x^7 term
+const BCH_POLY: u8 = 0x45;
+
+/// A TC transfer frame primary header
+#[derive(Default, Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub struct TcFrameHeader {
+    pub version: u8,
+    pub bypass_flag: bool,
+    pub control_command_flag: bool,
+    pub scid: u16,
+    pub vcid: u8,
+    /// The frame length field, which is one less than the number of bytes in the frame
+    pub frame_length: u16,
+    pub frame_sequence_number: u8,
+}
+
+impl fmt::Display for TcFrameHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "tc scid={} vcid={} bypass={} seq={} len={}",
+               self.scid,
+               self.vcid,
+               self.bypass_flag,
+               self.frame_sequence_number,
+               self.frame_length as usize + 1)
+    }
+}
+
+impl TcFrameHeader {
+    /// Decode a TC transfer frame primary header from the start of the given bytes
+    pub fn decode(bytes: &[u8]) -> Result<TcFrameHeader, String> {
+        if bytes.len() < TC_PRI_HEADER_SIZE {
+            return Err(format!("TC frame header requires {} bytes, but only {} were provided",
+                               TC_PRI_HEADER_SIZE,
+                               bytes.len()));
+        }
+
+        let id = u16::from_be_bytes([bytes[0], bytes[1]]);
+        let length = u16::from_be_bytes([bytes[2], bytes[3]]);
+
+        return Ok(TcFrameHeader { version: (id >> 14) as u8,
+                                  bypass_flag: id & 0x2000 != 0,
+                                  control_command_flag: id & 0x1000 != 0,
+                                  scid: id & 0x3FF,
+                                  vcid: (length >> 10) as u8,
+                                  frame_length: length & 0x3FF,
+                                  frame_sequence_number: bytes[4],
+        });
+    }
+
+    /// Encode the header into its 5 byte form
+    pub fn encode(&self) -> [u8; TC_PRI_HEADER_SIZE] {
+        let id: u16 = ((self.version as u16 & 0x3) << 14) |
+                      ((self.bypass_flag as u16) << 13) |
+                      ((self.control_command_flag as u16) << 12) |
+                      (self.scid & 0x3FF);
+        let length: u16 = ((self.vcid as u16 & 0x3F) << 10) | (self.frame_length & 0x3FF);
+
+        let id_bytes = id.to_be_bytes();
+        let length_bytes = length.to_be_bytes();
+
+        return [id_bytes[0], id_bytes[1], length_bytes[0], length_bytes[1], self.frame_sequence_number];
+    }
+}
+
+/// The TC frame settings describe the TC transfer frames used to carry commands
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TcFrameSettings {
+    pub scid: u16,
+
+    #[serde(default)]
+    pub vcid: u8,
+
+    /// Send frames as Type-BD frames, which bypass the acceptance checks of the spacecraft
+    #[serde(default)]
+    pub bypass: bool,
+
+    /// Add a frame error control field to each frame
+    #[serde(default = "default_fecf")]
+    pub fecf: bool,
+}
+
+fn default_fecf() -> bool {
+    true
+}
+
+impl Default for TcFrameSettings {
+    fn default() -> TcFrameSettings {
+        TcFrameSettings { scid: 0, vcid: 0, bypass: false, fecf: default_fecf() }
+    }
+}
+
+impl fmt::Display for TcFrameSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.scid, self.vcid)?;
+        if self.bypass {
+            write!(f, ":bd")?;
+        }
+        if !self.fecf {
+            write!(f, ":no_fecf")?;
+        }
+        Ok(())
+    }
+}
+
+impl TcFrameSettings {
+    /// Parse settings of the form "scid:vcid", with optional "bd" and "no_fecf" flags
+    pub fn parse(s: &str) -> Option<TcFrameSettings> {
+        let mut parts = s.split(':');
+
+        let scid = parts.next()?.parse::<u16>().ok()?;
+        let vcid = parts.next()?.parse::<u8>().ok()?;
+        let mut settings = TcFrameSettings { scid, vcid, ..Default::default() };
+        settings.validate().ok()?;
+
+        for flag in parts {
+            match flag {
+                "bd" => settings.bypass = true,
+                "no_fecf" => settings.fecf = false,
+                _ => return None,
+            }
+        }
+
+        return Some(settings);
+    }
+
+    /// Check that the spacecraft and virtual channel ids fit in their header fields
+    pub fn validate(&self) -> Result<(), String> {
+        if self.scid > TC_MAX_SCID {
+            return Err(format!("TC spacecraft id {} is larger than the maximum of {}", self.scid, TC_MAX_SCID));
+        }
+
+        if self.vcid > TC_MAX_VCID {
+            return Err(format!("TC virtual channel id {} is larger than the maximum of {}", self.vcid, TC_MAX_VCID));
+        }
+
+        return Ok(());
+    }
+}
+
+/// The TC frame builder wraps each command packet in a TC transfer frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcFrameBuilder {
+    pub settings: TcFrameSettings,
+
+    /// The sequence number for the next frame
+    pub frame_sequence_number: u8,
+}
+
+impl TcFrameBuilder {
+    pub fn new(settings: TcFrameSettings) -> Result<TcFrameBuilder, String> {
+        settings.validate()?;
+        return Ok(TcFrameBuilder { settings, frame_sequence_number: 0 });
+    }
+
+    /// Build a frame around the given data
+    pub fn build_frame(&mut self, data: &[u8]) -> Result<BytesMut, String> {
+        self.settings.validate()?;
+
+        let fecf_size = if self.settings.fecf { FECF_SIZE } else { 0 };
+        let frame_length = TC_PRI_HEADER_SIZE + data.len() + fecf_size;
+
+        if frame_length > TC_MAX_FRAME_LENGTH {
+            return Err(format!("TC frame of {} bytes is longer than the maximum of {} bytes", frame_length, TC_MAX_FRAME_LENGTH));
+        }
+
+        let header = TcFrameHeader { version: 0,
+                                     bypass_flag: self.settings.bypass,
+                                     control_command_flag: false,
+                                     scid: self.settings.scid,
+                                     vcid: self.settings.vcid,
+                                     frame_length: (frame_length - 1) as u16,
+                                     frame_sequence_number: self.frame_sequence_number,
+        };
+
+        let mut frame = BytesMut::with_capacity(frame_length);
+        frame.extend_from_slice(&header.encode());
+        frame.extend_from_slice(data);
+
+        if self.settings.fecf {
+            let crc = crc16_ccitt(&frame);
+            frame.put_u16_be(crc);
+        }
+
+        self.frame_sequence_number = self.frame_sequence_number.wrapping_add(1);
+
+        return Ok(frame);
+    }
+}
+
+impl Transform for TcFrameBuilder {
+    fn transform(&mut self, message: BytesMut) -> Result<Option<BytesMut>, String> {
+        return self.build_frame(&message).map(Some);
+    }
+}
+
+/// Compute the parity byte for a CLTU codeblock.
+///
+/// The 7 parity bits of the BCH (63,56) code are complemented, and followed by a 0 filler bit.
+pub fn bch_parity(info: &[u8; CLTU_INFO_BYTES]) -> u8 {
+    let mut register: u8 = 0;
+
+    for byte in info.iter() {
+        for bit_index in (0..8).rev() {
+            let bit = (byte >> bit_index) & 1;
+            let feedback = ((register >> 6) & 1) ^ bit;
+
+            register = (register << 1) & 0x7F;
+            if feedback != 0 {
+                register ^= BCH_POLY;
+            }
+        }
+    }
+
+    return (!register & 0x7F) << 1;
+}
+
+/// Encode a TC frame as a CLTU, with a start sequence, BCH codeblocks, and a tail sequence
+pub fn encode_cltu(frame: &[u8]) -> BytesMut {
+    let num_codeblocks = frame.len().div_ceil(CLTU_INFO_BYTES);
+    let cltu_length = CLTU_START_SEQUENCE.len() + num_codeblocks * (CLTU_INFO_BYTES + 1) + CLTU_TAIL_SEQUENCE.len();
+
+    let mut cltu = BytesMut::with_capacity(cltu_length);
+    cltu.extend_from_slice(&CLTU_START_SEQUENCE);
+
+    for chunk in frame.chunks(CLTU_INFO_BYTES) {
+        let mut info = [CLTU_FILL_BYTE; CLTU_INFO_BYTES];
+        info[..chunk.len()].copy_from_slice(chunk);
+
+        cltu.extend_from_slice(&info);
+        cltu.put_u8(bch_parity(&info));
+    }
+
+    cltu.extend_from_slice(&CLTU_TAIL_SEQUENCE);
+
+    return cltu;
+}
+
+/// The CLTU encoder wraps each TC frame in a CLTU
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CltuEncoder;
+
+impl Transform for CltuEncoder {
+    fn transform(&mut self, message: BytesMut) -> Result<Option<BytesMut>, String> {
+        return Ok(Some(encode_cltu(&message)));
+    }
+}
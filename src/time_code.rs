@@ -0,0 +1,448 @@
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+
+/// The CCSDS epoch, 1958-01-01T00:00:00, in seconds relative to the Unix epoch
+pub const CCSDS_EPOCH_UNIX_SECONDS: i64 = -378_691_200;
+
+/// The GPS epoch, 1980-01-06T00:00:00, in seconds relative to the Unix epoch
+pub const GPS_EPOCH_UNIX_SECONDS: i64 = 315_964_800;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+const NANOS_PER_SECOND: i64 = 1_000_000_000;
+
+/// The epoch of a time code
+///
+/// NOTE leap seconds are not accounted for, so times are converted as if every day has 86400 seconds.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub enum Epoch {
+    /// 1958-01-01T00:00:00
+    Ccsds,
+    /// 1970-01-01T00:00:00
+    Unix,
+    /// 1980-01-06T00:00:00
+    Gps,
+    /// A mission defined epoch, given in seconds relative to the Unix epoch
+    Custom(i64),
+}
+
+impl Default for Epoch {
+    fn default() -> Epoch {
+        return Epoch::Ccsds;
+    }
+}
+
+impl Epoch {
+    /// The epoch in seconds relative to the Unix epoch
+    pub fn unix_seconds(&self) -> i64 {
+        match self {
+            Epoch::Ccsds => CCSDS_EPOCH_UNIX_SECONDS,
+            Epoch::Unix => 0,
+            Epoch::Gps => GPS_EPOCH_UNIX_SECONDS,
+            Epoch::Custom(seconds) => *seconds,
+        }
+    }
+}
+
+/// A decoded time, kept as seconds and nanoseconds relative to the Unix epoch
+#[derive(Default, Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Hash, Serialize, Deserialize)]
+pub struct CcsdsTime {
+    pub seconds: i64,
+
+    #[serde(default)]
+    pub nanoseconds: u32,
+}
+
+impl CcsdsTime {
+    pub fn new(seconds: i64, nanoseconds: u32) -> CcsdsTime {
+        let seconds = seconds + nanoseconds as i64 / NANOS_PER_SECOND;
+        let nanoseconds = (nanoseconds as i64 % NANOS_PER_SECOND) as u32;
+        return CcsdsTime { seconds, nanoseconds };
+    }
+
+    /// The current time
+    pub fn now() -> CcsdsTime {
+        return CcsdsTime::from(SystemTime::now());
+    }
+
+    /// The time as floating point seconds since the Unix epoch
+    pub fn as_secs_f64(&self) -> f64 {
+        return self.seconds as f64 + self.nanoseconds as f64 / NANOS_PER_SECOND as f64;
+    }
+
+    /// The time from floating point seconds since the Unix epoch
+    pub fn from_secs_f64(seconds: f64) -> CcsdsTime {
+        let whole = seconds.floor();
+        let nanoseconds = ((seconds - whole) * NANOS_PER_SECOND as f64) as u32;
+        return CcsdsTime::new(whole as i64, nanoseconds.min(NANOS_PER_SECOND as u32 - 1));
+    }
+
+    fn total_nanos(&self) -> i128 {
+        return self.seconds as i128 * NANOS_PER_SECOND as i128 + self.nanoseconds as i128;
+    }
+
+    fn from_total_nanos(nanos: i128) -> CcsdsTime {
+        let seconds = nanos.div_euclid(NANOS_PER_SECOND as i128) as i64;
+        let nanoseconds = nanos.rem_euclid(NANOS_PER_SECOND as i128) as u32;
+        return CcsdsTime { seconds, nanoseconds };
+    }
+
+    /// The time relative to an epoch, in nanoseconds
+    fn nanos_since(&self, epoch: &Epoch) -> i128 {
+        return self.total_nanos() - epoch.unix_seconds() as i128 * NANOS_PER_SECOND as i128;
+    }
+
+    /// The duration from an earlier time to this time, or zero if the other time is later
+    pub fn duration_since(&self, earlier: &CcsdsTime) -> Duration {
+        let nanos = self.total_nanos() - earlier.total_nanos();
+        if nanos <= 0 {
+            return Duration::from_secs(0);
+        }
+
+        return Duration::new((nanos / NANOS_PER_SECOND as i128) as u64, (nanos % NANOS_PER_SECOND as i128) as u32);
+    }
+
+    /// This time moved later by a duration
+    pub fn add(&self, duration: Duration) -> CcsdsTime {
+        return CcsdsTime::from_total_nanos(self.total_nanos() + duration.as_nanos() as i128);
+    }
+}
+
+impl From<SystemTime> for CcsdsTime {
+    fn from(time: SystemTime) -> CcsdsTime {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(duration) => CcsdsTime::new(duration.as_secs() as i64, duration.subsec_nanos()),
+            Err(err) => CcsdsTime::from_total_nanos(-(err.duration().as_nanos() as i128)),
+        }
+    }
+}
+
+impl From<CcsdsTime> for SystemTime {
+    fn from(time: CcsdsTime) -> SystemTime {
+        if time.seconds >= 0 {
+            return UNIX_EPOCH + Duration::new(time.seconds as u64, time.nanoseconds);
+        } else {
+            let nanos = -time.total_nanos();
+            return UNIX_EPOCH - Duration::new((nanos / NANOS_PER_SECOND as i128) as u64, (nanos % NANOS_PER_SECOND as i128) as u32);
+        }
+    }
+}
+
+impl fmt::Display for CcsdsTime {
+    /// Display the time in ISO 8601 form, such as 2019-07-04T12:30:00.000000Z
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let days = self.seconds.div_euclid(SECONDS_PER_DAY);
+        let seconds_of_day = self.seconds.rem_euclid(SECONDS_PER_DAY);
+
+        // convert days since the Unix epoch to a civil date (Howard Hinnant's algorithm)
+        let shifted = days + 719_468;
+        let era = shifted.div_euclid(146_097);
+        let day_of_era = shifted.rem_euclid(146_097);
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        write!(f, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+               year,
+               month,
+               day,
+               seconds_of_day / 3600,
+               (seconds_of_day / 60) % 60,
+               seconds_of_day % 60,
+               self.nanoseconds / 1000)
+    }
+}
+
+/// The CCSDS Unsegmented time Code settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CucSettings {
+    /// The number of octets of whole seconds, from 1 to 4
+    pub coarse_octets: u8,
+
+    /// The number of octets of fractional seconds, from 0 to 3
+    pub fine_octets: u8,
+
+    #[serde(default)]
+    pub epoch: Epoch,
+
+    /// Whether the time code starts with a preamble field
+    #[serde(default)]
+    pub p_field: bool,
+}
+
+impl Default for CucSettings {
+    fn default() -> CucSettings {
+        CucSettings { coarse_octets: 4, fine_octets: 2, epoch: Epoch::Ccsds, p_field: false }
+    }
+}
+
+/// The CCSDS Day Segmented time code settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CdsSettings {
+    /// The number of octets of days, either 2 or 3
+    pub day_octets: u8,
+
+    /// The number of octets of sub-millisecond time, either 0 (none), 2 (microseconds)
+    /// or 4 (picoseconds)
+    #[serde(default)]
+    pub submillisecond_octets: u8,
+
+    #[serde(default)]
+    pub epoch: Epoch,
+
+    /// Whether the time code starts with a preamble field
+    #[serde(default)]
+    pub p_field: bool,
+}
+
+impl Default for CdsSettings {
+    fn default() -> CdsSettings {
+        CdsSettings { day_octets: 2, submillisecond_octets: 0, epoch: Epoch::Ccsds, p_field: false }
+    }
+}
+
+/// The time code settings select the time code format found in a packet's secondary header
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimeCodeSettings {
+    Cuc(CucSettings),
+    Cds(CdsSettings),
+}
+
+impl fmt::Display for Epoch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Epoch::Ccsds => write!(f, "ccsds"),
+            Epoch::Unix => write!(f, "unix"),
+            Epoch::Gps => write!(f, "gps"),
+            Epoch::Custom(seconds) => write!(f, "{}", seconds),
+        }
+    }
+}
+
+impl Epoch {
+    /// Parse an epoch given as "ccsds", "unix", "gps", or seconds relative to the Unix epoch
+    pub fn parse(s: &str) -> Option<Epoch> {
+        match s {
+            "ccsds" => Some(Epoch::Ccsds),
+            "unix" => Some(Epoch::Unix),
+            "gps" => Some(Epoch::Gps),
+            _ => s.parse::<i64>().ok().map(Epoch::Custom),
+        }
+    }
+}
+
+impl fmt::Display for TimeCodeSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (epoch, p_field) = match self {
+            TimeCodeSettings::Cuc(cuc) => {
+                write!(f, "cuc:{}:{}", cuc.coarse_octets, cuc.fine_octets)?;
+                (cuc.epoch, cuc.p_field)
+            },
+
+            TimeCodeSettings::Cds(cds) => {
+                write!(f, "cds:{}:{}", cds.day_octets, cds.submillisecond_octets)?;
+                (cds.epoch, cds.p_field)
+            },
+        };
+
+        if epoch != Epoch::Ccsds {
+            write!(f, ":{}", epoch)?;
+        }
+        if p_field {
+            write!(f, ":p_field")?;
+        }
+        Ok(())
+    }
+}
+
+impl TimeCodeSettings {
+    /// Parse settings of the form "cuc:coarse:fine" or "cds:days:submilliseconds",
+    /// with an optional epoch and "p_field" flag
+    pub fn parse(s: &str) -> Option<TimeCodeSettings> {
+        let mut parts = s.split(':');
+
+        let kind = parts.next()?;
+        let first = parts.next()?.parse::<u8>().ok()?;
+        let second = parts.next()?.parse::<u8>().ok()?;
+
+        let mut epoch = Epoch::Ccsds;
+        let mut p_field = false;
+        for part in parts {
+            match part {
+                "p_field" => p_field = true,
+                _ => epoch = Epoch::parse(part)?,
+            }
+        }
+
+        let settings = match kind {
+            "cuc" => TimeCodeSettings::Cuc(CucSettings { coarse_octets: first, fine_octets: second, epoch, p_field }),
+            "cds" => TimeCodeSettings::Cds(CdsSettings { day_octets: first, submillisecond_octets: second, epoch, p_field }),
+            _ => return None,
+        };
+
+        return settings.validate().ok().map(|_| settings);
+    }
+
+    /// Check that the settings describe a valid time code
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            TimeCodeSettings::Cuc(cuc) => {
+                if cuc.coarse_octets < 1 || cuc.coarse_octets > 4 || cuc.fine_octets > 3 {
+                    return Err(format!("CUC time code can not have {} coarse and {} fine octets",
+                                       cuc.coarse_octets,
+                                       cuc.fine_octets));
+                }
+            },
+
+            TimeCodeSettings::Cds(cds) => {
+                if (cds.day_octets != 2 && cds.day_octets != 3) ||
+                   (cds.submillisecond_octets != 0 && cds.submillisecond_octets != 2 && cds.submillisecond_octets != 4) {
+                    return Err(format!("CDS time code can not have {} day and {} sub-millisecond octets",
+                                       cds.day_octets,
+                                       cds.submillisecond_octets));
+                }
+            },
+        }
+
+        return Ok(());
+    }
+
+    /// The length of the time code in bytes
+    pub fn length(&self) -> usize {
+        match self {
+            TimeCodeSettings::Cuc(cuc) => {
+                cuc.p_field as usize + cuc.coarse_octets as usize + cuc.fine_octets as usize
+            },
+
+            TimeCodeSettings::Cds(cds) => {
+                cds.p_field as usize + cds.day_octets as usize + 4 + cds.submillisecond_octets as usize
+            },
+        }
+    }
+
+    /// Decode a time from the start of the given bytes
+    pub fn decode(&self, bytes: &[u8]) -> Result<CcsdsTime, String> {
+        self.validate()?;
+
+        if bytes.len() < self.length() {
+            return Err(format!("Time code requires {} bytes, but only {} were provided", self.length(), bytes.len()));
+        }
+
+        match self {
+            TimeCodeSettings::Cuc(cuc) => {
+                let start = cuc.p_field as usize;
+                let fine_start = start + cuc.coarse_octets as usize;
+
+                let coarse = read_be(&bytes[start..fine_start]);
+                let fine = read_be(&bytes[fine_start..fine_start + cuc.fine_octets as usize]);
+
+                let fine_nanos = (fine as u128 * NANOS_PER_SECOND as u128) >> (8 * cuc.fine_octets as u32);
+                let epoch_nanos = cuc.epoch.unix_seconds() as i128 * NANOS_PER_SECOND as i128;
+
+                return Ok(CcsdsTime::from_total_nanos(epoch_nanos + coarse as i128 * NANOS_PER_SECOND as i128 + fine_nanos as i128));
+            },
+
+            TimeCodeSettings::Cds(cds) => {
+                let start = cds.p_field as usize;
+                let ms_start = start + cds.day_octets as usize;
+                let sub_start = ms_start + 4;
+
+                let days = read_be(&bytes[start..ms_start]);
+                let millis = read_be(&bytes[ms_start..sub_start]);
+                let submillis = read_be(&bytes[sub_start..sub_start + cds.submillisecond_octets as usize]);
+
+                let sub_nanos = match cds.submillisecond_octets {
+                    2 => submillis * 1000,
+                    4 => submillis / 1000,
+                    _ => 0,
+                };
+
+                let nanos = cds.epoch.unix_seconds() as i128 * NANOS_PER_SECOND as i128 +
+                            days as i128 * SECONDS_PER_DAY as i128 * NANOS_PER_SECOND as i128 +
+                            millis as i128 * 1_000_000 +
+                            sub_nanos as i128;
+
+                return Ok(CcsdsTime::from_total_nanos(nanos));
+            },
+        }
+    }
+
+    /// Encode a time, returning an error if it can not be represented in this time code
+    pub fn encode(&self, time: &CcsdsTime) -> Result<Vec<u8>, String> {
+        self.validate()?;
+
+        let mut bytes = Vec::with_capacity(self.length());
+
+        match self {
+            TimeCodeSettings::Cuc(cuc) => {
+                let nanos = time.nanos_since(&cuc.epoch);
+                if nanos < 0 {
+                    return Err(format!("Time {} is before the time code epoch", time));
+                }
+
+                let coarse = (nanos / NANOS_PER_SECOND as i128) as u128;
+                if coarse >> (8 * cuc.coarse_octets as u32) != 0 {
+                    return Err(format!("Time {} does not fit in {} coarse octets", time, cuc.coarse_octets));
+                }
+
+                let sub_nanos = (nanos % NANOS_PER_SECOND as i128) as u128;
+                let fine = (sub_nanos << (8 * cuc.fine_octets as u32)) / NANOS_PER_SECOND as u128;
+
+                if cuc.p_field {
+                    // time code identification 001 (CUC, CCSDS epoch) or 010 (agency defined epoch)
+                    let id: u8 = if cuc.epoch == Epoch::Ccsds { 0b001 } else { 0b010 };
+                    bytes.push((id << 4) | ((cuc.coarse_octets - 1) << 2) | cuc.fine_octets);
+                }
+                write_be(&mut bytes, coarse as u64, cuc.coarse_octets as usize);
+                write_be(&mut bytes, fine as u64, cuc.fine_octets as usize);
+            },
+
+            TimeCodeSettings::Cds(cds) => {
+                let nanos = time.nanos_since(&cds.epoch);
+                if nanos < 0 {
+                    return Err(format!("Time {} is before the time code epoch", time));
+                }
+
+                let nanos_per_day = SECONDS_PER_DAY as i128 * NANOS_PER_SECOND as i128;
+                let days = (nanos / nanos_per_day) as u64;
+                if days >> (8 * cds.day_octets as u32) != 0 {
+                    return Err(format!("Time {} does not fit in {} day octets", time, cds.day_octets));
+                }
+
+                let nanos_of_day = nanos % nanos_per_day;
+                let millis = (nanos_of_day / 1_000_000) as u64;
+                let sub_nanos = (nanos_of_day % 1_000_000) as u64;
+
+                if cds.p_field {
+                    // time code identification 100 (CDS), with the epoch and field lengths
+                    let agency_epoch = (cds.epoch != Epoch::Ccsds) as u8;
+                    let day_length = (cds.day_octets == 3) as u8;
+                    let sub_length = cds.submillisecond_octets / 2;
+                    bytes.push((0b100 << 4) | (agency_epoch << 3) | (day_length << 2) | sub_length);
+                }
+                write_be(&mut bytes, days, cds.day_octets as usize);
+                write_be(&mut bytes, millis, 4);
+                match cds.submillisecond_octets {
+                    2 => write_be(&mut bytes, sub_nanos / 1000, 2),
+                    4 => write_be(&mut bytes, sub_nanos * 1000, 4),
+                    _ => {},
+                }
+            },
+        }
+
+        return Ok(bytes);
+    }
+}
+
+fn read_be(bytes: &[u8]) -> u64 {
+    return bytes.iter().fold(0, |value, byte| (value << 8) | *byte as u64);
+}
+
+fn write_be(bytes: &mut Vec<u8>, value: u64, num_bytes: usize) {
+    for index in (0..num_bytes).rev() {
+        bytes.push((value >> (8 * index)) as u8);
+    }
+}
@@ -0,0 +1,619 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+
+use bytes::BytesMut;
+
+use crate::*;
+use crate::framing::*;
+use crate::rule_route::*;
+use crate::stats::*;
+use crate::ccsds::*;
+use crate::transform::*;
+
+
+/// The size of a TM transfer frame primary header in bytes
+pub const TM_PRI_HEADER_SIZE: usize = 6;
+
+/// The attached sync marker that precedes each TM transfer frame
+pub const TM_DEFAULT_ASM: [u8; 4] = [0x1A, 0xCF, 0xFC, 0x1D];
+
+/// A common TM transfer frame length, which fills 5 interleaved Reed-Solomon codewords
+pub const TM_DEFAULT_FRAME_LENGTH: usize = 1115;
+
+/// The first header pointer value for a frame in which no packet starts
+pub const TM_FHP_NO_PACKET_START: u16 = 0x7FF;
+
+/// The first header pointer value for a frame containing only idle data
+pub const TM_FHP_IDLE_DATA: u16 = 0x7FE;
+
+/// A decoded TM transfer frame primary header
+#[derive(Default, Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub struct TmFrameHeader {
+    pub version: u8,
+    pub scid: u16,
+    pub vcid: u8,
+    pub ocf_flag: bool,
+    pub master_channel_frame_count: u8,
+    pub virtual_channel_frame_count: u8,
+    pub secondary_header_flag: bool,
+    pub sync_flag: bool,
+    pub packet_order_flag: bool,
+    pub segment_length_id: u8,
+    pub first_header_pointer: u16,
+}
+
+impl fmt::Display for TmFrameHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "tm scid={} vcid={} mcfc={} vcfc={} fhp={}",
+               self.scid,
+               self.vcid,
+               self.master_channel_frame_count,
+               self.virtual_channel_frame_count,
+               self.first_header_pointer)
+    }
+}
+
+impl TmFrameHeader {
+    /// Decode a TM transfer frame primary header from the start of the given bytes
+    pub fn decode(bytes: &[u8]) -> Result<TmFrameHeader, String> {
+        if bytes.len() < TM_PRI_HEADER_SIZE {
+            return Err(format!("TM frame header requires {} bytes, but only {} were provided",
+                               TM_PRI_HEADER_SIZE,
+                               bytes.len()));
+        }
+
+        let id = u16::from_be_bytes([bytes[0], bytes[1]]);
+        let status = u16::from_be_bytes([bytes[4], bytes[5]]);
+
+        return Ok(TmFrameHeader { version: (id >> 14) as u8,
+                                  scid: (id >> 4) & 0x3FF,
+                                  vcid: ((id >> 1) & 0x7) as u8,
+                                  ocf_flag: id & 0x1 != 0,
+                                  master_channel_frame_count: bytes[2],
+                                  virtual_channel_frame_count: bytes[3],
+                                  secondary_header_flag: status & 0x8000 != 0,
+                                  sync_flag: status & 0x4000 != 0,
+                                  packet_order_flag: status & 0x2000 != 0,
+                                  segment_length_id: ((status >> 11) & 0x3) as u8,
+                                  first_header_pointer: status & 0x7FF,
+        });
+    }
+
+    /// Encode the header into its 6 byte form
+    pub fn encode(&self) -> [u8; TM_PRI_HEADER_SIZE] {
+        let id: u16 = ((self.version as u16 & 0x3) << 14) |
+                      ((self.scid & 0x3FF) << 4) |
+                      ((self.vcid as u16 & 0x7) << 1) |
+                      (self.ocf_flag as u16);
+        let status: u16 = ((self.secondary_header_flag as u16) << 15) |
+                          ((self.sync_flag as u16) << 14) |
+                          ((self.packet_order_flag as u16) << 13) |
+                          ((self.segment_length_id as u16 & 0x3) << 11) |
+                          (self.first_header_pointer & 0x7FF);
+
+        let id_bytes = id.to_be_bytes();
+        let status_bytes = status.to_be_bytes();
+
+        return [id_bytes[0], id_bytes[1],
+                self.master_channel_frame_count,
+                self.virtual_channel_frame_count,
+                status_bytes[0], status_bytes[1]];
+    }
+
+    /// Whether this frame contains only idle data
+    pub fn is_idle(&self) -> bool {
+        return self.first_header_pointer == TM_FHP_IDLE_DATA;
+    }
+}
+
+/// The frame sync settings describe how to find TM transfer frames in a stream of bytes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameSyncSettings {
+    /// The attached sync marker which precedes each frame
+    #[serde(default = "default_asm")]
+    pub asm: Vec<u8>,
+
+    /// The length of each frame in bytes, not including the attached sync marker
+    #[serde(default = "default_frame_length")]
+    pub frame_length: usize,
+}
+
+fn default_asm() -> Vec<u8> {
+    TM_DEFAULT_ASM.to_vec()
+}
+
+fn default_frame_length() -> usize {
+    TM_DEFAULT_FRAME_LENGTH
+}
+
+impl Default for FrameSyncSettings {
+    fn default() -> FrameSyncSettings {
+        FrameSyncSettings { asm: default_asm(), frame_length: default_frame_length() }
+    }
+}
+
+/// The frame sync framer searches for the attached sync marker and extracts the
+/// fixed length frame that follows it.
+///
+/// Frames are returned without their sync marker. Bytes between the end of one frame
+/// and the next sync marker are dropped, and reported as a loss of synchronization.
+/// An error is reported once for each loss, however many reads the search takes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameSyncFramer {
+    pub settings: FrameSyncSettings,
+
+    /// The number of times synchronization was lost
+    pub sync_losses: u64,
+
+    /// The number of bytes dropped while searching for a sync marker
+    pub bytes_skipped: u64,
+
+    in_sync: bool,
+}
+
+impl FrameSyncFramer {
+    pub fn new(settings: FrameSyncSettings) -> FrameSyncFramer {
+        return FrameSyncFramer { settings, sync_losses: 0, bytes_skipped: 0, in_sync: true };
+    }
+
+    /// Drop bytes which can not be part of a frame, returning an error if this loses synchronization
+    fn skip(&mut self, buffer: &mut BytesMut, num_skipped: usize) -> Result<(), String> {
+        buffer.advance(num_skipped);
+        self.bytes_skipped += num_skipped as u64;
+
+        if num_skipped > 0 && self.in_sync {
+            self.in_sync = false;
+            self.sync_losses += 1;
+            return Err(format!("Frame sync lost, skipped {} bytes searching for a sync marker", num_skipped));
+        }
+
+        return Ok(());
+    }
+}
+
+impl Framer for FrameSyncFramer {
+    fn next_message(&mut self, buffer: &mut BytesMut) -> Result<Option<BytesMut>, String> {
+        let asm_len = self.settings.asm.len();
+        if asm_len == 0 || buffer.len() < asm_len {
+            return Ok(None);
+        }
+
+        let position = buffer.windows(asm_len)
+                             .position(|window| window == &self.settings.asm[..]);
+
+        match position {
+            Some(0) => {
+                self.in_sync = true;
+
+                if buffer.len() < asm_len + self.settings.frame_length {
+                    return Ok(None);
+                }
+
+                buffer.advance(asm_len);
+                return Ok(Some(buffer.split_to(self.settings.frame_length)));
+            },
+
+            Some(index) => {
+                // the frame at the sync marker is left for the next call, after the loss is reported
+                self.skip(buffer, index)?;
+                return self.next_message(buffer);
+            },
+
+            None => {
+                // keep the end of the buffer, as it may hold the start of a sync marker
+                let num_skipped = buffer.len() - (asm_len - 1);
+                self.skip(buffer, num_skipped)?;
+                return Ok(None);
+            },
+        }
+    }
+
+    fn encode_message(&mut self, message: &[u8], buffer: &mut BytesMut) -> Result<(), String> {
+        if message.len() != self.settings.frame_length {
+            return Err(format!("Frame length {} does not match the frame sync length {}",
+                               message.len(),
+                               self.settings.frame_length));
+        }
+
+        buffer.extend_from_slice(&self.settings.asm);
+        buffer.extend_from_slice(message);
+        return Ok(());
+    }
+}
+
+/// A virtual channel rule matches frames by virtual channel, and optionally spacecraft
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VirtualChannelRule {
+    /// The virtual channels matched by this rule. If none are given, all virtual channels match.
+    #[serde(default)]
+    pub vcids: Vec<u8>,
+
+    /// The spacecraft matched by this rule. If no spacecraft is given, all spacecraft match.
+    #[serde(default)]
+    pub scid: Option<u16>,
+
+    pub action: RouteAction,
+}
+
+impl RouteRule for VirtualChannelRule {
+    type Subject = TmFrameHeader;
+
+    fn matches(&self, header: &TmFrameHeader) -> bool {
+        let vcid_matches = self.vcids.is_empty() || self.vcids.contains(&header.vcid);
+        let scid_matches = self.scid.is_none_or(|scid| scid == header.scid);
+
+        return vcid_matches && scid_matches;
+    }
+
+    fn action(&self) -> &RouteAction {
+        return &self.action;
+    }
+}
+
+/// The virtual channel route settings describe a set of named outputs, and the rules
+/// that decide which outputs receive each TM frame.
+///
+/// Rules are checked in order, and the first matching rule decides what happens to a frame.
+/// Frames that match no rule use the default action.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VirtualChannelRouteSettings {
+    pub outputs: BTreeMap<String, StreamConfig>,
+
+    #[serde(default)]
+    pub rules: Vec<VirtualChannelRule>,
+
+    #[serde(default)]
+    pub default: RouteAction,
+
+    /// Drop frames containing only idle data instead of routing them
+    #[serde(default)]
+    pub drop_idle: bool,
+}
+
+impl VirtualChannelRouteSettings {
+    /// Check that every rule refers to an output that exists
+    pub fn validate(&self) -> Result<(), String> {
+        let actions = self.rules.iter().map(|rule| &rule.action).chain(std::iter::once(&self.default));
+        return check_output_names(actions, |name| self.outputs.contains_key(name));
+    }
+
+    /// Open each output and create a router
+    pub fn open(&self) -> Result<VirtualChannelRouter, String> {
+        self.validate()?;
+
+        let router = RuleRouter::open(self.rules.clone(), self.default.clone(), &self.outputs)?;
+        let mut vc_router = VirtualChannelRouter::new(router);
+        vc_router.drop_idle = self.drop_idle;
+
+        return Ok(vc_router);
+    }
+}
+
+/// A virtual channel router writes each TM frame to the outputs selected by its rules.
+#[derive(Debug)]
+pub struct VirtualChannelRouter {
+    pub router: RuleRouter<VirtualChannelRule>,
+    pub drop_idle: bool,
+}
+
+impl VirtualChannelRouter {
+    pub fn new(router: RuleRouter<VirtualChannelRule>) -> VirtualChannelRouter {
+        return VirtualChannelRouter { router, drop_idle: false };
+    }
+
+    /// Route a single frame, returning the number of outputs it was written to.
+    pub fn route_frame(&mut self, frame: &[u8]) -> Result<usize, String> {
+        let header = TmFrameHeader::decode(frame)?;
+
+        if self.drop_idle && header.is_idle() {
+            self.router.dropped += 1;
+            return Ok(0);
+        }
+
+        return Ok(self.router.route(&header, frame));
+    }
+}
+
+impl MessageRouter for VirtualChannelRouter {
+    fn route_message(&mut self, message: &[u8]) -> Result<usize, String> {
+        return self.route_frame(message);
+    }
+
+    fn record_drop(&mut self) {
+        self.router.dropped += 1;
+    }
+}
+
+/// The packet extractor settings describe the layout of the TM frames that packets are extracted from
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PacketExtractorSettings {
+    /// Whether each frame ends with a 2 byte frame error control field
+    #[serde(default)]
+    pub fecf_present: bool,
+
+    /// Pass idle packets on instead of discarding them
+    #[serde(default)]
+    pub keep_idle_packets: bool,
+}
+
+/// The reassembly state of a single virtual channel
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+struct VirtualChannelState {
+    buffer: BytesMut,
+    last_frame_count: Option<u8>,
+    in_sync: bool,
+}
+
+/// The packet extractor reassembles CCSDS Space Packets from the data fields of TM frames.
+///
+/// Packets may span frames, so each virtual channel keeps the partial packet at the end of its
+/// last frame. The first header pointer of each frame is used to find the start of the first packet
+/// in that frame. When a virtual channel's frame count skips, its partial packet is discarded.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct PacketExtractor {
+    pub settings: PacketExtractorSettings,
+
+    /// The number of complete packets extracted
+    pub packets_extracted: u64,
+
+    /// The number of partial packets discarded due to a frame count discontinuity or bad header
+    pub packets_discarded: u64,
+
+    /// The number of idle packets discarded
+    pub idle_packets: u64,
+
+    /// The number of frames containing only idle data
+    pub idle_frames: u64,
+
+    channels: BTreeMap<u8, VirtualChannelState>,
+}
+
+impl PacketExtractor {
+    pub fn new(settings: PacketExtractorSettings) -> PacketExtractor {
+        return PacketExtractor { settings, ..Default::default() };
+    }
+
+    /// The data field of a frame, between the headers and the trailer
+    fn data_field<'a>(&self, header: &TmFrameHeader, frame: &'a [u8]) -> Result<&'a [u8], String> {
+        let mut start = TM_PRI_HEADER_SIZE;
+        if header.secondary_header_flag {
+            let secondary_header_length = frame.get(TM_PRI_HEADER_SIZE)
+                                               .map(|byte| (byte & 0x3F) as usize + 1)
+                                               .ok_or("TM frame is too short for its secondary header")?;
+            start += secondary_header_length;
+        }
+
+        let mut trailer_length = 0;
+        if header.ocf_flag {
+            trailer_length += 4;
+        }
+        if self.settings.fecf_present {
+            trailer_length += 2;
+        }
+
+        if start + trailer_length > frame.len() {
+            return Err(format!("TM frame of {} bytes is too short for its headers and trailer", frame.len()));
+        }
+
+        return Ok(&frame[start..frame.len() - trailer_length]);
+    }
+
+    /// Extract the complete packets from a frame, adding them to the given packet list.
+    ///
+    /// An error is returned when a partial packet is discarded. Packets completed by
+    /// the frame are still added to the list in that case.
+    pub fn extract(&mut self, frame: &[u8], packets: &mut Vec<BytesMut>) -> Result<(), String> {
+        let header = TmFrameHeader::decode(frame)?;
+        let data = self.data_field(&header, frame)?;
+
+        let keep_idle_packets = self.settings.keep_idle_packets;
+        let state = self.channels.entry(header.vcid).or_default();
+
+        let mut errors: Vec<String> = Vec::new();
+        let mut discarded = 0;
+
+        // check for missing frames, which means any partial packet can not be completed
+        if let Some(last_frame_count) = state.last_frame_count {
+            let expected = last_frame_count.wrapping_add(1);
+            if header.virtual_channel_frame_count != expected {
+                if !state.buffer.is_empty() {
+                    discarded += 1;
+                }
+                errors.push(format!("VC {} frame count discontinuity (expected {}, received {})",
+                                    header.vcid,
+                                    expected,
+                                    header.virtual_channel_frame_count));
+                state.buffer.clear();
+                state.in_sync = false;
+            }
+        }
+        state.last_frame_count = Some(header.virtual_channel_frame_count);
+
+        if header.is_idle() {
+            self.idle_frames += 1;
+        } else if header.first_header_pointer == TM_FHP_NO_PACKET_START {
+            // the whole data field continues a packet from an earlier frame
+            if state.in_sync {
+                state.buffer.extend_from_slice(data);
+            }
+        } else {
+            let first_header = header.first_header_pointer as usize;
+
+            if first_header > data.len() {
+                errors.push(format!("VC {} first header pointer {} is beyond the data field", header.vcid, first_header));
+                if !state.buffer.is_empty() {
+                    discarded += 1;
+                }
+                state.buffer.clear();
+                state.in_sync = false;
+            } else {
+                if state.in_sync {
+                    state.buffer.extend_from_slice(&data[..first_header]);
+                    self.packets_extracted += take_packets(state, keep_idle_packets, &mut self.idle_packets, packets);
+                }
+
+                // the first header pointer must point just after the end of the previous packet
+                if !state.buffer.is_empty() {
+                    errors.push(format!("VC {} first header pointer {} does not follow the previous packet",
+                                        header.vcid,
+                                        first_header));
+                    discarded += 1;
+                    state.buffer.clear();
+                }
+
+                state.buffer.extend_from_slice(&data[first_header..]);
+                state.in_sync = true;
+            }
+        }
+
+        if state.in_sync {
+            self.packets_extracted += take_packets(state, keep_idle_packets, &mut self.idle_packets, packets);
+
+            // a packet header which is not valid means the rest of the buffer can not be trusted
+            if state.buffer.len() >= CCSDS_PRI_HEADER_SIZE {
+                let packet_header = CcsdsPrimaryHeader::decode(&state.buffer)?;
+                if packet_header.version != CCSDS_VERSION {
+                    errors.push(format!("VC {} packet has an invalid version {}", header.vcid, packet_header.version));
+                    discarded += 1;
+                    state.buffer.clear();
+                    state.in_sync = false;
+                }
+            }
+        }
+
+        self.packets_discarded += discarded;
+
+        if errors.is_empty() {
+            return Ok(());
+        } else {
+            return Err(errors.join(", "));
+        }
+    }
+
+    /// Forget all partial packets
+    pub fn reset(&mut self) {
+        self.channels.clear();
+    }
+}
+
+/// Remove each complete packet from the front of a virtual channel's buffer, returning
+/// the number of packets added to the packet list.
+fn take_packets(state: &mut VirtualChannelState,
+                keep_idle_packets: bool,
+                idle_packets: &mut u64,
+                packets: &mut Vec<BytesMut>) -> u64 {
+    let mut num_packets = 0;
+
+    while state.buffer.len() >= CCSDS_PRI_HEADER_SIZE {
+        let header = match CcsdsPrimaryHeader::decode(&state.buffer) {
+            Ok(header) if header.version == CCSDS_VERSION => header,
+            _ => break,
+        };
+
+        if state.buffer.len() < header.packet_length() {
+            break;
+        }
+
+        let packet = state.buffer.split_to(header.packet_length());
+
+        if header.is_idle() && !keep_idle_packets {
+            *idle_packets += 1;
+        } else {
+            packets.push(packet);
+            num_packets += 1;
+        }
+    }
+
+    return num_packets;
+}
+
+/// The TM packet framing settings describe how to find frames in a stream, and how to
+/// extract packets from those frames.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TmPacketFramingSettings {
+    #[serde(default)]
+    pub sync: FrameSyncSettings,
+
+    #[serde(default)]
+    pub extractor: PacketExtractorSettings,
+}
+
+/// The TM packet framer finds TM frames in a stream of bytes, and returns the CCSDS
+/// Space Packets that they carry.
+///
+/// Frame transforms, such as derandomizing or checking the FECF, are applied to each frame
+/// before its packets are extracted. A frame dropped or rejected by a transform counts as
+/// a missing frame for its virtual channel.
+pub struct TmPacketFramer {
+    pub sync: FrameSyncFramer,
+    pub extractor: PacketExtractor,
+    frame_transforms: Vec<Box<dyn Transform>>,
+    packets: VecDeque<BytesMut>,
+}
+
+impl fmt::Debug for TmPacketFramer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TmPacketFramer")
+         .field("sync", &self.sync)
+         .field("extractor", &self.extractor)
+         .field("frame_transforms", &self.frame_transforms.len())
+         .field("packets", &self.packets.len())
+         .finish()
+    }
+}
+
+impl TmPacketFramer {
+    pub fn new(settings: TmPacketFramingSettings) -> TmPacketFramer {
+        return TmPacketFramer { sync: FrameSyncFramer::new(settings.sync),
+                                extractor: PacketExtractor::new(settings.extractor),
+                                frame_transforms: Vec::new(),
+                                packets: VecDeque::new(),
+        };
+    }
+
+    /// Apply the given transforms, in order, to each frame before extracting its packets
+    pub fn with_frame_transforms(mut self, frame_transforms: Vec<Box<dyn Transform>>) -> TmPacketFramer {
+        self.frame_transforms = frame_transforms;
+        return self;
+    }
+}
+
+impl Framer for TmPacketFramer {
+    fn next_message(&mut self, buffer: &mut BytesMut) -> Result<Option<BytesMut>, String> {
+        loop {
+            if let Some(packet) = self.packets.pop_front() {
+                return Ok(Some(packet));
+            }
+
+            let frame = match self.sync.next_message(buffer)? {
+                Some(frame) => frame,
+                None => return Ok(None),
+            };
+
+            let frame = match apply_transforms(&mut self.frame_transforms, frame)? {
+                Some(frame) => frame,
+
+                // the frame was dropped, so look for the next one
+                None => continue,
+            };
+
+            let mut packets = Vec::new();
+            let result = self.extractor.extract(&frame, &mut packets);
+            self.packets.extend(packets);
+
+            // packets completed by the frame are kept, and returned after the error
+            result?;
+        }
+    }
+
+    fn encode_message(&mut self, _message: &[u8], _buffer: &mut BytesMut) -> Result<(), String> {
+        return Err("TM packet framing can only be used to read packets".to_string());
+    }
+
+    fn set_stats(&mut self, stats: &Arc<StreamStats>) {
+        for transform in self.frame_transforms.iter_mut() {
+            transform.set_stats(stats);
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use bytes::{BytesMut, BufMut};
+
+use crate::*;
+use crate::coding::*;
+use crate::reed_solomon::*;
+use crate::stats::*;
+use crate::tc::*;
+
+
+/// A transform changes each message passing between a read stream and a write stream,
+/// such as de-randomizing a frame or checking its frame error control field.
+pub trait Transform: Send {
+    /// Transform a message. A result of None drops the message.
+    fn transform(&mut self, message: BytesMut) -> Result<Option<BytesMut>, String>;
+
+    /// Record this transform's counters, such as corrected codewords, in the stats of the stream it is applied to
+    fn set_stats(&mut self, _stats: &Arc<StreamStats>) {
+    }
+}
+
+/// The transform settings select a transform for a stream.
+///
+/// Transforms can be written as descriptor strings, such as "derandomize", "fecf_check", "rs_decode:5",
+/// "tc_frame:42:0" or "cltu",
+/// and are given after the framing in a stream descriptor, as in "udp:127.0.0.1:8001|asm:1115|derandomize".
+/// With TM packet framing they apply to the frames, as in "udp:127.0.0.1:8001|tm_packets:1115|derandomize|fecf_check".
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransformSettings {
+    /// Apply the CCSDS pseudo-random sequence to each frame
+    Randomize,
+    /// Remove the CCSDS pseudo-random sequence from each frame. This is the same as Randomize.
+    Derandomize,
+    /// Check the frame error control field of each frame, rejecting frames that fail the check
+    FecfCheck,
+    /// Append a frame error control field to each frame
+    FecfAppend,
+    /// Correct each Reed-Solomon codeblock, and remove its parity symbols
+    ReedSolomonDecode(ReedSolomonSettings),
+    /// Add Reed-Solomon parity symbols to each frame
+    ReedSolomonEncode(ReedSolomonSettings),
+    /// Wrap each command packet in a TC transfer frame
+    TcFrame(TcFrameSettings),
+    /// Wrap each TC transfer frame in a CLTU
+    Cltu,
+}
+
+impl TransformSettings {
+    /// Create a transform from these settings
+    pub fn transform(&self) -> Result<Box<dyn Transform>, String> {
+        match self {
+            TransformSettings::Randomize | TransformSettings::Derandomize => return Ok(Box::new(Randomizer::new())),
+
+            TransformSettings::FecfCheck => return Ok(Box::new(FecfCheck::new())),
+
+            TransformSettings::FecfAppend => return Ok(Box::new(FecfAppend)),
+
+            TransformSettings::ReedSolomonDecode(rs_settings) => return Ok(Box::new(ReedSolomonDecoder::new(rs_settings.clone()))),
+
+            TransformSettings::ReedSolomonEncode(rs_settings) => return Ok(Box::new(ReedSolomonEncoder::new(rs_settings.clone()))),
+
+            TransformSettings::TcFrame(tc_settings) => return Ok(Box::new(TcFrameBuilder::new(tc_settings.clone())?)),
+
+            TransformSettings::Cltu => return Ok(Box::new(CltuEncoder)),
+        }
+    }
+
+    /// Check the settings of transforms which have them
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            TransformSettings::ReedSolomonDecode(rs_settings) | TransformSettings::ReedSolomonEncode(rs_settings) => return rs_settings.validate(),
+
+            TransformSettings::TcFrame(tc_settings) => return tc_settings.validate(),
+
+            _ => return Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for TransformSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransformSettings::Randomize => write!(f, "randomize"),
+
+            TransformSettings::Derandomize => write!(f, "derandomize"),
+
+            TransformSettings::FecfCheck => write!(f, "fecf_check"),
+
+            TransformSettings::FecfAppend => write!(f, "fecf_append"),
+
+            TransformSettings::ReedSolomonDecode(rs_settings) => write!(f, "rs_decode:{}", rs_settings),
+
+            TransformSettings::ReedSolomonEncode(rs_settings) => write!(f, "rs_encode:{}", rs_settings),
+
+            TransformSettings::TcFrame(tc_settings) => write!(f, "tc_frame:{}", tc_settings),
+
+            TransformSettings::Cltu => write!(f, "cltu"),
+        }
+    }
+}
+
+impl FromStr for TransformSettings {
+    type Err = StreamSettingsParseError;
+    fn from_str(s: &str) -> Result<TransformSettings, StreamSettingsParseError> {
+        if let Some(rs_str) = s.strip_prefix("rs_decode:") {
+            return ReedSolomonSettings::parse(rs_str).map(TransformSettings::ReedSolomonDecode)
+                                                     .ok_or(StreamSettingsParseError(()));
+        }
+
+        if let Some(rs_str) = s.strip_prefix("rs_encode:") {
+            return ReedSolomonSettings::parse(rs_str).map(TransformSettings::ReedSolomonEncode)
+                                                     .ok_or(StreamSettingsParseError(()));
+        }
+
+        if let Some(tc_str) = s.strip_prefix("tc_frame:") {
+            return TcFrameSettings::parse(tc_str).map(TransformSettings::TcFrame)
+                                                 .ok_or(StreamSettingsParseError(()));
+        }
+
+        match s {
+            "randomize" => Ok(TransformSettings::Randomize),
+
+            "derandomize" => Ok(TransformSettings::Derandomize),
+
+            "fecf_check" => Ok(TransformSettings::FecfCheck),
+
+            "fecf_append" => Ok(TransformSettings::FecfAppend),
+
+            "cltu" => Ok(TransformSettings::Cltu),
+
+            _ => Err(StreamSettingsParseError(())),
+        }
+    }
+}
+
+/// Apply a series of transforms to a message, stopping if any transform drops the message
+pub fn apply_transforms(transforms: &mut [Box<dyn Transform>], message: BytesMut) -> Result<Option<BytesMut>, String> {
+    let mut message = message;
+
+    for transform in transforms.iter_mut() {
+        match transform.transform(message)? {
+            Some(transformed) => {
+                message = transformed;
+            },
+
+            None => {
+                return Ok(None);
+            },
+        }
+    }
+
+    return Ok(Some(message));
+}
+
+/// The randomizer applies the CCSDS pseudo-random sequence to each frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Randomizer {
+    sequence: [u8; PN_SEQUENCE_LENGTH],
+}
+
+impl Default for Randomizer {
+    fn default() -> Randomizer {
+        return Randomizer::new();
+    }
+}
+
+impl Randomizer {
+    pub fn new() -> Randomizer {
+        return Randomizer { sequence: pn_sequence() };
+    }
+}
+
+impl Transform for Randomizer {
+    fn transform(&mut self, mut message: BytesMut) -> Result<Option<BytesMut>, String> {
+        randomize(&mut message, &self.sequence);
+        return Ok(Some(message));
+    }
+}
+
+/// The FECF check rejects frames whose frame error control field does not match their contents.
+///
+/// A rejected frame is returned as an error, so a framed stream skips it and reports a framing error.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct FecfCheck {
+    pub frames_good: u64,
+    pub frames_bad: u64,
+}
+
+impl FecfCheck {
+    pub fn new() -> FecfCheck {
+        return FecfCheck::default();
+    }
+}
+
+impl Transform for FecfCheck {
+    fn transform(&mut self, message: BytesMut) -> Result<Option<BytesMut>, String> {
+        match check_fecf(&message) {
+            Ok(()) => {
+                self.frames_good += 1;
+                return Ok(Some(message));
+            },
+
+            Err(string) => {
+                self.frames_bad += 1;
+                return Err(string);
+            },
+        }
+    }
+}
+
+/// The FECF append transform adds a frame error control field to the end of each frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FecfAppend;
+
+impl Transform for FecfAppend {
+    fn transform(&mut self, mut message: BytesMut) -> Result<Option<BytesMut>, String> {
+        let crc = crc16_ccitt(&message);
+
+        message.reserve(FECF_SIZE);
+        message.put_u16_be(crc);
+
+        return Ok(Some(message));
+    }
+}
@@ -0,0 +1,159 @@
+extern crate backplane;
+
+use std::collections::BTreeMap;
+
+mod common;
+use common::*;
+
+use backplane::*;
+use backplane::apid_route::*;
+use backplane::ccsds::*;
+use backplane::framing::*;
+use backplane::rule_route::*;
+
+
+fn packet(apid: u16, packet_type: PacketType) -> Vec<u8> {
+    let mut header = CcsdsPrimaryHeader { apid, packet_type, ..Default::default() };
+    header.set_packet_length(CCSDS_PRI_HEADER_SIZE + 2).unwrap();
+
+    let mut bytes = header.encode().to_vec();
+    bytes.extend_from_slice(&[0xAB, 0xCD]);
+    bytes
+}
+
+fn rule(apids: ApidSet, packet_type: Option<PacketType>, outputs: &[&str]) -> ApidRule {
+    ApidRule { apids,
+               packet_type,
+               time_range: None,
+               action: ApidAction::Route(outputs.iter().map(|name| name.to_string()).collect()),
+    }
+}
+
+/// Open a file output for each name, returning the outputs and their paths
+fn file_outputs(names: &[&str]) -> (BTreeMap<String, StreamConfig>, Vec<TempPath>) {
+    let mut outputs = BTreeMap::new();
+    let mut paths = Vec::new();
+
+    for name in names.iter() {
+        let path = TempPath::file(&format!("apid_route_{}.bin", name));
+        outputs.insert(name.to_string(), StreamConfig::Descriptor(format!("file:{}", path.display())));
+        paths.push(path);
+    }
+
+    (outputs, paths)
+}
+
+/// The APIDs of the packets written to a file
+fn written_apids(path: &TempPath) -> Vec<u16> {
+    let bytes = std::fs::read(path).unwrap();
+
+    bytes.chunks(CCSDS_PRI_HEADER_SIZE + 2)
+         .map(|packet| CcsdsPrimaryHeader::decode(packet).unwrap().apid)
+         .collect()
+}
+
+#[test]
+fn apid_sets_and_ranges() {
+    let set = ApidSet { apids: vec![3, 9], ranges: vec![(100, 199), (500, 500)] };
+
+    for apid in [3, 9, 100, 150, 199, 500].iter() {
+        assert!(set.contains(*apid), "{}", apid);
+    }
+    for apid in [0, 4, 99, 200, 499, 501, CCSDS_IDLE_APID].iter() {
+        assert!(!set.contains(*apid), "{}", apid);
+    }
+
+    assert!(!set.is_empty());
+    assert!(ApidSet::default().is_empty());
+}
+
+#[test]
+fn rules_match_apids_and_packet_types() {
+    let housekeeping = rule(ApidSet { apids: vec![], ranges: vec![(0x10, 0x1F)] }, None, &[]);
+    let commands = rule(ApidSet::default(), Some(PacketType::Command), &[]);
+
+    let info = |apid, packet_type| CcsdsPacketInfo { header: CcsdsPrimaryHeader { apid, packet_type, ..Default::default() }, time: None };
+
+    assert!(housekeeping.matches(&info(0x10, PacketType::Telemetry)));
+    assert!(housekeeping.matches(&info(0x1F, PacketType::Command)));
+    assert!(!housekeeping.matches(&info(0x20, PacketType::Telemetry)));
+
+    // an empty APID set matches every APID
+    assert!(commands.matches(&info(0x7FE, PacketType::Command)));
+    assert!(!commands.matches(&info(0x10, PacketType::Telemetry)));
+}
+
+#[test]
+fn first_matching_rule_routes_each_packet() {
+    let (outputs, paths) = file_outputs(&["display", "archive", "commands"]);
+
+    // housekeeping goes to the display and the archive, science to the archive, and a
+    // later rule for all commands does not see the housekeeping commands
+    let rules = vec![rule(ApidSet { apids: vec![], ranges: vec![(0x10, 0x1F)] }, None, &["display", "archive"]),
+                     rule(ApidSet { apids: vec![0x40, 0x41], ranges: vec![] }, Some(PacketType::Telemetry), &["archive"]),
+                     ApidRule { action: ApidAction::Drop, ..rule(ApidSet { apids: vec![0x50], ranges: vec![] }, None, &[]) },
+                     rule(ApidSet::default(), Some(PacketType::Command), &["commands"])];
+
+    let settings = ApidRouteSettings { outputs, rules, default: ApidAction::Route(vec!["archive".to_string()]), ..Default::default() };
+    let mut router = settings.open().unwrap();
+
+    assert_eq!(router.route_packet(&packet(0x12, PacketType::Telemetry)), Ok(2));
+    assert_eq!(router.route_packet(&packet(0x13, PacketType::Command)), Ok(2));
+    assert_eq!(router.route_packet(&packet(0x40, PacketType::Telemetry)), Ok(1));
+    assert_eq!(router.route_packet(&packet(0x41, PacketType::Command)), Ok(1));
+    assert_eq!(router.route_packet(&packet(0x50, PacketType::Command)), Ok(0));
+    assert_eq!(router.route_packet(&packet(0x60, PacketType::Telemetry)), Ok(1));
+
+    assert_eq!(router.router.routed, 5);
+    assert_eq!(router.router.dropped, 1);
+
+    // a packet too short for a header can not be routed
+    assert!(router.route_packet(&[0x00, 0x12]).is_err());
+
+    drop(router);
+    assert_eq!(written_apids(&paths[0]), vec![0x12, 0x13]);
+    assert_eq!(written_apids(&paths[1]), vec![0x12, 0x13, 0x40, 0x60]);
+    assert_eq!(written_apids(&paths[2]), vec![0x41]);
+}
+
+#[test]
+fn default_drop_discards_unmatched_packets() {
+    let (outputs, paths) = file_outputs(&["science"]);
+
+    let rules = vec![rule(ApidSet { apids: vec![7], ranges: vec![] }, None, &["science"])];
+    let settings = ApidRouteSettings { outputs, rules, default: ApidAction::Drop, ..Default::default() };
+    let mut router = settings.open().unwrap();
+
+    assert_eq!(router.route_packet(&packet(7, PacketType::Telemetry)), Ok(1));
+    assert_eq!(router.route_packet(&packet(8, PacketType::Telemetry)), Ok(0));
+    assert_eq!(router.router.routed, 1);
+    assert_eq!(router.router.dropped, 1);
+
+    drop(router);
+    assert_eq!(written_apids(&paths[0]), vec![7]);
+}
+
+#[test]
+fn failed_writes_are_not_counted_as_routed() {
+    // every packet is longer than the fixed framer's size, so its write fails
+    let mut outputs = BTreeMap::new();
+    outputs.insert("short".to_string(), FramedWriteStream::new(WriteStream::Null, Box::new(FixedFramer::new(4))));
+
+    let mut router = ApidRouter::new(RuleRouter::new(vec![], ApidAction::Route(vec!["short".to_string()]), outputs).unwrap());
+
+    assert_eq!(router.route_packet(&packet(1, PacketType::Telemetry)), Ok(0));
+    assert_eq!(router.router.routed, 0);
+    assert_eq!(router.router.dropped, 1);
+}
+
+#[test]
+fn rules_must_name_known_outputs() {
+    let (outputs, _) = file_outputs(&["archive"]);
+
+    let rules = vec![rule(ApidSet::default(), None, &["display"])];
+    let settings = ApidRouteSettings { outputs: outputs.clone(), rules, ..Default::default() };
+    assert!(settings.validate().is_err());
+
+    let settings = ApidRouteSettings { outputs, default: ApidAction::Route(vec!["missing".to_string()]), ..Default::default() };
+    assert!(settings.validate().is_err());
+}
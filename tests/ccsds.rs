@@ -0,0 +1,155 @@
+extern crate backplane;
+extern crate bytes;
+
+use bytes::BytesMut;
+
+use backplane::ccsds::*;
+use backplane::framing::*;
+
+
+/// A telemetry packet with the given APID and sequence count, and a data field of the given bytes
+fn packet(apid: u16, sequence_count: u16, data: &[u8]) -> Vec<u8> {
+    let mut header = CcsdsPrimaryHeader { apid, sequence_count, ..Default::default() };
+    header.set_packet_length(CCSDS_PRI_HEADER_SIZE + data.len()).unwrap();
+
+    let mut bytes = header.encode().to_vec();
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+#[test]
+fn header_fields_round_trip() {
+    let header = CcsdsPrimaryHeader { version: 0,
+                                      packet_type: PacketType::Command,
+                                      secondary_header_flag: true,
+                                      apid: 0x5A5,
+                                      sequence_flags: SequenceFlags::First,
+                                      sequence_count: 0x2ABC,
+                                      length: 0x1234,
+    };
+
+    // 000 1 1 10110100101, 01 10101010111100, 0x1234
+    let bytes = header.encode();
+    assert_eq!(bytes, [0x1D, 0xA5, 0x6A, 0xBC, 0x12, 0x34]);
+    assert_eq!(CcsdsPrimaryHeader::decode(&bytes).unwrap(), header);
+    assert_eq!(header.packet_length(), CCSDS_PRI_HEADER_SIZE + 0x1234 + 1);
+
+    // each field decodes on its own, without bleeding into its neighbours
+    let decoded = CcsdsPrimaryHeader::decode(&[0xE7, 0xFF, 0x3F, 0xFF, 0xFF, 0xFF]).unwrap();
+    assert_eq!(decoded.version, 7);
+    assert_eq!(decoded.packet_type, PacketType::Telemetry);
+    assert!(!decoded.secondary_header_flag);
+    assert_eq!(decoded.apid, CCSDS_IDLE_APID);
+    assert!(decoded.is_idle());
+    assert_eq!(decoded.sequence_flags, SequenceFlags::Continuation);
+    assert_eq!(decoded.sequence_count, 0x3FFF);
+    assert_eq!(decoded.length, 0xFFFF);
+    assert_eq!(decoded.packet_length(), CCSDS_MAX_PACKET_LENGTH);
+
+    for flags in [SequenceFlags::Continuation, SequenceFlags::First, SequenceFlags::Last, SequenceFlags::Unsegmented].iter() {
+        let header = CcsdsPrimaryHeader { sequence_flags: *flags, ..Default::default() };
+        assert_eq!(CcsdsPrimaryHeader::decode(&header.encode()).unwrap().sequence_flags, *flags);
+    }
+
+    assert!(CcsdsPrimaryHeader::decode(&[0x08, 0x00, 0xC0]).is_err());
+
+    let mut header = CcsdsPrimaryHeader::default();
+    assert!(header.set_packet_length(CCSDS_PRI_HEADER_SIZE).is_err());
+    assert!(header.set_packet_length(CCSDS_MAX_PACKET_LENGTH + 1).is_err());
+}
+
+#[test]
+fn packet_split_across_reads() {
+    let mut framer = CcsdsFramer::new(CcsdsFramingSettings::default());
+    let first = packet(0x10, 1, &[1, 2, 3, 4, 5]);
+    let second = packet(0x11, 2, &[6, 7]);
+
+    // part of a header, then the rest of the first packet and part of the second
+    let mut buffer = BytesMut::from(&first[..4]);
+    assert_eq!(framer.next_message(&mut buffer).unwrap(), None);
+
+    buffer.extend_from_slice(&first[4..8]);
+    assert_eq!(framer.next_message(&mut buffer).unwrap(), None);
+
+    buffer.extend_from_slice(&first[8..]);
+    buffer.extend_from_slice(&second[..3]);
+    assert_eq!(framer.next_message(&mut buffer).unwrap().unwrap(), &first[..]);
+    assert_eq!(framer.next_message(&mut buffer).unwrap(), None);
+
+    buffer.extend_from_slice(&second[3..]);
+    assert_eq!(framer.next_message(&mut buffer).unwrap().unwrap(), &second[..]);
+    assert!(buffer.is_empty());
+
+    assert_eq!(framer.resync_count, 0);
+    assert_eq!(framer.bytes_skipped, 0);
+}
+
+#[test]
+fn bad_version_resynchronizes_once() {
+    let mut framer = CcsdsFramer::new(CcsdsFramingSettings::default());
+    let valid = packet(0x20, 3, &[9, 9, 9]);
+
+    // three bytes of a header with a version of 7, then a valid packet
+    let mut buffer = BytesMut::from(&[0xE0, 0xE0, 0xE0][..]);
+    buffer.extend_from_slice(&valid);
+
+    assert!(framer.next_message(&mut buffer).is_err());
+    assert_eq!(framer.next_message(&mut buffer).unwrap().unwrap(), &valid[..]);
+    assert!(buffer.is_empty());
+
+    assert_eq!(framer.resync_count, 1);
+    assert_eq!(framer.bytes_skipped, 3);
+}
+
+#[test]
+fn out_of_range_length_resynchronizes_once() {
+    let settings = CcsdsFramingSettings { min_length: 8, max_length: 64, time_code: None };
+    let mut framer = CcsdsFramer::new(settings);
+
+    let too_long = packet(0x30, 0, &[0; 100]);
+    let valid = packet(0x31, 1, &[1, 2, 3]);
+
+    // a header giving a packet above the maximum, followed by bytes which never form a valid header
+    let mut buffer = BytesMut::from(&too_long[..CCSDS_PRI_HEADER_SIZE]);
+    buffer.extend_from_slice(&[0xFF; 10]);
+    buffer.extend_from_slice(&valid);
+
+    let mut errors = 0;
+    let message = loop {
+        match framer.next_message(&mut buffer) {
+            Ok(Some(message)) => break message,
+            Ok(None) => panic!("framer waited on a malformed header"),
+            Err(_) => errors += 1,
+        }
+    };
+
+    assert_eq!(errors, 1);
+    assert_eq!(&message[..], &valid[..]);
+    assert_eq!(framer.resync_count, 1);
+    assert_eq!(framer.bytes_skipped, CCSDS_PRI_HEADER_SIZE as u64 + 10);
+
+    // a second loss of synchronization is counted again
+    buffer.extend_from_slice(&[0xFF, 0xFF]);
+    buffer.extend_from_slice(&valid);
+    assert!(framer.next_message(&mut buffer).is_err());
+    assert_eq!(framer.next_message(&mut buffer).unwrap().unwrap(), &valid[..]);
+    assert_eq!(framer.resync_count, 2);
+    assert_eq!(framer.bytes_skipped, CCSDS_PRI_HEADER_SIZE as u64 + 12);
+}
+
+#[test]
+fn resync_waits_for_more_bytes() {
+    let mut framer = CcsdsFramer::new(CcsdsFramingSettings::default());
+    let valid = packet(0x40, 7, &[4, 5]);
+
+    // the error is reported once, even when the valid header arrives in a later read
+    let mut buffer = BytesMut::from(&[0xE0; 8][..]);
+    assert!(framer.next_message(&mut buffer).is_err());
+    assert_eq!(buffer.len(), CCSDS_PRI_HEADER_SIZE - 1);
+
+    buffer.extend_from_slice(&valid);
+    assert_eq!(framer.next_message(&mut buffer).unwrap().unwrap(), &valid[..]);
+
+    assert_eq!(framer.resync_count, 1);
+    assert_eq!(framer.bytes_skipped, 8);
+}
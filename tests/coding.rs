@@ -0,0 +1,65 @@
+extern crate backplane;
+extern crate bytes;
+
+use bytes::BytesMut;
+
+use backplane::coding::*;
+use backplane::transform::*;
+
+
+// the first bytes of the pseudo-random sequence from CCSDS 131.0-B
+const PN_START: [u8; 16] = [0xFF, 0x48, 0x0E, 0xC0, 0x9A, 0x0D, 0x70, 0xBC,
+                            0x8E, 0x2C, 0x93, 0xAD, 0xA7, 0xB7, 0x46, 0xCE];
+
+#[test]
+fn pn_sequence_known_answer() {
+    let sequence = pn_sequence();
+
+    assert_eq!(&sequence[..PN_START.len()], &PN_START[..]);
+}
+
+#[test]
+fn randomize_is_its_own_inverse() {
+    let sequence = pn_sequence();
+    let original: Vec<u8> = (0..600).map(|index| index as u8).collect();
+
+    let mut frame = original.clone();
+    randomize(&mut frame, &sequence);
+    assert_ne!(frame, original);
+
+    // the sequence repeats every 255 bytes
+    assert_eq!(frame[255] ^ original[255], PN_START[0]);
+
+    randomize(&mut frame, &sequence);
+    assert_eq!(frame, original);
+}
+
+#[test]
+fn crc16_known_answers() {
+    assert_eq!(crc16_ccitt(b"123456789"), 0x29B1);
+    assert_eq!(crc16_ccitt(&[]), 0xFFFF);
+
+    // a telecommand frame with a known frame error control field
+    let frame = [0x06, 0x00, 0x0C, 0xF0, 0x00, 0x04, 0x00, 0x55, 0x88, 0x73, 0xC9, 0x00, 0x00, 0x05, 0x21];
+    assert_eq!(crc16_ccitt(&frame), 0x75FB);
+}
+
+#[test]
+fn fecf_append_then_check() {
+    let mut append = FecfAppend;
+    let mut check = FecfCheck::new();
+
+    let frame = BytesMut::from(&b"frame contents"[..]);
+    let with_fecf = append.transform(frame).unwrap().unwrap();
+    assert_eq!(with_fecf.len(), 16);
+
+    let checked = check.transform(with_fecf.clone()).unwrap().unwrap();
+    assert_eq!(checked, with_fecf);
+
+    let mut corrupted = with_fecf;
+    corrupted[0] ^= 0x01;
+    assert!(check.transform(corrupted).is_err());
+
+    assert_eq!(check.frames_good, 1);
+    assert_eq!(check.frames_bad, 1);
+}
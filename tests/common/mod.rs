@@ -0,0 +1,59 @@
+// each test file uses only some of these helpers
+#![allow(dead_code)]
+
+use std::fmt;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+
+/// A file or directory in the temporary directory, named for this test process, which is
+/// removed when it is dropped, including when a test fails.
+#[derive(Debug)]
+pub struct TempPath {
+    path: PathBuf,
+}
+
+impl TempPath {
+    /// A file with the given name, which the test creates
+    pub fn file(name: &str) -> TempPath {
+        return TempPath { path: std::env::temp_dir().join(format!("backplane_{}_{}", std::process::id(), name)) };
+    }
+
+    /// An empty directory with the given name
+    pub fn dir(name: &str) -> TempPath {
+        let temp = TempPath::file(name);
+        let _ = std::fs::remove_dir_all(&temp.path);
+        std::fs::create_dir_all(&temp.path).unwrap();
+        return temp;
+    }
+}
+
+impl Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        return &self.path;
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        return &self.path;
+    }
+}
+
+impl fmt::Display for TempPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.path.display())
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        if self.path.is_dir() {
+            let _ = std::fs::remove_dir_all(&self.path);
+        } else {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
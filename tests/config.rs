@@ -0,0 +1,371 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+mod common;
+use common::*;
+
+use backplane::*;
+use backplane::apid_route::*;
+use backplane::ccsds::*;
+use backplane::config::*;
+use backplane::framing::*;
+use backplane::pacing::*;
+use backplane::queue::*;
+use backplane::reed_solomon::*;
+use backplane::router::*;
+use backplane::tc::*;
+use backplane::time_code::*;
+use backplane::transform::*;
+
+
+const FORMATS: [ConfigFormat; 3] = [ConfigFormat::Json, ConfigFormat::Toml, ConfigFormat::Yaml];
+
+/// Held by tests which set or read environment variables, as the environment is shared by every test thread
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+fn stream_settings() -> StreamSettings {
+    StreamSettings { udp: UdpSettings::from_str("udp:0.0.0.0:9000").unwrap(),
+                     framing: FramingSettings::LengthPrefix { width: LengthWidth::U16,
+                                                              endianness: Endianness::Big,
+                                                              offset: 4,
+                                                              adjustment: -2,
+                                                              max_length: Some(4096),
+                     },
+                     transforms: vec![TransformSettings::Derandomize,
+                                      TransformSettings::ReedSolomonDecode(ReedSolomonSettings::parse("5").unwrap()),
+                                      TransformSettings::FecfCheck],
+                     ..Default::default()
+    }
+}
+
+fn router_config() -> RouterConfig {
+    let mut config = RouterConfig::default();
+
+    config.inputs.insert("radio".to_string(), StreamConfig::Descriptor("udp:0.0.0.0:8001|asm:1115|derandomize".to_string()));
+    config.inputs.insert("archive".to_string(), StreamConfig::Settings { stream: StreamOption::File,
+                                                                         settings: stream_settings(),
+    });
+
+    config.outputs.insert("display".to_string(), StreamConfig::Descriptor("tcp_client:127.0.0.1:9000|ccsds".to_string()));
+    config.outputs.insert("serial".to_string(), StreamConfig::Settings { stream: StreamOption::Serial,
+                                                                         settings: StreamSettings::default(),
+    });
+
+    config.routes.insert("telemetry".to_string(), RouteSettings { inputs: vec!["radio".to_string(), "archive".to_string()],
+                                                                  outputs: vec!["display".to_string(), "serial".to_string()],
+                                                                  paused: false,
+                                                                  pacing: Some(PacingSettings::BytesPerSecond { rate: 10000, burst: 0 }),
+    });
+
+    config.queues.insert("display".to_string(), QueueSettings { capacity: 100,
+                                                                policy: FullQueuePolicy::DropOldest,
+                                                                max_bandwidth: Some(1_000_000),
+    });
+
+    config
+}
+
+fn apid_route_settings() -> ApidRouteSettings {
+    let mut outputs = BTreeMap::new();
+    outputs.insert("housekeeping".to_string(), StreamConfig::Descriptor("file:hk.bin".to_string()));
+
+    let rule = ApidRule { apids: ApidSet { apids: vec![1, 2], ranges: vec![(0x100, 0x1FF)] },
+                          packet_type: Some(PacketType::Telemetry),
+                          time_range: Some(TimeRange { start: Some(CcsdsTime::new(1_600_000_000, 0)), end: None }),
+                          action: ApidAction::Route(vec!["housekeeping".to_string()]),
+    };
+
+    ApidRouteSettings { outputs,
+                        rules: vec![rule],
+                        default: ApidAction::Drop,
+                        monitor_sequence: true,
+                        time_code: Some(TimeCodeSettings::Cds(CdsSettings { epoch: Epoch::Custom(946_728_000), ..Default::default() })),
+    }
+}
+
+#[test]
+fn router_config_round_trips_between_formats() {
+    let config = router_config();
+
+    for from in FORMATS.iter() {
+        let text = from.format(&config).unwrap();
+        let parsed: RouterConfig = from.parse(&text).unwrap();
+        assert_eq!(parsed, config, "{} round trip", from);
+
+        for to in FORMATS.iter() {
+            let converted: RouterConfig = to.parse(&to.format(&parsed).unwrap()).unwrap();
+            assert_eq!(converted, config, "{} to {} round trip", from, to);
+        }
+    }
+}
+
+#[test]
+fn stream_settings_round_trip_between_formats() {
+    let settings = stream_settings();
+
+    for format in FORMATS.iter() {
+        let parsed: StreamSettings = format.parse(&format.format(&settings).unwrap()).unwrap();
+        assert_eq!(parsed, settings, "{} round trip", format);
+    }
+}
+
+#[test]
+fn apid_route_settings_round_trip_between_formats() {
+    let settings = apid_route_settings();
+
+    for format in FORMATS.iter() {
+        let parsed: ApidRouteSettings = format.parse(&format.format(&settings).unwrap()).unwrap();
+        assert_eq!(parsed, settings, "{} round trip", format);
+    }
+}
+
+#[test]
+fn hand_written_toml_and_yaml_match_json() {
+    let json = r#"{
+        "inputs": { "radio": "udp:0.0.0.0:8001|ccsds" },
+        "outputs": { "archive": "file:archive.bin" },
+        "routes": { "telemetry": { "inputs": ["radio"], "outputs": ["archive"] } }
+    }"#;
+
+    let toml = r#"
+        # packets from the radio are archived
+        [inputs]
+        radio = "udp:0.0.0.0:8001|ccsds"
+
+        [outputs]
+        archive = "file:archive.bin"
+
+        [routes.telemetry]
+        inputs = ["radio"]
+        outputs = ["archive"]
+    "#;
+
+    let yaml = "
+# packets from the radio are archived
+inputs:
+  radio: udp:0.0.0.0:8001|ccsds
+outputs:
+  archive: file:archive.bin
+routes:
+  telemetry:
+    inputs: [radio]
+    outputs: [archive]
+";
+
+    let from_json: RouterConfig = ConfigFormat::Json.parse(json).unwrap();
+    let from_toml: RouterConfig = ConfigFormat::Toml.parse(toml).unwrap();
+    let from_yaml: RouterConfig = ConfigFormat::Yaml.parse(yaml).unwrap();
+
+    assert_eq!(from_toml, from_json);
+    assert_eq!(from_yaml, from_json);
+}
+
+#[test]
+fn format_is_selected_by_extension() {
+    let dir = TempPath::dir("config_extension");
+
+    let config = router_config();
+
+    for name in ["router.json", "router.toml", "router.yaml", "router.yml"].iter() {
+        let path = dir.join(name);
+        config.save(&path).unwrap();
+        assert_eq!(RouterConfig::load(&path).unwrap(), config, "{}", name);
+    }
+
+    assert!(ConfigFormat::from_path(&dir.join("router.ini")).is_err());
+    assert!(ConfigFormat::from_path(&dir.join("router")).is_err());
+}
+
+#[test]
+fn expands_environment_variables() {
+    let _env = ENV_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+
+    std::env::set_var("BACKPLANE_TEST_IP", "10.0.0.5");
+    std::env::remove_var("BACKPLANE_TEST_UNSET");
+
+    assert_eq!(expand_variables("udp:${BACKPLANE_TEST_IP}:${BACKPLANE_TEST_UNSET:-8001}").unwrap(), "udp:10.0.0.5:8001");
+    assert_eq!(expand_variables("cost $$5 and $x").unwrap(), "cost $5 and $x");
+    assert!(expand_variables("${BACKPLANE_TEST_UNSET}").unwrap_err().contains("BACKPLANE_TEST_UNSET"));
+    assert!(expand_variables("${BACKPLANE_TEST_IP").is_err());
+}
+
+#[test]
+fn includes_are_merged_under_the_including_file() {
+    let _env = ENV_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+    let dir = TempPath::dir("config_include");
+
+    std::env::set_var("BACKPLANE_TEST_STATION_PORT", "9100");
+
+    std::fs::write(dir.join("common.yaml"), "
+outputs:
+  archive: file:archive.bin
+routes:
+  telemetry:
+    inputs: [radio]
+    outputs: [archive]
+").unwrap();
+
+    std::fs::write(dir.join("station.toml"), r#"
+        include = "common.yaml"
+
+        [inputs]
+        radio = "udp:0.0.0.0:${BACKPLANE_TEST_STATION_PORT}|ccsds"
+
+        [outputs]
+        archive = "file:${BACKPLANE_TEST_ARCHIVE:-station.bin}"
+    "#).unwrap();
+
+    let config = RouterConfig::load(&dir.join("station.toml")).unwrap();
+
+    assert_eq!(config.inputs["radio"], StreamConfig::Descriptor("udp:0.0.0.0:9100|ccsds".to_string()));
+    assert_eq!(config.outputs["archive"], StreamConfig::Descriptor("file:station.bin".to_string()));
+    assert_eq!(config.routes["telemetry"].outputs, vec!["archive".to_string()]);
+}
+
+#[test]
+fn variables_set_numeric_fields() {
+    let _env = ENV_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+    let dir = TempPath::dir("config_numeric");
+
+    std::env::set_var("BACKPLANE_TEST_NUMERIC_PORT", "9200");
+    std::env::set_var("BACKPLANE_TEST_NUMERIC_PAUSED", "true");
+    std::env::set_var("BACKPLANE_TEST_NUMERIC_FILE", "1234");
+
+    std::fs::write(dir.join("ports.yaml"), r#"
+inputs:
+  radio:
+    stream: Udp
+    settings:
+      udp: { ip: "0.0.0.0", port: "${BACKPLANE_TEST_NUMERIC_PORT}" }
+  ground:
+    stream: TcpServer
+    settings:
+      tcp_server: { ip: "0.0.0.0", port: "${BACKPLANE_TEST_NUMERIC_UNSET:-9300}" }
+outputs:
+  archive:
+    stream: File
+    settings:
+      file: { file_name: "archive_${BACKPLANE_TEST_NUMERIC_FILE}.bin" }
+routes:
+  telemetry: { inputs: [radio, ground], outputs: [archive], paused: "${BACKPLANE_TEST_NUMERIC_PAUSED}" }
+"#).unwrap();
+
+    let config = RouterConfig::load(&dir.join("ports.yaml")).unwrap();
+
+    let (_, radio) = config.inputs["radio"].settings().unwrap();
+    assert_eq!(radio.udp.port, 9200);
+
+    let (_, ground) = config.inputs["ground"].settings().unwrap();
+    assert_eq!(ground.tcp_server.port, 9300);
+
+    // a reference within a longer string stays a string
+    let (_, archive) = config.outputs["archive"].settings().unwrap();
+    assert_eq!(archive.file.file_name, "archive_1234.bin");
+
+    assert!(config.routes["telemetry"].paused);
+}
+
+#[test]
+fn variables_keep_string_fields_as_strings() {
+    let _env = ENV_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+    let dir = TempPath::dir("config_string_fields");
+
+    std::env::set_var("BACKPLANE_TEST_STRING_NAME", "2024");
+    std::env::set_var("BACKPLANE_TEST_STRING_PORT", "9400");
+
+    std::fs::write(dir.join("numbers.json"), r#"{
+        "inputs": {
+            "radio": { "stream": "Udp", "settings": { "udp": { "ip": "0.0.0.0", "port": "${BACKPLANE_TEST_STRING_PORT}" } } }
+        },
+        "outputs": {
+            "archive": { "stream": "File", "settings": { "file": { "file_name": "${BACKPLANE_TEST_STRING_NAME}" } } },
+            "flag": "${BACKPLANE_TEST_STRING_NAME}"
+        },
+        "routes": { "telemetry": { "inputs": ["${BACKPLANE_TEST_STRING_NAME}"], "outputs": ["archive"] } }
+    }"#).unwrap();
+
+    let config = RouterConfig::load(&dir.join("numbers.json")).unwrap();
+
+    // the same variable sets a string field, a descriptor and a name, while another sets a port
+    let (_, archive) = config.outputs["archive"].settings().unwrap();
+    assert_eq!(archive.file.file_name, "2024");
+    assert_eq!(config.outputs["flag"], StreamConfig::Descriptor("2024".to_string()));
+    assert_eq!(config.routes["telemetry"].inputs, vec!["2024".to_string()]);
+
+    let (_, radio) = config.inputs["radio"].settings().unwrap();
+    assert_eq!(radio.udp.port, 9400);
+
+    // a literal number in a string field is still an error, at the key within the stream settings
+    std::fs::write(dir.join("literal.json"), r#"{
+        "outputs": { "archive": { "stream": "File", "settings": { "file": { "file_name": 2024 } } } }
+    }"#).unwrap();
+    let err = RouterConfig::load(&dir.join("literal.json")).unwrap_err();
+    assert!(err.contains("outputs.archive.settings.file.file_name"), "{}", err);
+}
+
+#[test]
+fn convert_keeps_variables_and_includes() {
+    let dir = TempPath::dir("config_convert_template");
+
+    std::fs::write(dir.join("station.json"), r#"{
+        "include": "common.yaml",
+        "inputs": { "radio": "udp:0.0.0.0:${BACKPLANE_TEST_CONVERT_UNSET}|ccsds" },
+        "outputs": { "archive": { "stream": "Udp", "settings": { "udp": { "ip": "${BACKPLANE_TEST_CONVERT_IP:-127.0.0.1}", "port": "${BACKPLANE_TEST_CONVERT_PORT}" } } } }
+    }"#).unwrap();
+
+    // the included file does not exist and the variable is unset, as neither is read
+    convert_config(&dir.join("station.json"), &dir.join("station.toml")).unwrap();
+
+    let text = std::fs::read_to_string(dir.join("station.toml")).unwrap();
+    assert!(text.contains("include = \"common.yaml\""), "{}", text);
+    assert!(text.contains("${BACKPLANE_TEST_CONVERT_UNSET}"), "{}", text);
+    assert!(text.contains("${BACKPLANE_TEST_CONVERT_PORT}"), "{}", text);
+
+    let converted = load_config_unexpanded(&dir.join("station.toml")).unwrap();
+    assert_eq!(converted, load_config_unexpanded(&dir.join("station.json")).unwrap());
+}
+
+#[test]
+fn config_errors_name_the_failing_key() {
+    let _env = ENV_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+    let dir = TempPath::dir("config_errors");
+
+    std::fs::write(dir.join("unset.json"), r#"{ "inputs": { "radio": "udp:${BACKPLANE_TEST_NEVER_SET}:8001" } }"#).unwrap();
+    let err = RouterConfig::load(&dir.join("unset.json")).unwrap_err();
+    assert!(err.contains("inputs.radio"), "{}", err);
+    assert!(err.contains("BACKPLANE_TEST_NEVER_SET"), "{}", err);
+
+    std::fs::write(dir.join("bad_type.json"), r#"{ "routes": { "telemetry": { "inputs": "radio", "outputs": [] } } }"#).unwrap();
+    let err = RouterConfig::load(&dir.join("bad_type.json")).unwrap_err();
+    assert!(err.contains("routes.telemetry.inputs"), "{}", err);
+
+    std::fs::write(dir.join("a.json"), r#"{ "include": "b.json" }"#).unwrap();
+    std::fs::write(dir.join("b.json"), r#"{ "include": ["a.json"] }"#).unwrap();
+    let err = RouterConfig::load(&dir.join("a.json")).unwrap_err();
+    assert!(err.contains("cycle"), "{}", err);
+}
+
+#[test]
+fn transform_settings_are_checked_on_load() {
+    let dir = TempPath::dir("config_transforms");
+
+    let bad_transforms = [(TransformSettings::ReedSolomonDecode(ReedSolomonSettings { interleave: 6, dual_basis: true }), "interleave"),
+                          (TransformSettings::TcFrame(TcFrameSettings { scid: 0x400, ..Default::default() }), "spacecraft id"),
+                          (TransformSettings::TcFrame(TcFrameSettings { vcid: 0x40, ..Default::default() }), "virtual channel id")];
+
+    for (transform, message) in bad_transforms.iter() {
+        let settings = StreamSettings { transforms: vec![TransformSettings::Derandomize, transform.clone()], ..Default::default() };
+        let mut config = RouterConfig::default();
+        config.outputs.insert("uplink".to_string(), StreamConfig::Settings { stream: StreamOption::Udp, settings });
+
+        let err = config.validate().unwrap_err();
+        assert!(err.contains(message), "{}", err);
+
+        let path = dir.join("transforms.json");
+        config.save(&path).unwrap();
+        let err = RouterConfig::load(&path).unwrap_err();
+        assert!(err.contains("outputs.uplink"), "{}", err);
+        assert!(err.contains(message), "{}", err);
+    }
+}
@@ -0,0 +1,119 @@
+extern crate backplane;
+extern crate serde_json;
+
+use std::net::{TcpListener, UdpSocket};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod common;
+use common::*;
+
+use backplane::control::*;
+use backplane::pacing::*;
+use backplane::router::*;
+
+
+#[test]
+fn commands_round_trip_through_strings() {
+    let commands = ["list",
+                    "stats",
+                    "open input radio udp:127.0.0.1:8001|ccsds",
+                    "open output debug tcp_client:127.0.0.1:9000",
+                    "close radio",
+                    "connect telemetry radio debug",
+                    "disconnect telemetry",
+                    "pause telemetry",
+                    "resume telemetry",
+                    "pace telemetry messages:10",
+                    "pace telemetry none"];
+    for command in commands.iter() {
+        assert_eq!(&ControlCommand::from_str(command).unwrap().to_string(), command);
+    }
+
+    assert_eq!(ControlCommand::from_str("pace telemetry delay:50"),
+               Ok(ControlCommand::Pace { route: "telemetry".to_string(), pacing: Some(PacingSettings::Delay { millis: 50 }) }));
+    assert_eq!(ControlCommand::from_str("  close   radio \n"), Ok(ControlCommand::Close { name: "radio".to_string() }));
+
+    for bad in ["", "open radio udp:127.0.0.1:8001", "open sideways radio udp:127.0.0.1:8001", "close", "pace telemetry fast", "reboot"].iter() {
+        assert!(ControlCommand::from_str(bad).is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn commands_edit_the_routing_graph() {
+    let dir = TempPath::dir("control_apply");
+    let mut router = Router::open(RouterConfig::default()).unwrap();
+
+    let apply = |router: &mut Router, command: &str| ControlCommand::from_str(command).unwrap().apply(router);
+
+    apply(&mut router, "open input radio udp:127.0.0.1:0").unwrap();
+    apply(&mut router, &format!("open output archive file:{}", dir.join("archive.bin").display())).unwrap();
+    assert!(router.config.inputs.contains_key("radio"));
+    assert!(router.config.outputs.contains_key("archive"));
+    assert!(apply(&mut router, "open output radio udp:127.0.0.1:9000").unwrap_err().contains("already exists"));
+
+    apply(&mut router, "connect telemetry radio archive").unwrap();
+    assert_eq!(router.config.routes["telemetry"].inputs, vec!["radio".to_string()]);
+    assert_eq!(router.config.routes["telemetry"].outputs, vec!["archive".to_string()]);
+
+    apply(&mut router, "pause telemetry").unwrap();
+    assert!(router.config.routes["telemetry"].paused);
+    apply(&mut router, "resume telemetry").unwrap();
+    assert!(!router.config.routes["telemetry"].paused);
+    assert!(apply(&mut router, "pause downlink").is_err());
+
+    // closing the route's only input leaves it empty, so it is removed
+    apply(&mut router, "close radio").unwrap();
+    assert!(!router.config.inputs.contains_key("radio"));
+    assert!(router.config.routes.is_empty());
+    assert!(router.config.outputs.contains_key("archive"));
+    assert!(apply(&mut router, "close radio").unwrap_err().contains("No stream named 'radio'"));
+
+    router.stop();
+}
+
+#[test]
+fn serves_commands_on_loopback() {
+    let dir = TempPath::dir("control_socket");
+
+    let router = Arc::new(Mutex::new(Router::open(RouterConfig::default()).unwrap()));
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let control_router = Arc::clone(&router);
+    thread::spawn(move || serve_control(listener, control_router));
+
+    let send = |command: &str| send_control_command(&address, &ControlCommand::from_str(command).unwrap());
+
+    let reply = send("open input radio udp:127.0.0.1:0").unwrap();
+    assert_eq!(reply["opened"], serde_json::json!(["input 'radio'"]));
+    send(&format!("open output archive file:{}", dir.join("archive.bin").display())).unwrap();
+    send("connect telemetry radio archive").unwrap();
+
+    let input_address = router.lock().unwrap().input_addresses()["radio"].clone();
+    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+    sender.send_to(b"hello", &input_address).unwrap();
+
+    // the output's counters are updated after the route's, so wait for both
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut stats = send("stats").unwrap();
+    while (stats["routes"]["telemetry"]["messages"] != 1 || stats["outputs"]["archive"]["bytes"] != 5) && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+        stats = send("stats").unwrap();
+    }
+    assert_eq!(stats["inputs"]["radio"]["messages"], 1);
+    assert_eq!(stats["outputs"]["archive"]["bytes"], 5);
+    assert_eq!(stats["routes"]["telemetry"]["messages"], 1);
+
+    send("close radio").unwrap();
+    let listed = send("list").unwrap();
+    assert_eq!(listed["routes"], serde_json::json!({}));
+    assert!(listed["inputs"].get("radio").is_none());
+
+    // a failed command is answered with its error
+    assert_eq!(send("close radio"), Err("No stream named 'radio'".to_string()));
+
+    router.lock().unwrap().stop();
+}
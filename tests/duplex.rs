@@ -0,0 +1,47 @@
+extern crate backplane;
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::Duration;
+
+use backplane::duplex::*;
+
+
+/// Connect a pair of TCP sockets, returning the accepted end and the connecting end
+fn tcp_pair() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+    (server, client)
+}
+
+#[test]
+fn bridge_stops_both_directions_when_one_side_closes() {
+    let (left, mut left_peer) = tcp_pair();
+    let (right, mut right_peer) = tcp_pair();
+
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        let _ = sender.send(bridge(DuplexStream::Tcp(left), DuplexStream::Tcp(right)));
+    });
+
+    left_peer.write_all(b"left to right").unwrap();
+    let mut received = [0u8; 13];
+    right_peer.read_exact(&mut received).unwrap();
+    assert_eq!(&received, b"left to right");
+
+    right_peer.write_all(b"right to left").unwrap();
+    left_peer.read_exact(&mut received).unwrap();
+    assert_eq!(&received, b"right to left");
+
+    // closing the left side ends the bridge, which closes the right side too
+    drop(left_peer);
+    let result = receiver.recv_timeout(Duration::from_secs(5)).expect("bridge did not return");
+    assert_eq!(result, Ok(()));
+
+    right_peer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut rest = Vec::new();
+    assert_eq!(right_peer.read_to_end(&mut rest).unwrap(), 0);
+}
@@ -0,0 +1,146 @@
+extern crate backplane;
+extern crate bytes;
+
+use bytes::BytesMut;
+
+use backplane::framing::*;
+
+
+/// Encode each message with a framer, then frame them back out of the encoded bytes
+fn round_trip(framer: &mut dyn Framer, messages: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    let mut buffer = BytesMut::new();
+    for message in messages.iter() {
+        framer.encode_message(message, &mut buffer).unwrap();
+    }
+
+    let mut framed = Vec::new();
+    while let Some(message) = framer.next_message(&mut buffer).unwrap() {
+        framed.push(message.to_vec());
+    }
+    assert!(buffer.is_empty());
+
+    framed
+}
+
+fn test_messages() -> Vec<Vec<u8>> {
+    vec![vec![1, 2, 3],
+         vec![0],
+         vec![0, 0, 1, 0],
+         vec![SLIP_END, SLIP_ESC, 7, SLIP_ESC_END, SLIP_END],
+         (0..=255).collect(),
+         (0..600).map(|index| (index % 7) as u8 + 1).collect()]
+}
+
+#[test]
+fn cobs_round_trip() {
+    let messages = test_messages();
+    assert_eq!(round_trip(&mut CobsFramer, &messages), messages);
+}
+
+#[test]
+fn cobs_known_answer() {
+    let mut buffer = BytesMut::new();
+    CobsFramer.encode_message(&[0x11, 0x22, 0x00, 0x33], &mut buffer).unwrap();
+    assert_eq!(&buffer[..], &[0x03, 0x11, 0x22, 0x02, 0x33, 0x00]);
+}
+
+#[test]
+fn slip_round_trip() {
+    let messages = test_messages();
+    assert_eq!(round_trip(&mut SlipFramer, &messages), messages);
+}
+
+#[test]
+fn slip_known_answer() {
+    let mut buffer = BytesMut::new();
+    SlipFramer.encode_message(&[1, SLIP_END, SLIP_ESC], &mut buffer).unwrap();
+    assert_eq!(&buffer[..], &[SLIP_END, 1, SLIP_ESC, SLIP_ESC_END, SLIP_ESC, SLIP_ESC_ESC, SLIP_END]);
+}
+
+#[test]
+fn length_prefix_round_trip() {
+    let messages = test_messages();
+
+    let mut framer = LengthPrefixFramer::new(LengthWidth::U16, Endianness::Big, 0, 0);
+    assert_eq!(round_trip(&mut framer, &messages), messages);
+
+    let mut framer = LengthPrefixFramer::new(LengthWidth::U32, Endianness::Little, 0, -1);
+    assert_eq!(round_trip(&mut framer, &messages), messages);
+}
+
+#[test]
+fn length_prefix_strips_prefix() {
+    let mut framer = LengthPrefixFramer::new(LengthWidth::U16, Endianness::Big, 0, 0);
+
+    let mut buffer = BytesMut::new();
+    framer.encode_message(&[7, 8, 9], &mut buffer).unwrap();
+    assert_eq!(&buffer[..], &[0, 3, 7, 8, 9]);
+
+    assert_eq!(framer.next_message(&mut buffer).unwrap().unwrap(), &[7, 8, 9][..]);
+}
+
+#[test]
+fn length_field_in_header_is_kept() {
+    // a two byte id, then a length field counting the bytes after it, less one
+    let mut framer = LengthPrefixFramer::new(LengthWidth::U16, Endianness::Big, 2, 1);
+
+    let mut buffer = BytesMut::from(&[0xAB, 0xCD, 0x00, 0x02, 1, 2, 3, 0xEE][..]);
+    let message = framer.next_message(&mut buffer).unwrap().unwrap();
+    assert_eq!(&message[..], &[0xAB, 0xCD, 0x00, 0x02, 1, 2, 3]);
+    assert_eq!(&buffer[..], &[0xEE]);
+
+    // the length field is filled in over whatever the message holds in its place
+    let mut encoded = BytesMut::new();
+    framer.encode_message(&[0xAB, 0xCD, 0xFF, 0xFF, 1, 2, 3], &mut encoded).unwrap();
+    assert_eq!(&encoded[..], &[0xAB, 0xCD, 0x00, 0x02, 1, 2, 3]);
+
+    // messages too short to hold the length field can not be encoded
+    assert!(framer.encode_message(&[0xAB, 0xCD, 0x00], &mut encoded).is_err());
+
+    let messages = vec![vec![1, 2, 0, 0, 5], vec![3, 4, 0, 0, 6, 7, 8, 9]];
+    let framed = round_trip(&mut framer, &messages);
+    assert_eq!(framed, vec![vec![1, 2, 0, 0, 5], vec![3, 4, 0, 3, 6, 7, 8, 9]]);
+}
+
+#[test]
+fn framers_wait_for_whole_messages() {
+    let mut framer = LengthPrefixFramer::new(LengthWidth::U8, Endianness::Big, 0, 0);
+    let mut buffer = BytesMut::from(&[3, 1, 2][..]);
+    assert_eq!(framer.next_message(&mut buffer).unwrap(), None);
+
+    buffer.extend_from_slice(&[3]);
+    assert_eq!(framer.next_message(&mut buffer).unwrap().unwrap(), &[1, 2, 3][..]);
+
+    let mut buffer = BytesMut::from(&[0x02, 0x11][..]);
+    assert_eq!(CobsFramer.next_message(&mut buffer).unwrap(), None);
+}
+
+#[test]
+fn length_prefix_max_length() {
+    let mut framer = LengthPrefixFramer::new(LengthWidth::U32, Endianness::Big, 0, 0).with_max_length(8);
+
+    // a corrupt length field is dropped a byte at a time instead of waiting for gigabytes
+    let mut buffer = BytesMut::from(&[0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 2, 5, 6][..]);
+    assert!(framer.next_message(&mut buffer).is_err());
+    assert_eq!(buffer.len(), 9);
+
+    let mut errors = 1;
+    let message = loop {
+        match framer.next_message(&mut buffer) {
+            Ok(Some(message)) => break message,
+            Ok(None) => panic!("framer waited on a corrupt length"),
+            Err(_) => errors += 1,
+        }
+    };
+    assert_eq!(errors, 4);
+    assert_eq!(&message[..], &[5, 6]);
+    assert!(buffer.is_empty());
+
+    // messages which would be read back as too long are not encoded
+    let mut encoded = BytesMut::new();
+    framer.encode_message(&[1, 2, 3, 4], &mut encoded).unwrap();
+    assert!(framer.encode_message(&[1, 2, 3, 4, 5], &mut encoded).is_err());
+
+    let settings = "length:u32be:0:0:8".parse::<FramingSettings>().unwrap();
+    assert_eq!(settings.to_string(), "length:u32be:0:0:8");
+}
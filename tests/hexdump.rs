@@ -0,0 +1,98 @@
+extern crate backplane;
+
+use std::str::FromStr;
+
+mod common;
+use common::*;
+
+use backplane::*;
+use backplane::ccsds::*;
+use backplane::hexdump::*;
+use backplane::router::*;
+use backplane::time_code::*;
+
+
+#[test]
+fn hexdump_descriptors() {
+    assert_eq!(HexdumpSettings::from_str("hexdump").unwrap(), HexdumpSettings::default());
+
+    let settings = HexdumpSettings::from_str("hexdump:width=32:max=64:noascii:ccsds:dump.txt").unwrap();
+    assert_eq!(settings, HexdumpSettings { width: 32,
+                                           ascii: false,
+                                           max_bytes: 64,
+                                           ccsds: true,
+                                           file_name: Some("dump.txt".to_string()),
+                                           print: false,
+    });
+
+    // a file name may contain ':', and '-' is stdout
+    assert_eq!(HexdumpSettings::from_str("print:C:/logs/dump.txt").unwrap().file_name, Some("C:/logs/dump.txt".to_string()));
+    assert_eq!(HexdumpSettings::from_str("hexdump:-").unwrap().file_name, None);
+
+    let descs = ["hexdump", "print", "hexdump:width=32:ccsds", "print:max=64:dump.txt", "print:width=8:max=4:noascii:ccsds:dump.txt"];
+    for desc in descs.iter() {
+        assert_eq!(&HexdumpSettings::from_str(desc).unwrap().to_string(), desc);
+    }
+
+    assert!(HexdumpSettings::from_str("hex").is_err());
+    assert!(HexdumpSettings::from_str("hexdump:width=0").is_err());
+    assert!(HexdumpSettings::from_str("hexdump:width=wide").is_err());
+}
+
+#[test]
+fn hexdump_render() {
+    let time = CcsdsTime::new(0, 0);
+
+    let text = HexdumpSettings::default().render(&time, b"Hello, world!\x00\x01");
+    assert_eq!(text,
+               "1970-01-01T00:00:00.000000Z 15 bytes\n\
+                0000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 00 01    |Hello, world!..|\n");
+
+    // lines are trimmed, and bytes past the maximum are counted rather than shown
+    let settings = HexdumpSettings { width: 4, ascii: false, max_bytes: 6, ..Default::default() };
+    let bytes: Vec<u8> = (0..10).collect();
+    assert_eq!(settings.render(&time, &bytes),
+               "1970-01-01T00:00:00.000000Z 10 bytes\n\
+                0000  00 01 02 03\n\
+                0004  04 05\n\
+                ... 4 more bytes\n");
+
+    assert_eq!(settings.render(&time, &[]), "1970-01-01T00:00:00.000000Z 0 bytes\n");
+}
+
+#[test]
+fn hexdump_render_ccsds() {
+    let mut header = CcsdsPrimaryHeader { apid: 5, ..Default::default() };
+    header.set_packet_length(CCSDS_PRI_HEADER_SIZE + 2).unwrap();
+    let mut packet = header.encode().to_vec();
+    packet.extend_from_slice(&[0xAB, 0xCD]);
+
+    let settings = HexdumpSettings { ccsds: true, max_bytes: 1, ..Default::default() };
+    let text = settings.render(&CcsdsTime::new(0, 0), &packet);
+
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[1], format!("  {}", header));
+    assert!(lines[1].contains("apid=5"));
+
+    let text = settings.render(&CcsdsTime::new(0, 0), &[0x00, 0x01]);
+    assert!(text.lines().nth(1).unwrap().starts_with("  ccsds error:"), "{}", text);
+}
+
+#[test]
+fn zero_width_is_rejected() {
+    let settings = HexdumpSettings { width: 0, ..Default::default() };
+    assert!(settings.validate().is_err());
+    assert!(settings.open_write_stream().is_err());
+
+    let mut config = RouterConfig::default();
+    let stream_settings = StreamSettings { hexdump: settings, ..Default::default() };
+    config.outputs.insert("dump".to_string(), StreamConfig::Settings { stream: StreamOption::Hexdump, settings: stream_settings });
+    assert!(config.validate().unwrap_err().contains("width"));
+
+    let path = TempPath::file("hexdump_width.json");
+    std::fs::write(&path, r#"{ "outputs": { "dump": { "stream": "Hexdump", "settings": { "hexdump": { "width": 0 } } } } }"#).unwrap();
+    let err = RouterConfig::load(&path).unwrap_err();
+    assert!(err.contains("outputs.dump"), "{}", err);
+    assert!(err.contains("width"), "{}", err);
+}
@@ -0,0 +1,91 @@
+#![cfg(feature = "metrics")]
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod common;
+use common::*;
+
+use backplane::StreamConfig;
+use backplane::metrics::*;
+use backplane::router::*;
+
+
+/// Make a GET request, returning the status line and body of the response
+fn scrape(address: &str, path: &str) -> (String, String) {
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, address).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    let (head, body) = response.split_at(response.find("\r\n\r\n").unwrap());
+    (head.lines().next().unwrap().to_string(), body[4..].to_string())
+}
+
+#[test]
+fn serves_stream_and_route_counters_on_loopback() {
+    let dir = TempPath::dir("metrics");
+
+    let input_desc = "udp:127.0.0.1:0".to_string();
+    let output_desc = format!("file:{}", dir.join("out.bin").display());
+
+    let mut config = RouterConfig::default();
+    config.inputs.insert("radio".to_string(), StreamConfig::Descriptor(input_desc.clone()));
+    config.outputs.insert("archive".to_string(), StreamConfig::Descriptor(output_desc.clone()));
+    config.routes.insert("telemetry".to_string(), RouteSettings { inputs: vec!["radio".to_string()],
+                                                                  outputs: vec!["archive".to_string()],
+                                                                  paused: false,
+                                                                  pacing: None,
+    });
+
+    let router = Router::open(config).unwrap();
+    let input_address = router.input_addresses()["radio"].clone();
+    let router = Arc::new(Mutex::new(router));
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let metrics_router = Arc::clone(&router);
+    thread::spawn(move || serve_metrics(listener, metrics_router));
+
+    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+    sender.send_to(b"hello", &input_address).unwrap();
+    sender.send_to(b"world!", &input_address).unwrap();
+
+    let input_labels = format!("direction=\"input\",name=\"radio\",descriptor=\"{}\"", input_desc);
+    let output_labels = format!("direction=\"output\",name=\"archive\",descriptor=\"{}\"", output_desc);
+    let expected = ["backplane_route_messages_total{route=\"telemetry\"} 2".to_string(),
+                    "backplane_route_bytes_total{route=\"telemetry\"} 11".to_string(),
+                    format!("backplane_stream_messages_total{{{}}} 2", input_labels),
+                    format!("backplane_stream_bytes_total{{{}}} 11", input_labels),
+                    format!("backplane_stream_messages_total{{{}}} 2", output_labels),
+                    format!("backplane_stream_errors_total{{{}}} 0", output_labels),
+                    "# TYPE backplane_stream_bytes_total counter".to_string(),
+                    format!("backplane_stream_last_activity_seconds{{{}}}", input_labels)];
+
+    // the input and output counters are updated separately from the route counters, so wait for all of them
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut body = String::new();
+    while Instant::now() < deadline {
+        let (status, scraped) = scrape(&address, METRICS_PATH);
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        body = scraped;
+
+        if expected.iter().all(|line| body.contains(line.as_str())) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    for line in expected.iter() {
+        assert!(body.contains(line.as_str()), "'{}' is missing from\n{}", line, body);
+    }
+
+    let (status, _) = scrape(&address, "/other");
+    assert_eq!(status, "HTTP/1.1 404 Not Found");
+
+    router.lock().unwrap().stop();
+}
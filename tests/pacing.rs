@@ -0,0 +1,96 @@
+extern crate backplane;
+
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod common;
+use common::*;
+
+use backplane::*;
+use backplane::pacing::*;
+use backplane::router::*;
+
+
+#[test]
+fn pacing_descriptors() {
+    let descs = ["bytes:1000", "bytes:10000:2000", "messages:5", "messages:5:10", "delay:250"];
+    for desc in descs.iter() {
+        assert_eq!(&PacingSettings::from_str(desc).unwrap().to_string(), desc);
+    }
+
+    assert_eq!(PacingSettings::from_str("bytes:10000:2000"), Ok(PacingSettings::BytesPerSecond { rate: 10000, burst: 2000 }));
+    assert_eq!(PacingSettings::from_str("delay:250"), Ok(PacingSettings::Delay { millis: 250 }));
+
+    // a burst of 0 is the same as no burst
+    assert_eq!(PacingSettings::from_str("messages:5:0").unwrap().to_string(), "messages:5");
+
+    for desc in ["bytes:0", "messages:0:10", "delay:0", "bytes:fast", "bytes", "messages:1:2:3", "speed:10"].iter() {
+        assert!(PacingSettings::from_str(desc).is_err(), "{}", desc);
+    }
+}
+
+#[test]
+fn pacers_wait_for_their_rate() {
+    let mut pacer = PacingSettings::MessagesPerSecond { rate: 10, burst: 0 }.pacer();
+
+    // the first message goes at once, and each following message waits for its share of the rate
+    assert_eq!(pacer.reserve(100), Duration::from_secs(0));
+    let wait = pacer.reserve(100);
+    assert!(wait > Duration::from_millis(90) && wait <= Duration::from_millis(100), "{:?}", wait);
+    let wait = pacer.reserve(100);
+    assert!(wait > Duration::from_millis(190) && wait <= Duration::from_millis(200), "{:?}", wait);
+
+    // a delay is a rate of one message per delay
+    let mut pacer = PacingSettings::Delay { millis: 50 }.pacer();
+    assert_eq!(pacer.reserve(1), Duration::from_secs(0));
+    let wait = pacer.reserve(1);
+    assert!(wait > Duration::from_millis(40) && wait <= Duration::from_millis(50), "{:?}", wait);
+}
+
+#[test]
+fn pacers_allow_bursts() {
+    let mut pacer = Pacer::new(PacingUnit::Bytes, 10_000.0, 500.0);
+
+    // after a quiet period, up to the burst size goes at once
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(pacer.reserve(500), Duration::from_secs(0));
+
+    // a message larger than the burst is still sent, and the next waits for its debt to be repaid
+    assert_eq!(pacer.reserve(1000), Duration::from_secs(0));
+    let wait = pacer.reserve(1);
+    assert!(wait > Duration::from_millis(90) && wait <= Duration::from_millis(100), "{:?}", wait);
+}
+
+#[test]
+fn paced_routes_do_not_hold_up_other_routes() {
+    let (replay, slow, fast) = (TempPath::file("pacing_replay.bin"), TempPath::file("pacing_slow.bin"), TempPath::file("pacing_fast.bin"));
+    std::fs::write(&replay, b"1\n2\n3\n4\n5\n").unwrap();
+
+    let mut config = RouterConfig::default();
+    config.inputs.insert("replay".to_string(), StreamConfig::Descriptor(format!("file:{}|line", replay)));
+    config.outputs.insert("slow".to_string(), StreamConfig::Descriptor(format!("file:{}|line", slow)));
+    config.outputs.insert("fast".to_string(), StreamConfig::Descriptor(format!("file:{}|line", fast)));
+    config.routes.insert("paced".to_string(), RouteSettings { inputs: vec!["replay".to_string()],
+                                                              outputs: vec!["slow".to_string()],
+                                                              pacing: Some(PacingSettings::MessagesPerSecond { rate: 2, burst: 0 }),
+                                                              ..Default::default() });
+    config.routes.insert("unpaced".to_string(), RouteSettings { inputs: vec!["replay".to_string()],
+                                                                outputs: vec!["fast".to_string()],
+                                                                ..Default::default() });
+    let router = Router::open(config).unwrap();
+
+    // the input and the unpaced route finish without waiting for the paced route
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while !router.is_finished() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(router.is_finished());
+    assert_eq!(router.output_counters()["fast"].messages, 5);
+    assert!(router.output_counters()["slow"].messages < 5);
+
+    // waiting for the router writes the rest of the paced messages
+    router.wait().unwrap();
+    assert_eq!(std::fs::read(&slow).unwrap(), b"1\n2\n3\n4\n5\n");
+    assert_eq!(std::fs::read(&fast).unwrap(), b"1\n2\n3\n4\n5\n");
+}
@@ -0,0 +1,156 @@
+extern crate backplane;
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+mod common;
+use common::*;
+
+use backplane::pcap::*;
+use backplane::stream_write::*;
+
+
+fn write_capture(path: &Path, link: PcapLink, port: u16, messages: &[&[u8]]) {
+    let mut writer = PcapWriter::new(File::create(path).unwrap(), link, port).unwrap();
+    for message in messages.iter() {
+        assert_eq!(writer.write_bytes(message), Ok(message.len()));
+    }
+}
+
+fn read_capture(path: &Path, port: Option<u16>) -> Result<Vec<PcapPacket>, String> {
+    let mut reader = PcapReader::new(BufReader::new(File::open(path).unwrap()), port)?;
+
+    let mut packets = Vec::new();
+    while let Some(packet) = reader.next_packet()? {
+        packets.push(packet);
+    }
+    return Ok(packets);
+}
+
+fn payloads(packets: &[PcapPacket]) -> Vec<Vec<u8>> {
+    packets.iter().map(|packet| packet.payload.clone()).collect()
+}
+
+#[test]
+fn udp_captures_round_trip() {
+    let path = TempPath::file("pcap_udp.pcap");
+    write_capture(&path, PcapLink::Udp, 8001, &[b"first", b"", b"third message"]);
+
+    let packets = read_capture(&path, None).unwrap();
+    assert_eq!(payloads(&packets), vec![b"first".to_vec(), Vec::new(), b"third message".to_vec()]);
+    assert!(packets.iter().all(|packet| packet.time > UNIX_EPOCH));
+
+    // the port filter keeps packets to or from the port
+    assert_eq!(read_capture(&path, Some(8001)).unwrap().len(), 3);
+    assert_eq!(read_capture(&path, Some(9000)).unwrap(), Vec::new());
+}
+
+#[test]
+fn user_captures_round_trip() {
+    let path = TempPath::file("pcap_user.pcap");
+    write_capture(&path, PcapLink::User, 8001, &[&[0x01, 0x02, 0x03], &[0xFF; 100]]);
+
+    // user records have no ports, so they pass any filter
+    let packets = read_capture(&path, Some(9000)).unwrap();
+    assert_eq!(payloads(&packets), vec![vec![0x01, 0x02, 0x03], vec![0xFF; 100]]);
+
+    let mut writer = PcapWriter::new(File::create(&path).unwrap(), PcapLink::User, 8001).unwrap();
+    assert!(writer.write_bytes(&vec![0x00; PCAP_SNAPLEN as usize + 1]).is_err());
+}
+
+/// A classic little endian, microsecond capture header with the given snapshot length and DLT_USER0
+fn classic_header(snaplen: u32) -> Vec<u8> {
+    let mut header = vec![0xD4, 0xC3, 0xB2, 0xA1, 0x02, 0x00, 0x04, 0x00];
+    header.extend_from_slice(&[0x00; 8]);
+    header.extend_from_slice(&snaplen.to_le_bytes());
+    header.extend_from_slice(&147u32.to_le_bytes());
+    header
+}
+
+fn classic_record(captured_length: u32, data: &[u8]) -> Vec<u8> {
+    let mut record = vec![0x00; 8];
+    record.extend_from_slice(&captured_length.to_le_bytes());
+    record.extend_from_slice(&captured_length.to_le_bytes());
+    record.extend_from_slice(data);
+    record
+}
+
+#[test]
+fn record_lengths_are_bounded() {
+    let path = TempPath::file("pcap_lengths.pcap");
+
+    // a record longer than the snapshot length is an error rather than an allocation of its length
+    let mut capture = classic_header(16);
+    capture.extend(classic_record(4, &[0x01, 0x02, 0x03, 0x04]));
+    capture.extend(classic_record(0xFFFF_FFF0, &[]));
+    std::fs::write(&path, &capture).unwrap();
+
+    let mut reader = PcapReader::new(BufReader::new(File::open(&path).unwrap()), None).unwrap();
+    assert_eq!(reader.next_packet().unwrap().unwrap().payload, vec![0x01, 0x02, 0x03, 0x04]);
+    let error = reader.next_packet().unwrap_err();
+    assert!(error.contains("longer than the limit of 16 bytes"), "{}", error);
+
+    // a snapshot length of 0 still has a limit
+    let mut capture = classic_header(0);
+    capture.extend(classic_record(PCAP_MAX_RECORD_SIZE as u32 + 1, &[]));
+    std::fs::write(&path, &capture).unwrap();
+    assert!(read_capture(&path, None).is_err());
+}
+
+/// A little endian pcapng block of the given type, padding the body to 32 bits
+fn ng_block(block_type: u32, body: &[u8]) -> Vec<u8> {
+    let padded = body.len().div_ceil(4) * 4;
+    let length = (12 + padded) as u32;
+
+    let mut block = block_type.to_le_bytes().to_vec();
+    block.extend_from_slice(&length.to_le_bytes());
+    block.extend_from_slice(body);
+    block.resize(8 + padded, 0);
+    block.extend_from_slice(&length.to_le_bytes());
+    block
+}
+
+/// A pcapng capture with one DLT_USER0 interface with the given if_tsresol, and one enhanced packet with the given timestamp
+fn ng_capture(tsresol: u8, timestamp: u64) -> Vec<u8> {
+    let mut section = 0x1A2B_3C4Du32.to_le_bytes().to_vec();
+    section.extend_from_slice(&[0x01, 0x00, 0x00, 0x00]);
+    section.extend_from_slice(&[0xFF; 8]);
+
+    let mut interface = vec![147, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+    interface.extend_from_slice(&[0x09, 0x00, 0x01, 0x00, tsresol, 0x00, 0x00, 0x00]);
+    interface.extend_from_slice(&[0x00; 4]);
+
+    let mut packet = 0u32.to_le_bytes().to_vec();
+    packet.extend_from_slice(&((timestamp >> 32) as u32).to_le_bytes());
+    packet.extend_from_slice(&(timestamp as u32).to_le_bytes());
+    packet.extend_from_slice(&3u32.to_le_bytes());
+    packet.extend_from_slice(&3u32.to_le_bytes());
+    packet.extend_from_slice(&[0xAA, 0xBB, 0xCC]);
+
+    let mut capture = ng_block(0x0A0D_0D0A, &section);
+    capture.extend(ng_block(1, &interface));
+    capture.extend(ng_block(6, &packet));
+    capture
+}
+
+#[test]
+fn pcapng_timestamps() {
+    let path = TempPath::file("pcap_timestamps.pcap");
+
+    // microseconds are the default resolution
+    std::fs::write(&path, ng_capture(6, 1_600_000_000_250_000)).unwrap();
+    let packets = read_capture(&path, None).unwrap();
+    assert_eq!(packets[0].payload, vec![0xAA, 0xBB, 0xCC]);
+    assert_eq!(packets[0].time, UNIX_EPOCH + Duration::new(1_600_000_000, 250_000_000));
+
+    // picoseconds, whose fraction of a second does not fit in 64 bits once scaled to nanoseconds
+    std::fs::write(&path, ng_capture(12, 10_000_000 * 1_000_000_000_000 + 750_000_000_000)).unwrap();
+    assert_eq!(read_capture(&path, None).unwrap()[0].time, UNIX_EPOCH + Duration::new(10_000_000, 750_000_000));
+
+    // whole seconds, where the largest timestamp is past what a time can hold
+    std::fs::write(&path, ng_capture(0, u64::MAX)).unwrap();
+    let error = read_capture(&path, None).unwrap_err();
+    assert!(error.contains("out of range"), "{}", error);
+}
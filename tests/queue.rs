@@ -0,0 +1,174 @@
+extern crate backplane;
+
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod common;
+use common::*;
+
+use backplane::*;
+use backplane::queue::*;
+use backplane::router::*;
+
+
+/// The size of each message replayed into a queue
+const MESSAGE_SIZE: usize = 16384;
+
+/// The number of messages replayed, which is far more than a socket's buffers hold
+const NUM_MESSAGES: u64 = 2000;
+
+const CAPACITY: usize = 8;
+
+/// A router replaying a large file to an archive, and to a queued TCP output whose peer never reads
+struct Flood {
+    router: Router,
+    /// The replayed and archived files, removed along with the flood
+    _replay: TempPath,
+    _archive: TempPath,
+    /// The peer's end of the output's connection, which is held open but never read
+    _peer: TcpStream,
+}
+
+fn flood_config(name: &str, display: &str, policy: FullQueuePolicy) -> (RouterConfig, TempPath, TempPath) {
+    let (replay, archive) = (TempPath::file(&format!("queue_{}_replay.bin", name)), TempPath::file(&format!("queue_{}_archive.bin", name)));
+
+    let mut config = RouterConfig::default();
+    config.inputs.insert("replay".to_string(), StreamConfig::Descriptor(format!("file:{}|fixed:{}", replay, MESSAGE_SIZE)));
+    config.outputs.insert("archive".to_string(), StreamConfig::Descriptor(format!("file:{}", archive)));
+    config.outputs.insert("display".to_string(), StreamConfig::Descriptor(display.to_string()));
+    config.routes.insert("all".to_string(), RouteSettings { inputs: vec!["replay".to_string()],
+                                                            outputs: vec!["archive".to_string(), "display".to_string()],
+                                                            ..Default::default() });
+    config.queues.insert("display".to_string(), QueueSettings { capacity: CAPACITY, policy, max_bandwidth: None });
+
+    return (config, replay, archive);
+}
+
+/// Flood a queued TCP client output
+fn flood(name: &str, policy: FullQueuePolicy) -> Flood {
+    let peer = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = peer.local_addr().unwrap().port();
+
+    let (config, replay, archive) = flood_config(name, &format!("tcp_client:127.0.0.1:{}", port), policy);
+    std::fs::write(&replay, vec![0xA5; MESSAGE_SIZE * NUM_MESSAGES as usize]).unwrap();
+    let router = Router::open(config).unwrap();
+    let (connection, _) = peer.accept().unwrap();
+
+    // the listener is kept open, so a disconnected output can connect again
+    thread::spawn(move || {
+        let _connections: Vec<TcpStream> = peer.incoming().filter_map(|connection| connection.ok()).collect();
+    });
+
+    return Flood { router, _replay: replay, _archive: archive, _peer: connection };
+}
+
+fn wait_for(deadline: Duration, mut done: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + deadline;
+    while !done() {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    return true;
+}
+
+/// Check that a dropping queue let the input finish, with every message archived and the rest either written or dropped
+fn assert_dropped(flood: &Flood) {
+    assert!(wait_for(Duration::from_secs(30), || flood.router.is_finished()), "input was held up");
+    assert_eq!(flood.router.output_counters()["archive"].messages, NUM_MESSAGES);
+
+    let display = flood.router.output_counters()["display"].clone();
+    assert!(display.dropped > 0);
+    assert!(display.messages + display.dropped <= NUM_MESSAGES);
+    assert!(display.messages + display.dropped >= NUM_MESSAGES - CAPACITY as u64 - 1, "{:?}", display);
+}
+
+#[test]
+fn drop_newest_does_not_stall_the_archive() {
+    let flood = flood("drop_newest", FullQueuePolicy::DropNewest);
+    assert_dropped(&flood);
+    assert_eq!(flood.router.output_counters()["display"].reconnects, 0);
+
+    // the messages left in the queue are given up on rather than waited for forever
+    let started = Instant::now();
+    flood.router.wait().unwrap();
+    assert!(started.elapsed() < Duration::from_millis(QUEUE_FLUSH_TIMEOUT_MS + 2000));
+}
+
+#[test]
+fn drop_oldest_does_not_stall_the_archive() {
+    let mut flood = flood("drop_oldest", FullQueuePolicy::DropOldest);
+    assert_dropped(&flood);
+    flood.router.stop();
+}
+
+#[test]
+fn disconnect_reconnects_without_stalling_the_archive() {
+    let mut flood = flood("disconnect", FullQueuePolicy::Disconnect);
+    assert_dropped(&flood);
+
+    assert!(wait_for(Duration::from_secs(5), || flood.router.output_counters()["display"].reconnects >= 1));
+    flood.router.stop();
+}
+
+#[test]
+fn block_holds_up_the_input() {
+    let mut flood = flood("block", FullQueuePolicy::Block);
+
+    thread::sleep(Duration::from_millis(500));
+    assert!(!flood.router.is_finished());
+    assert!(flood.router.output_counters()["archive"].messages < NUM_MESSAGES);
+    assert_eq!(flood.router.output_counters()["display"].dropped, 0);
+
+    // a blocked input still stops
+    let started = Instant::now();
+    flood.router.stop();
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn tcp_servers_reopen_without_waiting_for_a_client() {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let (config, replay, archive) = flood_config("server", &format!("tcp_server:127.0.0.1:{}", port), FullQueuePolicy::Disconnect);
+    std::fs::write(&replay, vec![0xA5; MESSAGE_SIZE * NUM_MESSAGES as usize]).unwrap();
+
+    // the client connects before any messages are routed
+    let mut outputs_only = config.clone();
+    outputs_only.inputs.clear();
+    outputs_only.routes.clear();
+    let mut router = Router::open(outputs_only).unwrap();
+    let client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+
+    router.reload(config).unwrap();
+    let flood = Flood { router, _replay: replay, _archive: archive, _peer: client };
+    assert_dropped(&flood);
+
+    assert!(wait_for(Duration::from_secs(5), || flood.router.output_counters()["display"].reconnects >= 1));
+
+    // the reopened server is bound again, and is not waiting in accept when the router stops
+    let mut router = flood.router;
+    let started = Instant::now();
+    router.stop();
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn disconnect_is_only_for_tcp_outputs() {
+    for (display, valid) in [("file:display.bin", false),
+                             ("hexdump:display.txt", false),
+                             ("record:display.rec", false),
+                             ("tcp_client:127.0.0.1:9000", true),
+                             ("tcp_server:127.0.0.1:9000", true)].iter() {
+        let (config, _, _) = flood_config("validate", display, FullQueuePolicy::Disconnect);
+
+        match config.validate() {
+            Ok(()) => assert!(valid, "{} was accepted", display),
+            Err(string) => {
+                assert!(!valid, "{}: {}", display, string);
+                assert!(string.contains("only TCP outputs"), "{}", string);
+            },
+        }
+    }
+}
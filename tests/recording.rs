@@ -0,0 +1,208 @@
+extern crate backplane;
+extern crate bytes;
+
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use bytes::BytesMut;
+
+mod common;
+use common::*;
+
+use backplane::StreamConfig;
+use backplane::ccsds::*;
+use backplane::framing::*;
+use backplane::recording::*;
+use backplane::router::*;
+use backplane::stream_read::*;
+use backplane::stream_write::*;
+use backplane::time_code::*;
+
+
+/// The path of a recording in its own temporary directory, so that its index is removed along with it
+fn temp_recording(name: &str) -> (TempPath, String) {
+    let dir = TempPath::dir(&format!("recording_{}", name));
+    let path = dir.join(format!("{}.rec", name)).display().to_string();
+    return (dir, path);
+}
+
+fn at(millis: u64) -> SystemTime {
+    return UNIX_EPOCH + Duration::from_secs(1_600_000_000) + Duration::from_millis(millis);
+}
+
+/// Record messages from two sources, with receive times spread over a few seconds
+fn write_recording(path: &str) {
+    let settings = RecordingSettings::from_str(&format!("record:{}", path)).unwrap();
+    let mut writer = RecordingWriter::create(&settings.file_name, &settings.index_file_name()).unwrap();
+
+    let messages: [(&str, u64, &[u8]); 4] = [("radio", 0, b"first"), ("ground", 500, b"second"), ("radio", 1500, b"third"), ("radio", 2500, b"fourth")];
+    for (source, millis, message) in messages.iter() {
+        writer.set_source(source).unwrap();
+        writer.set_received(at(*millis));
+        assert_eq!(writer.write_bytes(message), Ok(message.len()));
+    }
+}
+
+#[test]
+fn recordings_keep_receive_times() {
+    let (_dir, path) = temp_recording("times");
+    write_recording(&path);
+
+    let mut reader = RecordingReader::open(&path, &format!("{}.{}", path, RECORDING_INDEX_EXTENSION)).unwrap();
+    assert_eq!(reader.sources().values().cloned().collect::<Vec<String>>(), vec!["radio".to_string(), "ground".to_string()]);
+    assert_eq!(reader.first_time(), Ok(Some(at(0))));
+
+    let first = reader.next_record().unwrap().unwrap();
+    assert_eq!(first, Record { time: at(0), source: 1, payload: b"first".to_vec() });
+    assert_eq!(reader.next_record().unwrap().unwrap().time, at(500));
+}
+
+#[test]
+fn recordings_seek_and_replay() {
+    let (_dir, path) = temp_recording("replay");
+    write_recording(&path);
+
+    let mut reader = RecordingReader::open(&path, &format!("{}.{}", path, RECORDING_INDEX_EXTENSION)).unwrap();
+    reader.set_speed(ReplaySpeed::Max).unwrap();
+
+    // a time between messages seeks to the next message
+    reader.seek(at(1000)).unwrap();
+
+    let mut bytes = BytesMut::new();
+    assert!(matches!(reader.read_bytes(&mut bytes, 0), StreamReadResult::BytesRead(5)));
+    assert_eq!(&bytes[..], b"third");
+
+    bytes.clear();
+    assert!(matches!(reader.read_bytes(&mut bytes, 0), StreamReadResult::BytesRead(6)));
+    assert_eq!(&bytes[..], b"fourth");
+    assert!(matches!(reader.read_bytes(&mut bytes, 0), StreamReadResult::Finished));
+
+    // seeking back, and replaying one source
+    reader.filter_source("radio").unwrap();
+    reader.seek(at(0)).unwrap();
+    let mut payloads = Vec::new();
+    bytes.clear();
+    while let StreamReadResult::BytesRead(_) = reader.read_bytes(&mut bytes, 0) {
+        payloads.push(bytes.take().to_vec());
+    }
+    assert_eq!(payloads, vec![b"first".to_vec(), b"third".to_vec(), b"fourth".to_vec()]);
+
+    assert!(reader.filter_source("antenna").is_err());
+}
+
+#[test]
+fn framed_recordings_replay_with_start_time() {
+    let (_dir, path) = temp_recording("framed");
+    write_recording(&path);
+
+    let mut input = FramedReadStream::from_str(&format!("record:speed=max:start=1.5:{}|raw", path)).unwrap();
+    assert_eq!(input.read_message(), FrameReadResult::Message("third".into()));
+    assert_eq!(input.read_message(), FrameReadResult::Message("fourth".into()));
+    assert_eq!(input.read_message(), FrameReadResult::Finished);
+
+    // messages written through a framed stream are recorded with the time they were received
+    let mut output = FramedWriteStream::from_str(&format!("record:{}", path)).unwrap();
+    output.write_message_from("radio", b"late", at(10_000)).unwrap();
+    drop(output);
+
+    let mut reader = RecordingReader::open(&path, &format!("{}.{}", path, RECORDING_INDEX_EXTENSION)).unwrap();
+    assert_eq!(reader.next_record().unwrap().unwrap().time, at(10_000));
+}
+
+/// A packet with a secondary header holding the given CUC 4:2 time
+fn timed_packet(time: &CcsdsTime, payload: &[u8]) -> Vec<u8> {
+    let time_code = TimeCodeSettings::Cuc(CucSettings::default()).encode(time).unwrap();
+
+    let mut header = CcsdsPrimaryHeader { apid: 7, secondary_header_flag: true, ..Default::default() };
+    header.set_packet_length(CCSDS_PRI_HEADER_SIZE + time_code.len() + payload.len()).unwrap();
+
+    let mut packet = header.encode().to_vec();
+    packet.extend_from_slice(&time_code);
+    packet.extend_from_slice(payload);
+    return packet;
+}
+
+#[test]
+fn routed_packets_are_recorded_with_their_decoded_times() {
+    let (_dir, path) = temp_recording("packet_times");
+    let input_path = TempPath::file("recording_packet_times.bin");
+
+    let times = [at(0), at(10_000), at(20_000)];
+    let packets: Vec<Vec<u8>> = times.iter().enumerate()
+                                     .map(|(index, time)| timed_packet(&CcsdsTime::from(*time), &[index as u8; 4]))
+                                     .collect();
+    std::fs::write(&input_path, packets.concat()).unwrap();
+
+    let mut config = RouterConfig::default();
+    config.inputs.insert("archive".to_string(), StreamConfig::Descriptor(format!("file:{}|ccsds:7:1024:cuc:4:2", input_path)));
+    config.outputs.insert("recording".to_string(), StreamConfig::Descriptor(format!("record:{}", path)));
+    config.routes.insert("record".to_string(), RouteSettings { inputs: vec!["archive".to_string()],
+                                                               outputs: vec!["recording".to_string()],
+                                                               ..Default::default() });
+    let mut router = Router::open(config).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while router.output_counters()["recording"].messages < 3 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    router.stop();
+    drop(router);
+
+    // the packets were read at about the same moment, but are recorded at the times they carry
+    let mut reader = RecordingReader::open(&path, &format!("{}.{}", path, RECORDING_INDEX_EXTENSION)).unwrap();
+    for (time, packet) in times.iter().zip(packets.iter()) {
+        assert_eq!(reader.next_record().unwrap().unwrap(), Record { time: *time, source: 1, payload: packet.clone() });
+    }
+
+    // so replay seeks and paces on the packet times
+    let mut replay = FramedReadStream::from_str(&format!("record:speed=max:start=10:{}|raw", path)).unwrap();
+    assert_eq!(replay.read_message(), FrameReadResult::Message(packets[1][..].into()));
+    assert_eq!(replay.read_message(), FrameReadResult::Message(packets[2][..].into()));
+    assert_eq!(replay.read_message(), FrameReadResult::Finished);
+}
+
+#[test]
+fn record_lengths_are_bounded() {
+    let (_dir, path) = temp_recording("lengths");
+
+    let mut recording = RECORDING_MAGIC.to_vec();
+    recording.extend_from_slice(&[0x00; 10]);
+    recording.extend_from_slice(&u32::MAX.to_be_bytes());
+    std::fs::write(&path, &recording).unwrap();
+
+    let mut reader = RecordingReader::open(&path, &format!("{}.{}", path, RECORDING_INDEX_EXTENSION)).unwrap();
+    let error = reader.next_record().unwrap_err();
+    assert!(error.contains("longer than the limit"), "{}", error);
+
+    let mut writer = RecordingWriter::create(&path, &format!("{}.{}", path, RECORDING_INDEX_EXTENSION)).unwrap();
+    assert!(writer.write_bytes(&vec![0x00; RECORDING_MAX_MESSAGE_SIZE + 1]).is_err());
+}
+
+#[test]
+fn replay_speeds_must_be_positive() {
+    for speed in ["0", "-1", "NaN", "inf", "fast"].iter() {
+        assert!(ReplaySpeed::from_str(speed).is_err(), "{}", speed);
+    }
+    assert_eq!(ReplaySpeed::from_str("0.5"), Ok(ReplaySpeed::Multiple(0.5)));
+    assert_eq!(ReplaySpeed::from_str("max"), Ok(ReplaySpeed::Max));
+
+    let (_dir, path) = temp_recording("speed");
+    write_recording(&path);
+
+    let mut reader = RecordingReader::open(&path, &format!("{}.{}", path, RECORDING_INDEX_EXTENSION)).unwrap();
+    assert!(reader.set_speed(ReplaySpeed::Multiple(0.0)).is_err());
+    assert!(reader.set_speed(ReplaySpeed::Multiple(-2.0)).is_err());
+
+    let settings = RecordingSettings { file_name: path.clone(), speed: ReplaySpeed::Multiple(0.0), ..Default::default() };
+    assert!(settings.open_read_stream().is_err());
+
+    // a speed given in a config file is checked when the config is loaded
+    let config_path = TempPath::file("recording_speed.json");
+    let recording = format!(r#"{{ "file_name": "{}", "speed": {{ "Multiple": 0.0 }} }}"#, path);
+    let config = format!(r#"{{ "inputs": {{ "replay": {{ "stream": "Recording", "settings": {{ "recording": {} }} }} }} }}"#, recording);
+    std::fs::write(&config_path, config).unwrap();
+    let err = RouterConfig::load(&config_path).unwrap_err();
+    assert!(err.contains("inputs.replay"), "{}", err);
+    assert!(err.contains("Replay speed"), "{}", err);
+}
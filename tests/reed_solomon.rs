@@ -0,0 +1,148 @@
+extern crate backplane;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod common;
+use common::*;
+
+use backplane::StreamConfig;
+use backplane::reed_solomon::*;
+use backplane::router::*;
+
+
+// a simple deterministic byte sequence, so failures can be reproduced
+fn test_bytes(len: usize, seed: u32) -> Vec<u8> {
+    let mut state = seed;
+    (0..len).map(|_| {
+        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+        (state >> 16) as u8
+    }).collect()
+}
+
+fn round_trip(settings: ReedSolomonSettings, errors_per_codeword: usize) -> Result<Vec<u8>, String> {
+    let interleave = settings.interleave;
+    let encoder = ReedSolomonEncoder::new(settings.clone());
+    let mut decoder = ReedSolomonDecoder::new(settings);
+
+    let frame = test_bytes(RS_K * interleave, interleave as u32);
+    let mut codeblock = encoder.encode(&frame).unwrap();
+    assert_eq!(codeblock.len(), RS_N * interleave);
+    assert_eq!(&codeblock[..frame.len()], &frame[..]);
+
+    // corrupt symbols spread through each codeword, including the parity symbols
+    for codeword_index in 0..interleave {
+        for error_index in 0..errors_per_codeword {
+            let symbol_index = (error_index * 15 + codeword_index) % RS_N;
+            codeblock[symbol_index * interleave + codeword_index] ^= 0x5A;
+        }
+    }
+
+    let decoded = decoder.decode(&codeblock)?;
+    assert_eq!(&decoded[..], &frame[..]);
+    assert_eq!(decoder.symbols_corrected, (errors_per_codeword * interleave) as u64);
+
+    Ok(decoded.to_vec())
+}
+
+#[test]
+fn corrects_up_to_sixteen_errors_per_codeword() {
+    for interleave in RS_INTERLEAVE_DEPTHS.iter() {
+        for dual_basis in [true, false].iter() {
+            let settings = ReedSolomonSettings { interleave: *interleave, dual_basis: *dual_basis };
+            round_trip(settings, RS_MAX_CORRECTABLE).unwrap();
+        }
+    }
+}
+
+#[test]
+fn only_ccsds_interleave_depths_are_accepted() {
+    assert_eq!(ReedSolomonSettings::parse("8"), Some(ReedSolomonSettings { interleave: 8, dual_basis: true }));
+    assert_eq!(ReedSolomonSettings::parse("5:conventional"), Some(ReedSolomonSettings { interleave: 5, dual_basis: false }));
+
+    for depth in ["0", "6", "7", "9"].iter() {
+        assert_eq!(ReedSolomonSettings::parse(depth), None);
+    }
+
+    let settings = ReedSolomonSettings { interleave: 6, dual_basis: true };
+    assert!(ReedSolomonEncoder::new(settings.clone()).encode(&test_bytes(RS_K * 6, 1)).is_err());
+    assert!(ReedSolomonDecoder::new(settings).decode(&test_bytes(RS_N * 6, 1)).is_err());
+}
+
+#[test]
+fn clean_codeblock_decodes_without_corrections() {
+    let settings = ReedSolomonSettings { interleave: 5, dual_basis: true };
+    round_trip(settings, 0).unwrap();
+}
+
+#[test]
+fn reports_uncorrectable_codeblocks() {
+    let settings = ReedSolomonSettings { interleave: 2, dual_basis: true };
+    let encoder = ReedSolomonEncoder::new(settings.clone());
+    let mut decoder = ReedSolomonDecoder::new(settings);
+
+    let mut codeblock = encoder.encode(&test_bytes(RS_K * 2, 7)).unwrap();
+    for byte in codeblock.iter_mut().take(80) {
+        *byte ^= 0xFF;
+    }
+
+    assert!(decoder.decode(&codeblock).is_err());
+    assert_eq!(decoder.codeblocks_dropped, 1);
+    assert!(decoder.codewords_uncorrectable > 0);
+}
+
+#[test]
+fn router_inputs_count_corrections() {
+    let settings = ReedSolomonSettings { interleave: 1, dual_basis: true };
+    let encoder = ReedSolomonEncoder::new(settings);
+
+    // one codeblock which can be corrected, followed by one which can not
+    let mut corrected = encoder.encode(&test_bytes(RS_K, 3)).unwrap();
+    for symbol_index in 0..4 {
+        corrected[symbol_index * 20] ^= 0x5A;
+    }
+    let mut uncorrectable = encoder.encode(&test_bytes(RS_K, 4)).unwrap();
+    for byte in uncorrectable.iter_mut().take(40) {
+        *byte ^= 0xFF;
+    }
+
+    let input_path = TempPath::file("rs_router_in.bin");
+    let output_path = TempPath::file("rs_router_out.bin");
+    let mut codeblocks = corrected.to_vec();
+    codeblocks.extend_from_slice(&uncorrectable);
+    std::fs::write(&input_path, &codeblocks).unwrap();
+
+    let mut config = RouterConfig::default();
+    config.inputs.insert("downlink".to_string(), StreamConfig::Descriptor(format!("file:{}|fixed:{}|rs_decode:1", input_path, RS_N)));
+    config.outputs.insert("frames".to_string(), StreamConfig::Descriptor(format!("file:{}", output_path)));
+    config.routes.insert("decode".to_string(), RouteSettings { inputs: vec!["downlink".to_string()],
+                                                               outputs: vec!["frames".to_string()],
+                                                               ..Default::default() });
+    let mut router = Router::open(config).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while router.input_counters()["downlink"].uncorrectable == 0 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let counters = router.input_counters()["downlink"].clone();
+    assert_eq!(counters.corrected, 1);
+    assert_eq!(counters.uncorrectable, 1);
+    assert_eq!(counters.messages, 1);
+    router.stop();
+
+    assert_eq!(std::fs::read(&output_path).unwrap(), test_bytes(RS_K, 3));
+}
+
+#[test]
+fn dual_basis_tables_are_inverses() {
+    let codec = ReedSolomonCodec::new();
+
+    // the first entries of the conversion table from CCSDS 131.0-B Annex F
+    assert_eq!(codec.to_dual(0x01), 0x7B);
+    assert_eq!(codec.to_dual(0x02), 0xAF);
+
+    for symbol in 0..=255u8 {
+        assert_eq!(codec.to_conventional(codec.to_dual(symbol)), symbol);
+    }
+}
@@ -0,0 +1,290 @@
+extern crate backplane;
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+mod common;
+use common::*;
+
+use backplane::*;
+use backplane::ccsds::*;
+use backplane::queue::*;
+use backplane::router::*;
+
+
+fn config(inputs: &[(&str, &str)], outputs: &[(&str, &str)], routes: &[(&str, &[&str], &[&str])]) -> RouterConfig {
+    let mut config = RouterConfig::default();
+
+    for (name, desc) in inputs.iter() {
+        config.inputs.insert(name.to_string(), StreamConfig::Descriptor(desc.to_string()));
+    }
+
+    for (name, desc) in outputs.iter() {
+        config.outputs.insert(name.to_string(), StreamConfig::Descriptor(desc.to_string()));
+    }
+
+    for (name, route_inputs, route_outputs) in routes.iter() {
+        let route = RouteSettings { inputs: route_inputs.iter().map(|name| name.to_string()).collect(),
+                                    outputs: route_outputs.iter().map(|name| name.to_string()).collect(),
+                                    ..Default::default() };
+        config.routes.insert(name.to_string(), route);
+    }
+
+    config
+}
+
+fn assert_invalid(config: &RouterConfig, expected: &str) {
+    match config.validate() {
+        Ok(()) => panic!("expected an error containing '{}'", expected),
+        Err(string) => assert!(string.contains(expected), "'{}' does not contain '{}'", string, expected),
+    }
+}
+
+#[test]
+fn valid_config() {
+    let config = config(&[("radio", "udp:0.0.0.0:8001|ccsds"), ("replay", "file:archive.bin")],
+                        &[("archive", "file:today.bin"), ("display", "tcp_client:127.0.0.1:9000|ccsds")],
+                        &[("telemetry", &["radio", "replay"], &["archive", "display"])]);
+
+    assert_eq!(config.validate(), Ok(()));
+    assert_eq!(config.outputs_of("radio"), vec!["archive".to_string(), "display".to_string()]);
+}
+
+#[test]
+fn unknown_names() {
+    let unknown_input = config(&[("radio", "udp:0.0.0.0:8001")],
+                               &[("archive", "file:today.bin")],
+                               &[("telemetry", &["radio", "antenna"], &["archive"])]);
+    assert_invalid(&unknown_input, "unknown input 'antenna'");
+
+    let unknown_output = config(&[("radio", "udp:0.0.0.0:8001")],
+                                &[("archive", "file:today.bin")],
+                                &[("telemetry", &["radio"], &["display"])]);
+    assert_invalid(&unknown_output, "unknown output 'display'");
+
+    let empty_route = config(&[("radio", "udp:0.0.0.0:8001")], &[("archive", "file:today.bin")], &[("telemetry", &["radio"], &[])]);
+    assert_invalid(&empty_route, "at least one input and one output");
+
+    let shared_name = config(&[("radio", "udp:0.0.0.0:8001")], &[("radio", "file:today.bin")], &[]);
+    assert_invalid(&shared_name, "both an input and an output");
+
+    let bad_descriptor = config(&[("radio", "udp:0.0.0.0")], &[], &[]);
+    assert_invalid(&bad_descriptor, "Stream 'radio'");
+}
+
+#[test]
+fn bind_conflicts() {
+    let same_port = config(&[("radio", "udp:0.0.0.0:8001"), ("backup", "udp:127.0.0.1:8001")], &[], &[]);
+    assert_invalid(&same_port, "both bind");
+
+    let same_server = config(&[("commands", "tcp_server:0.0.0.0:9000")], &[("telemetry", "tcp_server:127.0.0.1:9000")], &[]);
+    assert_invalid(&same_server, "both bind");
+
+    let same_file = config(&[], &[("archive", "file:today.bin"), ("copy", "file:today.bin")], &[]);
+    assert_invalid(&same_file, "both bind");
+
+    // different protocols, different addresses, sending to a port, and reading a file twice do not conflict
+    let no_conflict = config(&[("radio", "udp:0.0.0.0:8001"),
+                               ("commands", "tcp_server:0.0.0.0:8001"),
+                               ("first", "file:archive.bin"),
+                               ("second", "file:archive.bin")],
+                             &[("forward", "udp:127.0.0.1:8001"),
+                               ("local", "tcp_server:127.0.0.1:8002"),
+                               ("remote", "tcp_server:10.0.0.1:8003")],
+                             &[]);
+    assert_eq!(no_conflict.validate(), Ok(()));
+}
+
+#[test]
+fn feedback_cycles() {
+    // an output sending to the port its own input reads from
+    let loopback = config(&[("radio", "udp:0.0.0.0:8001")],
+                          &[("forward", "udp:127.0.0.1:8001")],
+                          &[("telemetry", &["radio"], &["forward"])]);
+    assert_invalid(&loopback, "Routing cycle: input 'radio' -> output 'forward' -> input 'radio'");
+
+    // a cycle through two routes
+    let two_step = config(&[("first", "udp:0.0.0.0:8001"), ("second", "tcp_server:0.0.0.0:9000")],
+                          &[("to_second", "tcp_client:127.0.0.1:9000"), ("to_first", "udp:127.0.0.1:8001")],
+                          &[("forward", &["first"], &["to_second"]), ("back", &["second"], &["to_first"])]);
+    assert_invalid(&two_step, "Routing cycle");
+
+    // the same streams without the route back are a chain, not a cycle
+    let mut chain = two_step.clone();
+    chain.routes.remove("back");
+    assert_eq!(chain.validate(), Ok(()));
+
+    // an output that appends to the file an input replays
+    let file_loop = config(&[("replay", "file:archive.bin")],
+                           &[("archive", "file:archive.bin")],
+                           &[("copy", &["replay"], &["archive"])]);
+    assert_invalid(&file_loop, "Routing cycle");
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+#[test]
+fn reload_changes_only_what_changed() {
+    let (first_port, second_port) = (free_port(), free_port());
+    let first = format!("udp:127.0.0.1:{}", first_port);
+    let second = format!("udp:127.0.0.1:{}", second_port);
+    let (archive_path, copy_path) = (TempPath::file("router_archive.bin"), TempPath::file("router_copy.bin"));
+    let (archive, copy) = (format!("file:{}", archive_path), format!("file:{}", copy_path));
+
+    let initial = config(&[("first", &first), ("second", &second)],
+                         &[("archive", &archive)],
+                         &[("telemetry", &["first", "second"], &["archive"])]);
+    let mut router = Router::open(initial.clone()).unwrap();
+
+    // reloading the same config changes nothing
+    assert_eq!(router.reload(initial).unwrap(), ReloadSummary::default());
+
+    let mut changed = config(&[("first", &first)],
+                             &[("archive", &archive), ("copy", &copy)],
+                             &[("telemetry", &["first"], &["archive", "copy"])]);
+    let summary = router.reload(changed.clone()).unwrap();
+    assert_eq!(summary.opened, vec!["output 'copy'".to_string()]);
+    assert_eq!(summary.closed, vec!["input 'second'".to_string()]);
+    assert_eq!(summary.rerouted, vec!["input 'first'".to_string()]);
+    assert!(summary.errors.is_empty());
+
+    // changing an output reopens it, and reroutes the inputs writing to it
+    changed.outputs.insert("copy".to_string(), StreamConfig::Descriptor(format!("{}|line", copy)));
+    let summary = router.reload(changed).unwrap();
+    assert_eq!(summary.opened, vec!["output 'copy'".to_string()]);
+    assert_eq!(summary.closed, vec!["output 'copy'".to_string()]);
+    assert_eq!(summary.rerouted, vec!["input 'first'".to_string()]);
+
+    router.stop();
+}
+
+#[test]
+fn inputs_which_fail_to_open_are_reported_and_retried() {
+    let port = free_port();
+    let archive_path = TempPath::file("router_retry.bin");
+    let archive = format!("file:{}", archive_path);
+    let commands = config(&[("commands", &format!("tcp_client:127.0.0.1:{}", port))],
+                          &[("archive", &archive)],
+                          &[("store", &["commands"], &["archive"])]);
+
+    let mut router = Router::open(RouterConfig::default()).unwrap();
+
+    let summary = router.reload(commands.clone()).unwrap();
+    assert_eq!(summary.opened, vec!["output 'archive'".to_string()]);
+    assert_eq!(summary.errors.len(), 1);
+    assert!(summary.errors[0].contains("input 'commands'"), "{}", summary.errors[0]);
+
+    // once the server is up, reloading the same config opens the input
+    let _server = TcpListener::bind(("127.0.0.1", port)).unwrap();
+    let summary = router.reload(commands).unwrap();
+    assert_eq!(summary.opened, vec!["input 'commands'".to_string()]);
+    assert!(summary.errors.is_empty());
+
+    router.stop();
+}
+
+#[test]
+fn tcp_servers_do_not_wait_for_clients() {
+    let (input_port, output_port) = (free_port(), free_port());
+    let input = format!("tcp_server:127.0.0.1:{}", input_port);
+    let output = format!("tcp_server:127.0.0.1:{}|line", output_port);
+
+    let started = Instant::now();
+    let mut router = Router::open(config(&[("uplink", &input)], &[("downlink", &output)], &[("relay", &["uplink"], &["downlink"])])).unwrap();
+    assert!(started.elapsed() < Duration::from_secs(1));
+
+    // changing an input waiting for a client frees its port for the new input
+    let changed = config(&[("uplink", &format!("{}|line", input))], &[("downlink", &output)], &[("relay", &["uplink"], &["downlink"])]);
+    let summary = router.reload(changed).unwrap();
+    assert_eq!(summary.opened, vec!["input 'uplink'".to_string()]);
+    assert!(summary.errors.is_empty(), "{:?}", summary.errors);
+
+    let mut receiver = TcpStream::connect(("127.0.0.1", output_port)).unwrap();
+    receiver.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+    let mut sender = TcpStream::connect(("127.0.0.1", input_port)).unwrap();
+    sender.write_all(b"hello\n").unwrap();
+
+    let mut received = [0; 6];
+    receiver.read_exact(&mut received).unwrap();
+    assert_eq!(&received, b"hello\n");
+
+    router.stop();
+}
+
+#[test]
+fn reloaded_tcp_server_outputs_stay_bound() {
+    let (input_port, output_port) = (free_port(), free_port());
+    let input = format!("tcp_server:127.0.0.1:{}|line", input_port);
+    let output = format!("tcp_server:127.0.0.1:{}", output_port);
+    let routes: &[(&str, &[&str], &[&str])] = &[("relay", &["uplink"], &["downlink"])];
+
+    let mut router = Router::open(config(&[("uplink", &input)], &[("downlink", &output)], routes)).unwrap();
+
+    // changing the output's framing closes the old server before binding its port again
+    let mut changed = config(&[("uplink", &input)], &[("downlink", &format!("{}|line", output))], routes);
+    let summary = router.reload(changed.clone()).unwrap();
+    assert_eq!(summary.opened, vec!["output 'downlink'".to_string()]);
+    assert_eq!(summary.closed, vec!["output 'downlink'".to_string()]);
+    assert!(summary.errors.is_empty(), "{:?}", summary.errors);
+
+    // as does moving it behind a queue
+    changed.queues.insert("downlink".to_string(), QueueSettings::default());
+    let summary = router.reload(changed).unwrap();
+    assert_eq!(summary.opened, vec!["output 'downlink'".to_string()]);
+    assert!(summary.errors.is_empty(), "{:?}", summary.errors);
+
+    let mut receiver = TcpStream::connect(("127.0.0.1", output_port)).unwrap();
+    receiver.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+    let mut sender = TcpStream::connect(("127.0.0.1", input_port)).unwrap();
+    sender.write_all(b"hello\n").unwrap();
+
+    let mut received = [0; 6];
+    receiver.read_exact(&mut received).unwrap();
+    assert_eq!(&received, b"hello\n");
+
+    router.stop();
+}
+
+#[test]
+fn sequence_monitors_count_input_gaps() {
+    let path = TempPath::file("router_sequence.bin");
+    let mut bytes = Vec::new();
+    for count in [0, 1, 2, 5, 6].iter() {
+        let mut header = CcsdsPrimaryHeader { apid: 0x42, sequence_count: *count, ..Default::default() };
+        header.set_packet_length(CCSDS_PRI_HEADER_SIZE + 4).unwrap();
+        bytes.extend_from_slice(&header.encode());
+        bytes.extend_from_slice(&[0; 4]);
+    }
+    std::fs::write(&path, &bytes).unwrap();
+
+    let replay = format!("file:{}|ccsds", path);
+    let mut monitored = config(&[("replay", &replay)], &[], &[]);
+    monitored.monitor_sequence.insert("replay".to_string());
+    let mut router = Router::open(monitored).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !router.is_finished() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let counters = router.sequence_counters()["replay"];
+    assert_eq!(counters.packets, 5);
+    assert_eq!(counters.gaps, 1);
+    assert_eq!(counters.missing_packets, 2);
+
+    router.stop();
+
+    // only known inputs framed as CCSDS packets can be monitored
+    let mut unknown = config(&[("replay", &replay)], &[], &[]);
+    unknown.monitor_sequence.insert("radio".to_string());
+    assert_invalid(&unknown, "unknown input 'radio'");
+
+    let mut unframed = config(&[("replay", &format!("file:{}", path))], &[], &[]);
+    unframed.monitor_sequence.insert("replay".to_string());
+    assert_invalid(&unframed, "only inputs framed as CCSDS packets");
+}
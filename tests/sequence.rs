@@ -0,0 +1,99 @@
+extern crate backplane;
+
+use std::sync::{Arc, Mutex};
+
+use backplane::ccsds::*;
+use backplane::sequence::*;
+
+
+fn header(apid: u16, sequence_count: u16) -> CcsdsPrimaryHeader {
+    CcsdsPrimaryHeader { apid, sequence_count, ..Default::default() }
+}
+
+#[test]
+fn consecutive_counts_have_no_anomaly() {
+    let mut monitor = SequenceMonitor::new();
+
+    for count in 0..100 {
+        assert_eq!(monitor.check(&header(5, count)), None);
+    }
+
+    let counters = monitor.counters();
+    assert_eq!(counters.packets, 100);
+    assert_eq!(counters.gaps, 0);
+}
+
+#[test]
+fn counts_wrap_without_a_gap() {
+    let mut monitor = SequenceMonitor::new();
+
+    assert_eq!(monitor.check(&header(5, CCSDS_SEQUENCE_COUNT_MODULUS - 2)), None);
+    assert_eq!(monitor.check(&header(5, CCSDS_SEQUENCE_COUNT_MODULUS - 1)), None);
+    assert_eq!(monitor.check(&header(5, 0)), None);
+    assert_eq!(monitor.check(&header(5, 1)), None);
+}
+
+#[test]
+fn gaps_are_counted_across_the_wrap() {
+    let mut monitor = SequenceMonitor::new();
+
+    monitor.check(&header(5, 10));
+    let event = monitor.check(&header(5, 14)).unwrap();
+    assert_eq!(event, SequenceEvent { apid: 5, anomaly: SequenceAnomaly::Gap { expected: 11, received: 14, missing: 3 } });
+
+    monitor.check(&header(6, CCSDS_SEQUENCE_COUNT_MODULUS - 2));
+    let event = monitor.check(&header(6, 2)).unwrap();
+    assert_eq!(event, SequenceEvent { apid: 6, anomaly: SequenceAnomaly::Gap { expected: CCSDS_SEQUENCE_COUNT_MODULUS - 1, received: 2, missing: 3 } });
+
+    assert_eq!(monitor.apid_counters(5).unwrap().missing_packets, 3);
+    assert_eq!(monitor.apid_counters(6).unwrap().missing_packets, 3);
+
+    let counters = monitor.counters();
+    assert_eq!(counters.gaps, 2);
+    assert_eq!(counters.missing_packets, 6);
+}
+
+#[test]
+fn duplicates_and_out_of_order_packets() {
+    let mut monitor = SequenceMonitor::new();
+
+    monitor.check(&header(7, 100));
+    assert_eq!(monitor.check(&header(7, 100)).unwrap().anomaly, SequenceAnomaly::Duplicate { count: 100 });
+
+    assert_eq!(monitor.check(&header(7, 98)).unwrap().anomaly, SequenceAnomaly::OutOfOrder { expected: 101, received: 98 });
+
+    // an old packet does not move the sequence back
+    assert_eq!(monitor.check(&header(7, 101)), None);
+
+    // a count just before 0 is behind 0, not far ahead of it
+    monitor.check(&header(8, 0));
+    assert_eq!(monitor.check(&header(8, CCSDS_SEQUENCE_COUNT_MODULUS - 1)).unwrap().anomaly,
+               SequenceAnomaly::OutOfOrder { expected: 1, received: CCSDS_SEQUENCE_COUNT_MODULUS - 1 });
+
+    let counters = monitor.counters();
+    assert_eq!(counters.duplicates, 1);
+    assert_eq!(counters.out_of_order, 2);
+}
+
+#[test]
+fn apids_are_tracked_separately() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let callback_events = Arc::clone(&events);
+    let mut monitor = SequenceMonitor::with_callback(Box::new(move |event| callback_events.lock().unwrap().push(*event)));
+
+    monitor.check(&header(1, 0));
+    monitor.check(&header(2, 50));
+    monitor.check(&header(1, 1));
+    monitor.check(&header(2, 52));
+
+    // idle packets are not checked
+    monitor.check(&header(CCSDS_IDLE_APID, 9));
+    monitor.check(&header(CCSDS_IDLE_APID, 3));
+
+    assert_eq!(*events.lock().unwrap(),
+               vec![SequenceEvent { apid: 2, anomaly: SequenceAnomaly::Gap { expected: 51, received: 52, missing: 1 } }]);
+
+    // after a reset, the next packet starts a new sequence
+    monitor.reset_apid(2);
+    assert_eq!(monitor.check(&header(2, 1000)), None);
+}
@@ -0,0 +1,121 @@
+extern crate backplane;
+
+use std::io::Write;
+use std::net::{TcpListener, TcpStream};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod common;
+use common::*;
+
+use backplane::*;
+use backplane::framing::*;
+use backplane::router::*;
+use backplane::stats::*;
+
+
+#[test]
+fn counts_are_recorded() {
+    let stats = StreamStats::new();
+    assert_eq!(stats.counters(), StreamCounters::default());
+
+    stats.record_operation(100);
+    stats.record_operation(0);
+    stats.record_message();
+    stats.record_message();
+    stats.record_error();
+    stats.record_reconnect();
+    stats.record_drop(3);
+    stats.set_peer(Some("127.0.0.1:9000".to_string()));
+
+    let counters = stats.counters();
+    assert_eq!(counters.bytes, 100);
+    assert_eq!(counters.operations, 2);
+    assert_eq!(counters.messages, 2);
+    assert_eq!(counters.errors, 1);
+    assert_eq!(counters.reconnects, 1);
+    assert_eq!(counters.dropped, 3);
+    assert!(counters.last_activity.is_some());
+    assert_eq!(counters.peer, Some("127.0.0.1:9000".to_string()));
+
+    // an empty operation is counted, but is not activity
+    let idle = StreamStats::new();
+    idle.record_operation(0);
+    assert_eq!(idle.counters().operations, 1);
+    assert_eq!(idle.counters().last_activity, None);
+}
+
+#[test]
+fn rates_between_snapshots() {
+    let earlier = StreamCounters { bytes: 1000, messages: 10, ..Default::default() };
+    let later = StreamCounters { bytes: 3000, messages: 30, ..Default::default() };
+
+    let rates = later.rates_since(&earlier, Duration::from_secs(2));
+    assert_eq!(rates, StreamRates { bytes_per_second: 1000.0, messages_per_second: 10.0 });
+    assert_eq!(rates.to_string(), "1000.0 B/s 10.0 msg/s");
+
+    let rates = later.rates_since(&earlier, Duration::from_millis(500));
+    assert_eq!(rates.bytes_per_second, 4000.0);
+
+    // no time passing, or counters which went backwards, give no rate rather than a division by zero or underflow
+    assert_eq!(later.rates_since(&earlier, Duration::from_secs(0)), StreamRates::default());
+    assert_eq!(earlier.rates_since(&later, Duration::from_secs(1)), StreamRates::default());
+}
+
+#[test]
+fn framed_streams_count_reads_and_writes() {
+    let path = TempPath::file("stats_framed.bin");
+
+    let mut output = FramedWriteStream::from_str(&format!("file:{}|line", path.display())).unwrap();
+    output.write_message(b"first").unwrap();
+    output.write_message(b"second").unwrap();
+
+    let written = output.stats();
+    assert_eq!(written.messages, 2);
+    assert_eq!(written.operations, 2);
+    assert_eq!(written.bytes, 13);
+    drop(output);
+
+    let mut input = FramedReadStream::from_str(&format!("file:{}|line", path.display())).unwrap();
+    assert_eq!(input.read_message(), FrameReadResult::Message("first".into()));
+    assert_eq!(input.read_message(), FrameReadResult::Message("second".into()));
+    assert_eq!(input.read_message(), FrameReadResult::Finished);
+
+    let read = input.stats();
+    assert_eq!(read.messages, 2);
+    assert_eq!(read.bytes, 13);
+    assert_eq!(read.errors, 0);
+}
+
+#[test]
+fn tcp_inputs_count_reconnects() {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let archive = TempPath::file("stats_reconnects.bin");
+
+    let mut config = RouterConfig::default();
+    config.inputs.insert("uplink".to_string(), StreamConfig::Descriptor(format!("tcp_server:127.0.0.1:{}|line", port)));
+    config.outputs.insert("archive".to_string(), StreamConfig::Descriptor(format!("file:{}", archive.display())));
+    config.routes.insert("store".to_string(), RouteSettings { inputs: vec!["uplink".to_string()],
+                                                              outputs: vec!["archive".to_string()],
+                                                              ..Default::default() });
+    let mut router = Router::open(config).unwrap();
+
+    for _ in 0..2 {
+        let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        client.write_all(b"hello\n").unwrap();
+    }
+
+    // both clients are read by the same input, which keeps running after they disconnect
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while router.input_counters()["uplink"].messages < 2 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let counters = router.input_counters()["uplink"].clone();
+    assert_eq!(counters.messages, 2);
+    assert_eq!(counters.reconnects, 1);
+    assert!(!router.is_finished());
+
+    router.stop();
+}
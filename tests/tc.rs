@@ -0,0 +1,85 @@
+extern crate backplane;
+extern crate bytes;
+
+use bytes::BytesMut;
+
+use backplane::tc::*;
+use backplane::transform::*;
+
+
+#[test]
+fn bch_parity_known_answers() {
+    // with all zero information bits the register stays zero, so the parity is all ones before the filler bit
+    assert_eq!(bch_parity(&[0x00; 7]), 0xFE);
+
+    assert_eq!(bch_parity(&[0xFF; 7]), 0x86);
+    assert_eq!(bch_parity(&[CLTU_FILL_BYTE; 7]), 0xD6);
+    assert_eq!(bch_parity(&[0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07]), 0x70);
+    assert_eq!(bch_parity(&[0x20, 0x2A, 0x00, 0x0B, 0x00, 0x01, 0x02]), 0x0A);
+}
+
+#[test]
+fn bch_parity_filler_bit_is_zero() {
+    for value in 0..=255u8 {
+        assert_eq!(bch_parity(&[value; 7]) & 1, 0);
+    }
+}
+
+#[test]
+fn cltu_known_answer() {
+    let frame = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0xFF];
+
+    let mut expected = vec![0xEB, 0x90];
+    expected.extend_from_slice(&[0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x70]);
+    // the last codeblock is padded with fill bytes
+    expected.extend_from_slice(&[0xFF, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55]);
+    expected.push(bch_parity(&[0xFF, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55]));
+    expected.extend_from_slice(&[0xC5, 0xC5, 0xC5, 0xC5, 0xC5, 0xC5, 0xC5, 0x79]);
+
+    assert_eq!(&encode_cltu(&frame)[..], &expected[..]);
+}
+
+#[test]
+fn cltu_length() {
+    for frame_length in 1..30 {
+        let cltu = encode_cltu(&vec![0xA5; frame_length]);
+        let num_codeblocks = frame_length.div_ceil(CLTU_INFO_BYTES);
+
+        assert_eq!(cltu.len(), CLTU_START_SEQUENCE.len() + num_codeblocks * 8 + CLTU_TAIL_SEQUENCE.len());
+        assert_eq!(&cltu[..2], &CLTU_START_SEQUENCE[..]);
+        assert_eq!(&cltu[cltu.len() - 8..], &CLTU_TAIL_SEQUENCE[..]);
+    }
+}
+
+#[test]
+fn cltu_transform_encodes_frames() {
+    let frame = BytesMut::from(&[0x20, 0x2A, 0x00, 0x0B, 0x00, 0x01, 0x02][..]);
+
+    let cltu = CltuEncoder.transform(frame.clone()).unwrap().unwrap();
+    assert_eq!(cltu, encode_cltu(&frame));
+    assert_eq!(cltu[2 + CLTU_INFO_BYTES], 0x0A);
+}
+
+#[test]
+fn tc_frame_ids_must_fit_their_fields() {
+    let settings = TcFrameSettings { scid: TC_MAX_SCID, vcid: TC_MAX_VCID, ..Default::default() };
+    let mut builder = TcFrameBuilder::new(settings.clone()).unwrap();
+    let header = TcFrameHeader::decode(&builder.build_frame(&[0x01, 0x02]).unwrap()).unwrap();
+    assert_eq!((header.scid, header.vcid), (TC_MAX_SCID, TC_MAX_VCID));
+
+    let too_large = [TcFrameSettings { scid: TC_MAX_SCID + 1, ..settings.clone() },
+                     TcFrameSettings { vcid: TC_MAX_VCID + 1, ..settings.clone() }];
+    for bad_settings in too_large.iter() {
+        assert!(bad_settings.validate().is_err());
+        assert!(TcFrameBuilder::new(bad_settings.clone()).is_err());
+        assert!(TransformSettings::TcFrame(bad_settings.clone()).transform().is_err());
+    }
+
+    // ids are not masked into a frame for another spacecraft
+    builder.settings.scid = 0x400;
+    assert!(builder.build_frame(&[0x01, 0x02]).is_err());
+
+    assert_eq!(TcFrameSettings::parse("1023:63:bd"), Some(TcFrameSettings { scid: 1023, vcid: 63, bypass: true, fecf: true }));
+    assert_eq!(TcFrameSettings::parse("1024:0"), None);
+    assert_eq!(TcFrameSettings::parse("42:64"), None);
+}
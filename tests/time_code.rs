@@ -0,0 +1,178 @@
+extern crate backplane;
+extern crate bytes;
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+mod common;
+use common::*;
+
+use backplane::*;
+use backplane::apid_route::*;
+use backplane::ccsds::*;
+use backplane::framing::*;
+use backplane::rule_route::*;
+use backplane::time_code::*;
+
+
+/// 2000-01-01T12:00:00, in seconds since the Unix epoch
+const J2000_UNIX_SECONDS: i64 = 946_728_000;
+
+fn cuc(coarse_octets: u8, fine_octets: u8) -> TimeCodeSettings {
+    TimeCodeSettings::Cuc(CucSettings { coarse_octets, fine_octets, ..Default::default() })
+}
+
+fn cds(day_octets: u8, submillisecond_octets: u8) -> TimeCodeSettings {
+    TimeCodeSettings::Cds(CdsSettings { day_octets, submillisecond_octets, ..Default::default() })
+}
+
+#[test]
+fn cuc_known_answers() {
+    // the Unix epoch is 378691200 seconds after the CCSDS epoch
+    let unix_epoch = CcsdsTime::new(0, 0);
+    assert_eq!(cuc(4, 2).encode(&unix_epoch).unwrap(), vec![0x16, 0x92, 0x5E, 0x80, 0x00, 0x00]);
+
+    let half_second = CcsdsTime::new(0, 500_000_000);
+    assert_eq!(cuc(4, 2).encode(&half_second).unwrap(), vec![0x16, 0x92, 0x5E, 0x80, 0x80, 0x00]);
+    assert_eq!(cuc(4, 2).decode(&[0x16, 0x92, 0x5E, 0x80, 0x80, 0x00]).unwrap(), half_second);
+
+    // a 4 coarse, 2 fine octet code with the CCSDS epoch has the P field 0x1E
+    let with_p_field = TimeCodeSettings::Cuc(CucSettings { p_field: true, ..Default::default() });
+    assert_eq!(with_p_field.encode(&unix_epoch).unwrap(), vec![0x1E, 0x16, 0x92, 0x5E, 0x80, 0x00, 0x00]);
+
+    let gps = TimeCodeSettings::Cuc(CucSettings { coarse_octets: 4, fine_octets: 0, epoch: Epoch::Gps, p_field: false });
+    assert_eq!(gps.decode(&[0x00, 0x00, 0x00, 0x0A]).unwrap(), CcsdsTime::new(GPS_EPOCH_UNIX_SECONDS + 10, 0));
+}
+
+#[test]
+fn cds_known_answers() {
+    // J2000 is day 15340 after the CCSDS epoch, 43200000 milliseconds into the day
+    let j2000 = CcsdsTime::new(J2000_UNIX_SECONDS, 0);
+    assert_eq!(cds(2, 0).encode(&j2000).unwrap(), vec![0x3B, 0xEC, 0x02, 0x93, 0x2E, 0x00]);
+    assert_eq!(cds(2, 0).decode(&[0x3B, 0xEC, 0x02, 0x93, 0x2E, 0x00]).unwrap(), j2000);
+
+    let with_micros = CcsdsTime::new(J2000_UNIX_SECONDS, 123_000);
+    assert_eq!(cds(2, 2).encode(&with_micros).unwrap(), vec![0x3B, 0xEC, 0x02, 0x93, 0x2E, 0x00, 0x00, 0x7B]);
+
+    // a 16 bit day code with microseconds and the CCSDS epoch has the P field 0x41
+    let with_p_field = TimeCodeSettings::Cds(CdsSettings { submillisecond_octets: 2, p_field: true, ..Default::default() });
+    assert_eq!(with_p_field.encode(&with_micros).unwrap(), vec![0x41, 0x3B, 0xEC, 0x02, 0x93, 0x2E, 0x00, 0x00, 0x7B]);
+
+    let unix_epoch = cds(3, 4).decode(&[0x00, 0x11, 0x1F, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x03, 0xE8]).unwrap();
+    assert_eq!(unix_epoch, CcsdsTime::new(0, 1_000_001));
+}
+
+#[test]
+fn times_round_trip() {
+    let time = CcsdsTime::new(1_600_000_000, 250_000_000);
+
+    for settings in [cuc(4, 2), cuc(4, 3), cds(2, 0), cds(3, 2), cds(2, 4)].iter() {
+        let bytes = settings.encode(&time).unwrap();
+        assert_eq!(bytes.len(), settings.length());
+        assert_eq!(settings.decode(&bytes).unwrap(), time);
+    }
+}
+
+#[test]
+fn times_out_of_range() {
+    let before_epoch = CcsdsTime::new(CCSDS_EPOCH_UNIX_SECONDS - 1, 0);
+    assert!(cuc(4, 0).encode(&before_epoch).is_err());
+
+    // one coarse octet only covers 256 seconds
+    assert!(cuc(1, 0).encode(&CcsdsTime::new(0, 0)).is_err());
+
+    assert!(cuc(5, 0).validate().is_err());
+    assert!(cds(2, 3).validate().is_err());
+    assert!(cds(2, 0).decode(&[0x00; 5]).is_err());
+}
+
+#[test]
+fn time_code_descriptors() {
+    let descs = ["cuc:4:2", "cuc:2:0:gps", "cds:2:2:p_field", "cds:3:4:946728000:p_field"];
+    for desc in descs.iter() {
+        let settings = TimeCodeSettings::parse(desc).unwrap();
+        assert_eq!(&settings.to_string(), desc);
+    }
+
+    assert_eq!(TimeCodeSettings::parse("cds:2:0:unix"),
+               Some(TimeCodeSettings::Cds(CdsSettings { epoch: Epoch::Unix, ..Default::default() })));
+
+    assert_eq!(TimeCodeSettings::parse("cuc:5:0"), None);
+    assert_eq!(TimeCodeSettings::parse("cds:2"), None);
+    assert_eq!(TimeCodeSettings::parse("utc:4:2"), None);
+
+    let framing = FramingSettings::from_str("ccsds:7:1024:cuc:4:2").unwrap();
+    assert_eq!(framing, FramingSettings::Ccsds(CcsdsFramingSettings { min_length: 7, max_length: 1024, time_code: Some(cuc(4, 2)) }));
+    assert_eq!(framing.to_string(), "ccsds:7:1024:cuc:4:2");
+}
+
+/// A packet with a secondary header holding the given CUC 4:2 time
+fn timed_packet(apid: u16, time: &CcsdsTime) -> Vec<u8> {
+    let time_code = cuc(4, 2).encode(time).unwrap();
+
+    let mut header = CcsdsPrimaryHeader { apid, secondary_header_flag: true, ..Default::default() };
+    header.set_packet_length(CCSDS_PRI_HEADER_SIZE + time_code.len() + 4).unwrap();
+
+    let mut packet = header.encode().to_vec();
+    packet.extend_from_slice(&time_code);
+    packet.extend_from_slice(&[0xAB; 4]);
+    packet
+}
+
+#[test]
+fn framer_carries_packet_times() {
+    let first = CcsdsTime::new(1_600_000_000, 500_000_000);
+    let second = CcsdsTime::new(1_600_000_010, 0);
+
+    let mut untimed = CcsdsPrimaryHeader { apid: 3, ..Default::default() };
+    untimed.set_packet_length(10).unwrap();
+    let mut untimed_packet = untimed.encode().to_vec();
+    untimed_packet.extend_from_slice(&[0x00; 4]);
+
+    let bytes = [timed_packet(1, &first), untimed_packet, timed_packet(2, &second)].concat();
+
+    let path = TempPath::file("time_code.bin");
+    std::fs::write(&path, &bytes).unwrap();
+
+    let mut input = FramedReadStream::from_str(&format!("file:{}|ccsds:7:1024:cuc:4:2", path.display())).unwrap();
+
+    assert!(matches!(input.read_message(), FrameReadResult::Message(_)));
+    assert_eq!(input.message_time(), Some(first));
+
+    // a packet without a secondary header has no time
+    assert!(matches!(input.read_message(), FrameReadResult::Message(_)));
+    assert_eq!(input.message_time(), None);
+
+    assert!(matches!(input.read_message(), FrameReadResult::Message(_)));
+    assert_eq!(input.message_time(), Some(second));
+}
+
+#[test]
+fn apid_rules_match_framer_times() {
+    let start = CcsdsTime::new(1_600_000_000, 0);
+    let rule = ApidRule { apids: ApidSet::default(),
+                          packet_type: None,
+                          time_range: Some(TimeRange { start: Some(start), end: None }),
+                          action: ApidAction::Route(vec!["after".to_string()]),
+    };
+
+    let mut outputs = BTreeMap::new();
+    outputs.insert("after".to_string(), FramedWriteStream::new(WriteStream::Null, Box::new(RawFramer)));
+
+    // the router has no time code of its own, so it relies on the framer's times
+    let mut router = ApidRouter::new(RuleRouter::new(vec![rule], ApidAction::Drop, outputs).unwrap());
+
+    let later = CcsdsTime::new(1_600_000_001, 0);
+    let earlier = CcsdsTime::new(1_599_999_999, 0);
+
+    assert_eq!(router.route_timed_packet(&timed_packet(1, &later), Some(later)), Ok(1));
+    assert_eq!(router.route_timed_packet(&timed_packet(1, &earlier), Some(earlier)), Ok(0));
+    assert_eq!(router.route_packet(&timed_packet(1, &later)), Ok(0));
+
+    // with a time code, the router reads times from packets routed without one
+    router.time_code = Some(cuc(4, 2));
+    assert_eq!(router.route_packet(&timed_packet(1, &later)), Ok(1));
+
+    assert_eq!(router.router.routed, 2);
+    assert_eq!(router.router.dropped, 2);
+}
@@ -0,0 +1,338 @@
+extern crate backplane;
+extern crate bytes;
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use bytes::BytesMut;
+
+mod common;
+use common::*;
+
+use backplane::*;
+use backplane::ccsds::*;
+use backplane::coding::*;
+use backplane::framing::*;
+use backplane::rule_route::*;
+use backplane::tm::*;
+use backplane::transform::*;
+
+
+/// The length of each test frame's data field
+const DATA_FIELD_LENGTH: usize = 20;
+
+/// A packet of the given total length, with data bytes counting up from the sequence count
+fn packet(apid: u16, sequence_count: u16, packet_length: usize) -> Vec<u8> {
+    let mut header = CcsdsPrimaryHeader { apid, sequence_count, ..Default::default() };
+    header.set_packet_length(packet_length).unwrap();
+
+    let mut bytes = header.encode().to_vec();
+    bytes.extend((0..packet_length - CCSDS_PRI_HEADER_SIZE).map(|index| (sequence_count as usize + index) as u8));
+    bytes
+}
+
+/// A TM frame on the given virtual channel, carrying the given data field
+fn frame(vcid: u8, frame_count: u8, first_header_pointer: u16, data: &[u8]) -> Vec<u8> {
+    assert_eq!(data.len(), DATA_FIELD_LENGTH);
+
+    let header = TmFrameHeader { scid: 42,
+                                 vcid,
+                                 virtual_channel_frame_count: frame_count,
+                                 first_header_pointer,
+                                 ..Default::default() };
+
+    let mut bytes = header.encode().to_vec();
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+fn extract(extractor: &mut PacketExtractor, frame: &[u8]) -> (Vec<Vec<u8>>, Result<(), String>) {
+    let mut packets = Vec::new();
+    let result = extractor.extract(frame, &mut packets);
+    (packets.iter().map(|packet| packet.to_vec()).collect(), result)
+}
+
+#[test]
+fn first_header_pointer_finds_packets() {
+    let first = packet(1, 0, 10);
+    let second = packet(2, 0, 10);
+    let data = [first.clone(), second.clone()].concat();
+
+    let mut extractor = PacketExtractor::new(PacketExtractorSettings::default());
+    let (packets, result) = extract(&mut extractor, &frame(0, 0, 0, &data));
+    assert_eq!(result, Ok(()));
+    assert_eq!(packets, vec![first, second]);
+
+    // the end of a packet from before the extractor started is skipped
+    let third = packet(3, 0, 16);
+    let data = [vec![0xAA; 4], third.clone()].concat();
+    let (packets, result) = extract(&mut extractor, &frame(1, 0, 4, &data));
+    assert_eq!(result, Ok(()));
+    assert_eq!(packets, vec![third]);
+    assert_eq!(extractor.packets_extracted, 3);
+}
+
+#[test]
+fn packets_span_frames() {
+    let long = packet(1, 0, 50);
+    let short = packet(1, 1, 10);
+    let stream = [long.clone(), short.clone()].concat();
+
+    let mut extractor = PacketExtractor::new(PacketExtractorSettings::default());
+
+    let (packets, _) = extract(&mut extractor, &frame(0, 0, 0, &stream[0..20]));
+    assert!(packets.is_empty());
+
+    // no packet starts in the middle frame
+    let (packets, _) = extract(&mut extractor, &frame(0, 1, TM_FHP_NO_PACKET_START, &stream[20..40]));
+    assert!(packets.is_empty());
+
+    let (packets, result) = extract(&mut extractor, &frame(0, 2, 10, &stream[40..60]));
+    assert_eq!(result, Ok(()));
+    assert_eq!(packets, vec![long, short]);
+}
+
+#[test]
+fn frame_count_discontinuity_discards_partial_packet() {
+    let long = packet(1, 0, 30);
+    let next = packet(1, 1, 10);
+    let after = packet(1, 2, 10);
+
+    let mut extractor = PacketExtractor::new(PacketExtractorSettings::default());
+    let (packets, _) = extract(&mut extractor, &frame(0, 7, 0, &long[0..20]));
+    assert!(packets.is_empty());
+
+    // frame 8 is lost, so the long packet can not be completed, and the extractor picks up at the first header
+    let data = [long[20..30].to_vec(), next.clone()].concat();
+    let (packets, result) = extract(&mut extractor, &frame(0, 9, 10, &data));
+    assert!(result.unwrap_err().contains("discontinuity"));
+    assert_eq!(packets, vec![next]);
+    assert_eq!(extractor.packets_discarded, 1);
+
+    // frame counts wrap
+    let mut extractor = PacketExtractor::new(PacketExtractorSettings::default());
+    let data = [after.clone(), after.clone()].concat();
+    let _ = extract(&mut extractor, &frame(0, 255, 0, &data));
+    let (packets, result) = extract(&mut extractor, &frame(0, 0, 0, &data));
+    assert_eq!(result, Ok(()));
+    assert_eq!(packets.len(), 2);
+}
+
+#[test]
+fn virtual_channels_are_reassembled_separately() {
+    let on_zero = packet(1, 0, 30);
+    let on_one = packet(2, 0, 30);
+    let filler = packet(3, 0, 10);
+
+    let mut extractor = PacketExtractor::new(PacketExtractorSettings::default());
+    let _ = extract(&mut extractor, &frame(0, 0, 0, &on_zero[0..20]));
+    let _ = extract(&mut extractor, &frame(1, 0, 0, &on_one[0..20]));
+
+    let (packets, _) = extract(&mut extractor, &frame(1, 1, 10, &[on_one[20..30].to_vec(), filler.clone()].concat()));
+    assert_eq!(packets, vec![on_one, filler.clone()]);
+
+    let (packets, _) = extract(&mut extractor, &frame(0, 1, 10, &[on_zero[20..30].to_vec(), filler.clone()].concat()));
+    assert_eq!(packets, vec![on_zero, filler]);
+}
+
+#[test]
+fn idle_frames_and_packets() {
+    let data = [packet(1, 0, 10), packet(CCSDS_IDLE_APID, 0, 10)].concat();
+
+    let mut extractor = PacketExtractor::new(PacketExtractorSettings::default());
+    let (packets, result) = extract(&mut extractor, &frame(0, 0, TM_FHP_IDLE_DATA, &[0x55; DATA_FIELD_LENGTH]));
+    assert_eq!(result, Ok(()));
+    assert!(packets.is_empty());
+    assert_eq!(extractor.idle_frames, 1);
+
+    // an idle frame still counts in the virtual channel's frame count
+    let (packets, result) = extract(&mut extractor, &frame(0, 1, 0, &data));
+    assert_eq!(result, Ok(()));
+    assert_eq!(packets, vec![packet(1, 0, 10)]);
+    assert_eq!(extractor.idle_packets, 1);
+
+    let settings = PacketExtractorSettings { keep_idle_packets: true, ..Default::default() };
+    let mut extractor = PacketExtractor::new(settings);
+    let (packets, _) = extract(&mut extractor, &frame(0, 0, 0, &data));
+    assert_eq!(packets.len(), 2);
+}
+
+#[test]
+fn frame_transforms_apply_before_extraction() {
+    let first = packet(1, 0, 10);
+    let second = packet(2, 0, 10);
+    let data = [first.clone(), second.clone()].concat();
+
+    // each frame is randomized, with an FECF, and the second frame is corrupted
+    let mut framed = Vec::new();
+    for frame_count in 0..2 {
+        let mut tm_frame = BytesMut::from(&frame(0, frame_count, 0, &data)[..]);
+        tm_frame = FecfAppend.transform(tm_frame).unwrap().unwrap();
+        if frame_count == 1 {
+            tm_frame[8] ^= 0xFF;
+        }
+        randomize(&mut tm_frame, &pn_sequence());
+
+        framed.extend_from_slice(&TM_DEFAULT_ASM);
+        framed.extend_from_slice(&tm_frame);
+    }
+
+    let path = TempPath::file("tm_packets.bin");
+    std::fs::write(&path, &framed).unwrap();
+
+    let frame_length = TM_PRI_HEADER_SIZE + DATA_FIELD_LENGTH + 2;
+    let desc = format!("file:{}|tm_packets:{}:1acffc1d:fecf|derandomize|fecf_check", path.display(), frame_length);
+    let mut input = FramedReadStream::from_str(&desc).unwrap();
+
+    assert_eq!(input.read_message(), FrameReadResult::Message(BytesMut::from(&first[..])));
+    assert_eq!(input.read_message(), FrameReadResult::Message(BytesMut::from(&second[..])));
+
+    // the corrupted frame fails its check, so its packets are not extracted
+    match input.read_message() {
+        FrameReadResult::FramingError(_) => {},
+        other => panic!("expected a framing error, not {:?}", other),
+    }
+    assert_eq!(input.read_message(), FrameReadResult::Finished);
+}
+
+#[test]
+fn frame_header_fields_round_trip() {
+    let header = TmFrameHeader { version: 0,
+                                 scid: 0x2A5,
+                                 vcid: 6,
+                                 ocf_flag: true,
+                                 master_channel_frame_count: 0x81,
+                                 virtual_channel_frame_count: 0x7E,
+                                 secondary_header_flag: false,
+                                 sync_flag: false,
+                                 packet_order_flag: false,
+                                 segment_length_id: 3,
+                                 first_header_pointer: 0x123,
+    };
+
+    // 00 1010100101 110 1, then the counts, then 0 0 0 11 00100100011
+    let bytes = header.encode();
+    assert_eq!(bytes, [0x2A, 0x5D, 0x81, 0x7E, 0x19, 0x23]);
+    assert_eq!(TmFrameHeader::decode(&bytes).unwrap(), header);
+
+    // each field decodes on its own, without bleeding into its neighbours
+    let decoded = TmFrameHeader::decode(&[0xC0, 0x0E, 0x00, 0xFF, 0xE7, 0xFE]).unwrap();
+    assert_eq!(decoded.version, 3);
+    assert_eq!(decoded.scid, 0);
+    assert_eq!(decoded.vcid, 7);
+    assert!(!decoded.ocf_flag);
+    assert_eq!(decoded.master_channel_frame_count, 0);
+    assert_eq!(decoded.virtual_channel_frame_count, 0xFF);
+    assert!(decoded.secondary_header_flag && decoded.sync_flag && decoded.packet_order_flag);
+    assert_eq!(decoded.segment_length_id, 0);
+    assert_eq!(decoded.first_header_pointer, TM_FHP_IDLE_DATA);
+    assert!(decoded.is_idle());
+
+    assert!(TmFrameHeader::decode(&bytes[..5]).is_err());
+}
+
+fn sync_framer() -> FrameSyncFramer {
+    FrameSyncFramer::new(FrameSyncSettings { asm: TM_DEFAULT_ASM.to_vec(), frame_length: 8 })
+}
+
+#[test]
+fn frame_sync_skips_to_a_sync_marker() {
+    let mut framer = sync_framer();
+    let frame = [1, 2, 3, 4, 5, 6, 7, 8];
+
+    let mut buffer = BytesMut::from(&[0x55, 0x1A, 0xCF, 0x00][..]);
+    buffer.extend_from_slice(&TM_DEFAULT_ASM);
+    buffer.extend_from_slice(&frame);
+    buffer.extend_from_slice(&TM_DEFAULT_ASM);
+    buffer.extend_from_slice(&frame);
+
+    assert!(framer.next_message(&mut buffer).is_err());
+    assert_eq!(framer.sync_losses, 1);
+    assert_eq!(framer.bytes_skipped, 4);
+
+    assert_eq!(framer.next_message(&mut buffer).unwrap().unwrap(), &frame[..]);
+    assert_eq!(framer.next_message(&mut buffer).unwrap().unwrap(), &frame[..]);
+    assert!(buffer.is_empty());
+    assert_eq!(framer.sync_losses, 1);
+
+    let mut encoded = BytesMut::new();
+    framer.encode_message(&frame, &mut encoded).unwrap();
+    assert_eq!(&encoded[..4], &TM_DEFAULT_ASM[..]);
+    assert!(framer.encode_message(&frame[..7], &mut encoded).is_err());
+}
+
+#[test]
+fn frame_sync_keeps_a_partial_sync_marker() {
+    let mut framer = sync_framer();
+    let frame = [9; 8];
+
+    // the search reports one loss, and keeps the bytes which may start a sync marker
+    let mut buffer = BytesMut::from(&[0x00, 0x11, 0x22, 0x33, 0x44, 0x1A, 0xCF, 0xFC][..]);
+    assert!(framer.next_message(&mut buffer).is_err());
+    assert_eq!(&buffer[..], &[0x1A, 0xCF, 0xFC]);
+    assert_eq!(framer.bytes_skipped, 5);
+
+    buffer.extend_from_slice(&[0x1D]);
+    buffer.extend_from_slice(&frame[..4]);
+    assert_eq!(framer.next_message(&mut buffer).unwrap(), None);
+
+    buffer.extend_from_slice(&frame[4..]);
+    assert_eq!(framer.next_message(&mut buffer).unwrap().unwrap(), &frame[..]);
+
+    // more bytes without a sync marker are counted as a second loss, once
+    buffer.extend_from_slice(&[0x77; 10]);
+    assert!(framer.next_message(&mut buffer).is_err());
+    buffer.extend_from_slice(&[0x77; 10]);
+    assert_eq!(framer.next_message(&mut buffer).unwrap(), None);
+
+    assert_eq!(framer.sync_losses, 2);
+    assert_eq!(framer.bytes_skipped, 5 + 17);
+}
+
+/// The virtual channel of each frame written to a file
+fn written_vcids(path: &TempPath) -> Vec<u8> {
+    let bytes = std::fs::read(path).unwrap();
+
+    bytes.chunks(TM_PRI_HEADER_SIZE + DATA_FIELD_LENGTH)
+         .map(|frame| TmFrameHeader::decode(frame).unwrap().vcid)
+         .collect()
+}
+
+#[test]
+fn virtual_channels_are_routed_to_their_outputs() {
+    let names = ["realtime", "playback", "other"];
+    let paths: Vec<TempPath> = names.iter().map(|name| TempPath::file(&format!("tm_{}.bin", name))).collect();
+    let mut outputs = BTreeMap::new();
+    for (name, path) in names.iter().zip(paths.iter()) {
+        outputs.insert(name.to_string(), StreamConfig::Descriptor(format!("file:{}", path)));
+    }
+
+    let route = |names: &[&str]| RouteAction::Route(names.iter().map(|name| name.to_string()).collect());
+    let rules = vec![VirtualChannelRule { vcids: vec![0], scid: Some(42), action: route(&["realtime"]) },
+                     VirtualChannelRule { vcids: vec![1, 2], scid: None, action: route(&["playback", "other"]) },
+                     VirtualChannelRule { vcids: vec![7], scid: None, action: RouteAction::Drop }];
+
+    let settings = VirtualChannelRouteSettings { outputs, rules, default: route(&["other"]), drop_idle: true };
+    let mut router = settings.open().unwrap();
+
+    let data = [0; DATA_FIELD_LENGTH];
+    assert_eq!(router.route_frame(&frame(0, 0, 0, &data)), Ok(1));
+    assert_eq!(router.route_frame(&frame(1, 0, 0, &data)), Ok(2));
+    assert_eq!(router.route_frame(&frame(2, 0, 0, &data)), Ok(2));
+    assert_eq!(router.route_frame(&frame(3, 0, 0, &data)), Ok(1));
+    assert_eq!(router.route_frame(&frame(7, 0, 0, &data)), Ok(0));
+    assert_eq!(router.route_frame(&frame(0, 1, TM_FHP_IDLE_DATA, &data)), Ok(0));
+
+    // a frame from another spacecraft on the realtime channel falls through to the default
+    let mut other_spacecraft = frame(0, 2, 0, &data);
+    other_spacecraft[..2].copy_from_slice(&TmFrameHeader { scid: 7, ..Default::default() }.encode()[..2]);
+    assert_eq!(router.route_frame(&other_spacecraft), Ok(1));
+
+    assert_eq!(router.router.routed, 5);
+    assert_eq!(router.router.dropped, 2);
+
+    drop(router);
+    assert_eq!(written_vcids(&paths[0]), vec![0]);
+    assert_eq!(written_vcids(&paths[1]), vec![1, 2]);
+    assert_eq!(written_vcids(&paths[2]), vec![1, 2, 3, 0]);
+}